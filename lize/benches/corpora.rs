@@ -0,0 +1,60 @@
+//! Serialize and deserialize benchmarks over the shared corpora in `benches/corpora/`.
+//!
+//! The corpora are generated by `python/benchmarks/corpora.py`, so the Python
+//! benchmarks measure the same data shapes.
+
+use std::{fs, hint::black_box, path::PathBuf};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use lize::{SmallVec, Value, STACK_N};
+
+fn corpora() -> Vec<(String, Vec<u8>)> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("benches")
+        .join("corpora");
+
+    let mut corpora = fs::read_dir(&dir)
+        .expect("missing benches/corpora, run python/benchmarks/corpora.py")
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            (name, fs::read(&path).unwrap())
+        })
+        .collect::<Vec<_>>();
+    corpora.sort();
+    corpora
+}
+
+fn bench_serialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("serialize");
+
+    for (name, bytes) in corpora() {
+        let value = Value::deserialize_from(&bytes).unwrap();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(&name), &value, |b, value| {
+            b.iter(|| {
+                let mut buffer = SmallVec::<[u8; STACK_N]>::new();
+                black_box(value).serialize_into(&mut buffer).unwrap();
+                buffer
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_deserialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("deserialize");
+
+    for (name, bytes) in corpora() {
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(&name), &bytes, |b, bytes| {
+            b.iter(|| Value::deserialize_from(black_box(bytes)).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_serialize, bench_deserialize);
+criterion_main!(benches);
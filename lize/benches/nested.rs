@@ -0,0 +1,137 @@
+//! Serializing maps of functions nested 5 levels deep, the way the Python
+//! module writes a `Runnable` whose defaults hold more of them.
+//!
+//! Each function is an [`ext::RUNNABLE`] envelope kept as a value rather than
+//! pre-serialized bytes, so every byte is written once, straight into the
+//! output, however deep it's nested. Before benchmarking, the bytes handed to
+//! the writer and the bytes allocated while writing are counted: a nested
+//! function serialized on its own and then copied into its parent would need
+//! a buffer for it, so everything copied must add up to the output and a
+//! fixed amount besides, whatever the size of the bytecode.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    io::Write,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use lize::{ext, SerializeOptions, SmallVec, Value, STACK_N};
+
+/// Counts the bytes ever allocated, including what vectors grow into.
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const DEPTH: usize = 5;
+
+/// Bytes that writing may allocate whatever the size of the bytecode, for
+/// the stack of values still to write. Under 1 KiB today, while copying the
+/// nested functions would take more than the smallest output's 3 KiB.
+const OVERHEAD: usize = 2 << 10;
+
+/// A function envelope with `code_len` bytes of bytecode, whose defaults
+/// hold a map of `width` functions one level down.
+fn function(depth: usize, width: usize, code_len: usize) -> Value<'static> {
+    let mut fields = vec![
+        (Value::from("code"), Value::SliceLike(vec![0x97; code_len])),
+        (Value::from("name"), Value::StrLike(format!("f{depth}"))),
+    ];
+    if depth > 1 {
+        let inner = (0..width)
+            .map(|i| {
+                (
+                    Value::StrLike(format!("g{i}")),
+                    function(depth - 1, width, code_len),
+                )
+            })
+            .collect();
+        fields.push((
+            Value::from("defaults"),
+            Value::Vector(vec![Value::HashMap(inner)]),
+        ));
+    }
+    // Version 3 of the envelope: `[3, {code, name, defaults, ...}]`.
+    Value::Ext(
+        ext::RUNNABLE,
+        Box::new(Value::Vector(vec![
+            Value::SmallU8(3),
+            Value::HashMap(fields),
+        ])),
+    )
+}
+
+/// Counts the bytes written through it.
+#[derive(Default)]
+struct Written(usize);
+
+impl Write for Written {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn bench_nested(c: &mut Criterion) {
+    let mut group = c.benchmark_group("nested");
+
+    for code_len in [64, 4 << 10, 256 << 10] {
+        let value = function(DEPTH, 2, code_len);
+        let bytes = value.serialize().unwrap();
+
+        let before = ALLOCATED.load(Ordering::Relaxed);
+        let mut written = Written::default();
+        value
+            .serialize_to(&SerializeOptions::new(), &mut written)
+            .unwrap();
+        let allocated = ALLOCATED.load(Ordering::Relaxed) - before;
+        assert_eq!(
+            written.0,
+            bytes.len(),
+            "nested functions must be written exactly once"
+        );
+        assert!(
+            allocated < OVERHEAD,
+            "writing {} bytes allocated {allocated} more, so nested functions were copied",
+            bytes.len()
+        );
+
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(code_len), &value, |b, value| {
+            b.iter(|| {
+                let mut buffer = SmallVec::<[u8; STACK_N]>::new();
+                black_box(value).serialize_into(&mut buffer).unwrap();
+                buffer
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_nested);
+criterion_main!(benches);
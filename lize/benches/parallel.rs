@@ -0,0 +1,64 @@
+//! Serializing a large vector of maps on one thread and on rayon's thread pool.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use lize::{SerializeOptions, SmallVec, Value, STACK_N};
+
+fn rows(len: usize) -> Value<'static> {
+    Value::Vector(
+        (0..len as i64)
+            .map(|i| {
+                Value::HashMap(vec![
+                    (Value::from("id"), Value::I64(i)),
+                    (Value::from("name"), Value::StrLike(format!("row {i}"))),
+                    (Value::from("score"), Value::F64(i as f64 / 7.0)),
+                    (
+                        Value::from("tags"),
+                        Value::Vector(vec![Value::from("a"), Value::from("b")]),
+                    ),
+                ])
+            })
+            .collect(),
+    )
+}
+
+fn bench_parallel(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parallel");
+    // Canonical mode sorts every map, so there's more work to spread out.
+    let options = SerializeOptions::new().canonical(true);
+
+    for len in [10_000, 1_000_000] {
+        let value = rows(len);
+        let bytes = {
+            let mut buffer = SmallVec::<[u8; STACK_N]>::new();
+            value.serialize_with(&options, &mut buffer).unwrap();
+            buffer.len()
+        };
+        group.throughput(Throughput::Bytes(bytes as u64));
+
+        group.bench_with_input(BenchmarkId::new("serial", len), &value, |b, value| {
+            b.iter(|| {
+                let mut buffer = SmallVec::<[u8; STACK_N]>::new();
+                black_box(value)
+                    .serialize_with(&options, &mut buffer)
+                    .unwrap();
+                buffer
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("parallel", len), &value, |b, value| {
+            b.iter(|| {
+                let mut buffer = SmallVec::<[u8; STACK_N]>::new();
+                black_box(value)
+                    .serialize_parallel(&options, &mut buffer)
+                    .unwrap();
+                buffer
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_parallel);
+criterion_main!(benches);
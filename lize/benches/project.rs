@@ -0,0 +1,60 @@
+//! Projecting a few keys out of a map and re-serializing them, with the
+//! excluded entry growing from 1 KiB to 64 MiB.
+//!
+//! The time stays flat as the excluded entry grows, since decoding borrows it
+//! and projecting never visits it.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use lize::{SmallVec, Value, STACK_N};
+
+fn payload(excluded_len: usize) -> Vec<u8> {
+    let blob = vec![0xab_u8; excluded_len];
+    let value = Value::HashMap(vec![
+        (Value::from("id"), Value::I64(42)),
+        (Value::from("name"), Value::from("kept")),
+        (Value::from("blob"), Value::Slice(&blob)),
+    ]);
+    value.serialize().unwrap()
+}
+
+fn bench_project(c: &mut Criterion) {
+    let mut group = c.benchmark_group("project");
+
+    for excluded_len in [1 << 10, 1 << 20, 64 << 20] {
+        let bytes = payload(excluded_len);
+
+        // The kept string must point into the payload, not at a copy.
+        let value = Value::deserialize_from(&bytes).unwrap();
+        let projected = value.project(&["name"]);
+        let Value::HashMap(pairs) = &projected else {
+            unreachable!()
+        };
+        let Value::Str(name) = pairs[0].1 else {
+            unreachable!()
+        };
+        assert!(bytes.as_ptr_range().contains(&name.as_ptr()));
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(excluded_len),
+            &bytes,
+            |b, bytes| {
+                b.iter(|| {
+                    let value = Value::deserialize_from(black_box(bytes)).unwrap();
+                    let mut buffer = SmallVec::<[u8; STACK_N]>::new();
+                    value
+                        .project(&["id", "name"])
+                        .serialize_into(&mut buffer)
+                        .unwrap();
+                    buffer
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_project);
+criterion_main!(benches);
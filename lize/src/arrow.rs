@@ -0,0 +1,369 @@
+//! Conversion between vectors of maps and Arrow [`RecordBatch`]es, with the
+//! `arrow` feature.
+//!
+//! Each map is a row and each key a column. Integers, floats, booleans,
+//! strings and byte strings are supported, and any column may hold `None`s or
+//! be missing from some rows. Nested vectors, maps and extension values are
+//! rejected for now.
+//!
+//! # Example
+//! ```rust
+//! use lize::{arrow, Value};
+//!
+//! let rows = Value::Vector(vec![
+//!     Value::HashMap(vec![(Value::from("id"), Value::SmallU8(1))]),
+//!     Value::HashMap(vec![(Value::from("id"), Value::SmallU8(2))]),
+//! ]);
+//!
+//! let batch = arrow::to_record_batch(&rows)?;
+//! assert_eq!(batch.num_rows(), 2);
+//! assert_eq!(arrow::from_record_batch(&batch)?.serialize()?, rows.serialize()?);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::sync::Arc;
+
+use arrow_array::{
+    builder::{BinaryBuilder, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder},
+    cast::AsArray,
+    types::{
+        Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type, UInt16Type,
+        UInt32Type, UInt64Type, UInt8Type,
+    },
+    Array, ArrayRef, NullArray, RecordBatch, RecordBatchOptions,
+};
+use arrow_schema::{DataType, Field, Schema};
+
+use crate::{Error, Result, Value};
+
+pub use arrow_array;
+pub use arrow_schema;
+
+/// Converts a [`Value::Vector`] of [`Value::HashMap`]s with string keys into a
+/// record batch, with a nullable column per key in the order keys first appear.
+pub fn to_record_batch(value: &Value) -> Result<RecordBatch> {
+    let Value::Vector(rows) = value else {
+        return Err(table_error("expected a vector of maps"));
+    };
+
+    let mut names: Vec<&str> = vec![];
+    let mut columns: Vec<Vec<Option<&Value>>> = vec![];
+    for (row, item) in rows.iter().enumerate() {
+        let Value::HashMap(pairs) = item else {
+            return Err(table_error(format!("row {} is not a map", row)));
+        };
+
+        for (key, value) in pairs {
+            let Some(name) = key_str(key) else {
+                return Err(table_error(format!(
+                    "row {} has a key that isn't a string",
+                    row
+                )));
+            };
+            let column = match names.iter().position(|n| *n == name) {
+                Some(column) => column,
+                None => {
+                    names.push(name);
+                    columns.push(vec![None; rows.len()]);
+                    columns.len() - 1
+                }
+            };
+            columns[column][row] = unwrap_optional(value);
+        }
+    }
+
+    let mut fields = vec![];
+    let mut arrays = vec![];
+    for (name, column) in names.iter().zip(&columns) {
+        let array = build_array(name, column)?;
+        fields.push(Field::new(*name, array.data_type().clone(), true));
+        arrays.push(array);
+    }
+
+    let options = RecordBatchOptions::new().with_row_count(Some(rows.len()));
+    RecordBatch::try_new_with_options(Arc::new(Schema::new(fields)), arrays, &options)
+        .map_err(|err| table_error(err.to_string()))
+}
+
+/// Converts a record batch into a [`Value::Vector`] of [`Value::HashMap`]s, one
+/// per row. Nulls become `Optional(None)`, so every row has every column.
+pub fn from_record_batch(batch: &RecordBatch) -> Result<Value<'static>> {
+    let schema = batch.schema();
+    let mut rows: Vec<Vec<(Value<'static>, Value<'static>)>> = (0..batch.num_rows())
+        .map(|_| Vec::with_capacity(batch.num_columns()))
+        .collect();
+
+    for (field, array) in schema.fields().iter().zip(batch.columns()) {
+        let values = read_array(field.name(), array)?;
+        for (row, value) in rows.iter_mut().zip(values) {
+            row.push((Value::StrLike(field.name().clone()), value));
+        }
+    }
+
+    Ok(Value::Vector(
+        rows.into_iter().map(Value::HashMap).collect(),
+    ))
+}
+
+/// The kinds of values a column can hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Int,
+    Float,
+    Bool,
+    Str,
+    Bytes,
+}
+
+impl Kind {
+    fn of(value: &Value) -> Option<Self> {
+        Some(match value {
+            Value::I64(_) | Value::I32(_) | Value::U8(_) | Value::SmallU8(_) => Self::Int,
+            Value::F64(_) | Value::F32(_) => Self::Float,
+            Value::Bool(_) => Self::Bool,
+            Value::Str(_) | Value::StrLike(_) => Self::Str,
+            Value::Slice(_) | Value::SliceLike(_) => Self::Bytes,
+            _ => return None,
+        })
+    }
+}
+
+fn build_array(name: &str, column: &[Option<&Value>]) -> Result<ArrayRef> {
+    // Integers widen to floats in a column that holds both.
+    let mut kind = None;
+    for value in column.iter().flatten() {
+        let Some(next) = Kind::of(value) else {
+            return Err(table_error(format!(
+                "column {:?} holds nested or extension values, which aren't supported yet",
+                name
+            )));
+        };
+        kind = match (kind, next) {
+            (None, next) => Some(next),
+            (Some(Kind::Int), Kind::Float) | (Some(Kind::Float), Kind::Int) => Some(Kind::Float),
+            (Some(kind), next) if kind == next => Some(kind),
+            (Some(kind), next) => {
+                return Err(table_error(format!(
+                    "column {:?} mixes {:?} and {:?} values",
+                    name, kind, next
+                )))
+            }
+        };
+    }
+
+    let int = |value: &Value| match value {
+        Value::I64(i) => *i,
+        Value::I32(i) => *i as i64,
+        Value::U8(u) | Value::SmallU8(u) => *u as i64,
+        _ => unreachable!(),
+    };
+    let float = |value: &Value| match value {
+        Value::F64(f) => *f,
+        Value::F32(f) => *f as f64,
+        value => int(value) as f64,
+    };
+
+    Ok(match kind {
+        None => Arc::new(NullArray::new(column.len())),
+        Some(Kind::Int) => {
+            let mut builder = Int64Builder::with_capacity(column.len());
+            builder.extend(column.iter().map(|value| value.map(int)));
+            Arc::new(builder.finish())
+        }
+        Some(Kind::Float) => {
+            let mut builder = Float64Builder::with_capacity(column.len());
+            builder.extend(column.iter().map(|value| value.map(float)));
+            Arc::new(builder.finish())
+        }
+        Some(Kind::Bool) => {
+            let mut builder = BooleanBuilder::with_capacity(column.len());
+            builder.extend(column.iter().map(|value| value.and_then(Value::as_bool)));
+            Arc::new(builder.finish())
+        }
+        Some(Kind::Str) => {
+            let mut builder = StringBuilder::new();
+            builder.extend(column.iter().map(|value| value.and_then(key_str)));
+            Arc::new(builder.finish())
+        }
+        Some(Kind::Bytes) => {
+            let mut builder = BinaryBuilder::new();
+            for value in column {
+                builder.append_option(value.and_then(bytes));
+            }
+            Arc::new(builder.finish())
+        }
+    })
+}
+
+fn read_array(name: &str, array: &ArrayRef) -> Result<Vec<Value<'static>>> {
+    macro_rules! collect {
+        ($values:expr, $convert:expr) => {
+            $values
+                .iter()
+                .map(|v| v.map($convert).unwrap_or(Value::Optional(None)))
+                .collect()
+        };
+    }
+
+    Ok(match array.data_type() {
+        DataType::Null => (0..array.len()).map(|_| Value::Optional(None)).collect(),
+        DataType::Boolean => collect!(array.as_boolean(), Value::Bool),
+        DataType::Int8 => collect!(array.as_primitive::<Int8Type>(), |i| int(i as i64)),
+        DataType::Int16 => collect!(array.as_primitive::<Int16Type>(), |i| int(i as i64)),
+        DataType::Int32 => collect!(array.as_primitive::<Int32Type>(), |i| int(i as i64)),
+        DataType::Int64 => collect!(array.as_primitive::<Int64Type>(), int),
+        DataType::UInt8 => collect!(array.as_primitive::<UInt8Type>(), |i| int(i as i64)),
+        DataType::UInt16 => collect!(array.as_primitive::<UInt16Type>(), |i| int(i as i64)),
+        DataType::UInt32 => collect!(array.as_primitive::<UInt32Type>(), |i| int(i as i64)),
+        DataType::UInt64 => array
+            .as_primitive::<UInt64Type>()
+            .iter()
+            .map(|v| match v {
+                Some(u) => i64::try_from(u).map(int).map_err(|_| {
+                    table_error(format!(
+                        "column {:?} holds {}, which overflows an i64",
+                        name, u
+                    ))
+                }),
+                None => Ok(Value::Optional(None)),
+            })
+            .collect::<Result<_>>()?,
+        DataType::Float32 => collect!(array.as_primitive::<Float32Type>(), Value::F32),
+        DataType::Float64 => collect!(array.as_primitive::<Float64Type>(), Value::F64),
+        DataType::Utf8 => collect!(array.as_string::<i32>(), |s| Value::StrLike(s.to_owned())),
+        DataType::LargeUtf8 => {
+            collect!(array.as_string::<i64>(), |s| Value::StrLike(s.to_owned()))
+        }
+        DataType::Binary => collect!(array.as_binary::<i32>(), |b| Value::SliceLike(b.to_vec())),
+        DataType::LargeBinary => {
+            collect!(array.as_binary::<i64>(), |b| Value::SliceLike(b.to_vec()))
+        }
+        other => {
+            return Err(table_error(format!(
+                "column {:?} has type {}, which isn't supported yet",
+                name, other
+            )))
+        }
+    })
+}
+
+/// The smallest integer value holding `i`, as the Python module writes them.
+fn int(i: i64) -> Value<'static> {
+    match i {
+        0..=235 => Value::SmallU8(i as u8),
+        236..=255 => Value::U8(i as u8),
+        _ => match i32::try_from(i) {
+            Ok(i) => Value::I32(i),
+            Err(_) => Value::I64(i),
+        },
+    }
+}
+
+fn key_str<'v>(value: &'v Value) -> Option<&'v str> {
+    match value {
+        Value::Str(s) => Some(s),
+        Value::StrLike(s) => Some(s),
+        _ => None,
+    }
+}
+
+fn bytes<'v>(value: &'v Value) -> Option<&'v [u8]> {
+    match value {
+        Value::Slice(s) => Some(s),
+        Value::SliceLike(s) => Some(s),
+        _ => None,
+    }
+}
+
+/// `None` for nulls, and the inner value of `Some`s.
+fn unwrap_optional<'v, 'a>(mut value: &'v Value<'a>) -> Option<&'v Value<'a>> {
+    loop {
+        match value {
+            Value::Optional(None) => return None,
+            Value::Optional(Some(inner)) => value = inner,
+            value => return Some(value),
+        }
+    }
+}
+
+fn table_error(reason: impl Into<String>) -> anyhow::Error {
+    Error::InvalidTable(reason.into()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let rows = Value::Vector(vec![
+            Value::HashMap(vec![
+                (Value::from("id"), Value::SmallU8(1)),
+                (Value::from("score"), Value::F64(0.5)),
+                (Value::from("name"), Value::from("a")),
+                (Value::from("blob"), Value::Slice(b"\x00")),
+                (Value::from("ok"), Value::Bool(true)),
+            ]),
+            Value::HashMap(vec![
+                (Value::from("id"), Value::I64(1 << 40)),
+                (Value::from("score"), Value::Optional(None)),
+                (Value::from("name"), Value::from("b")),
+                (Value::from("blob"), Value::Slice(b"\xff")),
+                (Value::from("ok"), Value::Bool(false)),
+            ]),
+        ]);
+
+        let batch = to_record_batch(&rows)?;
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.schema().field(0).data_type(), &DataType::Int64);
+        assert_eq!(batch.schema().field(3).data_type(), &DataType::Binary);
+        assert_eq!(batch.column(1).null_count(), 1);
+        assert_eq!(from_record_batch(&batch)?.serialize()?, rows.serialize()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_and_mixed() -> Result<()> {
+        let rows = Value::Vector(vec![
+            Value::HashMap(vec![(Value::from("x"), Value::SmallU8(1))]),
+            Value::HashMap(vec![(Value::from("y"), Value::F64(2.5))]),
+            Value::HashMap(vec![(Value::from("x"), Value::F32(1.5))]),
+        ]);
+
+        let batch = to_record_batch(&rows)?;
+        assert_eq!(batch.schema().field(0).data_type(), &DataType::Float64);
+        assert_eq!(batch.column(0).null_count(), 1);
+        assert_eq!(batch.column(1).null_count(), 2);
+
+        let empty = to_record_batch(&Value::Vector(vec![]))?;
+        assert_eq!((empty.num_rows(), empty.num_columns()), (0, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let error = |rows: Value| {
+            let err = to_record_batch(&rows).unwrap_err();
+            match err.downcast_ref() {
+                Some(Error::InvalidTable(reason)) => reason.clone(),
+                _ => panic!("unexpected error {err}"),
+            }
+        };
+
+        let nested = Value::Vector(vec![Value::HashMap(vec![(
+            Value::from("tags"),
+            Value::Vector(vec![Value::from("a")]),
+        )])]);
+        assert!(error(nested).contains("nested"));
+
+        let mixed = Value::Vector(vec![
+            Value::HashMap(vec![(Value::from("x"), Value::SmallU8(1))]),
+            Value::HashMap(vec![(Value::from("x"), Value::from("one"))]),
+        ]);
+        assert!(error(mixed).contains("mixes"));
+
+        assert!(error(Value::Vector(vec![Value::SmallU8(1)])).contains("not a map"));
+    }
+}
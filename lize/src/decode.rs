@@ -0,0 +1,332 @@
+//! The decoder, which reads values without recursing so nesting depth is only
+//! bounded by memory (and [`DeserializeOptions::max_depth`]).
+
+use crate::{
+    ext, legacy, options::check_str_len, tag, varint, DeserializeOptions, Error, Result, Value,
+};
+
+/// A position in the input, with bounds-checked reads.
+pub(crate) struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// The longest slice or string [`Cursor::read_scalar`] accepts.
+    pub(crate) max_str_len: Option<usize>,
+}
+
+impl<'a> Cursor<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            pos: 0,
+            max_str_len: None,
+        }
+    }
+
+    /// How many bytes have been read.
+    pub(crate) fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Moves to `pos`, which must be within the input.
+    pub(crate) fn seek(&mut self, pos: usize) {
+        debug_assert!(pos <= self.bytes.len());
+        self.pos = pos;
+    }
+
+    /// Steps back over the byte just read.
+    pub(crate) fn unread(&mut self) {
+        self.pos -= 1;
+    }
+
+    /// The bytes that haven't been read yet.
+    pub(crate) fn rest(&self) -> &'a [u8] {
+        &self.bytes[self.pos..]
+    }
+
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(Error::UnexpectedEnd)?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into()?)
+    }
+
+    /// Reads a varint length or count, which can't exceed the bytes left since
+    /// every byte or item takes at least one byte.
+    pub(crate) fn read_len(&mut self) -> Result<usize> {
+        let (n, read) = varint::read(self.rest())?;
+        self.pos += read;
+        if n > self.rest().len() {
+            return Err(Error::UnexpectedEnd.into());
+        }
+        Ok(n)
+    }
+
+    /// Reads the length of a slice or string, checked against `max_str_len`
+    /// before the bytes left, so a declared length over the limit always fails
+    /// as too long.
+    fn read_str_len(&mut self) -> Result<usize> {
+        let (len, _) = varint::read(self.rest())?;
+        check_str_len(self.max_str_len, len)?;
+        self.read_len()
+    }
+
+    /// Reads a value that has no children, after its `tag`.
+    pub(crate) fn read_scalar(&mut self, tag: u8) -> Result<Value<'a>> {
+        Ok(match tag {
+            tag::I64 => Value::I64(i64::from_le_bytes(self.read_array()?)),
+            tag::TRUE => Value::Bool(true),
+            tag::FALSE => Value::Bool(false),
+            tag::F64 => Value::F64(f64::from_le_bytes(self.read_array()?)),
+            tag::NONE => Value::Optional(None),
+            tag::I32 => Value::I32(i32::from_le_bytes(self.read_array()?)),
+            tag::F32 => Value::F32(f32::from_le_bytes(self.read_array()?)),
+            tag::U8 => Value::U8(self.read_u8()?),
+            tag::SLICE => {
+                let len = self.read_str_len()?;
+                Value::Slice(self.take(len)?)
+            }
+            tag::STR => {
+                let len = self.read_str_len()?;
+                let s = std::str::from_utf8(self.take(len)?).map_err(|_| Error::InvalidUtf8)?;
+                Value::Str(s)
+            }
+            tag::SMALL_U8.. => Value::SmallU8(tag - tag::SMALL_U8),
+            _ => return Err(Error::UnknownTag(tag).into()),
+        })
+    }
+}
+
+/// The most items or entries reserved up front for a container. Its count
+/// is only checked against the bytes left, which every nested container
+/// passes again, so longer ones grow as their children are read.
+const MAX_RESERVED: usize = 4096;
+
+/// A container whose children are still being read.
+enum Frame<'a> {
+    Vector {
+        items: Vec<Value<'a>>,
+        remaining: usize,
+        select: bool,
+    },
+    Map {
+        pairs: Vec<(Value<'a>, Value<'a>)>,
+        key: Option<Value<'a>>,
+        remaining: usize,
+        select: bool,
+    },
+    Optional(Option<Value<'a>>),
+    /// `select` passes `keys` on to the items of runs, which stand for a vector.
+    Ext(u8, Option<Value<'a>>, bool),
+}
+
+impl<'a> Frame<'a> {
+    /// Adds a decoded child, returning whether the container is complete.
+    fn push(
+        &mut self,
+        value: Value<'a>,
+        options: &DeserializeOptions,
+        cursor: &mut Cursor<'a>,
+    ) -> Result<bool> {
+        Ok(match self {
+            Frame::Vector {
+                items, remaining, ..
+            } => {
+                items.push(value);
+                *remaining -= 1;
+                *remaining == 0
+            }
+            Frame::Map {
+                pairs,
+                key,
+                remaining,
+                select,
+            } => match key.take() {
+                Some(key) => {
+                    pairs.push((key, value));
+                    *remaining -= 1;
+                    *remaining == 0
+                }
+                // Unselected values are skipped without being decoded.
+                None if *select && !options.is_selected(&value) => {
+                    skip(cursor)?;
+                    *remaining -= 1;
+                    *remaining == 0
+                }
+                None => {
+                    *key = Some(value);
+                    false
+                }
+            },
+            Frame::Optional(inner) | Frame::Ext(_, inner, _) => {
+                *inner = Some(value);
+                true
+            }
+        })
+    }
+
+    /// Builds the container once [`Frame::push`] says it's complete.
+    fn finish(self) -> Option<Value<'a>> {
+        Some(match self {
+            Frame::Vector { items, .. } => Value::Vector(items),
+            Frame::Map { pairs, .. } => Value::HashMap(pairs),
+            Frame::Optional(inner) => Value::Optional(Some(Box::new(inner?))),
+            Frame::Ext(code, inner, _) => Value::Ext(code, Box::new(inner?)),
+        })
+    }
+}
+
+/// Decodes the value at the start of `bytes`, returning it and the bytes after it.
+pub(crate) fn decode<'a>(
+    bytes: &'a [u8],
+    options: &DeserializeOptions,
+) -> Result<(Value<'a>, &'a [u8])> {
+    decode_observed(bytes, options, &mut |_| Ok(()))
+}
+
+/// Like [`decode`], calling `observe` with the number of bytes read after
+/// each value, counting a container once its last item is read.
+pub(crate) fn decode_observed<'a, F>(
+    bytes: &'a [u8],
+    options: &DeserializeOptions,
+    observe: &mut F,
+) -> Result<(Value<'a>, &'a [u8])>
+where
+    F: FnMut(usize) -> Result<()>,
+{
+    // Usually an empty file, rather than a value cut short.
+    if bytes.is_empty() {
+        return Err(Error::EmptyInput.into());
+    }
+
+    let mut cursor = Cursor::new(bytes);
+    cursor.max_str_len = options.max_str_len;
+    let mut stack: Vec<Frame<'a>> = vec![];
+
+    loop {
+        let depth = stack.len();
+        options.check_depth(depth)?;
+
+        // `keys` applies at the top level and inside vectors, including runs,
+        // but not inside a kept map value.
+        let select = match stack.last() {
+            None => options.keys.is_some(),
+            Some(Frame::Vector { select, .. }) => *select,
+            Some(Frame::Ext(ext::RUNS, _, select)) => *select,
+            Some(_) => false,
+        };
+
+        let tag = cursor.read_u8()?;
+        let mut value = match tag {
+            tag::VECTOR => match cursor.read_len()? {
+                0 => Value::Vector(vec![]),
+                len => {
+                    stack.push(Frame::Vector {
+                        items: Vec::with_capacity(len.min(MAX_RESERVED)),
+                        remaining: len,
+                        select,
+                    });
+                    continue;
+                }
+            },
+            tag::MAP => match cursor.read_len()? {
+                0 => Value::HashMap(vec![]),
+                len => {
+                    stack.push(Frame::Map {
+                        pairs: Vec::with_capacity(len.min(MAX_RESERVED)),
+                        key: None,
+                        remaining: len,
+                        select,
+                    });
+                    continue;
+                }
+            },
+            tag::SOME => {
+                stack.push(Frame::Optional(None));
+                continue;
+            }
+            tag::EXT => {
+                stack.push(Frame::Ext(cursor.read_u8()?, None, select));
+                continue;
+            }
+            tag if tag::legacy::is_legacy(tag) => {
+                cursor.unread();
+                let len = legacy::encoded_len(cursor.rest())?;
+                legacy::decode(cursor.take(len)?, options, depth, select)?
+            }
+            tag => cursor.read_scalar(tag)?,
+        };
+
+        // Hand the value to its parents, closing every container it completes.
+        loop {
+            observe(cursor.pos)?;
+            let Some(parent) = stack.last_mut() else {
+                return Ok((value, cursor.rest()));
+            };
+            if !parent.push(value, options, &mut cursor)? {
+                break;
+            }
+            value = stack
+                .pop()
+                .and_then(Frame::finish)
+                .ok_or(Error::UnexpectedEnd)?;
+        }
+    }
+}
+
+/// Moves past the value at the cursor without building it.
+pub(crate) fn skip(cursor: &mut Cursor<'_>) -> Result<()> {
+    walk(cursor, false)
+}
+
+/// Like [`skip`], but fails wherever decoding the value would, checking that
+/// strings are UTF-8 and reading the insides of legacy values too.
+pub(crate) fn check(cursor: &mut Cursor<'_>) -> Result<()> {
+    walk(cursor, true)
+}
+
+fn walk(cursor: &mut Cursor<'_>, check: bool) -> Result<()> {
+    let mut pending = 1_usize;
+
+    while pending > 0 {
+        pending -= 1;
+
+        let tag = cursor.read_u8()?;
+        match tag {
+            tag::VECTOR => pending += cursor.read_len()?,
+            tag::MAP => pending += 2 * cursor.read_len()?,
+            tag::SOME => pending += 1,
+            tag::EXT => {
+                cursor.read_u8()?;
+                pending += 1;
+            }
+            tag::SLICE | tag::STR if !check => {
+                let len = cursor.read_len()?;
+                cursor.take(len)?;
+            }
+            tag if tag::legacy::is_legacy(tag) => {
+                cursor.unread();
+                let bytes = cursor.take(legacy::encoded_len(cursor.rest())?)?;
+                if check {
+                    legacy::decode(bytes, &DeserializeOptions::default(), 0, false)?;
+                }
+            }
+            tag => {
+                cursor.read_scalar(tag)?;
+            }
+        }
+    }
+
+    Ok(())
+}
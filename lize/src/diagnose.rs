@@ -0,0 +1,415 @@
+//! A lenient scan of a payload that reports every structural problem it can
+//! find, where decoding stops at the first.
+//!
+//! When a value inside a vector or map can't be read, the scan looks for where
+//! its next sibling starts: the first offset after it from which the values
+//! that should follow read cleanly and end with the input. Corrupted bytes
+//! that still read as values of the wrong kind can't be noticed, so recovery
+//! is best effort.
+//!
+//! # Example
+//! ```rust
+//! use lize::{diagnose, ProblemKind, Value};
+//!
+//! let value = Value::Vector((0..20).map(|i| Value::from(format!("item {}", i))).collect());
+//! let mut bytes = value.serialize()?;
+//! bytes[2] = 5; // the tag of the first item
+//! bytes[60] = 0xff; // inside the 8th item
+//!
+//! let problems = diagnose(&bytes, 10);
+//! assert_eq!(problems[0].kind, ProblemKind::UnknownTag);
+//! assert_eq!(problems[0].path, "$[0]");
+//! assert_eq!(problems[1].kind, ProblemKind::InvalidUtf8);
+//! assert_eq!((problems[1].offset, problems[1].path.as_str()), (58, "$[7]"));
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use crate::{
+    decode::{skip, Cursor},
+    legacy, tag, Error, Value,
+};
+
+/// How many of the values after a broken one must read cleanly from an offset
+/// for the scan to carry on from there, if there are that many.
+const LOOKAHEAD: usize = 256;
+
+/// How far past a broken value the scan looks for the next one.
+const WINDOW: usize = 64 * 1024;
+
+/// What kind of [`Problem`] [`diagnose`] found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProblemKind {
+    /// A byte where a value should start isn't a known tag.
+    UnknownTag,
+    /// A string doesn't hold valid UTF-8. The scan carries on right after it.
+    InvalidUtf8,
+    /// A length or count doesn't fit in the bytes left, or in a `usize`.
+    InvalidLength,
+    /// The input ends in the middle of a value.
+    Truncated,
+    /// There are bytes after the end of the value.
+    TrailingBytes,
+    /// No offset after a broken value looked like the start of the next one,
+    /// so the scan stopped there.
+    Unrecoverable,
+}
+
+impl ProblemKind {
+    /// The kind in snake case, like `unknown_tag`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::UnknownTag => "unknown_tag",
+            Self::InvalidUtf8 => "invalid_utf8",
+            Self::InvalidLength => "invalid_length",
+            Self::Truncated => "truncated",
+            Self::TrailingBytes => "trailing_bytes",
+            Self::Unrecoverable => "unrecoverable",
+        }
+    }
+
+    fn of(err: &anyhow::Error) -> Self {
+        match err.downcast_ref::<Error>() {
+            Some(Error::UnknownTag(_)) => Self::UnknownTag,
+            Some(Error::InvalidUtf8) => Self::InvalidUtf8,
+            Some(Error::EmptyInput | Error::UnexpectedEnd) => Self::Truncated,
+            Some(Error::TrailingBytes(_)) => Self::TrailingBytes,
+            _ => Self::InvalidLength,
+        }
+    }
+}
+
+/// A problem found by [`diagnose`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Problem {
+    /// Where the value that couldn't be read starts.
+    pub offset: usize,
+    pub kind: ProblemKind,
+    pub message: String,
+    /// Where the value sits, like `$[3]["name"]`: `[i]` for the items of
+    /// vectors, the key for map values whose key is a string or an integer,
+    /// `[#i]` for other map values, and `.keys[i]` for the keys themselves.
+    pub path: String,
+}
+
+/// Scans the payload in `bytes` for structural problems, returning up to
+/// `limit` of them in the order they're found. A payload that decodes returns
+/// none.
+pub fn diagnose(bytes: &[u8], limit: usize) -> Vec<Problem> {
+    let mut scan = Scan {
+        bytes,
+        cursor: Cursor::new(bytes),
+        stack: vec![],
+        problems: vec![],
+        limit,
+    };
+    scan.run();
+    scan.problems
+}
+
+/// A vector, map, `Some` or ext value whose children are being scanned.
+struct Level {
+    kind: Kind,
+    /// The children not read yet, counting the keys and values of maps, and
+    /// the one being read.
+    remaining: usize,
+    read: usize,
+    /// The path segment of the last key read, for the value after it.
+    key: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Vector,
+    Map,
+    Wrapper,
+}
+
+/// What reading one value found.
+enum Read<'a> {
+    Scalar(Value<'a>),
+    /// A string that isn't valid UTF-8, which is otherwise fine.
+    BadStr,
+    Open(Kind, usize),
+}
+
+struct Scan<'a> {
+    bytes: &'a [u8],
+    cursor: Cursor<'a>,
+    stack: Vec<Level>,
+    problems: Vec<Problem>,
+    limit: usize,
+}
+
+impl<'a> Scan<'a> {
+    fn run(&mut self) {
+        loop {
+            let start = self.cursor.position();
+            match self.read() {
+                Ok(Read::Open(kind, remaining)) if remaining > 0 => {
+                    self.stack.push(Level {
+                        kind,
+                        remaining,
+                        read: 0,
+                        key: String::new(),
+                    });
+                    continue;
+                }
+                Ok(Read::Open(..)) => self.done(None),
+                Ok(Read::Scalar(value)) => self.done(Some(&value)),
+                Ok(Read::BadStr) => {
+                    let err = Error::InvalidUtf8.into();
+                    if !self.report(start, ProblemKind::of(&err), err.to_string()) {
+                        return;
+                    }
+                    self.done(None);
+                }
+                Err(err) => {
+                    let kind = ProblemKind::of(&err);
+                    if !self.report(start, kind, err.to_string()) {
+                        return;
+                    }
+                    match self.resync(start) {
+                        Some(next) => self.cursor.seek(next),
+                        None if kind == ProblemKind::Truncated || self.stack.is_empty() => return,
+                        None => {
+                            self.report(
+                                start,
+                                ProblemKind::Unrecoverable,
+                                "couldn't find where the next value starts".to_string(),
+                            );
+                            return;
+                        }
+                    }
+                    self.done(None);
+                }
+            }
+
+            if self.stack.is_empty() {
+                let end = self.cursor.position();
+                if end < self.bytes.len() {
+                    self.report(
+                        end,
+                        ProblemKind::TrailingBytes,
+                        format!(
+                            "{} bytes after the end of the value",
+                            self.bytes.len() - end
+                        ),
+                    );
+                }
+                return;
+            }
+        }
+    }
+
+    /// Reads the tag and, for values without children, the rest of the value
+    /// at the cursor.
+    fn read(&mut self) -> anyhow::Result<Read<'a>> {
+        let cursor = &mut self.cursor;
+        let tag = cursor.read_u8()?;
+        Ok(match tag {
+            tag::VECTOR => Read::Open(Kind::Vector, cursor.read_len()?),
+            tag::MAP => Read::Open(Kind::Map, cursor.read_len()?.saturating_mul(2)),
+            tag::SOME => Read::Open(Kind::Wrapper, 1),
+            tag::EXT => {
+                cursor.read_u8()?;
+                Read::Open(Kind::Wrapper, 1)
+            }
+            tag::STR => {
+                let len = cursor.read_len()?;
+                match std::str::from_utf8(cursor.take(len)?) {
+                    Ok(s) => Read::Scalar(Value::Str(s)),
+                    Err(_) => Read::BadStr,
+                }
+            }
+            tag if tag::legacy::is_legacy(tag) => {
+                cursor.unread();
+                cursor.take(legacy::encoded_len(cursor.rest())?)?;
+                Read::Scalar(Value::Optional(None))
+            }
+            tag => Read::Scalar(cursor.read_scalar(tag)?),
+        })
+    }
+
+    /// Marks the child being read as done, closing every container it
+    /// completes. `value` is the child if it's a scalar, to name map values
+    /// after their keys.
+    fn done(&mut self, mut value: Option<&Value<'_>>) {
+        while let Some(level) = self.stack.last_mut() {
+            if level.kind == Kind::Map && level.read % 2 == 0 {
+                let entry = level.read / 2;
+                level.key = match value {
+                    Some(Value::Str(s)) => format!("[{:?}]", s),
+                    Some(
+                        key @ (Value::SmallU8(_) | Value::U8(_) | Value::I32(_) | Value::I64(_)),
+                    ) => format!("[{}]", key.as_i64().unwrap_or_default()),
+                    _ => format!("[#{}]", entry),
+                };
+            }
+            level.read += 1;
+            level.remaining -= 1;
+            if level.remaining > 0 {
+                return;
+            }
+            self.stack.pop();
+            value = None;
+        }
+    }
+
+    /// Looks for the offset after the broken value starting at `start` where
+    /// the values that should follow it read cleanly: the next [`LOOKAHEAD`]
+    /// of them, or all of them, ending right at the end of the input. Most
+    /// bytes are valid tags, so fewer would often match inside the broken
+    /// value.
+    fn resync(&self, start: usize) -> Option<usize> {
+        let last = self.bytes.len().min(start.saturating_add(WINDOW));
+        (start + 1..=last).find(|&offset| self.reads_from(offset))
+    }
+
+    fn reads_from(&self, offset: usize) -> bool {
+        let mut cursor = Cursor::new(self.bytes);
+        cursor.seek(offset);
+        let mut remaining = self.stack.iter().map(|l| l.remaining).collect::<Vec<_>>();
+        let mut read = 0;
+        loop {
+            // The broken value, then each one skipped, is done, along with
+            // every container it completes.
+            while let Some(left) = remaining.last_mut() {
+                *left -= 1;
+                if *left > 0 {
+                    break;
+                }
+                remaining.pop();
+            }
+            if remaining.is_empty() {
+                return cursor.position() == self.bytes.len();
+            }
+            if read == LOOKAHEAD {
+                return true;
+            }
+            if skip(&mut cursor).is_err() {
+                return false;
+            }
+            read += 1;
+        }
+    }
+
+    /// The path of the value being read.
+    fn path(&self) -> String {
+        let mut path = String::from("$");
+        for level in &self.stack {
+            match level.kind {
+                Kind::Vector => path.push_str(&format!("[{}]", level.read)),
+                Kind::Map if level.read % 2 == 0 => {
+                    path.push_str(&format!(".keys[{}]", level.read / 2))
+                }
+                Kind::Map => path.push_str(&level.key),
+                Kind::Wrapper => {}
+            }
+        }
+        path
+    }
+
+    /// Records a problem, returning whether the scan should go on.
+    fn report(&mut self, offset: usize, kind: ProblemKind, message: String) -> bool {
+        if self.problems.len() < self.limit {
+            self.problems.push(Problem {
+                offset,
+                kind,
+                message,
+                path: self.path(),
+            });
+        }
+        self.problems.len() < self.limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> Value<'static> {
+        Value::Vector(
+            (0..40)
+                .map(|i| {
+                    Value::HashMap(vec![
+                        (Value::from("id"), Value::I64(i * 1000)),
+                        (Value::from("name"), Value::from(format!("record {:02}", i))),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    fn find(bytes: &[u8], needle: &[u8], nth: usize) -> usize {
+        bytes
+            .windows(needle.len())
+            .enumerate()
+            .filter(|(_, window)| *window == needle)
+            .nth(nth)
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn test_diagnose_clean() -> anyhow::Result<()> {
+        assert_eq!(diagnose(&records().serialize()?, 10), vec![]);
+        assert_eq!(diagnose(&Value::I64(3).serialize()?, 10), vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_diagnose_two_regions() -> anyhow::Result<()> {
+        let mut bytes = records().serialize()?;
+        // The tag of the 5th record's id, and the first byte of the 31st
+        // record's name.
+        let id = find(&bytes, b"id", 4) + 2;
+        bytes[id] = 5;
+        let name = find(&bytes, b"record 30", 0);
+        bytes[name] = 0xff;
+
+        let found = diagnose(&bytes, 10)
+            .into_iter()
+            .map(|p| (p.offset, p.kind, p.path))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                (id, ProblemKind::UnknownTag, r#"$[4]["id"]"#.to_string()),
+                (
+                    name - 2,
+                    ProblemKind::InvalidUtf8,
+                    r#"$[30]["name"]"#.to_string()
+                ),
+            ]
+        );
+
+        assert_eq!(diagnose(&bytes, 1).len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_diagnose_truncated_and_trailing() -> anyhow::Result<()> {
+        let bytes = records().serialize()?;
+        let problems = diagnose(&bytes[..bytes.len() - 3], 10);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].kind, ProblemKind::Truncated);
+        assert_eq!(problems[0].path, r#"$[39]["name"]"#);
+
+        let mut trailing = bytes.clone();
+        trailing.extend([1, 2]);
+        let problems = diagnose(&trailing, 10);
+        assert_eq!(
+            (problems[0].offset, problems[0].kind),
+            (bytes.len(), ProblemKind::TrailingBytes)
+        );
+
+        assert_eq!(diagnose(&[], 10)[0].kind, ProblemKind::Truncated);
+        assert_eq!(
+            diagnose(&[tag::MAP, 1, 5, tag::TRUE], 10)[0].path,
+            "$.keys[0]"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use std::fmt;
+
+/// Errors produced by the encoder and decoder.
+///
+/// They are returned wrapped in [`anyhow::Error`], so use
+/// [`anyhow::Error::downcast_ref`] to inspect them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The value is nested deeper than the configured `max_depth`.
+    DepthLimitExceeded(usize),
+
+    /// A [`Value::Str`](crate::Value::Str) doesn't hold valid UTF-8.
+    InvalidUtf8,
+
+    /// A [frame](crate::frame) is malformed, or can't be opened with the given key.
+    InvalidFrame(&'static str),
+
+    /// An encrypted frame was tampered with, or the key is wrong.
+    AuthenticationFailed,
+
+    /// The input is empty, so there's no value to read at all.
+    EmptyInput,
+
+    /// The input ends in the middle of a value.
+    UnexpectedEnd,
+
+    /// This many bytes follow a value that should end the input.
+    TrailingBytes(usize),
+
+    /// A byte where a value should start isn't a known tag.
+    UnknownTag(u8),
+
+    /// A slice or string is longer than the configured `max_str_len`.
+    StringTooLong(usize),
+
+    /// The encoding is longer than the configured `max_output_bytes`.
+    OutputTooLarge(usize),
+
+    /// A length or count is longer than any `usize`.
+    InvalidLength,
+
+    /// A value can't be converted to or from an Arrow table.
+    InvalidTable(String),
+
+    /// A [`Value`](crate::Value) breaks an invariant the enum can't enforce,
+    /// see [`Value::validate`](crate::Value::validate).
+    InvalidValue(String),
+
+    /// A progress report asked to stop, see [`Progress`](crate::Progress).
+    Cancelled,
+
+    /// A value doesn't fit a [`Schema`](crate::Schema), or a schema is malformed.
+    InvalidSchema(String),
+
+    /// A [frame](crate::frame) was written with a different schema than the
+    /// one it's read with. Either side may be `None` for no schema.
+    SchemaMismatch {
+        expected: Option<u64>,
+        found: Option<u64>,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DepthLimitExceeded(limit) => {
+                write!(f, "value is nested deeper than the limit of {}", limit)
+            }
+            Self::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+            Self::InvalidFrame(reason) => write!(f, "invalid frame: {}", reason),
+            Self::AuthenticationFailed => write!(f, "frame failed authentication"),
+            Self::EmptyInput => write!(f, "input is empty"),
+            Self::UnexpectedEnd => write!(f, "input ends in the middle of a value"),
+            Self::TrailingBytes(len) => write!(f, "{} bytes after the end of the value", len),
+            Self::UnknownTag(tag) => write!(f, "unknown tag {}", tag),
+            Self::StringTooLong(limit) => {
+                write!(f, "string is longer than the limit of {} bytes", limit)
+            }
+            Self::OutputTooLarge(limit) => {
+                write!(f, "output is longer than the limit of {} bytes", limit)
+            }
+            Self::InvalidLength => write!(f, "invalid length"),
+            Self::InvalidTable(reason) => write!(f, "invalid table: {}", reason),
+            Self::Cancelled => write!(f, "cancelled"),
+            Self::InvalidValue(reason) => write!(f, "invalid value: {}", reason),
+            Self::InvalidSchema(reason) => write!(f, "invalid schema: {}", reason),
+            Self::SchemaMismatch { expected, found } => match (expected, found) {
+                (Some(expected), Some(found)) => write!(
+                    f,
+                    "frame was written with schema {:016x}, expected {:016x}",
+                    found, expected
+                ),
+                (Some(expected), None) => {
+                    write!(f, "frame has no schema, expected {:016x}", expected)
+                }
+                (None, Some(found)) => write!(
+                    f,
+                    "frame was written with schema {:016x}, but none was given",
+                    found
+                ),
+                (None, None) => write!(f, "schema mismatch"),
+            },
+        }
+    }
+}
+
+impl std::error::Error for Error {}
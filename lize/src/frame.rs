@@ -0,0 +1,1026 @@
+//! Self-delimiting frames, for storing several values in a file or stream.
+//!
+//! Each frame holds one encoded value behind a fixed 8-byte header, and may
+//! be encrypted with XChaCha20-Poly1305 when the `crypto` feature is enabled.
+//! A frame can also carry a [`Metadata`] map, which [`Framed::metadata`] reads
+//! without opening the body. See `FORMAT.md` for the exact layout.
+//!
+//! # Example
+//! ```rust
+//! use lize::{frame, Value};
+//!
+//! let bytes = Value::I64(1).serialize_framed(None)?;
+//! assert_eq!(Value::deserialize_framed(&bytes, None)?, Value::I64(1));
+//!
+//! let (payload, rest) = frame::open(&bytes, None)?;
+//! assert_eq!(Value::deserialize_from(&payload)?, Value::I64(1));
+//! assert!(rest.is_empty());
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::{
+    borrow::Cow,
+    io::{self, Read, Write},
+    ops::Range,
+};
+
+use crate::{Error, Result, Schema, SerializeOptions, SmallVec, Value, STACK_N};
+
+/// The first two bytes of every frame.
+pub const MAGIC: [u8; 2] = *b"LZ";
+
+/// The version of the frame header, independent of [`FORMAT_VERSION`](crate::FORMAT_VERSION).
+pub const FRAME_VERSION: u8 = 1;
+
+/// The length of the header: magic, version, flags and a `u32` body length.
+pub const HEADER_LEN: usize = 8;
+
+/// A 256-bit key for encrypted frames.
+pub type Key = [u8; 32];
+
+/// The length of the schema hash that follows the header of frames sealed
+/// with [`seal_with_schema`], a `u64`.
+pub const SCHEMA_HASH_LEN: usize = 8;
+
+/// Set in the flags byte when the body is encrypted.
+const ENCRYPTED: u8 = 0b1;
+
+/// Set in the flags byte when the header is followed by a schema hash.
+const SCHEMA: u8 = 0b10;
+
+/// Set in the flags byte when the header is followed by a metadata map.
+const METADATA: u8 = 0b100;
+
+/// Set in the flags byte when the metadata map is authenticated along with
+/// the header of an encrypted frame.
+const AUTHENTICATED_METADATA: u8 = 0b1000;
+
+/// Set in the flags byte when the header ends with a CRC-32 of the body.
+const CHECKSUM: u8 = 0b10000;
+
+/// The length of the checksum that ends the header of frames written with
+/// [`Writer::with_checksum`], a `u32`.
+pub const CHECKSUM_LEN: usize = 4;
+
+#[cfg(feature = "crypto")]
+const NONCE_LEN: usize = 24;
+
+#[cfg(feature = "crypto")]
+const TAG_LEN: usize = 16;
+
+/// Wraps an encoded payload in a frame, encrypting it if a `key` is given.
+///
+/// Every encrypted frame gets a fresh random nonce.
+pub fn seal(payload: &[u8], key: Option<&Key>) -> Result<Vec<u8>> {
+    seal_frame(payload, key, None)
+}
+
+/// Like [`seal`], but follows the header with the [hash](Schema::hash) of the
+/// `schema` the payload was encoded with, which [`open_with_schema`] checks.
+pub fn seal_with_schema(payload: &[u8], key: Option<&Key>, schema: &Schema) -> Result<Vec<u8>> {
+    seal_frame(payload, key, Some(schema.hash()))
+}
+
+/// Like [`seal`], but carries `metadata` between the header and the body, and
+/// follows the header with the hash of `schema` if there is one.
+///
+/// The metadata isn't part of the payload, so it never changes a hash of it.
+pub fn seal_with_metadata(
+    payload: &[u8],
+    key: Option<&Key>,
+    schema: Option<&Schema>,
+    metadata: &Metadata,
+) -> Result<Vec<u8>> {
+    seal_frame_with(
+        payload,
+        key,
+        schema.map(Schema::hash),
+        Some(metadata),
+        false,
+    )
+}
+
+fn seal_frame(payload: &[u8], key: Option<&Key>, schema: Option<u64>) -> Result<Vec<u8>> {
+    seal_frame_with(payload, key, schema, None, false)
+}
+
+fn seal_frame_with(
+    payload: &[u8],
+    key: Option<&Key>,
+    schema: Option<u64>,
+    metadata: Option<&Metadata>,
+    checksum: bool,
+) -> Result<Vec<u8>> {
+    let metadata_len = metadata.map_or(0, |metadata| 4 + metadata.encoded.len());
+    let mut out = Vec::with_capacity(
+        HEADER_LEN + SCHEMA_HASH_LEN + metadata_len + CHECKSUM_LEN + payload.len(),
+    );
+    let flags = if checksum { CHECKSUM } else { 0 };
+    match key {
+        Some(key) => encrypt(payload, key, flags, schema, metadata, &mut out)?,
+        None => {
+            write_header(&mut out, flags, schema, metadata, payload.len())?;
+            out.extend_from_slice(payload);
+        }
+    }
+
+    // The body is written after the header, so its checksum is filled in last.
+    if checksum {
+        let header = parse_header(&out)?;
+        let crc = crc32(&out[header.len..]);
+        out[header.len - CHECKSUM_LEN..header.len].copy_from_slice(&crc.to_le_bytes());
+    }
+    Ok(out)
+}
+
+/// A map carried by a frame besides its value, like who wrote it and when,
+/// which can be read without opening the body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    /// The map, encoded canonically.
+    encoded: Vec<u8>,
+    authenticated: bool,
+}
+
+impl Metadata {
+    /// Fails with [`Error::InvalidValue`] unless `map` is a [`Value::HashMap`].
+    pub fn new(map: &Value) -> Result<Self> {
+        if !matches!(map, Value::HashMap(_)) {
+            return Err(Error::InvalidValue(String::from("frame metadata must be a map")).into());
+        }
+        let mut encoded = Vec::new();
+        map.serialize_to(&SerializeOptions::new().canonical(true), &mut encoded)?;
+        Ok(Self {
+            encoded,
+            authenticated: false,
+        })
+    }
+
+    /// Authenticates the metadata along with the header of encrypted frames,
+    /// so changing it makes them fail to open. By default it's left out, so it
+    /// can be edited without the key. Plain frames have nothing to check it.
+    pub fn authenticated(mut self, authenticated: bool) -> Self {
+        self.authenticated = authenticated;
+        self
+    }
+}
+
+/// A frame read without opening its body.
+///
+/// # Example
+/// ```rust
+/// use lize::{frame, Value};
+///
+/// let metadata = frame::Metadata::new(&Value::HashMap(vec![(
+///     Value::from("producer"),
+///     Value::from("example"),
+/// )]))?;
+/// let bytes = frame::seal_with_metadata(&Value::I64(1).serialize()?, None, None, &metadata)?;
+///
+/// let framed = frame::Framed::parse(&bytes)?;
+/// assert_eq!(
+///     framed.metadata()?,
+///     Some(Value::HashMap(vec![(Value::from("producer"), Value::from("example"))]))
+/// );
+/// assert_eq!(Value::deserialize_from(&frame::open(&bytes, None)?.0)?, Value::I64(1));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Framed<'a> {
+    bytes: &'a [u8],
+    header: Header,
+}
+
+impl<'a> Framed<'a> {
+    /// Reads the header of the frame at the start of `bytes`.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self> {
+        Ok(Self {
+            header: parse_header(bytes)?,
+            bytes,
+        })
+    }
+
+    /// Whether the body is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.header.flags & ENCRYPTED != 0
+    }
+
+    /// The hash of the schema the frame was sealed with, if any.
+    pub fn schema_hash(&self) -> Option<u64> {
+        self.header.schema
+    }
+
+    /// The metadata map the frame carries, if any.
+    pub fn metadata(&self) -> Result<Option<Value<'a>>> {
+        self.header
+            .metadata
+            .clone()
+            .map(|range| Value::deserialize_from(&self.bytes[range]))
+            .transpose()
+    }
+}
+
+/// Reads the frame at the start of `bytes`, returning its payload and the bytes after it.
+///
+/// Encrypted frames need the `key` they were sealed with and fail with
+/// [`Error::AuthenticationFailed`] if anything was tampered with. Plain frames
+/// are rejected when a `key` is given, so an attacker can't strip the encryption.
+/// Frames sealed with a schema fail with [`Error::SchemaMismatch`].
+pub fn open<'a>(bytes: &'a [u8], key: Option<&Key>) -> Result<(Cow<'a, [u8]>, &'a [u8])> {
+    open_frame(bytes, key, None)
+}
+
+/// Like [`open`], but the frame must have been sealed by [`seal_with_schema`]
+/// with the same `schema`, or it fails with [`Error::SchemaMismatch`].
+pub fn open_with_schema<'a>(
+    bytes: &'a [u8],
+    key: Option<&Key>,
+    schema: &Schema,
+) -> Result<(Cow<'a, [u8]>, &'a [u8])> {
+    open_frame(bytes, key, Some(schema.hash()))
+}
+
+fn open_frame<'a>(
+    bytes: &'a [u8],
+    key: Option<&Key>,
+    schema: Option<u64>,
+) -> Result<(Cow<'a, [u8]>, &'a [u8])> {
+    let header = parse_header(bytes)?;
+    if header.schema != schema {
+        return Err(Error::SchemaMismatch {
+            expected: schema,
+            found: header.schema,
+        }
+        .into());
+    }
+    let end = header
+        .len
+        .checked_add(header.body_len)
+        .filter(|end| *end <= bytes.len())
+        .ok_or(Error::InvalidFrame("truncated body"))?;
+    let body = &bytes[header.len..end];
+    if header.checksum.is_some_and(|crc| crc != crc32(body)) {
+        return Err(Error::InvalidFrame("checksum mismatch").into());
+    }
+
+    let payload = match (header.flags & ENCRYPTED != 0, key) {
+        (false, None) => Cow::Borrowed(body),
+        (true, Some(key)) => Cow::Owned(decrypt(&bytes[..header.authenticated_len], body, key)?),
+        (true, None) => {
+            return Err(Error::InvalidFrame("frame is encrypted, but no key was given").into())
+        }
+        (false, Some(_)) => return Err(Error::InvalidFrame("expected an encrypted frame").into()),
+    };
+
+    Ok((payload, &bytes[end..]))
+}
+
+impl Value<'_> {
+    /// Serializes the value into a single frame, encrypted if a `key` is given.
+    ///
+    /// Use [`Value::deserialize_framed`] to read it back, or [`open`] and
+    /// [`Value::deserialize_from`] to borrow from a plain frame.
+    pub fn serialize_framed(&self, key: Option<&Key>) -> Result<Vec<u8>> {
+        let mut buffer = SmallVec::<[u8; STACK_N]>::new();
+        self.serialize_into(&mut buffer)?;
+        seal(&buffer, key)
+    }
+
+    /// Reads the value of a frame written by [`Value::serialize_framed`] with
+    /// the same `key`, failing like [`open`] does, or with
+    /// [`Error::TrailingBytes`] if anything follows the frame.
+    ///
+    /// The value owns its data, since an encrypted body is decrypted into a
+    /// buffer of its own.
+    pub fn deserialize_framed(bytes: &[u8], key: Option<&Key>) -> Result<Value<'static>> {
+        let (payload, rest) = open(bytes, key)?;
+        if !rest.is_empty() {
+            return Err(Error::TrailingBytes(rest.len()).into());
+        }
+        let value = Value::deserialize_from(&payload)?.into_owned();
+        Ok(value)
+    }
+}
+
+/// Writes values as consecutive frames.
+///
+/// # Example
+/// ```rust
+/// use lize::{frame, Value};
+///
+/// let mut writer = frame::Writer::new(Vec::new());
+/// writer.write(&Value::I64(1))?;
+/// writer.write(&Value::Bool(true))?;
+///
+/// let bytes = writer.into_inner();
+/// let mut reader = frame::Reader::new(bytes.as_slice());
+/// let first = reader.next_payload()?.unwrap();
+/// assert_eq!(Value::deserialize_from(&first)?, Value::I64(1));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Writer<W> {
+    inner: W,
+    key: Option<Key>,
+    schema: Option<u64>,
+    metadata: Option<Metadata>,
+    checksum: bool,
+    options: SerializeOptions,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            key: None,
+            schema: None,
+            metadata: None,
+            checksum: false,
+            options: SerializeOptions::default(),
+        }
+    }
+
+    /// Encrypts every frame with `key`.
+    pub fn with_key(mut self, key: Key) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Seals every frame like [`seal_with_schema`]. The values written must
+    /// already be encoded with [`Schema::encode`].
+    pub fn with_schema(mut self, schema: &Schema) -> Self {
+        self.schema = Some(schema.hash());
+        self
+    }
+
+    /// Carries `metadata` in every frame, like [`seal_with_metadata`].
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Ends the header of every frame with a CRC-32 of its body, which
+    /// readers check, so a damaged plain frame fails to open rather than
+    /// decoding to the wrong value. Encrypted frames are authenticated anyway.
+    pub fn with_checksum(mut self, checksum: bool) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Serializes values with `options`.
+    pub fn with_options(mut self, options: SerializeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Serializes `value` and writes it as one frame.
+    pub fn write(&mut self, value: &Value) -> Result<()> {
+        let mut buffer = SmallVec::<[u8; STACK_N]>::new();
+        value.serialize_with(&self.options, &mut buffer)?;
+        self.write_payload(&buffer)
+    }
+
+    /// Writes an already encoded payload as one frame.
+    pub fn write_payload(&mut self, payload: &[u8]) -> Result<()> {
+        let frame = seal_frame_with(
+            payload,
+            self.key.as_ref(),
+            self.schema,
+            self.metadata.as_ref(),
+            self.checksum,
+        )?;
+        self.inner.write_all(&frame)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// The writer frames are written to. Writing to it directly can leave a
+    /// stream that isn't made of frames.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Reads frames written by a [`Writer`].
+#[derive(Debug)]
+pub struct Reader<R> {
+    inner: R,
+    key: Option<Key>,
+    schema: Option<u64>,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            key: None,
+            schema: None,
+        }
+    }
+
+    /// Decrypts every frame with `key`, rejecting plain ones.
+    pub fn with_key(mut self, key: Key) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Expects every frame to be sealed with `schema`, like [`open_with_schema`].
+    /// The payloads are still encoded with it; see [`Schema::decode`].
+    pub fn with_schema(mut self, schema: &Schema) -> Self {
+        self.schema = Some(schema.hash());
+        self
+    }
+
+    /// Reads the payload of the next frame, or `None` at the end of the stream.
+    ///
+    /// A stream that ends in the middle of a frame is an [`Error::InvalidFrame`].
+    pub fn next_payload(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut header = [0_u8; HEADER_LEN];
+        match read_full(&mut self.inner, &mut header)? {
+            0 => return Ok(None),
+            HEADER_LEN => {}
+            _ => return Err(Error::InvalidFrame("truncated header").into()),
+        }
+
+        // The lengths in the header aren't trusted with an allocation up
+        // front: the frame grows as its bytes arrive, so a stream cut short
+        // fails as truncated however long the frame claims to be.
+        let mut frame = header.to_vec();
+        let mut read_more = |frame: &mut Vec<u8>, len: usize, truncated| -> Result<()> {
+            let read = (&mut self.inner).take(len as u64).read_to_end(frame)?;
+            if read < len {
+                return Err(Error::InvalidFrame(truncated).into());
+            }
+            Ok(())
+        };
+        if header[3] & SCHEMA != 0 {
+            read_more(&mut frame, SCHEMA_HASH_LEN, "truncated header")?;
+        }
+        if header[3] & METADATA != 0 {
+            read_more(&mut frame, 4, "truncated header")?;
+            let len = u32::from_le_bytes(frame[frame.len() - 4..].try_into()?);
+            read_more(&mut frame, len as usize, "truncated header")?;
+        }
+        if header[3] & CHECKSUM != 0 {
+            read_more(&mut frame, CHECKSUM_LEN, "truncated header")?;
+        }
+
+        let header = parse_header(&frame)?;
+        read_more(&mut frame, header.body_len, "truncated body")?;
+
+        let (payload, _) = open_frame(&frame, self.key.as_ref(), self.schema)?;
+        Ok(Some(payload.into_owned()))
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+/// Like [`Read::read_exact`], but returns how many bytes were read before the end.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Appends the header, returning how much of it encrypted frames authenticate.
+fn write_header(
+    out: &mut Vec<u8>,
+    mut flags: u8,
+    schema: Option<u64>,
+    metadata: Option<&Metadata>,
+    len: usize,
+) -> Result<usize> {
+    let len =
+        u32::try_from(len).map_err(|_| Error::InvalidFrame("payload is larger than 4 GiB"))?;
+    if schema.is_some() {
+        flags |= SCHEMA;
+    }
+    match metadata {
+        Some(metadata) if metadata.authenticated => flags |= METADATA | AUTHENTICATED_METADATA,
+        Some(_) => flags |= METADATA,
+        None => {}
+    }
+
+    let start = out.len();
+    out.extend_from_slice(&MAGIC);
+    out.push(FRAME_VERSION);
+    out.push(flags);
+    out.extend_from_slice(&len.to_le_bytes());
+    if let Some(hash) = schema {
+        out.extend_from_slice(&hash.to_le_bytes());
+    }
+    let unauthenticated = out.len();
+    if let Some(metadata) = metadata {
+        let metadata_len = u32::try_from(metadata.encoded.len())
+            .map_err(|_| Error::InvalidFrame("metadata is larger than 4 GiB"))?;
+        out.extend_from_slice(&metadata_len.to_le_bytes());
+        out.extend_from_slice(&metadata.encoded);
+    }
+    let authenticated = match metadata {
+        Some(metadata) if metadata.authenticated => out.len(),
+        _ => unauthenticated,
+    };
+    if flags & CHECKSUM != 0 {
+        out.extend_from_slice(&[0; CHECKSUM_LEN]);
+    }
+    Ok(authenticated - start)
+}
+
+/// The header at the start of a frame.
+#[derive(Debug)]
+struct Header {
+    flags: u8,
+    schema: Option<u64>,
+    /// Where the metadata map is, if there is one.
+    metadata: Option<Range<usize>>,
+    /// The length of the header, including the schema hash and the metadata.
+    len: usize,
+    /// How much of the header encrypted frames authenticate.
+    authenticated_len: usize,
+    body_len: usize,
+    /// The CRC-32 the body should have.
+    checksum: Option<u32>,
+}
+
+fn parse_header(bytes: &[u8]) -> Result<Header> {
+    let Some(header) = bytes.get(..HEADER_LEN) else {
+        return Err(Error::InvalidFrame("truncated header").into());
+    };
+    if header[..2] != MAGIC {
+        return Err(Error::InvalidFrame("not a lize frame").into());
+    }
+    if header[2] != FRAME_VERSION {
+        return Err(Error::InvalidFrame("unsupported frame version").into());
+    }
+    let flags = header[3];
+    if flags & !(ENCRYPTED | SCHEMA | METADATA | AUTHENTICATED_METADATA | CHECKSUM) != 0
+        || flags & (METADATA | AUTHENTICATED_METADATA) == AUTHENTICATED_METADATA
+    {
+        return Err(Error::InvalidFrame("unknown flags").into());
+    }
+    let body_len = u32::from_le_bytes(header[4..8].try_into()?) as usize;
+    let truncated = || Error::InvalidFrame("truncated header");
+
+    let mut len = HEADER_LEN;
+    let mut schema = None;
+    if flags & SCHEMA != 0 {
+        let hash = bytes
+            .get(len..len + SCHEMA_HASH_LEN)
+            .ok_or_else(truncated)?;
+        schema = Some(u64::from_le_bytes(hash.try_into()?));
+        len += SCHEMA_HASH_LEN;
+    }
+
+    let mut authenticated_len = len;
+    let mut metadata = None;
+    if flags & METADATA != 0 {
+        let metadata_len = bytes.get(len..len + 4).ok_or_else(truncated)?;
+        let start = len + 4;
+        let end = start + u32::from_le_bytes(metadata_len.try_into()?) as usize;
+        if end > bytes.len() {
+            return Err(truncated().into());
+        }
+        metadata = Some(start..end);
+        len = end;
+        if flags & AUTHENTICATED_METADATA != 0 {
+            authenticated_len = end;
+        }
+    }
+
+    let mut checksum = None;
+    if flags & CHECKSUM != 0 {
+        let crc = bytes.get(len..len + CHECKSUM_LEN).ok_or_else(truncated)?;
+        checksum = Some(u32::from_le_bytes(crc.try_into()?));
+        len += CHECKSUM_LEN;
+    }
+
+    Ok(Header {
+        flags,
+        schema,
+        metadata,
+        len,
+        authenticated_len,
+        body_len,
+        checksum,
+    })
+}
+
+/// Appends an encrypted frame: the header, a random nonce, then the ciphertext and tag.
+///
+/// The header, with the schema hash, is authenticated as associated data, and
+/// so is the metadata if it asks to be.
+#[cfg(feature = "crypto")]
+fn encrypt(
+    payload: &[u8],
+    key: &Key,
+    flags: u8,
+    schema: Option<u64>,
+    metadata: Option<&Metadata>,
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    use chacha20poly1305::{
+        aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+        XChaCha20Poly1305,
+    };
+
+    let start = out.len();
+    let authenticated_len = write_header(
+        out,
+        flags | ENCRYPTED,
+        schema,
+        metadata,
+        NONCE_LEN + payload.len() + TAG_LEN,
+    )?;
+
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: payload,
+                aad: &out[start..start + authenticated_len],
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("failed to encrypt frame"))?;
+
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(())
+}
+
+#[cfg(feature = "crypto")]
+fn decrypt(header: &[u8], body: &[u8], key: &Key) -> Result<Vec<u8>> {
+    use chacha20poly1305::{
+        aead::{Aead, KeyInit, Payload},
+        XChaCha20Poly1305, XNonce,
+    };
+
+    if body.len() < NONCE_LEN + TAG_LEN {
+        return Err(Error::InvalidFrame("truncated body").into());
+    }
+
+    let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+    let plaintext = XChaCha20Poly1305::new(key.into())
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| Error::AuthenticationFailed)?;
+
+    Ok(plaintext)
+}
+
+#[cfg(not(feature = "crypto"))]
+fn encrypt(
+    _payload: &[u8],
+    _key: &Key,
+    _flags: u8,
+    _schema: Option<u64>,
+    _metadata: Option<&Metadata>,
+    _out: &mut Vec<u8>,
+) -> Result<()> {
+    Err(Error::InvalidFrame("encrypting frames needs the `crypto` feature").into())
+}
+
+#[cfg(not(feature = "crypto"))]
+fn decrypt(_header: &[u8], _body: &[u8], _key: &Key) -> Result<Vec<u8>> {
+    Err(Error::InvalidFrame("decrypting frames needs the `crypto` feature").into())
+}
+
+/// The CRC-32 of `bytes`, as in zlib and gzip: the reflected IEEE 802.3
+/// polynomial, starting from and finished with all ones.
+fn crc32(bytes: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0xedb8_8320
+                } else {
+                    crc >> 1
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+
+    !bytes.iter().fold(!0, |crc, &byte| {
+        TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_frames() -> Result<()> {
+        let mut writer = Writer::new(Vec::new());
+        writer.write(&Value::I64(1))?;
+        writer.write(&Value::from("two"))?;
+        let bytes = writer.into_inner();
+
+        let (first, rest) = open(&bytes, None)?;
+        assert_eq!(Value::deserialize_from(&first)?, Value::I64(1));
+        assert!(matches!(first, Cow::Borrowed(_)));
+        let first_len = bytes.len() - rest.len();
+
+        let mut reader = Reader::new(rest);
+        let second = reader.next_payload()?.unwrap();
+        assert_eq!(Value::deserialize_from(&second)?, Value::from("two"));
+        assert_eq!(reader.next_payload()?, None);
+
+        let mut truncated = Reader::new(&bytes[..bytes.len() - 1]);
+        assert!(truncated.next_payload()?.is_some());
+        let err = truncated.next_payload().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::InvalidFrame("truncated body"))
+        );
+
+        // Lengths claimed by a header aren't allocated before they arrive.
+        let claims_body: &[u8] = &[b'L', b'Z', FRAME_VERSION, 0, 0xf0, 0xff, 0xff, 0xff, 0];
+        let claims_metadata: &[u8] = &[
+            b'L',
+            b'Z',
+            FRAME_VERSION,
+            METADATA,
+            0,
+            0,
+            0,
+            0,
+            0xf0,
+            0xff,
+            0xff,
+            0xff,
+            0,
+        ];
+        for (bytes, reason) in [
+            (claims_body, "truncated body"),
+            (claims_metadata, "truncated header"),
+        ] {
+            let err = Reader::new(bytes).next_payload().unwrap_err();
+            assert_eq!(err.downcast_ref(), Some(&Error::InvalidFrame(reason)));
+        }
+
+        assert_eq!(
+            Value::deserialize_framed(&bytes[..first_len], None)?,
+            Value::I64(1)
+        );
+        let err = Value::deserialize_framed(&bytes, None).unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&Error::TrailingBytes(bytes.len() - first_len))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksum_frames() -> Result<()> {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+
+        let metadata = Metadata::new(&Value::HashMap(vec![]))?;
+        let mut writer = Writer::new(Vec::new())
+            .with_metadata(metadata)
+            .with_checksum(true);
+        writer.write(&Value::from("checked"))?;
+        writer.write(&Value::I64(2))?;
+        let bytes = writer.into_inner();
+
+        let (first, rest) = open(&bytes, None)?;
+        assert_eq!(Value::deserialize_from(&first)?, Value::from("checked"));
+        let checksum_at = bytes.len() - rest.len() - first.len() - CHECKSUM_LEN;
+        assert_eq!(
+            bytes[checksum_at..checksum_at + CHECKSUM_LEN],
+            crc32(&first).to_le_bytes()
+        );
+        let mut reader = Reader::new(bytes.as_slice());
+        assert!(reader.next_payload()?.is_some());
+        assert!(reader.next_payload()?.is_some());
+        assert_eq!(reader.next_payload()?, None);
+
+        // A flipped bit in the body, which would still decode.
+        let mut damaged = bytes.clone();
+        damaged[checksum_at + CHECKSUM_LEN + 2] ^= 1;
+        let err = Reader::new(damaged.as_slice()).next_payload().unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&Error::InvalidFrame("checksum mismatch"))
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_encrypted_frames() -> Result<()> {
+        let key = [7_u8; 32];
+        let value = Value::from("secret");
+
+        let a = value.serialize_framed(Some(&key))?;
+        let b = value.serialize_framed(Some(&key))?;
+        assert_ne!(a, b, "nonces must not repeat");
+
+        let (payload, _) = open(&a, Some(&key))?;
+        assert_eq!(Value::deserialize_from(&payload)?, value);
+
+        let mut writer = Writer::new(Vec::new()).with_key(key).with_checksum(true);
+        writer.write(&value)?;
+        let checked = writer.into_inner();
+        assert!(Value::deserialize_framed(&checked, Some(&key))?.logical_eq(&value));
+        // Owned, so the string comes back as a `StrLike`.
+        assert!(Value::deserialize_framed(&a, Some(&key))?.logical_eq(&value));
+
+        let authentication_failed = |bytes: &[u8], key: &Key| {
+            open(bytes, Some(key))
+                .unwrap_err()
+                .downcast_ref::<Error>()
+                .cloned()
+        };
+        for i in [3, 4, HEADER_LEN, a.len() - 1] {
+            let mut tampered = a.clone();
+            tampered[i] ^= 1;
+            assert_ne!(authentication_failed(&tampered, &key), None);
+        }
+        let mut tampered = a.clone();
+        tampered[HEADER_LEN + NONCE_LEN] ^= 1;
+        assert_eq!(
+            authentication_failed(&tampered, &key),
+            Some(Error::AuthenticationFailed)
+        );
+        assert_eq!(
+            authentication_failed(&a, &[8; 32]),
+            Some(Error::AuthenticationFailed)
+        );
+
+        assert!(open(&a, None).is_err());
+        assert!(open(&value.serialize_framed(None)?, Some(&key)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_frames() -> Result<()> {
+        let schema = Schema::new(["id", "name"])?;
+        let record = schema.encode(Value::HashMap(vec![(Value::from("id"), Value::I64(1))]))?;
+
+        let mut writer = Writer::new(Vec::new()).with_schema(&schema);
+        writer.write(&record)?;
+        let bytes = writer.into_inner();
+        assert_eq!(bytes[3], SCHEMA);
+        assert_eq!(
+            bytes[HEADER_LEN..HEADER_LEN + SCHEMA_HASH_LEN],
+            schema.hash().to_le_bytes()
+        );
+
+        let (payload, rest) = open_with_schema(&bytes, None, &schema)?;
+        assert_eq!(schema.decode(Value::deserialize_from(&payload)?)?, {
+            Value::HashMap(vec![(Value::StrLike("id".into()), Value::I64(1))])
+        });
+        assert!(rest.is_empty());
+
+        let mut reader = Reader::new(bytes.as_slice()).with_schema(&schema);
+        assert_eq!(reader.next_payload()?.as_deref(), Some(&payload[..]));
+        assert_eq!(reader.next_payload()?, None);
+
+        let mismatch = |err: anyhow::Error| err.downcast::<Error>().unwrap();
+        let other = Schema::new(["name", "id"])?;
+        assert_eq!(
+            mismatch(open_with_schema(&bytes, None, &other).unwrap_err()),
+            Error::SchemaMismatch {
+                expected: Some(other.hash()),
+                found: Some(schema.hash()),
+            }
+        );
+        assert_eq!(
+            mismatch(open(&bytes, None).unwrap_err()),
+            Error::SchemaMismatch {
+                expected: None,
+                found: Some(schema.hash()),
+            }
+        );
+        assert_eq!(
+            mismatch(open_with_schema(&seal(&payload, None)?, None, &schema).unwrap_err()),
+            Error::SchemaMismatch {
+                expected: Some(schema.hash()),
+                found: None,
+            }
+        );
+        assert_eq!(
+            mismatch(Reader::new(bytes.as_slice()).next_payload().unwrap_err()),
+            Error::SchemaMismatch {
+                expected: None,
+                found: Some(schema.hash()),
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata_frames() -> Result<()> {
+        let tags = Value::HashMap(vec![
+            (Value::from("producer"), Value::from("test")),
+            (Value::from("created"), Value::I64(1_700_000_000)),
+        ]);
+        let metadata = Metadata::new(&tags)?;
+        let payload = Value::from("body").serialize()?;
+
+        let mut writer = Writer::new(Vec::new()).with_metadata(metadata.clone());
+        writer.write_payload(&payload)?;
+        writer.write_payload(&payload)?;
+        let bytes = writer.into_inner();
+
+        let framed = Framed::parse(&bytes)?;
+        assert!(!framed.is_encrypted());
+        // Written canonically, so the keys come out sorted.
+        assert_eq!(
+            framed.metadata()?,
+            Some(Value::HashMap(vec![
+                (Value::from("created"), Value::I64(1_700_000_000)),
+                (Value::from("producer"), Value::from("test")),
+            ]))
+        );
+        assert_eq!(Framed::parse(&seal(&payload, None)?)?.metadata()?, None);
+
+        let (first, rest) = open(&bytes, None)?;
+        assert_eq!(first, &payload[..]);
+        let mut reader = Reader::new(rest);
+        assert_eq!(reader.next_payload()?.as_deref(), Some(&payload[..]));
+        assert_eq!(reader.next_payload()?, None);
+
+        assert!(Metadata::new(&Value::I64(1)).is_err());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_encrypted_metadata_frames() -> Result<()> {
+        let key = [7_u8; 32];
+        let payload = Value::from("secret").serialize()?;
+        let tags = |tag: &'static str| Value::HashMap(vec![(Value::from("tag"), Value::from(tag))]);
+
+        // Editing unauthenticated metadata leaves the frame readable.
+        let bytes = seal_with_metadata(&payload, Some(&key), None, &Metadata::new(&tags("a"))?)?;
+        let edited = seal_with_metadata(&payload, None, None, &Metadata::new(&tags("b"))?)?;
+        // The last byte of the metadata: after the header, its length and `{"tag": "`.
+        let position = HEADER_LEN + 4 + 9;
+        let mut tampered = bytes.clone();
+        tampered[position] = b'b';
+        assert_eq!(
+            Framed::parse(&tampered)?.metadata()?,
+            Framed::parse(&edited)?.metadata()?
+        );
+        assert_eq!(open(&tampered, Some(&key))?.0, &payload[..]);
+
+        let metadata = Metadata::new(&tags("a"))?.authenticated(true);
+        let bytes = seal_with_metadata(&payload, Some(&key), None, &metadata)?;
+        assert_eq!(open(&bytes, Some(&key))?.0, &payload[..]);
+        let mut tampered = bytes.clone();
+        tampered[position] = b'b';
+        assert_eq!(
+            open(&tampered, Some(&key)).unwrap_err().downcast_ref(),
+            Some(&Error::AuthenticationFailed)
+        );
+
+        Ok(())
+    }
+}
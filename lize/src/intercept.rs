@@ -0,0 +1,295 @@
+//! [`SerializeInterceptor`], which leaves out or replaces parts of a value as
+//! it's written, without building a changed copy of it first.
+
+use std::{collections::HashSet, io::Write};
+
+use crate::{tag, write_len, Result, SerializeOptions, Value};
+
+/// One step of the way from the value being written to a value inside it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathSegment<'v> {
+    /// The value of the map entry with this key.
+    Key(&'v Value<'v>),
+    /// The item at this index of a vector, counting any left out before it.
+    Index(usize),
+}
+
+/// What a [`SerializeInterceptor`] does with a value or map entry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Writes it as it is.
+    Keep,
+    /// Leaves it out. Values that can't be left out, which are the value being
+    /// written and the insides of `Optional` and `Ext`, are written as `None`.
+    Skip,
+    /// Writes this instead, as it is, without calling the interceptor on it.
+    Replace(Value<'static>),
+}
+
+/// Called as a value is written, to leave out or replace parts of it. Set it
+/// with [`SerializeOptions::interceptor`].
+///
+/// Both methods are given the `path` from the value being written. Their
+/// default implementations keep everything, so only the one needed has to be
+/// implemented. Values that are kept aren't copied.
+///
+/// # Example
+/// ```rust
+/// use lize::{Action, PathSegment, SerializeInterceptor, SerializeOptions, SmallVec, Value};
+///
+/// /// Writes every float in the second item of a vector as 0.
+/// struct ZeroSecond;
+///
+/// impl SerializeInterceptor for ZeroSecond {
+///     fn on_value(&self, path: &[PathSegment<'_>], value: &Value<'_>) -> Action {
+///         match (path.first(), value) {
+///             (Some(PathSegment::Index(1)), Value::F64(_)) => Action::Replace(Value::F64(0.0)),
+///             _ => Action::Keep,
+///         }
+///     }
+/// }
+///
+/// let value = Value::Vector(vec![Value::F64(1.5), Value::F64(2.5)]);
+/// let mut buffer = SmallVec::new();
+/// value.serialize_with(&SerializeOptions::new().interceptor(ZeroSecond), &mut buffer)?;
+///
+/// let expected = Value::Vector(vec![Value::F64(1.5), Value::F64(0.0)]);
+/// assert_eq!(buffer.as_slice(), expected.serialize()?);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub trait SerializeInterceptor: Send + Sync {
+    /// Called with each entry of a map before its value is written, or
+    /// [`SerializeInterceptor::on_value`] is called with it. `path` leads to
+    /// the map.
+    fn on_map_entry(
+        &self,
+        _path: &[PathSegment<'_>],
+        _key: &Value<'_>,
+        _value: &Value<'_>,
+    ) -> Action {
+        Action::Keep
+    }
+
+    /// Called with every value that's written, other than map keys, replaced
+    /// values and entries left out by [`SerializeInterceptor::on_map_entry`].
+    /// `path` leads to the value; the insides of `Optional` and `Ext` share the
+    /// path of their container.
+    fn on_value(&self, _path: &[PathSegment<'_>], _value: &Value<'_>) -> Action {
+        Action::Keep
+    }
+}
+
+/// Leaves out, or replaces the values of, every map entry whose key is one of
+/// a set of strings, at any depth. Keys match `Str` and `Slice` keys with the
+/// same bytes.
+///
+/// ```rust
+/// use lize::{RedactKeys, SerializeOptions, SmallVec, Value};
+///
+/// let user = Value::HashMap(vec![
+///     (Value::from("name"), Value::from("Ada")),
+///     (Value::from("password"), Value::from("hunter2")),
+/// ]);
+/// let options = SerializeOptions::new().interceptor(RedactKeys::new(["password"]));
+/// let mut buffer = SmallVec::new();
+/// user.serialize_with(&options, &mut buffer)?;
+///
+/// let expected = Value::HashMap(vec![(Value::from("name"), Value::from("Ada"))]);
+/// assert_eq!(buffer.as_slice(), expected.serialize()?);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RedactKeys {
+    keys: HashSet<Vec<u8>>,
+    replacement: Option<Value<'static>>,
+}
+
+impl RedactKeys {
+    /// Leaves out the entries with these keys.
+    pub fn new<I, K>(keys: I) -> Self
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<[u8]>,
+    {
+        Self {
+            keys: keys.into_iter().map(|key| key.as_ref().to_vec()).collect(),
+            replacement: None,
+        }
+    }
+
+    /// Keeps the entries, writing `value` in place of theirs.
+    pub fn replacement(mut self, value: Value<'static>) -> Self {
+        self.replacement = Some(value);
+        self
+    }
+}
+
+impl SerializeInterceptor for RedactKeys {
+    fn on_map_entry(
+        &self,
+        _path: &[PathSegment<'_>],
+        key: &Value<'_>,
+        _value: &Value<'_>,
+    ) -> Action {
+        match key.key_bytes() {
+            Some(bytes) if self.keys.contains(bytes) => match &self.replacement {
+                Some(value) => Action::Replace(value.clone()),
+                None => Action::Skip,
+            },
+            _ => Action::Keep,
+        }
+    }
+}
+
+/// A value left to write.
+enum Pending<'v> {
+    /// A value the interceptor kept, whose insides it's still asked about.
+    Kept {
+        value: &'v Value<'v>,
+        depth: usize,
+        /// How much of the path leads to its container.
+        path_len: usize,
+        segment: Option<PathSegment<'v>>,
+    },
+    /// A map key, or a replacement, which is written as it is.
+    Plain(&'v Value<'v>, usize),
+    Owned(Value<'static>, usize),
+}
+
+impl<'v> Pending<'v> {
+    fn decide(
+        action: Action,
+        value: &'v Value<'v>,
+        depth: usize,
+        path_len: usize,
+        segment: Option<PathSegment<'v>>,
+    ) -> Option<Self> {
+        match action {
+            Action::Keep => Some(Self::Kept {
+                value,
+                depth,
+                path_len,
+                segment,
+            }),
+            Action::Skip => None,
+            Action::Replace(value) => Some(Self::Owned(value, depth)),
+        }
+    }
+
+    /// Like [`Pending::decide`], for values that can't be left out, which are
+    /// written as `None` instead.
+    fn required(action: Action, value: &'v Value<'v>, depth: usize, path_len: usize) -> Self {
+        Self::decide(action, value, depth, path_len, None)
+            .unwrap_or(Self::Owned(Value::Optional(None), depth))
+    }
+}
+
+/// Writes `value` like [`Value::write_observed`], asking `interceptor` about
+/// each part of it first.
+pub(crate) fn write<'v, W, F>(
+    value: &'v Value<'v>,
+    options: &SerializeOptions,
+    interceptor: &dyn SerializeInterceptor,
+    buffer: &mut W,
+    depth: usize,
+    observe: &mut F,
+) -> Result<()>
+where
+    W: Write + ?Sized,
+    F: FnMut(&W) -> Result<()>,
+{
+    let mut path: Vec<PathSegment<'v>> = vec![];
+    let action = interceptor.on_value(&path, value);
+    let mut stack = vec![Pending::required(action, value, depth, 0)];
+
+    while let Some(pending) = stack.pop() {
+        let (value, depth) = match pending {
+            Pending::Plain(value, depth) => {
+                value.write_plain(options, buffer, depth, observe)?;
+                continue;
+            }
+            Pending::Owned(value, depth) => {
+                value.write_plain(options, buffer, depth, observe)?;
+                continue;
+            }
+            Pending::Kept {
+                value,
+                depth,
+                path_len,
+                segment,
+            } => {
+                path.truncate(path_len);
+                path.extend(segment);
+                (value, depth)
+            }
+        };
+
+        // Children are pushed in order, then reversed, so they're written in
+        // order and the count can be taken without collecting them first.
+        let start = stack.len();
+        match value {
+            Value::Vector(items) => {
+                options.check_depth(depth)?;
+                for (i, item) in items.iter().enumerate() {
+                    path.push(PathSegment::Index(i));
+                    let action = interceptor.on_value(&path, item);
+                    path.pop();
+                    stack.extend(Pending::decide(
+                        action,
+                        item,
+                        depth + 1,
+                        path.len(),
+                        Some(PathSegment::Index(i)),
+                    ));
+                }
+                write_len(buffer, tag::VECTOR, stack.len() - start)?;
+            }
+            Value::HashMap(pairs) => {
+                options.check_depth(depth)?;
+                let mut pairs: Vec<_> = pairs.iter().collect();
+                if options.canonical {
+                    pairs.sort_by(|(a, _), (b, _)| a.cmp_structural(b));
+                }
+
+                let mut len = 0;
+                for (key, value) in pairs {
+                    let value = match interceptor.on_map_entry(&path, key, value) {
+                        Action::Skip => continue,
+                        Action::Replace(value) => Pending::Owned(value, depth + 1),
+                        Action::Keep => {
+                            path.push(PathSegment::Key(key));
+                            let action = interceptor.on_value(&path, value);
+                            path.pop();
+                            let segment = Some(PathSegment::Key(key));
+                            match Pending::decide(action, value, depth + 1, path.len(), segment) {
+                                Some(value) => value,
+                                None => continue,
+                            }
+                        }
+                    };
+                    len += 1;
+                    stack.push(Pending::Plain(key, depth + 1));
+                    stack.push(value);
+                }
+                write_len(buffer, tag::MAP, len)?;
+            }
+            Value::Optional(Some(inner)) | Value::Ext(_, inner) => {
+                options.check_depth(depth)?;
+                match value {
+                    Value::Ext(code, _) => buffer.write_all(&[tag::EXT, *code])?,
+                    _ => buffer.write_all(&[tag::SOME])?,
+                }
+                let action = interceptor.on_value(&path, inner);
+                stack.push(Pending::required(action, inner, depth + 1, path.len()));
+            }
+            _ => {
+                value.write_plain(options, buffer, depth, observe)?;
+                continue;
+            }
+        }
+        observe(buffer)?;
+        stack[start..].reverse();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! Decoding of the slices and containers written before format version 4.
+//!
+//! Their items are prefixed with one-byte lengths, and vectors and maps end
+//! with a marker instead of starting with a count. Since every item fits in
+//! 255 bytes, they can't nest deeply, so they are decoded recursively.
+
+use crate::{
+    decode::Cursor, options::check_str_len, tag, DeserializeOptions, Error, Result, Value,
+};
+
+/// Returns how many bytes the legacy value at the start of `bytes` takes.
+pub(crate) fn encoded_len(bytes: &[u8]) -> Result<usize> {
+    let byte = |i: usize| bytes.get(i).copied().ok_or(Error::UnexpectedEnd);
+
+    let len = match byte(0)? {
+        tag::legacy::SLICE | tag::legacy::SOME | tag::legacy::STR => 2 + byte(1)? as usize,
+        tag::legacy::VECTOR => {
+            let mut offset = 1;
+            loop {
+                offset += 1 + byte(offset)? as usize;
+                if byte(offset)? == tag::legacy::VECTOR_END {
+                    break offset + 1;
+                }
+            }
+        }
+        tag::legacy::MAP => {
+            let mut offset = 1;
+            loop {
+                offset += 1 + byte(offset)? as usize;
+                offset += 1 + byte(offset)? as usize;
+                if byte(offset)? == tag::legacy::MAP_END {
+                    break offset + 1;
+                }
+            }
+        }
+        tag => return Err(Error::UnknownTag(tag).into()),
+    };
+
+    if len > bytes.len() {
+        return Err(Error::UnexpectedEnd.into());
+    }
+    Ok(len)
+}
+
+/// Decodes a value inside a legacy payload. `bytes` ends where the value does,
+/// or, for a top-level value, where the input does.
+///
+/// `select` has the same meaning as in [`crate::decode`].
+pub(crate) fn decode<'a>(
+    bytes: &'a [u8],
+    options: &DeserializeOptions,
+    depth: usize,
+    select: bool,
+) -> Result<Value<'a>> {
+    options.check_depth(depth)?;
+
+    let mut cursor = Cursor::new(bytes);
+    let item = |cursor: &mut Cursor<'a>, select: bool| -> Result<Value<'a>> {
+        let len = cursor.read_u8()? as usize;
+        decode(cursor.take(len)?, options, depth + 1, select)
+    };
+
+    Ok(match cursor.read_u8()? {
+        tag::legacy::SLICE => {
+            let len = cursor.read_u8()? as usize;
+            check_str_len(options.max_str_len, len)?;
+            Value::Slice(cursor.take(len)?)
+        }
+        tag::legacy::STR => {
+            let len = cursor.read_u8()? as usize;
+            check_str_len(options.max_str_len, len)?;
+            Value::Str(std::str::from_utf8(cursor.take(len)?).map_err(|_| Error::InvalidUtf8)?)
+        }
+        tag::legacy::SOME => Value::Optional(Some(Box::new(item(&mut cursor, false)?))),
+        tag::legacy::VECTOR => {
+            let mut items = vec![];
+            loop {
+                items.push(item(&mut cursor, select)?);
+                if cursor.rest().first() == Some(&tag::legacy::VECTOR_END) {
+                    break;
+                }
+            }
+            Value::Vector(items)
+        }
+        tag::legacy::MAP => {
+            let mut pairs = vec![];
+            loop {
+                let key = item(&mut cursor, false)?;
+
+                // Unselected values are skipped without being decoded.
+                if !select || options.is_selected(&key) {
+                    pairs.push((key, item(&mut cursor, false)?));
+                } else {
+                    let len = cursor.read_u8()? as usize;
+                    cursor.take(len)?;
+                }
+
+                if cursor.rest().first() == Some(&tag::legacy::MAP_END) {
+                    break;
+                }
+            }
+            Value::HashMap(pairs)
+        }
+        tag::EXT => {
+            let code = cursor.read_u8()?;
+            let value = decode(cursor.rest(), options, depth + 1, false)?;
+            Value::Ext(code, Box::new(value))
+        }
+        tag => cursor.read_scalar(tag)?,
+    })
+}
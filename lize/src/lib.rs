@@ -2,32 +2,217 @@
 
 use std::io::Write;
 
+#[cfg(feature = "arrow")]
+pub mod arrow;
+mod decode;
+mod diagnose;
+mod error;
+pub mod frame;
+mod intercept;
+mod legacy;
+mod options;
+mod ordering;
+pub mod packed;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod patch;
+mod progress;
+mod project;
+mod schema;
+mod shape;
+mod stats;
+mod validate;
+mod varint;
+
 pub use anyhow::Result;
+pub use diagnose::{diagnose, Problem, ProblemKind};
+pub use error::Error;
+pub use intercept::{Action, PathSegment, RedactKeys, SerializeInterceptor};
+pub use options::{DeserializeOptions, SerializeOptions};
+pub use progress::Progress;
+pub use schema::Schema;
 pub use smallvec::SmallVec;
+pub use stats::Stats;
 
 pub const STACK_N: usize = 128;
 
+/// The version of the wire format produced by this crate.
+///
+/// Any change to how a [`Value`] is encoded bumps this number. Payloads
+/// written by older versions must still decode; see `tests/golden/` and `FORMAT.md`.
+pub const FORMAT_VERSION: u8 = 4;
+
+/// The byte order of every multi-byte number in a payload or frame header, on
+/// any host, spelled like Python's `sys.byteorder`. See `FORMAT.md`.
+pub const ENDIANNESS: &str = "little";
+
+/// Well-known codes for [`Value::Ext`].
+///
+/// Codes not listed here may be assigned by later versions; applications'
+/// own types must use a code in [`ext::USER`].
+pub mod ext {
+    /// An arbitrary-precision integer, stored as a little-endian two's complement
+    /// [`Value::Slice`](crate::Value::Slice).
+    pub const BIG_INT: u8 = 1;
+
+    /// An exact fraction, stored as a [`Value::Vector`](crate::Value::Vector) of
+    /// `[numerator, denominator]`.
+    pub const FRACTION: u8 = 2;
+
+    /// A Python function, stored as a versioned envelope of its marshalled
+    /// code, name and other attributes; see `FORMAT.md` for each version's
+    /// layout.
+    pub const RUNNABLE: u8 = 3;
+
+    /// A duration, stored as a [`Value::Vector`](crate::Value::Vector) of
+    /// `[days, seconds, microseconds]` integers, normalized like Python's `timedelta`.
+    pub const TIMEDELTA: u8 = 4;
+
+    /// An instance of a subclass of a string or byte string, stored as a
+    /// [`Value::Vector`](crate::Value::Vector) of `[qualified class name, value]`.
+    pub const SUBCLASS: u8 = 5;
+
+    /// A Python object rebuilt by calling a global, stored as a
+    /// [`Value::Vector`](crate::Value::Vector) of `[qualified name]` for the
+    /// global itself, or `[qualified name, args, state, list items, dict items]`
+    /// from its `__reduce__`.
+    pub const REDUCE: u8 = 6;
+
+    /// A vector of booleans, stored as a [`Value::Slice`](crate::Value::Slice)
+    /// whose first byte is the number of unused high bits in its last byte,
+    /// followed by the booleans packed eight to a byte, lowest bit first.
+    pub const BITS: u8 = 7;
+
+    /// A map value marking its key as deleted rather than null, see
+    /// [`Value::merge`](crate::Value::merge). Its payload is `Optional(None)`.
+    pub const DELETE: u8 = 8;
+
+    /// An unordered collection of distinct values, stored as a
+    /// [`Value::Vector`](crate::Value::Vector) of its items. Canonical writers
+    /// sort the items by their encoding.
+    pub const SET: u8 = 9;
+
+    /// An immutable [`SET`], stored the same way.
+    pub const FROZEN_SET: u8 = 10;
+
+    /// A string holding lone surrogates, which Python allows but UTF-8 can't
+    /// encode, stored as a [`Value::Slice`](crate::Value::Slice) of its UTF-8
+    /// encoding with each surrogate encoded like any other code point.
+    pub const SURROGATE_STR: u8 = 11;
+
+    /// A field missing from a map written with a [`Schema`](crate::Schema),
+    /// as opposed to one set to null, see [`Value::absent`](crate::Value::absent).
+    /// Its payload is `Optional(None)`.
+    pub const ABSENT: u8 = 12;
+
+    /// A vector with runs of equal adjacent items, stored as a
+    /// [`Value::Vector`](crate::Value::Vector) of `[item, count, item, count, ...]`,
+    /// where each count is a positive integer.
+    pub const RUNS: u8 = 13;
+
+    /// An IP address, stored as a [`Value::Slice`](crate::Value::Slice) of its
+    /// version, `4` or `6`, followed by its 4 or 16 bytes in network order.
+    pub const IP_ADDRESS: u8 = 14;
+
+    /// An IP network, stored like an [`IP_ADDRESS`] of its first address
+    /// followed by one byte of prefix length.
+    pub const IP_NETWORK: u8 = 15;
+
+    /// A date and time, stored as a [`Value::Vector`](crate::Value::Vector) of
+    /// `[year, month, day, hour, minute, second, microsecond, fold, zone]`,
+    /// where `zone` is `None`, an IANA time zone key or an offset from UTC
+    /// in microseconds.
+    pub const DATETIME: u8 = 16;
+
+    /// A numpy structured array, stored as a [`Value::Vector`](crate::Value::Vector)
+    /// of `[shape, structure]`, with a column of little-endian bytes per field.
+    pub const STRUCTURED_ARRAY: u8 = 17;
+
+    /// An object kept outside the payload, stored as the id the application
+    /// gave it, which can be any value.
+    pub const PERSISTENT_ID: u8 = 18;
+
+    /// A double-ended queue, stored as a [`Value::Vector`](crate::Value::Vector)
+    /// of `[items, maxlen]`, where `items` is a vector in order from the left
+    /// and `maxlen` is the most items it holds, or `None` if it's unbounded.
+    pub const DEQUE: u8 = 19;
+
+    /// Codes reserved for applications' own types. lize never assigns these.
+    pub const USER: std::ops::RangeInclusive<u8> = 240..=255;
+}
+
+/// Tag bytes, the first byte of every encoded value. See `FORMAT.md`.
+pub(crate) mod tag {
+    pub const I64: u8 = 0;
+    pub const SOME: u8 = 3;
+    pub const TRUE: u8 = 6;
+    pub const FALSE: u8 = 7;
+    pub const F64: u8 = 8;
+    pub const NONE: u8 = 10;
+    pub const I32: u8 = 11;
+    pub const F32: u8 = 12;
+    pub const U8: u8 = 13;
+    pub const EXT: u8 = 14;
+    pub const VECTOR: u8 = 16;
+    pub const MAP: u8 = 17;
+    pub const SLICE: u8 = 18;
+    pub const STR: u8 = 19;
+    /// `SmallU8(n)` is encoded as the single byte `SMALL_U8 + n`.
+    pub const SMALL_U8: u8 = 20;
+
+    /// Tags written before format version 4, which are still decoded.
+    pub mod legacy {
+        pub const SLICE: u8 = 1;
+        pub const VECTOR: u8 = 2;
+        pub const VECTOR_END: u8 = 3;
+        pub const MAP: u8 = 4;
+        pub const MAP_END: u8 = 5;
+        pub const SOME: u8 = 9;
+        pub const STR: u8 = 15;
+
+        pub fn is_legacy(tag: u8) -> bool {
+            matches!(tag, SLICE | VECTOR | MAP | SOME | STR)
+        }
+    }
+}
+
 /// Represents a value.
 ///
+/// A few invariants aren't enforced by the variants themselves: `SmallU8`
+/// holds at most 235, and map keys are distinct. Build such values with
+/// [`Value::small_u8`] and [`Value::map_from_pairs`], or check a whole tree
+/// with [`Value::validate`]; values that break them are unsupported.
+///
+/// `Value` implements [`Drop`], so that dropping a deeply nested value doesn't
+/// overflow the stack. Its fields can't be moved out by a pattern; take them
+/// with [`std::mem::take`] through a `&mut` instead.
+///
 /// # Example
 /// ```rust
 /// use lize::Value;
 ///
 /// let value = Value::from(1234_i64);
 /// assert_eq!(value, Value::I64(1234));
+///
+/// let mut list = Value::Vector(vec![value]);
+/// let items = match &mut list {
+///     Value::Vector(items) => std::mem::take(items),
+///     _ => vec![],
+/// };
+/// assert_eq!(items.len(), 1);
 /// ```
 #[derive(Debug, PartialEq)]
 pub enum Value<'a> {
     /// A 64-bit signed integer. (code: `0`)
     I64(i64),
 
-    /// A slice of bytes. (code: `1`)
+    /// A slice of bytes. (code: `18`)
     Slice(&'a [u8]),
 
-    /// A vector of values. (code: `2`, ends with `3`)
+    /// A vector of values. (code: `16`)
     Vector(Vec<Value<'a>>),
 
-    /// A map of values. (code: `4`, ends with `5`)
+    /// A map of values. (code: `17`)
     HashMap(Vec<(Value<'a>, Value<'a>)>),
 
     /// A boolean. (code: `6`, `7`)
@@ -36,11 +221,11 @@ pub enum Value<'a> {
     /// A 64-bit float. (code: `8`)
     F64(f64),
 
-    /// An optional value. (code: `9`, `10` for `None`)
+    /// An optional value. (code: `3`, `10` for `None`)
     Optional(Option<Box<Value<'a>>>),
 
-    /// A `SliceLike` acts like a slice when deserializing, which will be never reached.
-    /// This is created for uses where you cannot use the reference of the slice or lifetime errors.
+    /// An owned [`Value::Slice`], encoded the same way, for bytes that can't be
+    /// borrowed. Decoding never produces it; the bytes come back as a `Slice`.
     SliceLike(Vec<u8>),
 
     /// A 32-bit signed integer. (code: `11`)
@@ -54,6 +239,19 @@ pub enum Value<'a> {
 
     /// A small u8. Must be <= 235. Occupies a single byte.
     SmallU8(u8),
+
+    /// An extension value, made of a one-byte code and a payload. (code: `14`)
+    ///
+    /// See [`ext`] for the codes with a well-known meaning.
+    Ext(u8, Box<Value<'a>>),
+
+    /// A UTF-8 string. (code: `19`)
+    ///
+    /// Unlike [`Value::Slice`], it's guaranteed to decode as text on every side.
+    Str(&'a str),
+
+    /// An owned [`Value::Str`], encoded the same way.
+    StrLike(String),
 }
 
 impl<'a> Value<'a> {
@@ -73,188 +271,322 @@ impl<'a> Value<'a> {
     }
 
     pub fn serialize_into(&self, buffer: &mut SmallVec<[u8; STACK_N]>) -> Result<()> {
-        match self {
-            Self::I64(i) => {
-                buffer.write_all(&[0])?;
-                buffer.write_all(&i.to_le_bytes())?;
-            }
-            Self::Slice(s) => {
-                buffer.write_all(&[1])?;
+        self.serialize_with(&SerializeOptions::default(), buffer)
+    }
 
-                let ln = s.len() as u8;
-                buffer.write_all(&ln.to_le_bytes())?;
-                buffer.write_all(&s)?;
-            }
-            Self::Vector(v) => {
-                buffer.write_all(&[2])?;
+    /// Serializes into `buffer` with the given options.
+    ///
+    /// Values are written without recursing, so nesting depth is only bounded by
+    /// memory (and [`SerializeOptions::max_depth`]).
+    ///
+    /// If it fails, `buffer` is left as it was, without a partial value.
+    pub fn serialize_with(
+        &self,
+        options: &SerializeOptions,
+        buffer: &mut SmallVec<[u8; STACK_N]>,
+    ) -> Result<()> {
+        let start = buffer.len();
+        let result = self.serialize_to(options, buffer);
+        if result.is_err() {
+            buffer.truncate(start);
+        }
+        result
+    }
 
-                for item in v {
-                    let mut buf = SmallVec::<[u8; STACK_N]>::new();
-                    item.serialize_into(&mut buf)?;
+    /// Streams the encoding into `writer` with the given options, a few bytes
+    /// at a time. Wrap unbuffered writers in a [`std::io::BufWriter`].
+    ///
+    /// If it fails, part of the value may already have been written.
+    pub fn serialize_to<W: Write + ?Sized>(
+        &self,
+        options: &SerializeOptions,
+        buffer: &mut W,
+    ) -> Result<()> {
+        self.check(options)?;
+
+        let Some(limit) = options.max_output_bytes else {
+            return self.write_to(options, buffer, 0);
+        };
+
+        let mut limited = Limited::new(buffer, limit);
+        let result = self.write_to(options, &mut limited, 0);
+        limited.check(result)
+    }
 
-                    let ln = buf.len() as u8;
-                    buffer.write_all(&ln.to_le_bytes())?;
-                    buffer.write_all(&buf)?;
-                }
+    /// Writes the start of a [`Value::Vector`] of `len` items, so a vector can
+    /// be streamed by writing each item after it with [`Value::serialize_to`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use lize::{SerializeOptions, Value};
+    ///
+    /// let mut buffer = vec![];
+    /// Value::write_vector_head(&mut buffer, 2)?;
+    /// for i in [1, 2] {
+    ///     Value::I64(i).serialize_to(&SerializeOptions::default(), &mut buffer)?;
+    /// }
+    /// assert_eq!(buffer, Value::Vector(vec![Value::I64(1), Value::I64(2)]).serialize()?);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn write_vector_head<W: Write + ?Sized>(buffer: &mut W, len: usize) -> Result<()> {
+        write_len(buffer, tag::VECTOR, len)
+    }
 
-                buffer.write_all(&[3])?;
-            }
-            Self::HashMap(h) => {
-                buffer.write_all(&[4])?;
+    /// Writes the start of a [`Value::HashMap`] of `len` entries, to be followed
+    /// by each key and then its value, like [`Value::write_vector_head`].
+    pub fn write_map_head<W: Write + ?Sized>(buffer: &mut W, len: usize) -> Result<()> {
+        write_len(buffer, tag::MAP, len)
+    }
 
-                for (key, value) in h {
-                    let mut keybuf = SmallVec::<[u8; STACK_N]>::new();
-                    let mut valbuf = SmallVec::<[u8; STACK_N]>::new();
-                    key.serialize_into(&mut keybuf)?;
-                    value.serialize_into(&mut valbuf)?;
+    /// Writes the start of a [`Value::Slice`] of `len` bytes, to be followed by
+    /// the bytes themselves, so they can be copied straight from their source.
+    pub fn write_slice_head<W: Write + ?Sized>(buffer: &mut W, len: usize) -> Result<()> {
+        write_len(buffer, tag::SLICE, len)
+    }
 
-                    let ln_key = keybuf.len() as u8;
-                    buffer.write_all(&ln_key.to_le_bytes())?;
-                    buffer.write_all(&keybuf)?;
+    /// Writes the start of a [`Value::Ext`] with `code`, to be followed by its
+    /// payload.
+    ///
+    /// # Example
+    /// ```rust
+    /// use lize::Value;
+    ///
+    /// let mut buffer = vec![];
+    /// Value::write_ext_head(&mut buffer, 200)?;
+    /// Value::write_slice_head(&mut buffer, 3)?;
+    /// buffer.extend_from_slice(b"raw");
+    /// assert_eq!(buffer, Value::Ext(200, Box::new(Value::Slice(b"raw"))).serialize()?);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn write_ext_head<W: Write + ?Sized>(buffer: &mut W, code: u8) -> Result<()> {
+        Ok(buffer.write_all(&[tag::EXT, code])?)
+    }
 
-                    let ln_val = valbuf.len() as u8;
-                    buffer.write_all(&ln_val.to_le_bytes())?;
-                    buffer.write_all(&valbuf)?;
-                }
+    /// Writes the value, nested `depth` containers deep, without applying
+    /// [`SerializeOptions::max_output_bytes`].
+    pub(crate) fn write_to<W: Write + ?Sized>(
+        &self,
+        options: &SerializeOptions,
+        buffer: &mut W,
+        depth: usize,
+    ) -> Result<()> {
+        self.write_observed(options, buffer, depth, &mut |_| Ok(()))
+    }
 
-                buffer.write_all(&[5])?;
+    /// Like [`Value::write_to`], calling `observe` with the writer after each
+    /// value, counting a container as soon as its head is written.
+    pub(crate) fn write_observed<W, F>(
+        &self,
+        options: &SerializeOptions,
+        buffer: &mut W,
+        depth: usize,
+        observe: &mut F,
+    ) -> Result<()>
+    where
+        W: Write + ?Sized,
+        F: FnMut(&W) -> Result<()>,
+    {
+        match &options.interceptor {
+            Some(interceptor) => {
+                intercept::write(self, options, &**interceptor, buffer, depth, observe)
             }
-            Self::Bool(b) => {
-                if *b {
-                    buffer.write_all(&[6])?;
-                } else {
-                    buffer.write_all(&[7])?;
+            None => self.write_plain(options, buffer, depth, observe),
+        }
+    }
+
+    /// Like [`Value::write_observed`], ignoring any interceptor.
+    pub(crate) fn write_plain<W, F>(
+        &self,
+        options: &SerializeOptions,
+        buffer: &mut W,
+        depth: usize,
+        observe: &mut F,
+    ) -> Result<()>
+    where
+        W: Write + ?Sized,
+        F: FnMut(&W) -> Result<()>,
+    {
+        // Values left to write, last first, with their depth. Small values
+        // never need more than the inline capacity, so they don't allocate.
+        let mut stack = SmallVec::<[(&Value, usize); 16]>::new();
+        stack.push((self, depth));
+
+        while let Some((value, depth)) = stack.pop() {
+            options.check_depth(depth)?;
+
+            match value {
+                Self::I64(i) => write_tagged(buffer, tag::I64, &i.to_le_bytes())?,
+                Self::Slice(s) => write_bytes(buffer, tag::SLICE, s)?,
+                Self::SliceLike(s) => write_bytes(buffer, tag::SLICE, s)?,
+                Self::Str(s) => write_bytes(buffer, tag::STR, s.as_bytes())?,
+                Self::StrLike(s) => write_bytes(buffer, tag::STR, s.as_bytes())?,
+                Self::Vector(v) => {
+                    write_len(buffer, tag::VECTOR, v.len())?;
+
+                    stack.extend(v.iter().rev().map(|item| (item, depth + 1)));
                 }
-            }
-            Self::F64(f) => {
-                buffer.write_all(&[8])?;
-                buffer.write_all(&f.to_le_bytes())?;
-            }
-            Self::Optional(value) => match value {
-                Some(bv) => {
-                    buffer.write_all(&[9])?;
-                    let mut buf = SmallVec::<[u8; STACK_N]>::new();
-                    bv.serialize_into(&mut buf)?;
-
-                    let ln = buf.len() as u8;
-                    buffer.write_all(&ln.to_le_bytes())?;
-                    buffer.write_all(&buf)?;
+                Self::HashMap(h) => {
+                    write_len(buffer, tag::MAP, h.len())?;
+
+                    let mut pairs: Vec<_> = h.iter().collect();
+                    if options.canonical {
+                        pairs.sort_by(|(a, _), (b, _)| a.cmp_structural(b));
+                    }
+
+                    for (key, value) in pairs.into_iter().rev() {
+                        stack.push((value, depth + 1));
+                        stack.push((key, depth + 1));
+                    }
                 }
-                None => buffer.write_all(&[10])?,
-            },
-            Self::SliceLike(v) => {
-                buffer.write_all(&[1])?;
-
-                let ln = v.len() as u8;
-                buffer.write_all(&ln.to_le_bytes())?;
-                buffer.write_all(&v)?;
-            }
-            Self::I32(i) => {
-                buffer.write_all(&[11])?;
-                buffer.write_all(&i.to_le_bytes())?;
-            }
-            Self::F32(f) => {
-                buffer.write_all(&[12])?;
-                buffer.write_all(&f.to_le_bytes())?;
-            }
-            Self::U8(u) => {
-                buffer.write_all(&[13])?;
-                buffer.write_all(&u.to_le_bytes())?;
-            }
-            Self::SmallU8(u) => {
-                // 20 because we may never reach there.
-                if u > &235 {
-                    return Err(anyhow::anyhow!("SmallU8 must be less than or equal to 235"));
+                Self::Bool(b) => buffer.write_all(&[if *b { tag::TRUE } else { tag::FALSE }])?,
+                Self::F64(f) => {
+                    let f = if options.canonical {
+                        canonical_f64(*f)
+                    } else {
+                        *f
+                    };
+                    write_tagged(buffer, tag::F64, &f.to_le_bytes())?
+                }
+                Self::Optional(Some(inner)) => {
+                    buffer.write_all(&[tag::SOME])?;
+                    stack.push((inner, depth + 1));
+                }
+                Self::Optional(None) => buffer.write_all(&[tag::NONE])?,
+                Self::I32(i) => write_tagged(buffer, tag::I32, &i.to_le_bytes())?,
+                Self::F32(f) => {
+                    let f = if options.canonical {
+                        canonical_f32(*f)
+                    } else {
+                        *f
+                    };
+                    write_tagged(buffer, tag::F32, &f.to_le_bytes())?
+                }
+                Self::U8(u) => buffer.write_all(&[tag::U8, *u])?,
+                Self::SmallU8(u) => {
+                    let Some(byte) = u.checked_add(tag::SMALL_U8) else {
+                        return Err(
+                            Error::InvalidValue(format!("SmallU8({}) is over 235", u)).into()
+                        );
+                    };
+                    buffer.write_all(&[byte])?;
+                }
+                Self::Ext(code, inner) => {
+                    buffer.write_all(&[tag::EXT, *code])?;
+                    stack.push((inner, depth + 1));
                 }
-                buffer.write_all(&(u + 20).to_le_bytes())?;
             }
+            observe(buffer)?;
         }
 
         Ok(())
     }
 
-    pub fn deserialize_from(slice: &'a [u8]) -> Result<Self> {
-        let tag = &slice[0];
-        match tag {
-            0 => {
-                let i = i64::from_le_bytes(slice[1..9].try_into()?);
-                Ok(Self::I64(i))
-            }
-            1 => {
-                let ln = u8::from_le_bytes(slice[1..2].try_into()?) as usize;
-                Ok(Self::Slice(&slice[2..(2 + ln)]))
-            }
-            2 => {
-                let mut offset = 1_usize;
-                let mut data: Vec<Value> = vec![];
-
-                // [
-                //     0    1      2~2   |  3
-                //     TAG, LEN=1, DATA  |
-                //                       ^ offset = 2 + 1
-                // ]
-                loop {
-                    let ln = u8::from_le_bytes(slice[offset..offset + 1].try_into()?) as usize;
-                    let s = &slice[(offset + 1)..(offset + 1 + ln)];
-                    data.push(Value::deserialize_from(s)?);
-                    offset += 1 + ln;
-
-                    if &slice[offset] == &3 {
-                        break;
-                    }
-                }
-
-                Ok(Self::Vector(data))
-            }
-            4 => {
-                let mut offset = 1_usize;
-                let mut data: Vec<(Value, Value)> = vec![];
+    /// Feeds the canonical encoding (see [`SerializeOptions::canonical`]) to
+    /// `hasher` without building it, so the hash of a large value can be taken
+    /// in constant extra memory. Hashers from the `sha2` and `blake3` crates
+    /// implement [`Write`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use lize::{SerializeOptions, SmallVec, Value};
+    /// use sha2::{Digest, Sha256};
+    ///
+    /// let value = Value::HashMap(vec![(Value::from("b"), Value::I64(2))]);
+    ///
+    /// let mut hasher = Sha256::new();
+    /// value.hash_canonical(&mut hasher)?;
+    ///
+    /// let mut buffer = SmallVec::new();
+    /// value.serialize_with(&SerializeOptions::new().canonical(true), &mut buffer)?;
+    /// assert_eq!(hasher.finalize(), Sha256::digest(&buffer));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn hash_canonical<H: Write + ?Sized>(&self, hasher: &mut H) -> Result<()> {
+        self.serialize_to(&SerializeOptions::new().canonical(true), hasher)
+    }
 
-                loop {
-                    let ln_key = u8::from_le_bytes(slice[offset..offset + 1].try_into()?) as usize;
-                    let d = &slice[(offset + 1)..(offset + 1 + ln_key)];
-                    let key = Value::deserialize_from(d)?;
-                    offset += 1 + ln_key;
+    pub fn deserialize_from(slice: &'a [u8]) -> Result<Self> {
+        Self::deserialize_with(slice, &DeserializeOptions::default())
+    }
 
-                    let ln_val = u8::from_le_bytes(slice[offset..offset + 1].try_into()?) as usize;
-                    let d = &slice[(offset + 1)..(offset + 1 + ln_val)];
-                    let value = Value::deserialize_from(d)?;
-                    offset += 1 + ln_val;
+    /// Deserializes a value with the given options.
+    ///
+    /// Like [`Value::serialize_with`], this doesn't recurse, except into the
+    /// small containers of payloads written before format version 4.
+    pub fn deserialize_with(slice: &'a [u8], options: &DeserializeOptions) -> Result<Self> {
+        decode::decode(slice, options).map(|(value, _)| value)
+    }
 
-                    data.push((key, value));
+    /// Deserializes the first of several values written back to back, returning
+    /// it and the bytes after it, which are left undecoded.
+    ///
+    /// # Example
+    /// ```rust
+    /// use lize::{DeserializeOptions, Value};
+    ///
+    /// let mut bytes = Value::from("first").serialize()?.to_vec();
+    /// bytes.extend_from_slice(&Value::from("second").serialize()?);
+    ///
+    /// let (first, rest) = Value::deserialize_first(&bytes, &DeserializeOptions::new())?;
+    /// assert_eq!(first, Value::from("first"));
+    /// assert_eq!(Value::deserialize_from(rest)?, Value::from("second"));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn deserialize_first(
+        slice: &'a [u8],
+        options: &DeserializeOptions,
+    ) -> Result<(Self, &'a [u8])> {
+        decode::decode(slice, options)
+    }
 
-                    if &slice[offset] == &5 {
-                        break;
-                    }
-                }
+    /// Reads the start of the [`Value::Vector`] at the start of `slice`,
+    /// returning its number of items and the bytes holding them, or `None` if
+    /// it's another kind of value. The items can then be read one at a time
+    /// with [`Value::deserialize_first`], without decoding the whole vector.
+    ///
+    /// # Example
+    /// ```rust
+    /// use lize::{DeserializeOptions, Value};
+    ///
+    /// let bytes = Value::Vector(vec![Value::I64(1), Value::from("two")]).serialize()?;
+    /// let (len, mut rest) = Value::read_vector_head(&bytes)?.unwrap();
+    /// assert_eq!(len, 2);
+    ///
+    /// let mut items = vec![];
+    /// for _ in 0..len {
+    ///     let (item, after) = Value::deserialize_first(rest, &DeserializeOptions::new())?;
+    ///     items.push(item);
+    ///     rest = after;
+    /// }
+    /// assert_eq!(items, [Value::I64(1), Value::from("two")]);
+    /// assert!(Value::read_vector_head(&Value::I64(1).serialize()?)?.is_none());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn read_vector_head(slice: &'a [u8]) -> Result<Option<(usize, &'a [u8])>> {
+        if slice.is_empty() {
+            return Err(Error::EmptyInput.into());
+        }
+        let mut cursor = decode::Cursor::new(slice);
+        if cursor.read_u8()? != tag::VECTOR {
+            return Ok(None);
+        }
+        let len = cursor.read_len()?;
+        Ok(Some((len, cursor.rest())))
+    }
 
-                Ok(Value::HashMap(data))
-            }
-            6 => Ok(Value::Bool(true)),
-            7 => Ok(Value::Bool(false)),
-            8 => {
-                let f = f64::from_le_bytes(slice[1..9].try_into()?);
-                Ok(Value::F64(f))
-            }
-            9 => {
-                let ln = u8::from_le_bytes(slice[1..2].try_into()?) as usize;
-                let d = &slice[2..(2 + ln)];
-                let value = Value::deserialize_from(d)?;
-                Ok(Value::Optional(Some(Box::new(value))))
-            }
-            10 => Ok(Value::Optional(None)),
-            11 => {
-                let i = i32::from_le_bytes(slice[1..5].try_into()?);
-                Ok(Value::I32(i))
-            }
-            12 => {
-                let f = f32::from_le_bytes(slice[1..5].try_into()?);
-                Ok(Value::F32(f))
-            }
-            13 => Ok(Value::U8(u8::from_le_bytes(slice[1..2].try_into()?))),
-            _ if tag >= &20 => Ok(Value::SmallU8(tag - 20)),
-            _ => Err(anyhow::anyhow!("Unknown tag: {}", tag)),
+    /// Like [`Value::read_vector_head`], for a [`Value::Ext`]: returns its
+    /// code and the bytes starting with its payload, or `None` if it's another
+    /// kind of value.
+    pub fn read_ext_head(slice: &'a [u8]) -> Result<Option<(u8, &'a [u8])>> {
+        if slice.is_empty() {
+            return Err(Error::EmptyInput.into());
+        }
+        let mut cursor = decode::Cursor::new(slice);
+        if cursor.read_u8()? != tag::EXT {
+            return Ok(None);
         }
+        let code = cursor.read_u8()?;
+        Ok(Some((code, cursor.rest())))
     }
 
     pub fn as_i64(&self) -> Option<i64> {
@@ -300,8 +632,14 @@ impl<'a> Value<'a> {
     }
 
     pub fn as_vec_for_slice(&self) -> Option<Vec<u8>> {
+        self.as_bytes().map(<[u8]>::to_vec)
+    }
+
+    /// The bytes of a [`Value::Slice`] or [`Value::SliceLike`].
+    pub fn as_bytes(&self) -> Option<&[u8]> {
         match self {
-            Value::Slice(s) => Some(s.to_vec()),
+            Value::Slice(s) => Some(s),
+            Value::SliceLike(s) => Some(s),
             _ => None,
         }
     }
@@ -309,6 +647,7 @@ impl<'a> Value<'a> {
     pub fn as_str(&self) -> Option<&'a str> {
         match self {
             Value::Slice(s) => Some(std::str::from_utf8(s).ok()?),
+            Value::Str(s) => Some(s),
             _ => None,
         }
     }
@@ -320,19 +659,191 @@ impl<'a> Value<'a> {
             _ => None,
         }
     }
+
+    pub fn as_ext(&self) -> Option<(u8, &Value<'a>)> {
+        match self {
+            Value::Ext(code, value) => Some((*code, value)),
+            _ => None,
+        }
+    }
 }
 
 impl<'a> From<&'a str> for Value<'a> {
     fn from(s: &'a str) -> Self {
-        Value::Slice(s.as_bytes())
+        Value::Str(s)
     }
 }
 
 impl<'a> From<Value<'a>> for &'a str {
     fn from(value: Value<'a>) -> Self {
-        match value {
-            Value::Slice(s) => std::str::from_utf8(s).unwrap(),
-            _ => unreachable!(),
+        value.as_str().unwrap()
+    }
+}
+
+impl Drop for Value<'_> {
+    /// Drops nested containers from an explicit stack, since the recursive
+    /// default would overflow the call stack on deeply nested values.
+    fn drop(&mut self) {
+        let mut stack = vec![];
+        self.take_children(&mut stack);
+
+        while let Some(mut value) = stack.pop() {
+            value.take_children(&mut stack);
+        }
+    }
+}
+
+impl<'a> Clone for Value<'a> {
+    /// Copies nested containers through an explicit stack, like [`Drop`].
+    fn clone(&self) -> Self {
+        self.rebuild(|value| match value {
+            Self::Slice(s) => Self::Slice(s),
+            Self::Str(s) => Self::Str(s),
+            value => value.to_owned_leaf(),
+        })
+    }
+}
+
+impl<'a> Value<'a> {
+    /// Copies the value into one that borrows nothing, turning every
+    /// [`Value::Slice`] and [`Value::Str`] into its owned counterpart, so it can
+    /// outlive the buffer it was decoded from.
+    ///
+    /// # Example
+    /// ```rust
+    /// use lize::Value;
+    ///
+    /// let owned: Value<'static> = {
+    ///     let buffer = Value::Slice(b"abc").serialize()?;
+    ///     let borrowed = Value::deserialize_from(&buffer)?;
+    ///     borrowed.into_owned()
+    /// };
+    /// assert_eq!(owned, Value::SliceLike(b"abc".to_vec()));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn into_owned(self) -> Value<'static> {
+        self.rebuild(|value| match value {
+            Self::Slice(s) => Value::SliceLike(s.to_vec()),
+            Self::Str(s) => Value::StrLike((*s).to_owned()),
+            value => value.to_owned_leaf(),
+        })
+    }
+
+    /// Copies a value that neither borrows nor contains others.
+    fn to_owned_leaf<'b>(&self) -> Value<'b> {
+        match self {
+            Self::I64(i) => Value::I64(*i),
+            Self::SliceLike(s) => Value::SliceLike(s.clone()),
+            Self::StrLike(s) => Value::StrLike(s.clone()),
+            Self::Bool(b) => Value::Bool(*b),
+            Self::F64(f) => Value::F64(*f),
+            Self::Optional(None) => Value::Optional(None),
+            Self::I32(i) => Value::I32(*i),
+            Self::F32(f) => Value::F32(*f),
+            Self::U8(u) => Value::U8(*u),
+            Self::SmallU8(u) => Value::SmallU8(*u),
+            Self::Slice(_)
+            | Self::Str(_)
+            | Self::Vector(_)
+            | Self::HashMap(_)
+            | Self::Optional(Some(_))
+            | Self::Ext(..) => unreachable!(),
+        }
+    }
+
+    /// Rebuilds the value's containers through an explicit stack, like
+    /// [`Drop`], converting everything else with `leaf`.
+    fn rebuild<'s, 'b>(&'s self, leaf: impl Fn(&'s Value<'a>) -> Value<'b>) -> Value<'b> {
+        /// A value to copy, or a container to build from the last copies.
+        enum Step<'v, 'a> {
+            Copy(&'v Value<'a>),
+            Vector(usize),
+            Map(usize),
+            Optional,
+            Ext(u8),
+        }
+
+        let mut steps = vec![Step::Copy(self)];
+        let mut done: Vec<Value<'b>> = vec![];
+
+        while let Some(step) = steps.pop() {
+            let value = match step {
+                Step::Copy(value) => match value {
+                    Self::Vector(v) => {
+                        steps.push(Step::Vector(v.len()));
+                        steps.extend(v.iter().rev().map(Step::Copy));
+                        continue;
+                    }
+                    Self::HashMap(h) => {
+                        steps.push(Step::Map(h.len()));
+                        for (key, value) in h.iter().rev() {
+                            steps.extend([Step::Copy(value), Step::Copy(key)]);
+                        }
+                        continue;
+                    }
+                    Self::Optional(Some(inner)) => {
+                        steps.extend([Step::Optional, Step::Copy(inner)]);
+                        continue;
+                    }
+                    Self::Ext(code, inner) => {
+                        steps.extend([Step::Ext(*code), Step::Copy(inner)]);
+                        continue;
+                    }
+                    value => leaf(value),
+                },
+                Step::Vector(len) => Value::Vector(done.split_off(done.len() - len)),
+                Step::Map(len) => {
+                    let mut items = done.split_off(done.len() - 2 * len).into_iter();
+                    Value::HashMap(
+                        std::iter::from_fn(|| Some((items.next()?, items.next()?))).collect(),
+                    )
+                }
+                Step::Optional => Value::Optional(Some(Box::new(done.pop().unwrap()))),
+                Step::Ext(code) => Value::Ext(code, Box::new(done.pop().unwrap())),
+            };
+            done.push(value);
+        }
+
+        done.pop().unwrap()
+    }
+}
+
+impl<'a> Value<'a> {
+    /// Moves the children that are containers themselves onto `stack`.
+    fn take_children(&mut self, stack: &mut Vec<Value<'a>>) {
+        let is_container = |value: &Value| {
+            matches!(
+                value,
+                Value::Vector(_) | Value::HashMap(_) | Value::Optional(Some(_)) | Value::Ext(..)
+            )
+        };
+
+        match self {
+            Self::Vector(v) => stack.extend(v.drain(..).filter(is_container)),
+            Self::HashMap(h) => {
+                for (key, value) in h.drain(..) {
+                    stack.extend([key, value].into_iter().filter(is_container));
+                }
+            }
+            Self::Optional(Some(inner)) | Self::Ext(_, inner) if is_container(inner) => {
+                stack.push(std::mem::replace(&mut **inner, Value::Optional(None)));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl From<String> for Value<'_> {
+    fn from(s: String) -> Self {
+        Value::StrLike(s)
+    }
+}
+
+impl From<Value<'_>> for String {
+    fn from(mut value: Value<'_>) -> Self {
+        match &mut value {
+            Value::StrLike(s) => std::mem::take(s),
+            _ => value.as_str().unwrap().to_owned(),
         }
     }
 }
@@ -432,12 +943,12 @@ where
     K: From<Value<'a>> + std::hash::Hash + Eq,
     V: From<Value<'a>>,
 {
-    fn from(value: Value<'a>) -> Self {
-        match value {
+    fn from(mut value: Value<'a>) -> Self {
+        match &mut value {
             Value::HashMap(m) => {
                 let mut map = std::collections::HashMap::new();
 
-                for (k, v) in m {
+                for (k, v) in m.drain(..) {
                     map.insert(K::from(k), V::from(v));
                 }
 
@@ -461,9 +972,9 @@ impl<'a, T> From<Value<'a>> for Vec<T>
 where
     T: From<Value<'a>>,
 {
-    fn from(value: Value<'a>) -> Self {
-        match value {
-            Value::Vector(v) => v.into_iter().map(|item| T::from(item)).collect(),
+    fn from(mut value: Value<'a>) -> Self {
+        match &mut value {
+            Value::Vector(v) => v.drain(..).map(|item| T::from(item)).collect(),
             _ => unreachable!(),
         }
     }
@@ -505,8 +1016,99 @@ where
     Ok(v.into())
 }
 
+/// Writes `tag` and a varint length.
+fn write_len<W: Write + ?Sized>(buffer: &mut W, tag: u8, len: usize) -> Result<()> {
+    let mut head = SmallVec::<[u8; 11]>::new();
+    head.push(tag);
+    varint::write(&mut head, len);
+    Ok(buffer.write_all(&head)?)
+}
+
+/// `f` as written in canonical mode: `-0.0` as `0.0`, and every NaN as the
+/// same quiet NaN, so floats that compare equal encode the same.
+fn canonical_f64(f: f64) -> f64 {
+    if f.is_nan() {
+        f64::NAN
+    } else if f == 0.0 {
+        0.0
+    } else {
+        f
+    }
+}
+
+/// Like [`canonical_f64`], for `f32`.
+fn canonical_f32(f: f32) -> f32 {
+    if f.is_nan() {
+        f32::NAN
+    } else if f == 0.0 {
+        0.0
+    } else {
+        f
+    }
+}
+
+/// Writes `tag` and the up to 8 bytes of a scalar after it in a single write.
+fn write_tagged<W: Write + ?Sized>(buffer: &mut W, tag: u8, bytes: &[u8]) -> Result<()> {
+    let mut head = [0; 9];
+    head[0] = tag;
+    head[1..=bytes.len()].copy_from_slice(bytes);
+    Ok(buffer.write_all(&head[..=bytes.len()])?)
+}
+
+/// A writer that refuses writes past `left` more bytes, for
+/// [`SerializeOptions::max_output_bytes`].
+pub(crate) struct Limited<'w, W: ?Sized> {
+    inner: &'w mut W,
+    limit: usize,
+    left: usize,
+    exceeded: bool,
+}
+
+impl<'w, W: ?Sized> Limited<'w, W> {
+    pub(crate) fn new(inner: &'w mut W, limit: usize) -> Self {
+        Self {
+            inner,
+            limit,
+            left: limit,
+            exceeded: false,
+        }
+    }
+
+    /// Turns the failure of a write that hit the limit into [`Error::OutputTooLarge`].
+    pub(crate) fn check(&self, result: Result<()>) -> Result<()> {
+        match result {
+            Err(_) if self.exceeded => Err(Error::OutputTooLarge(self.limit).into()),
+            result => result,
+        }
+    }
+}
+
+impl<W: Write + ?Sized> Write for Limited<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.len() > self.left {
+            self.exceeded = true;
+            return Err(std::io::Error::other("output limit exceeded"));
+        }
+        let written = self.inner.write(buf)?;
+        self.left -= written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes a tag, then `bytes` prefixed with their length.
+fn write_bytes<W: Write + ?Sized>(buffer: &mut W, tag: u8, bytes: &[u8]) -> Result<()> {
+    write_len(buffer, tag, bytes.len())?;
+    Ok(buffer.write_all(bytes)?)
+}
+
 #[cfg(test)]
 mod tests {
+    use std::cmp::Ordering;
+
     use super::*;
 
     #[test]
@@ -535,6 +1137,12 @@ mod tests {
 
         assert_eq!(deserialized, value);
 
+        // Owned bytes are written the same way and read back borrowed.
+        let owned = Value::SliceLike(data.to_vec());
+        assert_eq!(owned.serialize()?, value.serialize()?);
+        assert_eq!(Value::deserialize_from(&owned.serialize()?)?, value);
+        assert_eq!(owned.as_bytes(), value.as_bytes());
+
         Ok(())
     }
 
@@ -572,6 +1180,23 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_empty() -> Result<()> {
+        let cases = [
+            (Value::Vector(vec![]), [tag::VECTOR, 0]),
+            (Value::HashMap(vec![]), [tag::MAP, 0]),
+            (Value::Slice(b""), [tag::SLICE, 0]),
+            (Value::Str(""), [tag::STR, 0]),
+        ];
+
+        for (value, encoded) in cases {
+            assert_eq!(value.serialize()?, encoded);
+            assert_eq!(Value::deserialize_from(&encoded)?, value);
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_boolean() -> Result<()> {
         let data = Value::Vector(vec![Value::Bool(true), Value::Bool(false)]);
@@ -588,7 +1213,7 @@ mod tests {
 
     #[test]
     fn test_float() -> Result<()> {
-        let data = Value::F64(-3.14159265358979363846);
+        let data = Value::F64(-std::f64::consts::PI);
 
         let mut buffer = SmallVec::<[u8; STACK_N]>::new();
         data.serialize_into(&mut buffer)?;
@@ -629,6 +1254,705 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_str() -> Result<()> {
+        let data = Value::Vector(vec![
+            Value::Str("hello"),
+            Value::StrLike("world".to_string()),
+        ]);
+
+        let mut buffer = SmallVec::<[u8; STACK_N]>::new();
+        data.serialize_into(&mut buffer)?;
+
+        let deserialized = Value::deserialize_from(&buffer)?;
+
+        assert_eq!(
+            deserialized,
+            Value::Vector(vec![Value::Str("hello"), Value::Str("world")])
+        );
+
+        let err = Value::deserialize_from(&[15, 1, 0xff]).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&Error::InvalidUtf8));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ext() -> Result<()> {
+        let data = Value::Vector(vec![
+            Value::Ext(
+                ext::BIG_INT,
+                Box::new(Value::Slice(&[0, 0, 0, 0, 0, 0, 0, 0, 1])),
+            ),
+            Value::Ext(200, Box::new(Value::I32(-7))),
+        ]);
+
+        let mut buffer = SmallVec::<[u8; STACK_N]>::new();
+        data.serialize_into(&mut buffer)?;
+
+        let deserialized = Value::deserialize_from(&buffer)?;
+
+        assert_eq!(deserialized, data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_depth() -> Result<()> {
+        let data = Value::Vector(vec![Value::Optional(Some(Box::new(Value::I64(1))))]);
+
+        let mut buffer = SmallVec::<[u8; STACK_N]>::new();
+        data.serialize_with(&SerializeOptions::new().max_depth(2), &mut buffer)?;
+
+        let err = data
+            .serialize_with(&SerializeOptions::new().max_depth(1), &mut SmallVec::new())
+            .unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&Error::DepthLimitExceeded(1)));
+
+        let deserialized =
+            Value::deserialize_with(&buffer, &DeserializeOptions::new().max_depth(2))?;
+        assert_eq!(deserialized, data);
+
+        let err =
+            Value::deserialize_with(&buffer, &DeserializeOptions::new().max_depth(1)).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&Error::DepthLimitExceeded(1)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_output_bytes() -> Result<()> {
+        let data = Value::Vector(vec![Value::from("abc"), Value::Slice(&[0; 100])]);
+        let len = data.serialize()?.len();
+
+        let mut buffer = SmallVec::<[u8; STACK_N]>::new();
+        buffer.push(0xff);
+        data.serialize_with(&SerializeOptions::new().max_output_bytes(len), &mut buffer)?;
+        assert_eq!(buffer.len(), 1 + len);
+
+        // Failing leaves the buffer as it was.
+        buffer.truncate(1);
+        let options = SerializeOptions::new().max_output_bytes(len - 1);
+        let err = data.serialize_with(&options, &mut buffer).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&Error::OutputTooLarge(len - 1)));
+        assert_eq!(buffer.as_slice(), [0xff]);
+
+        // Writers never get more than the limit.
+        let mut written = vec![];
+        let options = SerializeOptions::new().max_output_bytes(10);
+        assert!(data.serialize_to(&options, &mut written).is_err());
+        assert!(written.len() <= 10);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_str_len() -> Result<()> {
+        let options = DeserializeOptions::new().max_str_len(3);
+        let data = Value::Vector(vec![Value::from("abc"), Value::Slice(b"xyz")]);
+        assert_eq!(Value::deserialize_with(&data.serialize()?, &options)?, data);
+
+        // A string declaring 1 GiB over a few bytes fails on its length alone.
+        let mut huge = vec![tag::STR];
+        varint::write(&mut huge, 1 << 30);
+        huge.extend_from_slice(b"abcd");
+        let cases: &[&[u8]] = &[&huge, &[tag::SLICE, 4, 1, 2, 3, 4], &[1, 4, b's', 1, 2, 3]];
+        for bytes in cases {
+            let err = Value::deserialize_with(bytes, &options).unwrap_err();
+            assert_eq!(
+                err.downcast_ref(),
+                Some(&Error::StringTooLong(3)),
+                "decoding {bytes:?}"
+            );
+        }
+        let err = Value::deserialize_from(&huge).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&Error::UnexpectedEnd));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deep_nesting() -> Result<()> {
+        const DEPTH: usize = 20_000;
+
+        let mut data = Value::SmallU8(1);
+        for _ in 0..DEPTH {
+            data = Value::HashMap(vec![(Value::Str("k"), data)]);
+        }
+
+        let serialized = data.serialize()?;
+        assert_eq!(serialized.len(), DEPTH * 5 + 1);
+        assert_eq!(data.clone().serialize()?, serialized);
+        assert_eq!(data.clone().into_owned().serialize()?, serialized);
+
+        let mut value = &Value::deserialize_from(&serialized)?;
+        for _ in 0..DEPTH {
+            let Value::HashMap(pairs) = value else {
+                panic!("expected a map, got {value:?}");
+            };
+            assert_eq!(pairs.len(), 1);
+            assert_eq!(pairs[0].0, Value::Str("k"));
+            value = &pairs[0].1;
+        }
+        assert_eq!(*value, Value::SmallU8(1));
+
+        // Compared without recursing, however deep.
+        let nest = |leaf| {
+            let mut value = leaf;
+            for _ in 0..DEPTH {
+                value = Value::HashMap(vec![(Value::StrLike("k".to_owned()), value)]);
+            }
+            value
+        };
+        assert!(data.logical_eq(&nest(Value::I64(1))));
+        assert!(!data.logical_eq(&nest(Value::I64(2))));
+        assert!(Value::logical_eq_encoded(
+            &serialized,
+            &nest(Value::I32(1)).serialize()?,
+            &DeserializeOptions::new()
+        )?);
+        assert_eq!(data.cmp_structural(&data.clone()), Ordering::Equal);
+        assert_eq!(data.cmp_structural(&nest(Value::I64(1))), Ordering::Less);
+        assert_eq!(
+            nest(Value::I64(2)).cmp_structural(&nest(Value::I64(1))),
+            Ordering::Greater
+        );
+
+        // Canonical output sorts map keys with it, so deep keys are fine too.
+        let keys = Value::HashMap(vec![
+            (data.clone(), Value::SmallU8(1)),
+            (nest(Value::I64(0)), Value::SmallU8(2)),
+        ]);
+        keys.serialize_with(
+            &SerializeOptions::new().canonical(true),
+            &mut SmallVec::<[u8; STACK_N]>::new(),
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_owned() -> Result<()> {
+        let original = Value::HashMap(vec![
+            (Value::Str("name"), Value::Slice(b"\x00\x01")),
+            (
+                Value::StrLike("items".to_owned()),
+                Value::Vector(vec![
+                    Value::Optional(Some(Box::new(Value::Str("x")))),
+                    Value::Ext(ext::BIG_INT, Box::new(Value::Slice(b"\xff"))),
+                    Value::I64(-1),
+                ]),
+            ),
+        ]);
+
+        let buffer = original.serialize()?;
+        let owned: Value<'static> = Value::deserialize_from(&buffer)?.into_owned();
+        drop(buffer);
+
+        assert_eq!(owned.serialize()?, original.serialize()?);
+        let Value::HashMap(pairs) = &owned else {
+            panic!("expected a map, got {owned:?}");
+        };
+        assert_eq!(pairs[0].0, Value::StrLike("name".to_owned()));
+        assert_eq!(pairs[0].1, Value::SliceLike(vec![0, 1]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_logical_eq() -> Result<()> {
+        let options = DeserializeOptions::new();
+        let row = |id: Value<'static>, score| {
+            Value::HashMap(vec![(Value::from("id"), id), (Value::from("score"), score)])
+        };
+        let narrow = Value::Vector(vec![
+            row(Value::SmallU8(5), Value::F32(0.5)),
+            Value::Ext(
+                ext::SET,
+                Box::new(Value::Vector(vec![Value::U8(1), Value::F64(f64::NAN)])),
+            ),
+        ]);
+        let wide = Value::Vector(vec![
+            Value::HashMap(vec![
+                (Value::from("score"), Value::F64(0.5)),
+                (Value::StrLike("id".to_owned()), Value::I64(5)),
+            ]),
+            Value::Ext(
+                ext::SET,
+                Box::new(Value::Vector(vec![Value::F64(-f64::NAN), Value::I32(1)])),
+            ),
+        ]);
+        assert_ne!(narrow.serialize()?, wide.serialize()?);
+        assert!(narrow.logical_eq(&wide));
+        assert!(Value::logical_eq_encoded(
+            &narrow.serialize()?,
+            &wide.serialize()?,
+            &options
+        )?);
+
+        let unequal = [
+            (Value::I64(1), Value::F64(1.0)),
+            (Value::from("a"), Value::Slice(b"a")),
+            (
+                Value::Vector(vec![Value::I64(1), Value::I64(2)]),
+                Value::Vector(vec![Value::I64(2), Value::I64(1)]),
+            ),
+            (
+                row(Value::I64(1), Value::F64(0.0)),
+                row(Value::I64(1), Value::Optional(None)),
+            ),
+        ];
+        for (a, b) in &unequal {
+            assert!(!a.logical_eq(b), "{a:?} == {b:?}");
+            assert!(!Value::logical_eq_encoded(
+                &a.serialize()?,
+                &b.serialize()?,
+                &options
+            )?);
+        }
+
+        assert!(Value::Optional(Some(Box::new(Value::I32(3)))).logical_eq(&Value::SmallU8(3)));
+        assert!(Value::F64(-0.0).logical_eq(&Value::F32(0.0)));
+
+        // Packed vectors equal what they stand for.
+        let ones = Value::Vector(vec![Value::SmallU8(1); 5]);
+        let runs = |runs| Value::Ext(ext::RUNS, Box::new(Value::Vector(runs)));
+        let split = runs(vec![
+            Value::I64(1),
+            Value::SmallU8(2),
+            Value::U8(1),
+            Value::I32(3),
+        ]);
+        assert!(ones.logical_eq(&split));
+        assert!(split.logical_eq(&runs(vec![Value::SmallU8(1), Value::SmallU8(5)])));
+        assert!(!ones.logical_eq(&runs(vec![Value::SmallU8(1), Value::SmallU8(4)])));
+        let bools = Value::Vector(vec![Value::Bool(true), Value::Bool(false)]);
+        let bits = Value::Ext(ext::BITS, Box::new(Value::Slice(&[6, 0b01])));
+        assert!(bits.logical_eq(&bools));
+        assert!(!bits.logical_eq(&Value::Vector(vec![Value::Bool(true)])));
+        assert!(Value::logical_eq_encoded(
+            &bools.serialize()?,
+            &bits.serialize()?,
+            &options
+        )?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_interceptor() -> Result<()> {
+        let write = |value: &Value<'_>, options: &SerializeOptions| -> Result<Vec<u8>> {
+            let mut buffer = SmallVec::<[u8; STACK_N]>::new();
+            value.serialize_with(options, &mut buffer)?;
+            Ok(buffer.to_vec())
+        };
+        let user = |token: Value<'static>| {
+            Value::HashMap(vec![
+                (Value::from("token"), token.clone()),
+                (
+                    Value::from("auth"),
+                    Value::Optional(Some(Box::new(Value::HashMap(vec![
+                        (Value::Slice(b"token"), token),
+                        (Value::from("kind"), Value::from("bearer")),
+                    ])))),
+                ),
+            ])
+        };
+        let users = Value::Vector(vec![user(Value::from("a")), user(Value::from("b"))]);
+
+        let options = SerializeOptions::new().interceptor(RedactKeys::new(["token"]));
+        let redacted = Value::HashMap(vec![(
+            Value::from("auth"),
+            Value::Optional(Some(Box::new(Value::HashMap(vec![(
+                Value::from("kind"),
+                Value::from("bearer"),
+            )])))),
+        )]);
+        let expected = Value::Vector(vec![redacted.clone(), redacted]);
+        assert_eq!(write(&users, &options)?, expected.serialize()?);
+
+        let hidden = Value::from("***");
+        let options = SerializeOptions::new()
+            .canonical(true)
+            .interceptor(RedactKeys::new(["token"]).replacement(hidden.clone()));
+        let expected = Value::Vector(vec![user(hidden.clone()), user(hidden)]);
+        assert_eq!(
+            write(&users, &options)?,
+            write(&expected, &SerializeOptions::new().canonical(true))?
+        );
+
+        /// Leaves out odd items of the top-level vector, and replaces the
+        /// value being written if it's a string.
+        struct EvenItems;
+        impl SerializeInterceptor for EvenItems {
+            fn on_value(&self, path: &[PathSegment<'_>], value: &Value<'_>) -> Action {
+                match (path, value) {
+                    ([PathSegment::Index(i)], _) if i % 2 == 1 => Action::Skip,
+                    ([], Value::Str(_)) => Action::Skip,
+                    _ => Action::Keep,
+                }
+            }
+        }
+        let options = SerializeOptions::new().interceptor(EvenItems);
+        let items = Value::Vector((0..5).map(Value::I64).collect());
+        let expected = Value::Vector(vec![Value::I64(0), Value::I64(2), Value::I64(4)]);
+        assert_eq!(write(&items, &options)?, expected.serialize()?);
+        assert_eq!(
+            write(&Value::from("a"), &options)?,
+            Value::Optional(None).serialize()?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_encoded() -> Result<()> {
+        let value = Value::Vector(vec![
+            Value::HashMap(vec![(Value::from("a"), Value::Slice(b"bytes"))]),
+            Value::Ext(ext::SET, Box::new(Value::Vector(vec![Value::F32(1.5)]))),
+            Value::Optional(Some(Box::new(Value::SmallU8(3)))),
+        ]);
+        let bytes = value.serialize()?;
+        Value::validate_encoded(&bytes)?;
+
+        for len in 0..bytes.len() {
+            assert!(Value::validate_encoded(&bytes[..len]).is_err());
+        }
+        let mut trailing = bytes.to_vec();
+        trailing.push(0);
+        let err = Value::validate_encoded(&trailing).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&Error::TrailingBytes(1)));
+
+        // Skipping strings doesn't read them, but checking does.
+        let err = Value::validate_encoded(&[19, 1, 0xff]).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&Error::InvalidUtf8));
+        let err = Value::validate_encoded(&[16, 1, 5]).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&Error::UnknownTag(5)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_shape_fingerprint() -> Result<()> {
+        let record = |id: Value<'static>, tags: Vec<Value<'static>>, score| {
+            Value::HashMap(vec![
+                (Value::from("id"), id),
+                (Value::from("tags"), Value::Vector(tags)),
+                (Value::from("score"), Value::F64(score)),
+            ])
+        };
+        let shape = record(Value::I64(1), vec![Value::from("a")], 0.5).shape_fingerprint()?;
+
+        // Widths, lengths and the order of entries don't matter.
+        let same = [
+            record(Value::SmallU8(7), vec![Value::from("x")], 2.0),
+            record(
+                Value::I32(-9),
+                vec![Value::from("b"), Value::from("c")],
+                1.0,
+            ),
+            Value::HashMap(vec![
+                (Value::from("score"), Value::F32(1.5)),
+                (Value::from("tags"), Value::Vector(vec![Value::from("d")])),
+                (
+                    Value::from("id"),
+                    Value::Ext(ext::BIG_INT, Box::new(Value::Slice(&[1; 9]))),
+                ),
+            ]),
+        ];
+        for value in same {
+            assert_eq!(value.shape_fingerprint()?, shape);
+        }
+
+        let different = [
+            record(Value::from("1"), vec![Value::from("a")], 0.5),
+            record(Value::I64(1), vec![Value::I64(1)], 0.5),
+            record(Value::I64(1), vec![Value::from("a"), Value::I64(1)], 0.5),
+            Value::HashMap(vec![
+                (Value::from("id"), Value::I64(1)),
+                (Value::from("tags"), Value::Vector(vec![])),
+            ]),
+        ];
+        for value in different {
+            assert_ne!(value.shape_fingerprint()?, shape);
+        }
+
+        // Bits and runs are shaped like the vectors they stand for.
+        let bools = Value::Vector(vec![Value::Bool(true), Value::Bool(false)]);
+        let bits = Value::Ext(ext::BITS, Box::new(Value::Slice(&[6, 1])));
+        assert_eq!(bits.shape_fingerprint()?, bools.shape_fingerprint()?);
+        let runs = Value::Ext(
+            ext::RUNS,
+            Box::new(Value::Vector(vec![Value::Bool(true), Value::SmallU8(5)])),
+        );
+        assert_eq!(runs.shape_fingerprint()?, bools.shape_fingerprint()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed() {
+        let cases: &[(&[u8], Error)] = &[
+            (&[], Error::EmptyInput),
+            (&[0, 1, 2], Error::UnexpectedEnd),
+            (&[16, 2, 20], Error::UnexpectedEnd),
+            (&[18, 0xff, 0xff, 0xff, 0xff, 0x0f], Error::UnexpectedEnd),
+            (
+                &[
+                    18, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f,
+                ],
+                Error::InvalidLength,
+            ),
+            (&[5], Error::UnknownTag(5)),
+            (&[19, 1, 0xff], Error::InvalidUtf8),
+        ];
+
+        for (bytes, expected) in cases {
+            let err = Value::deserialize_from(bytes).unwrap_err();
+            assert_eq!(err.downcast_ref(), Some(expected), "decoding {bytes:?}");
+        }
+
+        let err = Value::read_vector_head(&[]).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&Error::EmptyInput));
+    }
+
+    #[test]
+    fn test_default_options() -> Result<()> {
+        let data = Value::HashMap(vec![(
+            Value::Slice(b"k"),
+            Value::Vector(vec![Value::Bool(true)]),
+        )]);
+
+        let mut plain = SmallVec::<[u8; STACK_N]>::new();
+        data.serialize_into(&mut plain)?;
+
+        let mut with = SmallVec::<[u8; STACK_N]>::new();
+        data.serialize_with(&SerializeOptions::new(), &mut with)?;
+
+        assert_eq!(plain, with);
+        assert_eq!(
+            Value::deserialize_with(&with, &DeserializeOptions::new())?,
+            Value::deserialize_from(&plain)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmp_structural() {
+        let mut values = vec![
+            Value::HashMap(vec![]),
+            Value::Vector(vec![Value::SmallU8(1), Value::SmallU8(2)]),
+            Value::Vector(vec![Value::SmallU8(1)]),
+            Value::from("b"),
+            Value::Slice(b"b"),
+            Value::from("a"),
+            Value::F64(f64::INFINITY),
+            Value::F64(-1.5),
+            Value::F32(-1.5),
+            Value::I64(300),
+            Value::Optional(Some(Box::new(Value::I32(7)))),
+            Value::I32(7),
+            Value::SmallU8(7),
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Optional(None),
+            Value::Ext(ext::FRACTION, Box::new(Value::Vector(vec![]))),
+        ];
+        values.sort_by(Value::cmp_structural);
+
+        assert_eq!(
+            values,
+            vec![
+                Value::Optional(None),
+                Value::Bool(false),
+                Value::Bool(true),
+                Value::SmallU8(7),
+                Value::I32(7),
+                Value::Optional(Some(Box::new(Value::I32(7)))),
+                Value::I64(300),
+                Value::F32(-1.5),
+                Value::F64(-1.5),
+                Value::F64(f64::INFINITY),
+                Value::from("a"),
+                Value::Slice(b"b"),
+                Value::from("b"),
+                Value::Vector(vec![Value::SmallU8(1)]),
+                Value::Vector(vec![Value::SmallU8(1), Value::SmallU8(2)]),
+                Value::HashMap(vec![]),
+                Value::Ext(ext::FRACTION, Box::new(Value::Vector(vec![]))),
+            ]
+        );
+
+        assert_eq!(
+            Value::Slice(b"x").cmp_structural(&Value::SliceLike(b"x".to_vec())),
+            Ordering::Equal
+        );
+        assert_eq!(
+            Value::F64(f64::NAN).cmp_structural(&Value::F64(f64::INFINITY)),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn test_sort_vector_by_key() {
+        let row = |id: Value<'static>, tag: &'static str| {
+            Value::HashMap(vec![
+                (Value::from("tag"), Value::from(tag)),
+                (Value::from("id"), id),
+            ])
+        };
+
+        let mut rows = Value::Vector(vec![
+            Value::SmallU8(0),
+            row(Value::F64(0.5), "float"),
+            Value::HashMap(vec![(Value::from("tag"), Value::from("no id"))]),
+            row(Value::SmallU8(2), "first 2"),
+            row(Value::Bool(true), "bool"),
+            row(Value::I64(2), "second 2"),
+        ]);
+        assert!(rows.sort_vector_by_key("id"));
+
+        assert_eq!(
+            rows,
+            Value::Vector(vec![
+                row(Value::Bool(true), "bool"),
+                row(Value::SmallU8(2), "first 2"),
+                row(Value::I64(2), "second 2"),
+                row(Value::F64(0.5), "float"),
+                Value::SmallU8(0),
+                Value::HashMap(vec![(Value::from("tag"), Value::from("no id"))]),
+            ])
+        );
+
+        let mut numbers = Value::Vector(vec![
+            Value::SmallU8(1),
+            Value::SmallU8(3),
+            Value::SmallU8(2),
+        ]);
+        assert!(numbers.sort_vector_by(|a, b| b.cmp_structural(a)));
+        assert_eq!(
+            numbers,
+            Value::Vector(vec![
+                Value::SmallU8(3),
+                Value::SmallU8(2),
+                Value::SmallU8(1)
+            ])
+        );
+
+        let mut not_a_vector = Value::SmallU8(1);
+        assert!(!not_a_vector.sort_vector_by_key("id"));
+        assert_eq!(not_a_vector, Value::SmallU8(1));
+    }
+
+    #[test]
+    fn test_canonical() -> Result<()> {
+        let a = Value::HashMap(vec![
+            (Value::from("zeta"), Value::SmallU8(1)),
+            (Value::from("alpha"), Value::SmallU8(2)),
+        ]);
+        let b = Value::HashMap(vec![
+            (Value::from("alpha"), Value::SmallU8(2)),
+            (Value::from("zeta"), Value::SmallU8(1)),
+        ]);
+        let options = SerializeOptions::new().canonical(true);
+
+        let mut x = SmallVec::<[u8; STACK_N]>::new();
+        let mut y = SmallVec::<[u8; STACK_N]>::new();
+        a.serialize_with(&options, &mut x)?;
+        b.serialize_with(&options, &mut y)?;
+
+        assert_eq!(x, y);
+        assert_eq!(Value::deserialize_from(&x)?, b);
+        assert_ne!(a.serialize()?, b.serialize()?);
+
+        let float = |f: f64| -> Result<SmallVec<[u8; STACK_N]>> {
+            let mut buffer = SmallVec::new();
+            Value::F64(f).serialize_with(&options, &mut buffer)?;
+            Ok(buffer)
+        };
+        assert_eq!(float(-0.0)?, float(0.0)?);
+        assert_eq!(
+            float(-f64::NAN)?,
+            float(f64::from_bits(0x7ff8_0000_0000_0001))?
+        );
+        assert_ne!(float(1.0)?, float(-1.0)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keys() -> Result<()> {
+        let row = |id: u8, status: &'static str| {
+            Value::HashMap(vec![
+                (Value::from("id"), Value::SmallU8(id)),
+                (Value::from("name"), Value::from("row")),
+                (
+                    Value::from("status"),
+                    Value::HashMap(vec![
+                        (Value::from("id"), Value::from(status)),
+                        (Value::from("name"), Value::from("kept")),
+                    ]),
+                ),
+            ])
+        };
+        let projected = |id: u8, status: &'static str| {
+            Value::HashMap(vec![
+                (Value::from("id"), Value::SmallU8(id)),
+                (
+                    Value::from("status"),
+                    Value::HashMap(vec![
+                        (Value::from("id"), Value::from(status)),
+                        (Value::from("name"), Value::from("kept")),
+                    ]),
+                ),
+            ])
+        };
+
+        let data = Value::Vector(vec![
+            row(1, "open"),
+            Value::Vector(vec![row(2, "done")]),
+            Value::SmallU8(7),
+        ]);
+        let mut buffer = SmallVec::<[u8; STACK_N]>::new();
+        data.serialize_into(&mut buffer)?;
+
+        let options = DeserializeOptions::new().keys(["id", "status", "missing"]);
+        assert_eq!(
+            Value::deserialize_with(&buffer, &options)?,
+            Value::Vector(vec![
+                projected(1, "open"),
+                Value::Vector(vec![projected(2, "done")]),
+                Value::SmallU8(7),
+            ])
+        );
+
+        let options = DeserializeOptions::new().keys(Vec::<String>::new());
+        assert_eq!(
+            Value::deserialize_with(&buffer, &options)?,
+            Value::Vector(vec![
+                Value::HashMap(vec![]),
+                Value::Vector(vec![Value::HashMap(vec![])]),
+                Value::SmallU8(7),
+            ])
+        );
+
+        // Runs stand for a vector, so their items are projected too.
+        let runs = Value::Ext(
+            ext::RUNS,
+            Box::new(Value::Vector(vec![row(1, "open"), Value::SmallU8(3)])),
+        );
+        let options = DeserializeOptions::new().keys(["id"]);
+        assert_eq!(
+            Value::deserialize_with(&runs.serialize()?, &options)?,
+            Value::Ext(
+                ext::RUNS,
+                Box::new(Value::Vector(vec![
+                    Value::HashMap(vec![(Value::from("id"), Value::SmallU8(1))]),
+                    Value::SmallU8(3),
+                ])),
+            )
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_from() -> Result<()> {
         let a = 123_i64;
@@ -639,6 +1963,20 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_from_str() -> Result<()> {
+        assert_eq!(Value::from("hello"), Value::Str("hello"));
+        assert_eq!(
+            Value::from("hello").serialize()?,
+            Value::from("hello".to_string()).serialize()?
+        );
+
+        let b: String = deserialize(&serialize("hello")?)?;
+        assert_eq!(b, "hello");
+
+        Ok(())
+    }
+
     #[test]
     fn test_serde() -> Result<()> {
         let a = vec![123_i64];
@@ -0,0 +1,220 @@
+use std::{fmt, sync::Arc};
+
+use crate::{Error, Result, SerializeInterceptor, Value};
+
+/// Options for [`Value::serialize_with`](crate::Value::serialize_with).
+///
+/// # Example
+/// ```rust
+/// use lize::{SerializeOptions, SmallVec, Value, STACK_N};
+///
+/// let options = SerializeOptions::new().max_depth(8);
+///
+/// let mut buffer = SmallVec::<[u8; STACK_N]>::new();
+/// Value::I64(1).serialize_with(&options, &mut buffer)?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Clone, Default)]
+pub struct SerializeOptions {
+    /// The maximum nesting of containers. `None` means unlimited.
+    pub max_depth: Option<usize>,
+    /// Write map entries sorted by key, `-0.0` as `0.0` and every NaN alike, so
+    /// equal values always encode to the same bytes.
+    pub canonical: bool,
+    /// The most bytes to write. `None` means unlimited.
+    pub max_output_bytes: Option<usize>,
+    /// Run [`Value::validate`] before writing anything.
+    pub validate: bool,
+    /// Run [`Value::validate_keys`] before writing anything.
+    pub check_duplicate_keys: bool,
+    /// Called as the value is written, to leave out or replace parts of it.
+    pub interceptor: Option<Arc<dyn SerializeInterceptor>>,
+}
+
+impl fmt::Debug for SerializeOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SerializeOptions")
+            .field("max_depth", &self.max_depth)
+            .field("canonical", &self.canonical)
+            .field("max_output_bytes", &self.max_output_bytes)
+            .field("validate", &self.validate)
+            .field("check_duplicate_keys", &self.check_duplicate_keys)
+            .field("interceptor", &self.interceptor.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+/// Interceptors are equal only if they're the same one.
+impl PartialEq for SerializeOptions {
+    fn eq(&self, other: &Self) -> bool {
+        self.max_depth == other.max_depth
+            && self.canonical == other.canonical
+            && self.max_output_bytes == other.max_output_bytes
+            && self.validate == other.validate
+            && self.check_duplicate_keys == other.check_duplicate_keys
+            && match (&self.interceptor, &other.interceptor) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (a, b) => a.is_none() && b.is_none(),
+            }
+    }
+}
+
+impl Eq for SerializeOptions {}
+
+impl SerializeOptions {
+    /// Creates the default options, same as [`Value::serialize_into`](crate::Value::serialize_into).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fails with [`Error::DepthLimitExceeded`] for values nested deeper than `depth`.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Sorts map entries by [`Value::cmp_structural`] on their keys, so maps with
+    /// the same entries encode to the same bytes regardless of insertion order.
+    /// Floats are normalized too: `-0.0` is written as `0.0`, and every NaN as
+    /// the same quiet NaN.
+    pub fn canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// Fails with [`Error::OutputTooLarge`] as soon as the encoding would take
+    /// more than `len` bytes, without writing past them.
+    pub fn max_output_bytes(mut self, len: usize) -> Self {
+        self.max_output_bytes = Some(len);
+        self
+    }
+
+    /// Checks the value with [`Value::validate`] before writing it, e.g.
+    /// `.validate(cfg!(debug_assertions))` to catch malformed values in debug
+    /// builds only. This walks the whole value, and sorts the keys of every map.
+    pub fn validate(mut self, validate: bool) -> Self {
+        self.validate = validate;
+        self
+    }
+
+    /// Checks the value with [`Value::validate_keys`] before writing it, so a
+    /// map with two equal keys fails instead of silently losing one of them
+    /// when it's decoded into a dict. Implied by [`SerializeOptions::validate`].
+    pub fn check_duplicate_keys(mut self, check: bool) -> Self {
+        self.check_duplicate_keys = check;
+        self
+    }
+
+    /// Writes the value through `interceptor`, which can leave out or replace
+    /// any part of it, such as the entries [`RedactKeys`](crate::RedactKeys)
+    /// matches.
+    pub fn interceptor(mut self, interceptor: impl SerializeInterceptor + 'static) -> Self {
+        self.interceptor = Some(Arc::new(interceptor));
+        self
+    }
+
+    pub(crate) fn check_depth(&self, depth: usize) -> Result<()> {
+        check_depth(self.max_depth, depth)
+    }
+}
+
+/// Options for [`Value::deserialize_with`](crate::Value::deserialize_with).
+///
+/// # Example
+/// ```rust
+/// use lize::{DeserializeOptions, Value};
+///
+/// let options = DeserializeOptions::new().max_depth(8);
+/// let value = Value::deserialize_with(&[0, 1, 0, 0, 0, 0, 0, 0, 0], &options)?;
+///
+/// assert_eq!(value, Value::I64(1));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeserializeOptions {
+    /// The maximum nesting of containers. `None` means unlimited.
+    pub max_depth: Option<usize>,
+    /// Only keep map entries with these keys. `None` keeps every entry.
+    pub keys: Option<Vec<String>>,
+    /// The longest slice or string allowed, in bytes. `None` means unlimited.
+    pub max_str_len: Option<usize>,
+}
+
+impl DeserializeOptions {
+    /// Creates the default options, same as [`Value::deserialize_from`](crate::Value::deserialize_from).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fails with [`Error::DepthLimitExceeded`] for values nested deeper than `depth`.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Fails with [`Error::StringTooLong`] for slices and strings longer than
+    /// `len` bytes, checked against their length prefix before reading them.
+    /// Skipped map entries aren't checked.
+    pub fn max_str_len(mut self, len: usize) -> Self {
+        self.max_str_len = Some(len);
+        self
+    }
+
+    /// Only decodes map entries whose key is one of `keys`, skipping the rest.
+    ///
+    /// The selection applies to the top-level map, or to every map in a
+    /// top-level vector (recursively), but never inside a kept value. Keys
+    /// match `Str` and `Slice` keys with the same bytes.
+    ///
+    /// ```rust
+    /// use lize::{DeserializeOptions, Value};
+    ///
+    /// let row = Value::HashMap(vec![
+    ///     (Value::from("id"), Value::SmallU8(1)),
+    ///     (Value::from("name"), Value::from("lize")),
+    /// ]);
+    /// let bytes = row.serialize()?;
+    ///
+    /// let options = DeserializeOptions::new().keys(["id"]);
+    /// let value = Value::deserialize_with(&bytes, &options)?;
+    ///
+    /// assert_eq!(value, Value::HashMap(vec![(Value::from("id"), Value::SmallU8(1))]));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn keys<I, S>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.keys = Some(keys.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub(crate) fn check_depth(&self, depth: usize) -> Result<()> {
+        check_depth(self.max_depth, depth)
+    }
+
+    pub(crate) fn is_selected(&self, key: &Value) -> bool {
+        let Some(keys) = &self.keys else {
+            return true;
+        };
+        let Some(bytes) = key.key_bytes() else {
+            return false;
+        };
+        keys.iter().any(|k| k.as_bytes() == bytes)
+    }
+}
+
+fn check_depth(max_depth: Option<usize>, depth: usize) -> Result<()> {
+    match max_depth {
+        Some(limit) if depth > limit => Err(Error::DepthLimitExceeded(limit).into()),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn check_str_len(max_str_len: Option<usize>, len: usize) -> Result<()> {
+    match max_str_len {
+        Some(limit) if len > limit => Err(Error::StringTooLong(limit).into()),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,439 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use crate::{canonical_f64, ext, packed, DeserializeOptions, Result, Value};
+
+impl<'a> Value<'a> {
+    /// A total order over all values, used to sort vectors and, in
+    /// [`canonical`](crate::SerializeOptions::canonical) mode, map keys.
+    ///
+    /// Kinds are ordered `None < bools < ints < floats < slices < vectors < maps < Ext`.
+    /// Within a kind:
+    ///
+    /// - ints compare by value, then by width (`SmallU8 < U8 < I32 < I64`);
+    /// - floats compare by [`f64::total_cmp`], then by width (`F32 < F64`), so
+    ///   NaNs sort after every other float, or before them if their sign bit is set;
+    /// - slices compare bytewise, then raw bytes before strings;
+    /// - vectors and maps compare their items (map entries key first) lexicographically;
+    /// - `Ext` values compare by code, then by payload.
+    ///
+    /// `Optional(Some(x))` sorts right after `x`. Values that encode to the same
+    /// bytes, such as `Slice` and `SliceLike`, compare equal.
+    ///
+    /// ```rust
+    /// use std::{cmp::Ordering, collections::HashMap};
+    /// use lize::Value;
+    ///
+    /// assert_eq!(Value::Bool(true).cmp_structural(&Value::I64(0)), Ordering::Less);
+    /// assert_eq!(Value::SmallU8(9).cmp_structural(&Value::I32(10)), Ordering::Less);
+    /// assert_eq!(Value::from("b").cmp_structural(&Value::from("ab")), Ordering::Greater);
+    /// assert_eq!(Value::F64(f64::NAN).cmp_structural(&Value::F64(f64::INFINITY)), Ordering::Greater);
+    /// ```
+    pub fn cmp_structural(&self, other: &Value) -> Ordering {
+        // Pending comparisons, the next one last, so nesting is only limited
+        // by memory. The first that isn't equal decides.
+        let mut steps = vec![Compare::Values(self, other)];
+
+        while let Some(step) = steps.pop() {
+            let order = match step {
+                Compare::Then(order) => order,
+                Compare::Values(a, b) => {
+                    let (a, a_wrapped) = a.unwrap_optionals();
+                    let (b, b_wrapped) = b.unwrap_optionals();
+                    let kind = a.kind().cmp(&b.kind());
+                    let wrapped = a_wrapped.cmp(&b_wrapped);
+                    if kind.is_ne() {
+                        return kind;
+                    }
+
+                    match (a, b) {
+                        // Items (map entries key first) in order, then the
+                        // lengths, then how deep in `Some` each is.
+                        (Value::Vector(x), Value::Vector(y)) => {
+                            steps.push(Compare::Then(wrapped));
+                            steps.push(Compare::Then(x.len().cmp(&y.len())));
+                            steps.extend(x.iter().zip(y).rev().map(|(x, y)| Compare::Values(x, y)));
+                        }
+                        (Value::HashMap(x), Value::HashMap(y)) => {
+                            steps.push(Compare::Then(wrapped));
+                            steps.push(Compare::Then(x.len().cmp(&y.len())));
+                            steps.extend(x.iter().zip(y).rev().flat_map(|((k1, v1), (k2, v2))| {
+                                [Compare::Values(v1, v2), Compare::Values(k1, k2)]
+                            }));
+                        }
+                        (Value::Ext(c1, x), Value::Ext(c2, y)) => {
+                            steps.push(Compare::Then(wrapped));
+                            steps.push(Compare::Values(x, y));
+                            steps.push(Compare::Then(c1.cmp(c2)));
+                        }
+                        _ => {
+                            let order = match (a, b) {
+                                (Value::Bool(x), Value::Bool(y)) => x.cmp(y),
+                                _ => match (a.int(), b.int(), a.float(), b.float()) {
+                                    (Some(x), Some(y), ..) => x.cmp(&y),
+                                    (.., Some(x), Some(y)) => x.total_cmp(&y),
+                                    _ => a.bytes().cmp(b.bytes()),
+                                },
+                            };
+                            steps.push(Compare::Then(
+                                order.then_with(|| a.width().cmp(&b.width())).then(wrapped),
+                            ));
+                        }
+                    }
+                    continue;
+                }
+            };
+            if order.is_ne() {
+                return order;
+            }
+        }
+        Ordering::Equal
+    }
+
+    /// Whether two values mean the same, whatever widths and orders they were
+    /// written in. Where `==` compares variants:
+    ///
+    /// - ints are equal if their values are, whatever their widths, and so are
+    ///   `F32` and `F64`, though an int never equals a float;
+    /// - `-0.0` equals `0.0`, and every NaN equals every other NaN;
+    /// - maps are equal if they hold the same entries in any order, and so are
+    ///   [sets](ext::SET) with the same items;
+    /// - [runs](ext::RUNS) and [packed booleans](ext::BITS) are expanded, so
+    ///   they equal the vectors they stand for, however those were written;
+    /// - `Optional(Some(x))` equals `x`, which is how readers see it;
+    /// - borrowed and owned slices or strings are equal, though a slice never
+    ///   equals a string.
+    ///
+    /// ```rust
+    /// use lize::Value;
+    ///
+    /// assert!(Value::SmallU8(5).logical_eq(&Value::I64(5)));
+    /// assert!(Value::F64(f64::NAN).logical_eq(&Value::F32(-f32::NAN)));
+    /// assert!(!Value::I64(1).logical_eq(&Value::F64(1.0)));
+    ///
+    /// let a = Value::HashMap(vec![(Value::from("a"), Value::U8(1)), (Value::from("b"), Value::I32(2))]);
+    /// let b = Value::HashMap(vec![(Value::from("b"), Value::I64(2)), (Value::from("a"), Value::SmallU8(1))]);
+    /// assert!(a.logical_eq(&b));
+    /// ```
+    pub fn logical_eq(&self, other: &Value) -> bool {
+        let mut classes = Classes::default();
+        classes.of(self) == classes.of(other)
+    }
+
+    /// Whether the values encoded in `a` and `b` are equal by
+    /// [`Value::logical_eq`].
+    ///
+    /// Top-level vectors are compared an item at a time, so only one item of
+    /// each is decoded at once. Other values are decoded whole, without
+    /// copying their strings.
+    pub fn logical_eq_encoded(a: &[u8], b: &[u8], options: &DeserializeOptions) -> Result<bool> {
+        if let (Some((a_len, mut a)), Some((b_len, mut b))) =
+            (Value::read_vector_head(a)?, Value::read_vector_head(b)?)
+        {
+            if a_len != b_len {
+                return Ok(false);
+            }
+            for _ in 0..a_len {
+                let (x, a_rest) = Value::deserialize_first(a, options)?;
+                let (y, b_rest) = Value::deserialize_first(b, options)?;
+                if !x.logical_eq(&y) {
+                    return Ok(false);
+                }
+                (a, b) = (a_rest, b_rest);
+            }
+            return Ok(true);
+        }
+
+        let a = Value::deserialize_with(a, options)?;
+        let b = Value::deserialize_with(b, options)?;
+        Ok(a.logical_eq(&b))
+    }
+
+    /// Sorts the items of a vector with `compare`, keeping equal items in order.
+    ///
+    /// Returns `false`, leaving `self` untouched, if it isn't a [`Value::Vector`].
+    pub fn sort_vector_by<F>(&mut self, compare: F) -> bool
+    where
+        F: FnMut(&Value<'a>, &Value<'a>) -> Ordering,
+    {
+        match self {
+            Value::Vector(items) => {
+                items.sort_by(compare);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Sorts a vector of maps by the value under `key`, using [`Value::cmp_structural`].
+    ///
+    /// The sort is stable. Items that aren't maps, or don't have `key`, sort last.
+    /// Returns `false`, leaving `self` untouched, if it isn't a [`Value::Vector`].
+    ///
+    /// ```rust
+    /// use lize::Value;
+    ///
+    /// let row = |id: i64| Value::HashMap(vec![(Value::from("id"), Value::I64(id))]);
+    /// let mut rows = Value::Vector(vec![row(2), Value::HashMap(vec![]), row(1)]);
+    ///
+    /// rows.sort_vector_by_key("id");
+    /// assert_eq!(rows, Value::Vector(vec![row(1), row(2), Value::HashMap(vec![])]));
+    /// ```
+    pub fn sort_vector_by_key(&mut self, key: &str) -> bool {
+        fn lookup<'v>(item: &'v Value, key: &str) -> Option<&'v Value<'v>> {
+            match item {
+                Value::HashMap(pairs) => pairs
+                    .iter()
+                    .find(|(k, _)| k.key_bytes() == Some(key.as_bytes()))
+                    .map(|(_, v)| v),
+                _ => None,
+            }
+        }
+
+        self.sort_vector_by(|a, b| match (lookup(a, key), lookup(b, key)) {
+            (Some(x), Some(y)) => x.cmp_structural(y),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        })
+    }
+
+    /// The bytes of a string or slice, which is how map keys are looked up by name.
+    pub(crate) fn key_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Str(s) => Some(s.as_bytes()),
+            Value::StrLike(s) => Some(s.as_bytes()),
+            Value::Slice(b) => Some(b),
+            Value::SliceLike(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    fn unwrap_optionals(&self) -> (&Value<'a>, usize) {
+        let mut value = self;
+        let mut wrapped = 0;
+        while let Value::Optional(Some(inner)) = value {
+            value = inner;
+            wrapped += 1;
+        }
+        (value, wrapped)
+    }
+
+    fn kind(&self) -> u8 {
+        match self {
+            Value::Optional(_) => 0,
+            Value::Bool(_) => 1,
+            Value::SmallU8(_) | Value::U8(_) | Value::I32(_) | Value::I64(_) => 2,
+            Value::F32(_) | Value::F64(_) => 3,
+            Value::Slice(_) | Value::SliceLike(_) | Value::Str(_) | Value::StrLike(_) => 4,
+            Value::Vector(_) => 5,
+            Value::HashMap(_) => 6,
+            Value::Ext(..) => 7,
+        }
+    }
+
+    /// Breaks ties between values of the same kind that compare equal.
+    fn width(&self) -> u8 {
+        match self {
+            Value::SmallU8(_) | Value::F32(_) | Value::Slice(_) | Value::SliceLike(_) => 0,
+            Value::U8(_) | Value::F64(_) | Value::Str(_) | Value::StrLike(_) => 1,
+            Value::I32(_) => 2,
+            Value::I64(_) => 3,
+            _ => 0,
+        }
+    }
+
+    fn int(&self) -> Option<i64> {
+        match self {
+            Value::SmallU8(i) | Value::U8(i) => Some(*i as i64),
+            Value::I32(i) => Some(*i as i64),
+            Value::I64(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    fn float(&self) -> Option<f64> {
+        match self {
+            Value::F32(f) => Some(*f as f64),
+            Value::F64(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        self.key_bytes().unwrap_or_default()
+    }
+}
+
+/// A step of [`Value::cmp_structural`].
+enum Compare<'v, 'a, 'b> {
+    Values(&'v Value<'a>, &'v Value<'b>),
+    Then(Ordering),
+}
+
+/// What [`Value::logical_eq`] sees of a value, with each child replaced by the
+/// id of its own class, so equal values get equal classes.
+#[derive(PartialEq, Eq, Hash)]
+enum Class<'v> {
+    None,
+    Bool(bool),
+    Int(i64),
+    /// The bits of the float, made canonical.
+    Float(u64),
+    /// The bytes, and whether they're a string rather than a slice.
+    Bytes(&'v [u8], bool),
+    /// Runs of equal items, as their id and count, so a vector equals the
+    /// [runs](ext::RUNS) or [packed booleans](ext::BITS) standing for it.
+    Vector(Vec<(usize, usize)>),
+    /// The ids of each key and value, sorted.
+    Map(Vec<(usize, usize)>),
+    /// The ids of the items of a [set](ext::SET) or frozen set, sorted.
+    Set(u8, Vec<usize>),
+    Ext(u8, usize),
+}
+
+/// A step of the walk over a value.
+enum Step<'v, 'a> {
+    Enter(&'v Value<'a>),
+    /// Builds a vector from the classes of the last `len` values.
+    Vector(usize),
+    /// Builds a vector from the classes of the last values, repeating each
+    /// as many times as its count.
+    Runs(Vec<usize>),
+    /// Builds a map from the classes of the last `len` keys and values.
+    Map(usize),
+    Set(u8, usize),
+    Ext(u8),
+}
+
+/// Gives each distinct [`Class`] an id, walking values with a stack of their
+/// own rather than recursing, so nesting is only limited by memory.
+#[derive(Default)]
+struct Classes<'v> {
+    ids: HashMap<Class<'v>, usize>,
+}
+
+impl<'v> Classes<'v> {
+    fn id(&mut self, class: Class<'v>) -> usize {
+        let next = self.ids.len();
+        *self.ids.entry(class).or_insert(next)
+    }
+
+    /// The id of the class of `value`, equal to that of any value it's
+    /// [logically equal](Value::logical_eq) to.
+    fn of<'a>(&mut self, value: &'v Value<'a>) -> usize {
+        let mut steps = vec![Step::Enter(value)];
+        let mut done: Vec<usize> = vec![];
+
+        while let Some(step) = steps.pop() {
+            let class = match step {
+                Step::Enter(value) => {
+                    let (value, _) = value.unwrap_optionals();
+                    match value {
+                        Value::Optional(_) => Class::None,
+                        Value::Bool(b) => Class::Bool(*b),
+                        Value::Vector(items) => {
+                            steps.push(Step::Vector(items.len()));
+                            steps.extend(items.iter().rev().map(Step::Enter));
+                            continue;
+                        }
+                        Value::HashMap(pairs) => {
+                            steps.push(Step::Map(pairs.len()));
+                            steps.extend(
+                                pairs
+                                    .iter()
+                                    .rev()
+                                    .flat_map(|(k, v)| [Step::Enter(v), Step::Enter(k)]),
+                            );
+                            continue;
+                        }
+                        Value::Ext(code, payload) => match (*code, &**payload) {
+                            // Malformed runs and booleans are compared as
+                            // they are, like any other ext.
+                            (ext::RUNS, payload) => {
+                                match packed::runs(payload) {
+                                    Ok(runs) => {
+                                        steps.push(Step::Runs(
+                                            runs.iter().map(|(_, n)| *n).collect(),
+                                        ));
+                                        steps.extend(
+                                            runs.iter().rev().map(|(item, _)| Step::Enter(item)),
+                                        );
+                                    }
+                                    Err(_) => {
+                                        steps.push(Step::Ext(ext::RUNS));
+                                        steps.push(Step::Enter(payload));
+                                    }
+                                }
+                                continue;
+                            }
+                            (ext::BITS, payload) => match packed::bits(payload) {
+                                Ok(bits) => {
+                                    let items = bits.map(|bit| (self.id(Class::Bool(bit)), 1));
+                                    Class::Vector(merge_runs(items))
+                                }
+                                Err(_) => {
+                                    steps.push(Step::Ext(ext::BITS));
+                                    steps.push(Step::Enter(payload));
+                                    continue;
+                                }
+                            },
+                            (ext::SET | ext::FROZEN_SET, Value::Vector(items)) => {
+                                steps.push(Step::Set(*code, items.len()));
+                                steps.extend(items.iter().map(Step::Enter));
+                                continue;
+                            }
+                            (code, payload) => {
+                                steps.push(Step::Ext(code));
+                                steps.push(Step::Enter(payload));
+                                continue;
+                            }
+                        },
+                        _ => match (value.int(), value.float()) {
+                            (Some(i), _) => Class::Int(i),
+                            (_, Some(f)) => Class::Float(canonical_f64(f).to_bits()),
+                            _ => Class::Bytes(value.bytes(), value.width() == 1),
+                        },
+                    }
+                }
+                Step::Vector(len) => {
+                    let items = done.split_off(done.len() - len);
+                    Class::Vector(merge_runs(items.into_iter().map(|id| (id, 1))))
+                }
+                Step::Runs(counts) => {
+                    let items = done.split_off(done.len() - counts.len());
+                    Class::Vector(merge_runs(items.into_iter().zip(counts)))
+                }
+                Step::Map(len) => {
+                    let ids = done.split_off(done.len() - 2 * len);
+                    let mut entries: Vec<_> = ids.chunks(2).map(|kv| (kv[0], kv[1])).collect();
+                    entries.sort_unstable();
+                    Class::Map(entries)
+                }
+                Step::Set(code, len) => {
+                    let mut items = done.split_off(done.len() - len);
+                    items.sort_unstable();
+                    Class::Set(code, items)
+                }
+                Step::Ext(code) => {
+                    let payload = done.pop().expect("the payload was entered first");
+                    Class::Ext(code, payload)
+                }
+            };
+            done.push(self.id(class));
+        }
+
+        done.pop().expect("the value was entered")
+    }
+}
+
+/// Merges adjacent runs of the same id.
+fn merge_runs(items: impl IntoIterator<Item = (usize, usize)>) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = vec![];
+    for (id, count) in items {
+        match runs.last_mut() {
+            Some((last, total)) if *last == id => *total += count,
+            _ => runs.push((id, count)),
+        }
+    }
+    runs
+}
@@ -0,0 +1,174 @@
+//! Reading the vectors that [`ext::BITS`](crate::ext::BITS) and
+//! [`ext::RUNS`](crate::ext::RUNS) values stand for.
+//!
+//! A few bytes of either can stand for a long vector, so readers should expand
+//! them as they go rather than trust their lengths up front.
+//!
+//! # Example
+//! ```rust
+//! use lize::{packed, Value};
+//!
+//! let payload = Value::Vector(vec![Value::from("a"), Value::SmallU8(3), Value::from("b"), Value::SmallU8(1)]);
+//! let runs = packed::runs(&payload)?;
+//! assert_eq!(packed::expand(&runs).count(), 4);
+//!
+//! let payload = Value::Slice(&[6, 0b01]);
+//! assert_eq!(packed::bits(&payload)?.collect::<Vec<_>>(), [true, false]);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use crate::{Error, Result, Value};
+
+/// The most items an [`ext::RUNS`](crate::ext::RUNS) value may stand for.
+/// Longer ones are malformed, whatever limits the reader sets.
+pub const MAX_RUNS_LEN: usize = 1 << 28;
+
+/// The `(item, count)` runs of an [`ext::RUNS`](crate::ext::RUNS) payload.
+///
+/// Fails unless the payload is a vector with a positive integer count after
+/// every item, and the counts add up to at most [`MAX_RUNS_LEN`].
+pub fn runs<'v, 'a>(payload: &'v Value<'a>) -> Result<Vec<(&'v Value<'a>, usize)>> {
+    let invalid = |reason: &str| Error::InvalidValue(format!("runs {}", reason));
+    let Value::Vector(items) = payload else {
+        return Err(invalid("aren't a vector").into());
+    };
+    if !items.len().is_multiple_of(2) {
+        return Err(invalid("have an item without a count").into());
+    }
+
+    let mut runs = Vec::with_capacity(items.len() / 2);
+    let mut len: usize = 0;
+    for pair in items.chunks(2) {
+        let count = match &pair[1] {
+            Value::SmallU8(n) | Value::U8(n) => Some(*n as usize),
+            Value::I32(n) => usize::try_from(*n).ok(),
+            Value::I64(n) => usize::try_from(*n).ok(),
+            _ => None,
+        };
+        let count = count
+            .filter(|&count| count > 0)
+            .ok_or_else(|| invalid("have a count that isn't a positive integer"))?;
+        len = len
+            .checked_add(count)
+            .filter(|&len| len <= MAX_RUNS_LEN)
+            .ok_or_else(|| invalid("expand to too many items"))?;
+        runs.push((&pair[0], count));
+    }
+    Ok(runs)
+}
+
+/// The number of items `runs` stand for.
+pub fn runs_len(runs: &[(&Value, usize)]) -> usize {
+    runs.iter().map(|(_, count)| count).sum()
+}
+
+/// The items `runs` stand for, one at a time.
+pub fn expand<'r, 'v, 'a>(
+    runs: &'r [(&'v Value<'a>, usize)],
+) -> impl Iterator<Item = &'v Value<'a>> + 'r {
+    runs.iter()
+        .flat_map(|&(item, count)| std::iter::repeat_n(item, count))
+}
+
+/// The booleans of an [`ext::BITS`](crate::ext::BITS) payload.
+///
+/// Fails unless the payload is a slice whose first byte, the number of unused
+/// bits, is less than 8 and fits in the bytes after it.
+pub fn bits<'v>(payload: &'v Value) -> Result<Bits<'v>> {
+    let invalid = || Error::InvalidValue("packed booleans are malformed".to_string());
+    let bytes: &[u8] = match payload {
+        Value::Slice(bytes) => bytes,
+        Value::SliceLike(bytes) => bytes,
+        _ => return Err(invalid().into()),
+    };
+    let (&unused, bytes) = bytes.split_first().ok_or_else(invalid)?;
+    let len = (bytes.len() * 8)
+        .checked_sub(unused as usize)
+        .filter(|_| unused < 8)
+        .ok_or_else(invalid)?;
+    Ok(Bits {
+        bytes,
+        next: 0,
+        len,
+    })
+}
+
+/// The booleans of an [`ext::BITS`](crate::ext::BITS) payload, see [`bits`].
+#[derive(Debug, Clone)]
+pub struct Bits<'b> {
+    bytes: &'b [u8],
+    next: usize,
+    len: usize,
+}
+
+impl Iterator for Bits<'_> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        if self.next == self.len {
+            return None;
+        }
+        let bit = self.bytes[self.next / 8] & (1 << (self.next % 8)) != 0;
+        self.next += 1;
+        Some(bit)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.len - self.next;
+        (left, Some(left))
+    }
+
+    fn nth(&mut self, n: usize) -> Option<bool> {
+        self.next = self.next.saturating_add(n).min(self.len);
+        self.next()
+    }
+}
+
+impl ExactSizeIterator for Bits<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_malformed() -> Result<()> {
+        let runs_of = |count: Value<'static>| Value::Vector(vec![Value::SmallU8(1), count]);
+        for count in [
+            Value::I64(1 << 40),
+            Value::I64(1 << 62),
+            Value::I64(MAX_RUNS_LEN as i64 + 1),
+            Value::I64(-1),
+            Value::SmallU8(0),
+            Value::from("1"),
+        ] {
+            assert!(runs(&runs_of(count)).is_err());
+        }
+        assert_eq!(
+            runs(&runs_of(Value::I64(MAX_RUNS_LEN as i64)))
+                .map(|r| runs_len(&r))
+                .ok(),
+            Some(MAX_RUNS_LEN)
+        );
+
+        // Each count fits, but not their sum.
+        let half = Value::I64(MAX_RUNS_LEN as i64 / 2 + 1);
+        let pairs = Value::Vector(vec![
+            Value::SmallU8(1),
+            half.clone(),
+            Value::SmallU8(2),
+            half,
+        ]);
+        assert!(runs(&pairs).is_err());
+        assert!(runs(&Value::Vector(vec![Value::SmallU8(1)])).is_err());
+        assert!(runs(&Value::SmallU8(1)).is_err());
+
+        assert!(bits(&Value::Slice(&[])).is_err());
+        assert!(bits(&Value::Slice(&[8, 0])).is_err());
+        assert!(bits(&Value::Slice(&[1])).is_err());
+        assert_eq!(bits(&Value::Slice(&[0])).map(|b| b.len()).ok(), Some(0));
+        assert_eq!(bits(&Value::Slice(&[4, 0, 0b1000]))?.nth(11), Some(true));
+        assert_eq!(bits(&Value::Slice(&[4, 0, 0b1000]))?.nth(12), None);
+        assert!(bits(&Value::Vector(vec![])).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+//! Serializing the items of a large vector on rayon's thread pool.
+
+use rayon::prelude::*;
+
+use crate::{tag, write_len, Error, Limited, Result, SerializeOptions, SmallVec, Value, STACK_N};
+
+/// Vectors with fewer items are written on the calling thread, since splitting
+/// them costs more than it saves.
+const MIN_PARALLEL_ITEMS: usize = 1024;
+
+/// How many chunks each thread gets, so threads that finish early can take more.
+const CHUNKS_PER_THREAD: usize = 4;
+
+impl Value<'_> {
+    /// Like [`Value::serialize_with`], but a top-level vector of at least 1024
+    /// items has them written in chunks on rayon's thread pool, then joined.
+    /// The output is the same.
+    ///
+    /// [`SerializeOptions::max_output_bytes`] applies to each chunk as it's
+    /// written, then to the whole output before it's copied into `buffer`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use lize::{SerializeOptions, SmallVec, Value};
+    ///
+    /// let value = Value::Vector((0..5000).map(Value::I64).collect());
+    ///
+    /// let mut buffer = SmallVec::new();
+    /// value.serialize_parallel(&SerializeOptions::new(), &mut buffer)?;
+    /// assert_eq!(buffer.as_slice(), value.serialize()?);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn serialize_parallel(
+        &self,
+        options: &SerializeOptions,
+        buffer: &mut SmallVec<[u8; STACK_N]>,
+    ) -> Result<()> {
+        let Value::Vector(items) = self else {
+            return self.serialize_with(options, buffer);
+        };
+        // Interceptors see paths from the top-level vector, which the chunks don't.
+        if items.len() < MIN_PARALLEL_ITEMS || options.interceptor.is_some() {
+            return self.serialize_with(options, buffer);
+        }
+        options.check_depth(0)?;
+        self.check(options)?;
+
+        let limit = options.max_output_bytes.unwrap_or(usize::MAX);
+        let chunk_len = items
+            .len()
+            .div_ceil(rayon::current_num_threads() * CHUNKS_PER_THREAD);
+        let chunks = items
+            .par_chunks(chunk_len)
+            .map(|chunk| {
+                let mut bytes = vec![];
+                let mut writer = Limited::new(&mut bytes, limit);
+                for item in chunk {
+                    let result = item.write_to(options, &mut writer, 1);
+                    writer.check(result)?;
+                }
+                Ok(bytes)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // The count covers every item, so it's written once, ahead of the chunks.
+        let mut head = SmallVec::<[u8; 11]>::new();
+        write_len(&mut head, tag::VECTOR, items.len())?;
+        let len = head.len() + chunks.iter().map(Vec::len).sum::<usize>();
+        if len > limit {
+            return Err(Error::OutputTooLarge(limit).into());
+        }
+
+        buffer.reserve(len);
+        buffer.extend_from_slice(&head);
+        for chunk in chunks {
+            buffer.extend_from_slice(&chunk);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize_parallel() -> Result<()> {
+        let item = |i: i64| {
+            Value::HashMap(vec![
+                (Value::from("id"), Value::I64(i)),
+                (Value::from("name"), Value::StrLike(format!("item {i}"))),
+                (
+                    Value::from("b"),
+                    Value::Vector(vec![Value::Bool(i % 2 == 0)]),
+                ),
+            ])
+        };
+
+        for len in [0, 10, MIN_PARALLEL_ITEMS as i64, 10_007] {
+            let value = Value::Vector((0..len).map(item).collect());
+            for options in [
+                SerializeOptions::new(),
+                SerializeOptions::new().canonical(true),
+            ] {
+                let mut serial = SmallVec::new();
+                value.serialize_with(&options, &mut serial)?;
+                let mut parallel = SmallVec::new();
+                value.serialize_parallel(&options, &mut parallel)?;
+                assert_eq!(parallel, serial, "{len} items, {options:?}");
+            }
+        }
+
+        let value = Value::Vector((0..5000).map(item).collect());
+        let len = value.serialize()?.len();
+        let mut buffer = SmallVec::new();
+        for (limit, fits) in [(len, true), (len - 1, false), (100, false)] {
+            let options = SerializeOptions::new().max_output_bytes(limit);
+            let result = value.serialize_parallel(&options, &mut buffer);
+            match fits {
+                true => assert_eq!(buffer.len(), len),
+                false => assert_eq!(
+                    result.unwrap_err().downcast_ref(),
+                    Some(&Error::OutputTooLarge(limit))
+                ),
+            }
+            buffer.clear();
+        }
+
+        let options = SerializeOptions::new().max_depth(1);
+        assert!(value.serialize_parallel(&options, &mut buffer).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+use std::cmp::Ordering;
+
+use crate::{ext, Value};
+
+impl<'a> Value<'a> {
+    /// The marker for a map entry to delete, as opposed to one set to
+    /// `Optional(None)`. It's an [`ext::DELETE`] value.
+    pub fn delete() -> Value<'static> {
+        Value::Ext(ext::DELETE, Box::new(Value::Optional(None)))
+    }
+
+    /// Whether this is the marker made by [`Value::delete`].
+    pub fn is_delete(&self) -> bool {
+        matches!(self, Value::Ext(ext::DELETE, _))
+    }
+
+    /// Applies a merge patch, like JSON Merge Patch (RFC 7386).
+    ///
+    /// If `patch` is a map, each of its entries is merged into the entry of
+    /// `self` with the same key, with [`Value::delete`] removing it. Entries
+    /// `patch` leaves out are kept, and `Optional(None)` sets an entry to null.
+    /// `self` becomes a map first if it isn't one. Any other `patch` replaces
+    /// `self`.
+    ///
+    /// ```rust
+    /// use lize::Value;
+    ///
+    /// let mut value = Value::HashMap(vec![
+    ///     (Value::from("a"), Value::I64(1)),
+    ///     (Value::from("b"), Value::I64(2)),
+    /// ]);
+    /// value.merge(Value::HashMap(vec![
+    ///     (Value::from("a"), Value::delete()),
+    ///     (Value::from("c"), Value::Optional(None)),
+    /// ]));
+    ///
+    /// assert_eq!(
+    ///     value,
+    ///     Value::HashMap(vec![
+    ///         (Value::from("b"), Value::I64(2)),
+    ///         (Value::from("c"), Value::Optional(None)),
+    ///     ])
+    /// );
+    /// ```
+    pub fn merge(&mut self, mut patch: Value<'a>) {
+        let Value::HashMap(patch_pairs) = &mut patch else {
+            *self = patch;
+            return;
+        };
+        if !matches!(self, Value::HashMap(_)) {
+            *self = Value::HashMap(vec![]);
+        }
+        let Value::HashMap(pairs) = self else {
+            unreachable!()
+        };
+
+        for (key, value) in std::mem::take(patch_pairs) {
+            let index = pairs
+                .iter()
+                .position(|(k, _)| k.cmp_structural(&key) == Ordering::Equal);
+            match (index, value.is_delete()) {
+                (Some(index), true) => {
+                    pairs.remove(index);
+                }
+                (None, true) => {}
+                (Some(index), false) => pairs[index].1.merge(value),
+                (None, false) => {
+                    // Merged into nothing, so nested markers are dropped.
+                    let mut fresh = Value::Optional(None);
+                    fresh.merge(value);
+                    pairs.push((key, fresh));
+                }
+            }
+        }
+    }
+
+    /// Returns the merge patch that turns `self` into `new`, so that
+    /// `self.merge(self.diff(new))` equals `new` up to the order of map entries.
+    ///
+    /// Entries missing from `new` become [`Value::delete`] markers, and unchanged
+    /// ones are left out. Values that aren't both maps are replaced whole.
+    pub fn diff(&self, new: &Value<'a>) -> Value<'a> {
+        let (Value::HashMap(old), Value::HashMap(new)) = (self, new) else {
+            return new.clone();
+        };
+        let find = |pairs: &'_ [(Value<'a>, Value<'a>)], key: &Value| {
+            pairs
+                .iter()
+                .position(|(k, _)| k.cmp_structural(key) == Ordering::Equal)
+        };
+
+        let mut patch = vec![];
+        for (key, value) in new {
+            match find(old, key).map(|i| &old[i].1) {
+                Some(old_value) if old_value.cmp_structural(value) == Ordering::Equal => {}
+                Some(old_value) => patch.push((key.clone(), old_value.diff(value))),
+                None => patch.push((key.clone(), value.clone())),
+            }
+        }
+        for (key, _) in old {
+            if find(new, key).is_none() {
+                patch.push((key.clone(), Value::delete()));
+            }
+        }
+
+        Value::HashMap(patch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SerializeOptions, SmallVec};
+
+    fn map<'a>(pairs: impl IntoIterator<Item = (&'a str, Value<'a>)>) -> Value<'a> {
+        Value::HashMap(
+            pairs
+                .into_iter()
+                .map(|(k, v)| (Value::from(k), v))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut value = map([
+            ("keep", Value::I64(1)),
+            ("drop", Value::I64(2)),
+            ("nested", map([("a", Value::I64(3)), ("b", Value::I64(4))])),
+        ]);
+        value.merge(map([
+            ("drop", Value::delete()),
+            ("missing", Value::delete()),
+            ("null", Value::Optional(None)),
+            (
+                "nested",
+                map([("a", Value::delete()), ("c", Value::I64(5))]),
+            ),
+            ("new", map([("x", Value::delete()), ("y", Value::I64(6))])),
+        ]));
+
+        assert_eq!(
+            value,
+            map([
+                ("keep", Value::I64(1)),
+                ("nested", map([("b", Value::I64(4)), ("c", Value::I64(5))])),
+                ("null", Value::Optional(None)),
+                ("new", map([("y", Value::I64(6))])),
+            ])
+        );
+
+        let mut scalar = Value::I64(1);
+        scalar.merge(map([("a", Value::I64(2))]));
+        assert_eq!(scalar, map([("a", Value::I64(2))]));
+        scalar.merge(Value::Bool(true));
+        assert_eq!(scalar, Value::Bool(true));
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = map([
+            ("same", Value::I64(1)),
+            ("gone", Value::I64(2)),
+            ("null", Value::I64(3)),
+            ("nested", map([("a", Value::I64(4)), ("b", Value::I64(5))])),
+        ]);
+        let new = map([
+            ("same", Value::I64(1)),
+            ("null", Value::Optional(None)),
+            ("nested", map([("a", Value::I64(4)), ("b", Value::I64(6))])),
+            ("added", Value::from("x")),
+        ]);
+
+        let patch = old.diff(&new);
+        assert_eq!(
+            patch,
+            map([
+                ("null", Value::Optional(None)),
+                ("nested", map([("b", Value::I64(6))])),
+                ("added", Value::from("x")),
+                ("gone", Value::delete()),
+            ])
+        );
+
+        let canonical = |value: &Value| {
+            let mut buffer = SmallVec::new();
+            value
+                .serialize_with(&SerializeOptions::new().canonical(true), &mut buffer)
+                .unwrap();
+            buffer
+        };
+        let mut merged = old.clone();
+        merged.merge(patch);
+        assert_eq!(canonical(&merged), canonical(&new));
+
+        assert_eq!(old.diff(&old), Value::HashMap(vec![]));
+    }
+}
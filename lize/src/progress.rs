@@ -0,0 +1,204 @@
+use std::io::Write;
+
+use crate::{decode, DeserializeOptions, Limited, Result, SerializeOptions, Value};
+
+/// How much of a value [`Value::serialize_with_progress`] has written, or
+/// [`Value::deserialize_with_progress`] has read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Bytes written or read so far.
+    pub bytes: usize,
+    /// Values written or read so far, counting each container and each of its
+    /// items, keys included.
+    pub values: usize,
+}
+
+/// Calls `report` each time another `interval` bytes have gone by.
+struct Reporter<F> {
+    interval: usize,
+    next: usize,
+    progress: Progress,
+    report: F,
+}
+
+impl<F: FnMut(Progress) -> Result<()>> Reporter<F> {
+    fn new(interval: usize, report: F) -> Self {
+        let interval = interval.max(1);
+        Self {
+            interval,
+            next: interval,
+            progress: Progress::default(),
+            report,
+        }
+    }
+
+    fn value(&mut self, bytes: usize) -> Result<()> {
+        self.progress.values += 1;
+        self.progress.bytes = bytes;
+        if bytes >= self.next {
+            self.next = (bytes / self.interval + 1) * self.interval;
+            (self.report)(self.progress)?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        (self.report)(self.progress)
+    }
+}
+
+/// Counts the bytes written through it.
+struct Counting<'w, W: ?Sized> {
+    inner: &'w mut W,
+    bytes: usize,
+}
+
+impl<W: Write + ?Sized> Write for Counting<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.bytes += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<'a> Value<'a> {
+    /// Like [`Value::serialize_to`], calling `report` each time another
+    /// `interval` bytes have been written, and once more at the end. If
+    /// `report` fails, e.g. with [`Error::Cancelled`](crate::Error::Cancelled),
+    /// writing stops there with its error.
+    ///
+    /// # Example
+    /// ```rust
+    /// use lize::{Error, Progress, SerializeOptions, Value};
+    ///
+    /// let value = Value::Vector((0..1000).map(Value::I64).collect());
+    /// let mut reports = vec![];
+    /// value.serialize_with_progress(&SerializeOptions::new(), &mut vec![], 4096, |progress| {
+    ///     reports.push(progress);
+    ///     Ok(())
+    /// })?;
+    /// assert_eq!(reports.len(), 3);
+    /// assert_eq!(reports[2], Progress { bytes: 9003, values: 1001 });
+    ///
+    /// let cancelled = value.serialize_with_progress(&SerializeOptions::new(), &mut vec![], 4096, |_| {
+    ///     Err(Error::Cancelled.into())
+    /// });
+    /// assert_eq!(cancelled.unwrap_err().downcast_ref(), Some(&Error::Cancelled));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn serialize_with_progress<W, F>(
+        &self,
+        options: &SerializeOptions,
+        buffer: &mut W,
+        interval: usize,
+        report: F,
+    ) -> Result<()>
+    where
+        W: Write + ?Sized,
+        F: FnMut(Progress) -> Result<()>,
+    {
+        self.check(options)?;
+        let mut reporter = Reporter::new(interval, report);
+        match options.max_output_bytes {
+            None => self.write_counted(options, buffer, &mut reporter)?,
+            Some(limit) => {
+                let mut limited = Limited::new(buffer, limit);
+                let result = self.write_counted(options, &mut limited, &mut reporter);
+                limited.check(result)?;
+            }
+        }
+        reporter.finish()
+    }
+
+    fn write_counted<W, F>(
+        &self,
+        options: &SerializeOptions,
+        buffer: &mut W,
+        reporter: &mut Reporter<F>,
+    ) -> Result<()>
+    where
+        W: Write + ?Sized,
+        F: FnMut(Progress) -> Result<()>,
+    {
+        let mut counting = Counting {
+            inner: buffer,
+            bytes: 0,
+        };
+        self.write_observed(options, &mut counting, 0, &mut |counting| {
+            reporter.value(counting.bytes)
+        })
+    }
+
+    /// Like [`Value::deserialize_with`], calling `report` each time another
+    /// `interval` bytes have been read, and once more at the end. If `report`
+    /// fails, reading stops there with its error.
+    pub fn deserialize_with_progress<F>(
+        slice: &'a [u8],
+        options: &DeserializeOptions,
+        interval: usize,
+        report: F,
+    ) -> Result<Self>
+    where
+        F: FnMut(Progress) -> Result<()>,
+    {
+        let mut reporter = Reporter::new(interval, report);
+        let (value, _) =
+            decode::decode_observed(slice, options, &mut |bytes| reporter.value(bytes))?;
+        reporter.finish()?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress() -> Result<()> {
+        let value = Value::HashMap(
+            (0..100)
+                .map(|i| (Value::I64(i), Value::from("x")))
+                .collect(),
+        );
+        let bytes = value.serialize()?;
+
+        let mut written = vec![];
+        value.serialize_with_progress(&SerializeOptions::new(), &mut vec![], 100, |p| {
+            written.push(p);
+            Ok(())
+        })?;
+        let mut read = vec![];
+        Value::deserialize_with_progress(&bytes, &DeserializeOptions::new(), 100, |p| {
+            read.push(p);
+            Ok(())
+        })?;
+
+        // Both count the map, then each key and value.
+        let total = Progress {
+            bytes: bytes.len(),
+            values: 201,
+        };
+        assert_eq!(written.last(), Some(&total));
+        assert_eq!(read.last(), Some(&total));
+        assert_eq!(written.len(), bytes.len() / 100 + 1);
+        // One report per 100 bytes, as soon as they're crossed.
+        let (total_report, reports) = written.split_last().unwrap();
+        assert_eq!(total_report, &total);
+        assert!(reports
+            .iter()
+            .enumerate()
+            .all(|(i, p)| p.bytes / 100 == i + 1));
+
+        // The output limit still applies, and wins over the report.
+        let options = SerializeOptions::new().max_output_bytes(10);
+        let err = value
+            .serialize_with_progress(&options, &mut vec![], 1, |_| Ok(()))
+            .unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&crate::Error::OutputTooLarge(10)));
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+use crate::Value;
+
+impl<'a> Value<'a> {
+    /// A copy of the value that borrows every slice and string from it,
+    /// including owned ones, so only the containers are allocated.
+    pub fn as_borrowed(&self) -> Value<'_> {
+        self.rebuild(|value| match value {
+            Value::Slice(s) => Value::Slice(s),
+            Value::SliceLike(s) => Value::Slice(s),
+            Value::Str(s) => Value::Str(s),
+            Value::StrLike(s) => Value::Str(s),
+            value => value.to_owned_leaf(),
+        })
+    }
+
+    /// The entries of a map whose keys are in `keys`, borrowed from it like
+    /// [`Value::as_borrowed`]. Entries left out aren't visited at all, and
+    /// values other than maps are returned whole.
+    ///
+    /// ```rust
+    /// use lize::Value;
+    ///
+    /// let value = Value::HashMap(vec![
+    ///     (Value::from("id"), Value::SmallU8(1)),
+    ///     (Value::from("blob"), Value::Slice(&[0; 1024])),
+    /// ]);
+    ///
+    /// assert_eq!(
+    ///     value.project(&["id"]),
+    ///     Value::HashMap(vec![(Value::from("id"), Value::SmallU8(1))])
+    /// );
+    /// ```
+    pub fn project(&self, keys: &[&str]) -> Value<'_> {
+        match self {
+            Value::HashMap(pairs) => Value::HashMap(
+                pairs
+                    .iter()
+                    .filter(|(key, _)| key.is_one_of(keys))
+                    .map(|(key, value)| (key.as_borrowed(), value.as_borrowed()))
+                    .collect(),
+            ),
+            value => value.as_borrowed(),
+        }
+    }
+
+    /// Removes the entries of a map whose keys aren't in `keys`. Does nothing
+    /// to other values.
+    pub fn retain_keys(&mut self, keys: &[&str]) {
+        if let Value::HashMap(pairs) = self {
+            pairs.retain(|(key, _)| key.is_one_of(keys));
+        }
+    }
+
+    /// Removes the entries of a map whose keys are in `keys`. Does nothing to
+    /// other values.
+    pub fn remove_keys(&mut self, keys: &[&str]) {
+        if let Value::HashMap(pairs) = self {
+            pairs.retain(|(key, _)| !key.is_one_of(keys));
+        }
+    }
+
+    /// Whether this is a string, or a slice of UTF-8, equal to one of `keys`.
+    fn is_one_of(&self, keys: &[&str]) -> bool {
+        let key = match self {
+            Value::StrLike(s) => s.as_str(),
+            key => match key.as_str() {
+                Some(s) => s,
+                None => return false,
+            },
+        };
+        keys.contains(&key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Result;
+
+    #[test]
+    fn test_project() -> Result<()> {
+        let blob = vec![7_u8; 1 << 16];
+        let value = Value::HashMap(vec![
+            (Value::from("id"), Value::SmallU8(1)),
+            (Value::from("name"), Value::StrLike("kept".to_owned())),
+            (Value::from("blob"), Value::Slice(&blob)),
+            (Value::from("tags"), Value::Vector(vec![Value::from("a")])),
+        ]);
+        let buffer = value.serialize()?;
+        let decoded = Value::deserialize_from(&buffer)?;
+
+        let projected = decoded.project(&["tags", "name", "missing"]);
+        assert_eq!(
+            projected.serialize()?,
+            Value::HashMap(vec![
+                (Value::from("name"), Value::from("kept")),
+                (Value::from("tags"), Value::Vector(vec![Value::from("a")])),
+            ])
+            .serialize()?
+        );
+
+        // Kept strings point into the decoded buffer rather than copies.
+        let Value::HashMap(pairs) = &projected else {
+            panic!("expected a map, got {projected:?}");
+        };
+        let Value::Str(name) = &pairs[0].1 else {
+            panic!("expected a borrowed string, got {:?}", pairs[0].1);
+        };
+        assert!(buffer.as_ptr_range().contains(&name.as_ptr()));
+
+        // Owned strings are borrowed too.
+        assert_eq!(
+            value.project(&["name"]),
+            Value::HashMap(vec![(Value::from("name"), Value::Str("kept"))])
+        );
+        assert_eq!(Value::SmallU8(3).project(&["id"]), Value::SmallU8(3));
+
+        let mut retained = decoded.clone();
+        retained.retain_keys(&["id", "blob"]);
+        let mut removed = decoded.clone();
+        removed.remove_keys(&["name", "tags"]);
+        assert_eq!(retained, removed);
+        assert_eq!(
+            retained.serialize()?,
+            Value::HashMap(vec![
+                (Value::from("id"), Value::SmallU8(1)),
+                (Value::from("blob"), Value::Slice(&blob)),
+            ])
+            .serialize()?
+        );
+
+        Ok(())
+    }
+}
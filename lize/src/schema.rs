@@ -0,0 +1,208 @@
+use crate::{ext, Error, Result, Value};
+
+/// The field names of maps that are sent often enough that writing their keys
+/// every time is a waste, shared out of band by both sides.
+///
+/// [`Schema::encode`] turns such a map into a vector of its values in field
+/// order, and [`Schema::decode`] turns it back. Frames written with
+/// [`frame::seal_with_schema`](crate::frame::seal_with_schema) carry the
+/// schema's [`hash`](Schema::hash), so reading them with another schema fails
+/// instead of pairing values with the wrong keys.
+///
+/// # Example
+/// ```rust
+/// use lize::{Schema, Value};
+///
+/// let schema = Schema::new(["id", "name", "email"])?;
+/// let record = schema.encode(Value::HashMap(vec![
+///     (Value::from("name"), Value::from("lize")),
+///     (Value::from("id"), Value::I64(1)),
+/// ]))?;
+/// assert_eq!(
+///     record,
+///     Value::Vector(vec![Value::I64(1), Value::from("lize"), Value::absent()])
+/// );
+///
+/// assert_eq!(
+///     schema.decode(record)?,
+///     Value::HashMap(vec![
+///         (Value::StrLike("id".into()), Value::I64(1)),
+///         (Value::StrLike("name".into()), Value::from("lize")),
+///     ])
+/// );
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    fields: Vec<String>,
+    hash: u64,
+}
+
+impl Schema {
+    /// Creates a schema with these field names, which must be distinct.
+    pub fn new<I, S>(fields: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let fields: Vec<String> = fields.into_iter().map(Into::into).collect();
+        for (i, field) in fields.iter().enumerate() {
+            if fields[..i].contains(field) {
+                return Err(
+                    Error::InvalidSchema(format!("field {:?} appears twice", field)).into(),
+                );
+            }
+        }
+
+        let hash = {
+            let names = Value::Vector(fields.iter().map(|field| Value::Str(field)).collect());
+            fnv1a(&names.serialize()?)
+        };
+        Ok(Self { fields, hash })
+    }
+
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    /// The 64-bit FNV-1a hash of the field names encoded as a vector of
+    /// strings, which identifies the schema in frame headers. See `FORMAT.md`.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Turns a map with string keys into a vector of its values in field order,
+    /// with [`Value::absent`] for the fields it doesn't have.
+    ///
+    /// Fails with [`Error::InvalidSchema`] if `value` isn't a map, or has a key
+    /// that isn't a field.
+    pub fn encode<'a>(&self, mut value: Value<'a>) -> Result<Value<'a>> {
+        let Value::HashMap(pairs) = &mut value else {
+            return Err(Error::InvalidSchema("expected a map".into()).into());
+        };
+
+        let mut values: Vec<Option<Value<'a>>> = self.fields.iter().map(|_| None).collect();
+        for (key, value) in std::mem::take(pairs) {
+            let name = match &key {
+                Value::Str(s) => *s,
+                Value::StrLike(s) => s.as_str(),
+                _ => return Err(Error::InvalidSchema("map keys must be strings".into()).into()),
+            };
+            let Some(index) = self.fields.iter().position(|field| field == name) else {
+                return Err(Error::InvalidSchema(format!(
+                    "{:?} isn't a field of the schema",
+                    name
+                ))
+                .into());
+            };
+            values[index] = Some(value);
+        }
+
+        Ok(Value::Vector(
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Some(value) => value,
+                    None => Value::absent(),
+                })
+                .collect(),
+        ))
+    }
+
+    /// Turns a vector written by [`Schema::encode`] back into a map, leaving
+    /// out the fields that were absent.
+    pub fn decode<'a>(&self, mut value: Value<'a>) -> Result<Value<'a>> {
+        let values = match &mut value {
+            Value::Vector(values) if values.len() == self.fields.len() => std::mem::take(values),
+            Value::Vector(values) => {
+                return Err(Error::InvalidSchema(format!(
+                    "expected {} fields, got {}",
+                    self.fields.len(),
+                    values.len()
+                ))
+                .into())
+            }
+            _ => return Err(Error::InvalidSchema("expected a vector of fields".into()).into()),
+        };
+
+        Ok(Value::HashMap(
+            self.fields
+                .iter()
+                .zip(values)
+                .filter(|(_, value)| !value.is_absent())
+                .map(|(field, value)| (Value::StrLike(field.clone()), value))
+                .collect(),
+        ))
+    }
+}
+
+impl Value<'_> {
+    /// The marker [`Schema::encode`] writes for a missing field, as opposed to
+    /// one set to `Optional(None)`. It's an [`ext::ABSENT`] value.
+    pub fn absent() -> Value<'static> {
+        Value::Ext(ext::ABSENT, Box::new(Value::Optional(None)))
+    }
+
+    /// Whether this is the marker made by [`Value::absent`].
+    pub fn is_absent(&self) -> bool {
+        matches!(self, Value::Ext(ext::ABSENT, _))
+    }
+}
+
+/// 64-bit FNV-1a, which is short enough to write out in any language.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_errors() {
+        let invalid = |err: anyhow::Error| match err.downcast::<Error>() {
+            Ok(Error::InvalidSchema(reason)) => reason,
+            other => panic!("expected InvalidSchema, got {:?}", other),
+        };
+
+        assert_eq!(
+            invalid(Schema::new(["a", "b", "a"]).unwrap_err()),
+            "field \"a\" appears twice"
+        );
+
+        let schema = Schema::new(["a", "b"]).unwrap();
+        assert_eq!(
+            invalid(schema.encode(Value::I64(1)).unwrap_err()),
+            "expected a map"
+        );
+        assert_eq!(
+            invalid(
+                schema
+                    .encode(Value::HashMap(vec![(Value::from("c"), Value::I64(1))]))
+                    .unwrap_err()
+            ),
+            "\"c\" isn't a field of the schema"
+        );
+        assert_eq!(
+            invalid(
+                schema
+                    .decode(Value::Vector(vec![Value::I64(1)]))
+                    .unwrap_err()
+            ),
+            "expected 2 fields, got 1"
+        );
+    }
+
+    #[test]
+    fn test_schema_hash() -> Result<()> {
+        // Pinned, since other implementations must compute the same hash.
+        assert_eq!(Schema::new(["id", "name"])?.hash(), 0x0088_58bc_0342_5bcb);
+        assert_ne!(
+            Schema::new(["id", "name"])?.hash(),
+            Schema::new(["name", "id"])?.hash()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+//! [`Value::shape_fingerprint`], a hash of what kinds of values a value holds
+//! and under which keys, which stays the same as the values themselves change.
+
+use crate::{ext, schema::fnv1a, Result, Value};
+
+/// The kinds a shape tells apart. Integers and floats of every width are one
+/// kind each, as are strings and byte strings however they're held.
+mod kind {
+    pub const INT: u8 = 0;
+    pub const FLOAT: u8 = 1;
+    pub const BOOL: u8 = 2;
+    pub const STR: u8 = 3;
+    pub const BYTES: u8 = 4;
+    pub const NONE: u8 = 5;
+    pub const VECTOR: u8 = 6;
+    pub const MAP: u8 = 7;
+    pub const EXT: u8 = 8;
+}
+
+/// A step of the walk over a value.
+enum Step<'v, 'a> {
+    Enter(&'v Value<'a>),
+    /// Hashes the shapes of the last `len` items into the shape of a vector.
+    Vector(usize),
+    /// Hashes the shapes of the values of `pairs` into the shape of a map.
+    Map(&'v [(Value<'a>, Value<'a>)]),
+    /// Hashes the shape of the payload into the shape of an `Ext`.
+    Ext(u8),
+}
+
+impl Value<'_> {
+    /// Returns a hash of the shape of the value: the kind of every value in
+    /// it and the keys of every map, but not the values themselves.
+    ///
+    /// The shape of a vector is the set of distinct shapes of its items, so
+    /// vectors of any length holding the same kinds share it, and the shape of
+    /// a map doesn't depend on the order of its entries. Integers of any
+    /// width, including [`ext::BIG_INT`], are one kind, as are floats, strings
+    /// and byte strings. `Optional(Some)` has the shape of what it holds.
+    ///
+    /// The hash is stable across runs and platforms, but not meant to resist
+    /// deliberate collisions.
+    ///
+    /// ```rust
+    /// use lize::Value;
+    ///
+    /// let user = |name, age| {
+    ///     Value::HashMap(vec![(Value::from("name"), Value::from(name)), (Value::from("age"), Value::I64(age))])
+    /// };
+    /// assert_eq!(user("Ada", 36).shape_fingerprint()?, user("Alan", 41).shape_fingerprint()?);
+    ///
+    /// let renamed = Value::HashMap(vec![(Value::from("nick"), Value::from("Ada"))]);
+    /// assert_ne!(user("Ada", 36).shape_fingerprint()?, renamed.shape_fingerprint()?);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn shape_fingerprint(&self) -> Result<u64> {
+        let mut steps = vec![Step::Enter(self)];
+        let mut shapes: Vec<u64> = vec![];
+
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Enter(value) => match value {
+                    Value::I64(_) | Value::I32(_) | Value::U8(_) | Value::SmallU8(_) => {
+                        shapes.push(fnv1a(&[kind::INT]))
+                    }
+                    Value::Ext(ext::BIG_INT, _) => shapes.push(fnv1a(&[kind::INT])),
+                    Value::F64(_) | Value::F32(_) => shapes.push(fnv1a(&[kind::FLOAT])),
+                    Value::Bool(_) => shapes.push(fnv1a(&[kind::BOOL])),
+                    Value::Str(_) | Value::StrLike(_) => shapes.push(fnv1a(&[kind::STR])),
+                    Value::Ext(ext::SURROGATE_STR, _) => shapes.push(fnv1a(&[kind::STR])),
+                    Value::Slice(_) | Value::SliceLike(_) => shapes.push(fnv1a(&[kind::BYTES])),
+                    Value::Optional(None) => shapes.push(fnv1a(&[kind::NONE])),
+                    Value::Optional(Some(inner)) => steps.push(Step::Enter(inner)),
+                    Value::Vector(items) => {
+                        steps.push(Step::Vector(items.len()));
+                        steps.extend(items.iter().map(Step::Enter));
+                    }
+                    // A vector of booleans, however many.
+                    Value::Ext(ext::BITS, _) => {
+                        shapes.push(fnv1a(&[kind::BOOL]));
+                        steps.push(Step::Vector(1));
+                    }
+                    // The counts are left out, so it's shaped like the vector
+                    // it stands for.
+                    Value::Ext(ext::RUNS, payload) => match &**payload {
+                        Value::Vector(runs) => {
+                            let items = runs.iter().step_by(2);
+                            steps.push(Step::Vector(items.len()));
+                            steps.extend(items.map(Step::Enter));
+                        }
+                        payload => {
+                            steps.push(Step::Ext(ext::RUNS));
+                            steps.push(Step::Enter(payload));
+                        }
+                    },
+                    Value::Ext(code, payload) => {
+                        steps.push(Step::Ext(*code));
+                        steps.push(Step::Enter(payload));
+                    }
+                    Value::HashMap(pairs) => {
+                        steps.push(Step::Map(pairs));
+                        steps.extend(pairs.iter().map(|(_, value)| Step::Enter(value)));
+                    }
+                },
+                Step::Vector(len) => {
+                    let mut items = shapes.split_off(shapes.len() - len);
+                    items.sort_unstable();
+                    items.dedup();
+
+                    let mut bytes = vec![kind::VECTOR];
+                    for shape in items {
+                        bytes.extend(shape.to_le_bytes());
+                    }
+                    shapes.push(fnv1a(&bytes));
+                }
+                Step::Map(pairs) => {
+                    // Values were entered in order, so they come off in reverse.
+                    let values = shapes.split_off(shapes.len() - pairs.len());
+                    let mut entries = pairs
+                        .iter()
+                        .rev()
+                        .zip(values)
+                        .map(|((key, _), shape)| Ok((key.serialize()?, shape)))
+                        .collect::<Result<Vec<_>>>()?;
+                    entries.sort_unstable();
+
+                    let mut bytes = vec![kind::MAP];
+                    for (key, shape) in entries {
+                        bytes.extend((key.len() as u64).to_le_bytes());
+                        bytes.extend(key);
+                        bytes.extend(shape.to_le_bytes());
+                    }
+                    shapes.push(fnv1a(&bytes));
+                }
+                Step::Ext(code) => {
+                    let payload = shapes.pop().expect("the payload was entered first");
+                    let mut bytes = vec![kind::EXT, code];
+                    bytes.extend(payload.to_le_bytes());
+                    shapes.push(fnv1a(&bytes));
+                }
+            }
+        }
+
+        Ok(shapes.pop().expect("the value was entered"))
+    }
+}
@@ -0,0 +1,232 @@
+//! Counts of what an encoded value holds, gathered without decoding it, so
+//! callers can tell how large it will get before building it.
+//!
+//! # Example
+//! ```rust
+//! use lize::{Stats, Value};
+//!
+//! let value = Value::Vector(vec![Value::from("ab"), Value::I64(300)]);
+//! let stats = Stats::of(&value.serialize()?)?;
+//!
+//! assert_eq!((stats.vectors, stats.items), (1, 2));
+//! assert_eq!((stats.strs, stats.str_bytes, stats.ints), (1, 2, 1));
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::{decode, decode::Cursor, ext, legacy, packed, tag, DeserializeOptions, Result, Value};
+
+/// How many values of each kind an encoded value holds, including itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Stats {
+    /// `SmallU8` and `U8` values.
+    pub small_ints: usize,
+    /// `I32` and `I64` values.
+    pub ints: usize,
+    pub floats: usize,
+    /// Booleans and `Optional(None)`s.
+    pub constants: usize,
+    /// `Optional(Some)` wrappers.
+    pub somes: usize,
+    pub strs: usize,
+    /// The total length of every string.
+    pub str_bytes: usize,
+    pub slices: usize,
+    /// The total length of every slice.
+    pub slice_bytes: usize,
+    pub vectors: usize,
+    /// The total number of items in every vector.
+    pub items: usize,
+    /// The total number of items every [`ext::RUNS`] stands for, which aren't
+    /// in `items`.
+    pub run_items: usize,
+    /// The total number of booleans in every [`ext::BITS`].
+    pub bits: usize,
+    pub maps: usize,
+    /// How many maps have each number of entries.
+    pub map_sizes: BTreeMap<usize, usize>,
+    pub exts: usize,
+}
+
+impl Stats {
+    /// Counts the value at the start of `bytes`, ignoring anything after it.
+    ///
+    /// Fails on the same malformed input as decoding would, except for invalid
+    /// UTF-8, which isn't checked.
+    pub fn of(bytes: &[u8]) -> Result<Self> {
+        let mut stats = Self::default();
+        let mut cursor = Cursor::new(bytes);
+        let mut pending = 1_usize;
+
+        while pending > 0 {
+            pending -= 1;
+
+            let tag = cursor.read_u8()?;
+            match tag {
+                tag::VECTOR => {
+                    let len = cursor.read_len()?;
+                    stats.vectors += 1;
+                    stats.items += len;
+                    pending += len;
+                }
+                tag::MAP => {
+                    let len = cursor.read_len()?;
+                    stats.maps += 1;
+                    *stats.map_sizes.entry(len).or_default() += 1;
+                    pending += 2 * len;
+                }
+                tag::SOME => {
+                    stats.somes += 1;
+                    pending += 1;
+                }
+                // Packed vectors are decoded to count what they stand for.
+                tag::EXT => match cursor.read_u8()? {
+                    code @ (ext::RUNS | ext::BITS) => {
+                        let rest = cursor.rest();
+                        let (payload, after) =
+                            decode::decode(rest, &DeserializeOptions::default())?;
+                        cursor.take(rest.len() - after.len())?;
+                        stats.add(&Value::Ext(code, Box::new(payload)))?;
+                    }
+                    _ => {
+                        stats.exts += 1;
+                        pending += 1;
+                    }
+                },
+                tag::SLICE | tag::STR => {
+                    let len = cursor.read_len()?;
+                    cursor.take(len)?;
+                    if tag == tag::STR {
+                        stats.strs += 1;
+                        stats.str_bytes += len;
+                    } else {
+                        stats.slices += 1;
+                        stats.slice_bytes += len;
+                    }
+                }
+                tag if tag::legacy::is_legacy(tag) => {
+                    cursor.unread();
+                    let len = legacy::encoded_len(cursor.rest())?;
+                    let value = legacy::decode(
+                        cursor.take(len)?,
+                        &DeserializeOptions::default(),
+                        0,
+                        false,
+                    )?;
+                    stats.add(&value)?;
+                }
+                tag => stats.add(&cursor.read_scalar(tag)?)?,
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// Counts a decoded value.
+    fn add(&mut self, value: &Value) -> Result<()> {
+        let mut stack = vec![value];
+
+        while let Some(value) = stack.pop() {
+            match value {
+                Value::SmallU8(_) | Value::U8(_) => self.small_ints += 1,
+                Value::I32(_) | Value::I64(_) => self.ints += 1,
+                Value::F32(_) | Value::F64(_) => self.floats += 1,
+                Value::Bool(_) | Value::Optional(None) => self.constants += 1,
+                Value::Optional(Some(inner)) => {
+                    self.somes += 1;
+                    stack.push(inner);
+                }
+                Value::Str(s) => {
+                    self.strs += 1;
+                    self.str_bytes += s.len();
+                }
+                Value::StrLike(s) => {
+                    self.strs += 1;
+                    self.str_bytes += s.len();
+                }
+                Value::Slice(s) => {
+                    self.slices += 1;
+                    self.slice_bytes += s.len();
+                }
+                Value::SliceLike(s) => {
+                    self.slices += 1;
+                    self.slice_bytes += s.len();
+                }
+                Value::Vector(items) => {
+                    self.vectors += 1;
+                    self.items += items.len();
+                    stack.extend(items);
+                }
+                Value::HashMap(pairs) => {
+                    self.maps += 1;
+                    *self.map_sizes.entry(pairs.len()).or_default() += 1;
+                    stack.extend(pairs.iter().flat_map(|(k, v)| [k, v]));
+                }
+                Value::Ext(code, inner) => {
+                    self.exts += 1;
+                    match *code {
+                        ext::RUNS => self.run_items += packed::runs_len(&packed::runs(inner)?),
+                        ext::BITS => self.bits += packed::bits(inner)?.len(),
+                        _ => {}
+                    }
+                    stack.push(inner);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() -> Result<()> {
+        let value = Value::HashMap(vec![
+            (Value::from("a"), Value::Vector(vec![])),
+            (
+                Value::from("bc"),
+                Value::Optional(Some(Box::new(Value::Slice(b"xyz")))),
+            ),
+            (Value::from("d"), Value::Vector(vec![Value::Bool(true)])),
+        ]);
+        let mut bytes = value.serialize()?;
+        bytes.push(0xff);
+
+        let stats = Stats::of(&bytes)?;
+        assert_eq!((stats.maps, stats.map_sizes.get(&3)), (1, Some(&1)));
+        assert_eq!((stats.strs, stats.str_bytes), (3, 4));
+        assert_eq!((stats.slices, stats.slice_bytes), (1, 3));
+        assert_eq!((stats.vectors, stats.items), (2, 1));
+        assert_eq!((stats.somes, stats.constants), (1, 1));
+
+        // Legacy containers are counted like current ones.
+        let legacy = Stats::of(&[2, 1, 21, 1, 22, 3])?;
+        assert_eq!((legacy.vectors, legacy.items, legacy.small_ints), (1, 2, 2));
+
+        assert!(Stats::of(&[tag::VECTOR, 2, 21]).is_err());
+
+        // Packed vectors count what they stand for.
+        let packed = Value::Vector(vec![
+            Value::Ext(
+                ext::RUNS,
+                Box::new(Value::Vector(vec![
+                    Value::SmallU8(0),
+                    Value::I64(10_000_000),
+                ])),
+            ),
+            Value::Ext(ext::BITS, Box::new(Value::Slice(&[6, 0xff, 0x01]))),
+        ]);
+        let stats = Stats::of(&packed.serialize()?)?;
+        assert_eq!(
+            (stats.run_items, stats.bits, stats.exts),
+            (10_000_000, 10, 2)
+        );
+        assert_eq!((stats.vectors, stats.items), (2, 4));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+use std::cmp::Ordering;
+
+use crate::{
+    decode::{self, Cursor},
+    tag, Error, Result, SerializeOptions, Value,
+};
+
+impl<'a> Value<'a> {
+    /// Creates a [`Value::SmallU8`], failing with [`Error::InvalidValue`] if
+    /// `u` is over 235, the largest that fits in its single byte.
+    ///
+    /// ```rust
+    /// use lize::Value;
+    ///
+    /// assert_eq!(Value::small_u8(235)?, Value::SmallU8(235));
+    /// assert!(Value::small_u8(236).is_err());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn small_u8(u: u8) -> Result<Self> {
+        check_small_u8(u)?;
+        Ok(Value::SmallU8(u))
+    }
+
+    /// Creates a [`Value::HashMap`], failing with [`Error::InvalidValue`] if
+    /// two keys encode to the same bytes.
+    ///
+    /// ```rust
+    /// use lize::Value;
+    ///
+    /// let map = Value::map_from_pairs(vec![(Value::from("a"), Value::I64(1))])?;
+    /// assert!(Value::map_from_pairs(vec![
+    ///     (Value::from("a"), Value::I64(1)),
+    ///     (Value::StrLike("a".into()), Value::I64(2)),
+    /// ])
+    /// .is_err());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn map_from_pairs(pairs: Vec<(Value<'a>, Value<'a>)>) -> Result<Self> {
+        check_keys(&pairs)?;
+        Ok(Value::HashMap(pairs))
+    }
+
+    /// Creates a [`Value::Str`], which every side decodes as text, unlike the
+    /// same bytes in a [`Value::Slice`].
+    pub fn str(s: &'a str) -> Self {
+        Value::Str(s)
+    }
+
+    /// Checks every value in the tree for what the enum can't enforce by
+    /// itself, failing with [`Error::InvalidValue`] on the first problem:
+    ///
+    /// - [`Value::SmallU8`] holds at most 235;
+    /// - no two keys of a [`Value::HashMap`] encode to the same bytes, except
+    ///   NaN keys, which are never equal to anything, as in Python.
+    ///
+    /// Values built any other way are unsupported: they may fail to
+    /// serialize, or write payloads that other implementations reject or read
+    /// differently. Payloads of [`Value::Ext`] aren't checked against their
+    /// [`ext`](crate::ext) code.
+    ///
+    /// ```rust
+    /// use lize::Value;
+    ///
+    /// let value = Value::Vector(vec![Value::HashMap(vec![
+    ///     (Value::I64(1), Value::SmallU8(1)),
+    ///     (Value::I64(1), Value::SmallU8(2)),
+    /// ])]);
+    /// assert!(value.validate().is_err());
+    /// ```
+    pub fn validate(&self) -> Result<()> {
+        let mut stack = vec![self];
+        while let Some(value) = stack.pop() {
+            match value {
+                Value::SmallU8(u) => check_small_u8(*u)?,
+                Value::Vector(items) => stack.extend(items),
+                Value::HashMap(pairs) => {
+                    check_keys(pairs)?;
+                    stack.extend(pairs.iter().flat_map(|(k, v)| [k, v]));
+                }
+                Value::Optional(Some(inner)) | Value::Ext(_, inner) => stack.push(inner),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `bytes` holds exactly one well-formed value, failing where
+    /// [`Value::deserialize_from`] would, or with [`Error::TrailingBytes`] if
+    /// anything follows it. Only tags, lengths and strings are checked, without
+    /// building the value, so it's much cheaper than decoding. As with
+    /// [`Value::validate`], payloads of [`Value::Ext`] aren't checked against
+    /// their [`ext`](crate::ext) code.
+    ///
+    /// ```rust
+    /// use lize::Value;
+    ///
+    /// let bytes = Value::Vector(vec![Value::from("a"), Value::I64(1)]).serialize()?;
+    /// assert!(Value::validate_encoded(&bytes).is_ok());
+    /// assert!(Value::validate_encoded(&bytes[..bytes.len() - 1]).is_err());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn validate_encoded(bytes: &[u8]) -> Result<()> {
+        if bytes.is_empty() {
+            return Err(Error::EmptyInput.into());
+        }
+        let mut cursor = Cursor::new(bytes);
+        decode::check(&mut cursor)?;
+        if !cursor.rest().is_empty() {
+            return Err(Error::TrailingBytes(cursor.rest().len()).into());
+        }
+        Ok(())
+    }
+
+    /// Like [`Value::validate`], but only checks that no map has two keys that
+    /// encode to the same bytes, as a decoder would keep just one of them.
+    ///
+    /// ```rust
+    /// use lize::Value;
+    ///
+    /// let value = Value::HashMap(vec![
+    ///     (Value::from("id"), Value::SmallU8(1)),
+    ///     (Value::from("id"), Value::SmallU8(2)),
+    /// ]);
+    /// assert!(value.validate_keys().is_err());
+    /// ```
+    pub fn validate_keys(&self) -> Result<()> {
+        let mut stack = vec![self];
+        while let Some(value) = stack.pop() {
+            match value {
+                Value::Vector(items) => stack.extend(items),
+                Value::HashMap(pairs) => {
+                    check_keys(pairs)?;
+                    stack.extend(pairs.iter().flat_map(|(k, v)| [k, v]));
+                }
+                Value::Optional(Some(inner)) | Value::Ext(_, inner) => stack.push(inner),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the checks `options` ask for before writing.
+    pub(crate) fn check(&self, options: &SerializeOptions) -> Result<()> {
+        if options.validate {
+            self.validate()
+        } else if options.check_duplicate_keys {
+            self.validate_keys()
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn check_small_u8(u: u8) -> Result<()> {
+    if u > u8::MAX - tag::SMALL_U8 {
+        return Err(Error::InvalidValue(format!("SmallU8({}) is over 235", u)).into());
+    }
+    Ok(())
+}
+
+fn check_keys(pairs: &[(Value<'_>, Value<'_>)]) -> Result<()> {
+    let mut order: Vec<usize> = (0..pairs.len()).collect();
+    order.sort_by(|&a, &b| pairs[a].0.cmp_structural(&pairs[b].0));
+
+    for window in order.windows(2) {
+        let (a, b) = (window[0].min(window[1]), window[0].max(window[1]));
+        let key = &pairs[a].0;
+        if !is_nan(key) && key.cmp_structural(&pairs[b].0) == Ordering::Equal {
+            return Err(Error::InvalidValue(format!(
+                "map entries {} and {} have the same key",
+                a, b
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+fn is_nan(value: &Value<'_>) -> bool {
+    match value {
+        Value::F32(f) => f.is_nan(),
+        Value::F64(f) => f.is_nan(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let invalid = |err: anyhow::Error| match err.downcast::<Error>() {
+            Ok(Error::InvalidValue(reason)) => reason,
+            other => panic!("expected InvalidValue, got {:?}", other),
+        };
+
+        assert_eq!(
+            invalid(Value::small_u8(250).unwrap_err()),
+            "SmallU8(250) is over 235"
+        );
+        assert_eq!(
+            invalid(
+                Value::Optional(Some(Box::new(Value::Ext(1, Box::new(Value::SmallU8(236))))))
+                    .validate()
+                    .unwrap_err()
+            ),
+            "SmallU8(236) is over 235"
+        );
+
+        // Keys are only the same if they encode to the same bytes, so 1 as an
+        // `I64` and as a `SmallU8` are different keys, and so are strings and slices.
+        let map = Value::HashMap(vec![
+            (Value::from("x"), Value::I64(0)),
+            (Value::I64(1), Value::I64(1)),
+            (Value::SmallU8(1), Value::I64(2)),
+            (Value::SliceLike(b"x".to_vec()), Value::I64(3)),
+        ]);
+        assert!(map.validate().is_ok());
+
+        let map = Value::Vector(vec![Value::HashMap(vec![
+            (Value::from("x"), Value::I64(0)),
+            (Value::I64(1), Value::I64(1)),
+            (Value::StrLike("x".into()), Value::I64(2)),
+        ])]);
+        assert_eq!(
+            invalid(map.validate().unwrap_err()),
+            "map entries 0 and 2 have the same key"
+        );
+
+        let nans = Value::HashMap(vec![
+            (Value::F64(f64::NAN), Value::I64(1)),
+            (Value::F64(f64::NAN), Value::I64(2)),
+        ]);
+        assert!(nans.validate().is_ok());
+
+        let options = SerializeOptions::new().validate(true);
+        let mut buffer = crate::SmallVec::new();
+        assert!(map.serialize_with(&options, &mut buffer).is_err());
+        assert!(map.serialize_into(&mut buffer).is_ok());
+
+        let options = SerializeOptions::new().check_duplicate_keys(true);
+        assert_eq!(
+            invalid(map.serialize_with(&options, &mut buffer).unwrap_err()),
+            "map entries 0 and 2 have the same key"
+        );
+        assert!(nans.serialize_with(&options, &mut buffer).is_ok());
+    }
+}
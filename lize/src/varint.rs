@@ -0,0 +1,42 @@
+//! LEB128 lengths: 7 bits per byte, least significant group first, with the
+//! high bit set on every byte but the last. Lengths below 128 take one byte.
+
+use crate::{Error, Result};
+
+/// The most bytes a `u64` can take.
+const MAX_LEN: usize = 10;
+
+pub(crate) fn write(buffer: &mut impl Extend<u8>, mut n: usize) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            buffer.extend([byte]);
+            return;
+        }
+        buffer.extend([byte | 0x80]);
+    }
+}
+
+/// Reads a length from the start of `bytes`, returning it and how many bytes it took.
+pub(crate) fn read(bytes: &[u8]) -> Result<(usize, usize)> {
+    let mut n: u64 = 0;
+    for (i, byte) in bytes.iter().take(MAX_LEN).enumerate() {
+        let group = (byte & 0x7f) as u64;
+        if i == MAX_LEN - 1 && group > 1 {
+            return Err(Error::InvalidLength.into());
+        }
+        n |= group << (7 * i);
+
+        if byte & 0x80 == 0 {
+            let n = usize::try_from(n).map_err(|_| Error::InvalidLength)?;
+            return Ok((n, i + 1));
+        }
+    }
+
+    if bytes.len() < MAX_LEN {
+        Err(Error::UnexpectedEnd.into())
+    } else {
+        Err(Error::InvalidLength.into())
+    }
+}
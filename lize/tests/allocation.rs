@@ -0,0 +1,54 @@
+//! How much memory decoding untrusted input may take, measured with an
+//! allocator that tracks the most ever allocated at once.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use lize::Value;
+
+struct Peak {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for Peak {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let current = self.current.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        self.peak.fetch_max(current, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.current.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Peak = Peak {
+    current: AtomicUsize::new(0),
+    peak: AtomicUsize::new(0),
+};
+
+#[test]
+fn test_stacked_headers() {
+    // A hundred nested maps, each claiming 2^20 entries, about as many as
+    // there are bytes left, so every header passes the length check.
+    let mut data = vec![];
+    for _ in 0..100 {
+        data.extend([17, 0x80, 0x80, 0x40]);
+    }
+    data.resize(data.len() + (1 << 20), 20);
+
+    let before = ALLOCATOR.current.load(Ordering::Relaxed);
+    ALLOCATOR.peak.store(before, Ordering::Relaxed);
+    assert!(Value::deserialize_from(&data).is_err());
+    let used = ALLOCATOR.peak.load(Ordering::Relaxed) - before;
+
+    // Trusting the counts would reserve gigabytes; the entries that are
+    // really there take under a hundred bytes for each byte of input, with
+    // the room a growing vector keeps spare.
+    assert!(used < 128 * data.len(), "decoding took {used} bytes");
+}
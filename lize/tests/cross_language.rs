@@ -0,0 +1,153 @@
+//! Payloads exchanged with the Python module.
+//!
+//! `from_python.lize` is written by `python/tests/test_all.py` and must decode
+//! to [`from_python`] here; `from_rust.lize` is written here and checked by the
+//! Python tests. The `schema_*` fixtures do the same for frames written with
+//! [`schema`], and `hash_keys_from_rust.lize` holds a map keyed by binary
+//! hashes. Run with `LIZE_BLESS=1` to create a missing fixture.
+
+use std::{fs, path::PathBuf};
+
+use lize::{frame, Error, Result, Schema, SerializeOptions, SmallVec, Value};
+use sha2::{Digest, Sha256};
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("cross_language")
+        .join(name)
+}
+
+/// What the Python module encodes `{"name": "lize", "version": 3, ...}` as.
+fn from_python() -> Value<'static> {
+    Value::HashMap(vec![
+        (Value::from("name"), Value::from("lize")),
+        (Value::from("version"), Value::SmallU8(3)),
+        (Value::from("tags"), Value::from(vec!["fast", "small"])),
+        (
+            Value::from("nested"),
+            Value::HashMap(vec![(Value::from("ok"), Value::Bool(true))]),
+        ),
+        (Value::from("ratio"), Value::F64(0.1)),
+        (Value::from("blob"), Value::Slice(b"\x00\xff")),
+    ])
+}
+
+fn from_rust() -> Value<'static> {
+    Value::HashMap(vec![
+        (Value::from("name"), Value::from("lize")),
+        (Value::from("version"), Value::from(3_i64)),
+        (Value::from("tags"), Value::from(vec!["fast", "small"])),
+        (
+            Value::from("nested"),
+            Value::HashMap(vec![(Value::from("ok"), Value::from(true))]),
+        ),
+        (Value::from("ratio"), Value::from(0.1_f64)),
+        (Value::from("blob"), Value::from(&b"\x00\xff"[..])),
+    ])
+}
+
+#[test]
+fn test_decode_from_python() -> Result<()> {
+    let bytes = fs::read(fixture("from_python.lize"))?;
+    assert_eq!(Value::deserialize_from(&bytes)?, from_python());
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_for_python() -> Result<()> {
+    let path = fixture("from_rust.lize");
+    let bytes = from_rust().serialize()?;
+
+    if !path.exists() && std::env::var_os("LIZE_BLESS").is_some() {
+        fs::write(&path, &bytes)?;
+    }
+
+    assert_eq!(bytes, fs::read(&path)?);
+
+    Ok(())
+}
+
+/// The schema both sides write records with.
+fn schema() -> Result<Schema> {
+    Schema::new(["id", "name", "email", "tags", "active"])
+}
+
+/// A record without an `email`, as each side encodes it.
+fn record() -> Value<'static> {
+    Value::HashMap(vec![
+        (Value::from("active"), Value::Bool(true)),
+        (Value::from("id"), Value::SmallU8(7)),
+        (Value::from("name"), Value::from("lize")),
+        (Value::from("tags"), Value::from(vec!["fast", "small"])),
+    ])
+}
+
+#[test]
+fn test_decode_schema_from_python() -> Result<()> {
+    let bytes = fs::read(fixture("schema_from_python.lize"))?;
+    let (payload, rest) = frame::open_with_schema(&bytes, None, &schema()?)?;
+    assert!(rest.is_empty());
+
+    // Decoded in field order, leaving out the absent `email`.
+    let field = |name: &str| Value::StrLike(name.into());
+    assert_eq!(
+        schema()?.decode(Value::deserialize_from(&payload)?)?,
+        Value::HashMap(vec![
+            (field("id"), Value::SmallU8(7)),
+            (field("name"), Value::from("lize")),
+            (field("tags"), Value::from(vec!["fast", "small"])),
+            (field("active"), Value::Bool(true)),
+        ])
+    );
+
+    let other = Schema::new(["id", "name", "email", "active", "tags"])?;
+    let err = frame::open_with_schema(&bytes, None, &other).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::SchemaMismatch { .. })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_schema_for_python() -> Result<()> {
+    let path = fixture("schema_from_rust.lize");
+    let schema = schema()?;
+    let bytes = frame::seal_with_schema(&schema.encode(record())?.serialize()?, None, &schema)?;
+
+    if !path.exists() && std::env::var_os("LIZE_BLESS").is_some() {
+        fs::write(&path, &bytes)?;
+    }
+
+    assert_eq!(bytes, fs::read(&path)?);
+
+    Ok(())
+}
+
+/// A map from the first 16 bytes of the SHA-256 of `key {i}` to `i`, written
+/// canonically, which the Python tests decode and write back the same.
+#[test]
+fn test_encode_hash_keys_for_python() -> Result<()> {
+    let path = fixture("hash_keys_from_rust.lize");
+    let value = Value::HashMap(
+        (0..8)
+            .map(|i| {
+                let hash = Sha256::digest(format!("key {}", i));
+                (Value::SliceLike(hash[..16].to_vec()), Value::SmallU8(i))
+            })
+            .collect(),
+    );
+    let mut bytes = SmallVec::new();
+    value.serialize_with(&SerializeOptions::new().canonical(true), &mut bytes)?;
+
+    if !path.exists() && std::env::var_os("LIZE_BLESS").is_some() {
+        fs::write(&path, &bytes)?;
+    }
+
+    assert_eq!(&bytes[..], fs::read(&path)?);
+
+    Ok(())
+}
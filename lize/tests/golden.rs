@@ -0,0 +1,203 @@
+//! Golden fixtures for the wire format.
+//!
+//! Every case below is encoded and compared byte-for-byte against
+//! `tests/golden/v{FORMAT_VERSION}/<name>.bin`. Fixtures from older versions
+//! must keep decoding to the same value.
+//!
+//! To add fixtures for a new format version, bump `FORMAT_VERSION` and run:
+//!
+//! ```text
+//! LIZE_BLESS=1 cargo test -p lize --test golden
+//! ```
+//!
+//! Blessing only writes *missing* fixtures; committed ones are never overwritten.
+
+use std::{fs, path::PathBuf};
+
+use lize::{ext, packed, Result, Value, ENDIANNESS, FORMAT_VERSION};
+
+fn cases() -> Vec<(&'static str, Value<'static>)> {
+    vec![
+        ("i64", Value::I64(-6969694200)),
+        ("slice", Value::Slice(b"hello")),
+        (
+            "vector",
+            Value::Vector(vec![Value::I64(1234), Value::Slice(b"world")]),
+        ),
+        (
+            "hashmap",
+            Value::HashMap(vec![
+                (Value::Slice(b"hello"), Value::Slice(b"world")),
+                (Value::Slice(b"money"), Value::I64(6969694200)),
+            ]),
+        ),
+        ("bool_true", Value::Bool(true)),
+        ("bool_false", Value::Bool(false)),
+        ("f64", Value::F64(-std::f64::consts::PI)),
+        (
+            "optional_some",
+            Value::Optional(Some(Box::new(Value::I32(-7)))),
+        ),
+        ("optional_none", Value::Optional(None)),
+        ("i32", Value::I32(123456789)),
+        ("f32", Value::F32(1.5)),
+        ("u8", Value::U8(255)),
+        ("small_u8", Value::SmallU8(235)),
+        ("str", Value::Str("hello")),
+        (
+            "ext",
+            Value::Ext(
+                ext::FRACTION,
+                Box::new(Value::Vector(vec![Value::SmallU8(1), Value::SmallU8(3)])),
+            ),
+        ),
+        ("empty_vector", Value::Vector(vec![])),
+        ("empty_hashmap", Value::HashMap(vec![])),
+        ("long_slice", Value::Slice(&[7; 300])),
+        (
+            "short_items",
+            Value::HashMap(vec![(
+                Value::Str("abc"),
+                Value::Vector(vec![Value::Str("a"), Value::Str("b")]),
+            )]),
+        ),
+        // Every byte differs, so a byte-swapped read can't pass.
+        ("i64_byte_order", Value::I64(0x0102030405060708)),
+        ("i32_byte_order", Value::I32(0x01020304)),
+        ("f64_byte_order", Value::F64(f64::from_bits(0x3ff2030405060708))),
+        ("f32_byte_order", Value::F32(f32::from_bits(0x3f020304))),
+        (
+            "runs",
+            Value::Ext(
+                ext::RUNS,
+                Box::new(Value::Vector(vec![
+                    Value::SmallU8(0),
+                    Value::I32(1000),
+                    Value::Str("x"),
+                    Value::SmallU8(2),
+                ])),
+            ),
+        ),
+        (
+            "bits",
+            Value::Ext(ext::BITS, Box::new(Value::Slice(&[6, 0b1010_0101, 0b01]))),
+        ),
+    ]
+}
+
+fn golden_dir(version: u8) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("v{version}"))
+}
+
+#[test]
+fn test_golden_current_version() -> Result<()> {
+    let dir = golden_dir(FORMAT_VERSION);
+    let bless = std::env::var_os("LIZE_BLESS").is_some();
+
+    for (name, value) in cases() {
+        let path = dir.join(format!("{name}.bin"));
+        let bytes = value.serialize()?;
+
+        if !path.exists() {
+            if !bless {
+                panic!("missing fixture {path:?}, run with LIZE_BLESS=1 to create it");
+            }
+            fs::create_dir_all(&dir)?;
+            fs::write(&path, &bytes)?;
+        }
+
+        let expected = fs::read(&path)?;
+        assert_eq!(
+            bytes, expected,
+            "encoding of {name:?} changed; bump FORMAT_VERSION and add new fixtures"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_golden_old_versions_decode() -> Result<()> {
+    for version in 1..=FORMAT_VERSION {
+        let dir = golden_dir(version);
+        assert!(dir.exists(), "missing fixtures for format v{version}");
+
+        for (name, value) in cases() {
+            let path = dir.join(format!("{name}.bin"));
+            if !path.exists() {
+                // The case was added after this version.
+                continue;
+            }
+
+            let bytes = fs::read(&path)?;
+            let decoded = Value::deserialize_from(&bytes)?;
+            assert_eq!(
+                decoded, value,
+                "v{version} fixture {name:?} decodes differently"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// The fixtures spell out their bytes, rather than taking them from
+/// `to_le_bytes`, so a big-endian host reading or writing them natively fails.
+#[test]
+fn test_golden_byte_order() -> Result<()> {
+    assert_eq!(ENDIANNESS, "little");
+    let dir = golden_dir(FORMAT_VERSION);
+    let expected: [(&str, &[u8]); 4] = [
+        ("i64_byte_order", &[0, 8, 7, 6, 5, 4, 3, 2, 1]),
+        ("i32_byte_order", &[11, 4, 3, 2, 1]),
+        ("f64_byte_order", &[8, 8, 7, 6, 5, 4, 3, 0xf2, 0x3f]),
+        ("f32_byte_order", &[12, 4, 3, 2, 0x3f]),
+    ];
+    for (name, bytes) in expected {
+        assert_eq!(fs::read(dir.join(format!("{name}.bin")))?, bytes, "{name}");
+    }
+
+    assert_eq!(
+        Value::deserialize_from(&[0, 8, 7, 6, 5, 4, 3, 2, 1])?,
+        Value::I64(72623859790382856)
+    );
+    assert_eq!(
+        Value::deserialize_from(&[12, 4, 3, 2, 0x3f])?,
+        Value::F32(f32::from_bits(0x3f020304))
+    );
+    Ok(())
+}
+
+/// Packed vectors spell out their layout too, since readers in other languages
+/// expand them by hand.
+#[test]
+fn test_golden_packed() -> Result<()> {
+    let dir = golden_dir(FORMAT_VERSION);
+    // Ext, code 13, a vector of 4: `0` 1000 times, then `"x"` twice.
+    let runs: &[u8] = &[14, 13, 16, 4, 20, 11, 0xe8, 3, 0, 0, 19, 1, b'x', 22];
+    assert_eq!(fs::read(dir.join("runs.bin"))?, runs);
+
+    let value = Value::deserialize_from(runs)?;
+    let (code, payload) = value.as_ext().expect("an ext");
+    assert_eq!(code, ext::RUNS);
+    let items = packed::runs(payload)?;
+    assert_eq!(packed::runs_len(&items), 1002);
+    assert_eq!(packed::expand(&items).last(), Some(&Value::Str("x")));
+
+    // Ext, code 7, a slice of 3: 6 unused bits, then 10 booleans from the
+    // lowest bit of each byte up.
+    let bits: &[u8] = &[14, 7, 18, 3, 6, 0b1010_0101, 0b01];
+    assert_eq!(fs::read(dir.join("bits.bin"))?, bits);
+
+    let value = Value::deserialize_from(bits)?;
+    let (code, payload) = value.as_ext().expect("an ext");
+    assert_eq!(code, ext::BITS);
+    assert_eq!(
+        packed::bits(payload)?.collect::<Vec<_>>(),
+        [true, false, true, false, false, true, false, true, true, false]
+    );
+    Ok(())
+}
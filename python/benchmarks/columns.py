@@ -0,0 +1,87 @@
+"""Compares `to_columns` with deserializing a list of dicts and pivoting it in Python.
+
+    python python/benchmarks/columns.py [--rows 1000000] [--output results/columns.txt]
+
+Each row has a few more keys than the ones asked for, as in an analytics
+query picking columns out of wide records.
+"""
+
+import argparse
+import os
+import platform
+import random
+import time
+from pathlib import Path
+from typing import Any, Callable, Dict, List
+
+import lize
+
+KEYS = ["id", "score", "city"]
+ROUNDS = 3
+
+
+def build(rows: int) -> bytes:
+    rng = random.Random("columns")
+    return lize.serialize(
+        [
+            {
+                "id": i,
+                "score": rng.uniform(0, 100),
+                "city": rng.choice(["Taipei", "Berlin", "Lima"]),
+                "active": rng.random() < 0.5,
+                "tags": ["a", "b"],
+                "note": "x" * rng.randrange(20),
+            }
+            for i in range(rows)
+        ]
+    )
+
+
+def pivot(data: bytes) -> Dict[str, List[Any]]:
+    rows = lize.deserialize(data)
+    return {key: [row.get(key) for row in rows] for key in KEYS}
+
+
+STRATEGIES: Dict[str, Callable[[bytes], Any]] = {
+    "deserialize + pivot": pivot,
+    "to_columns": lambda data: lize.to_columns(data, KEYS),
+    "to_columns typed": lambda data: lize.to_columns(data, KEYS, typed=True),
+}
+
+
+def best_of(strategy: Callable[[bytes], Any], data: bytes) -> float:
+    times = []
+    for _ in range(ROUNDS):
+        start = time.perf_counter()
+        strategy(data)
+        times.append(time.perf_counter() - start)
+    return min(times)
+
+
+def main() -> None:
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--rows", type=int, default=1_000_000)
+    parser.add_argument("--output", type=Path, help="also write the results to this file")
+    args = parser.parse_args()
+
+    data = build(args.rows)
+    lines: List[str] = [
+        f"# python {platform.python_version()}, {platform.machine()}, {os.cpu_count()} cpus, "
+        f"{args.rows} rows, {len(data)} bytes, best of {ROUNDS}",
+        f"{'strategy':<22} {'time':>9} {'speedup':>8}",
+    ]
+    baseline = None
+    for name, strategy in STRATEGIES.items():
+        elapsed = best_of(strategy, data)
+        baseline = baseline or elapsed
+        lines.append(f"{name:<22} {elapsed:>7.3f} s {baseline / elapsed:>7.2f}x")
+
+    report = "\n".join(lines) + "\n"
+    print(report, end="")
+    if args.output is not None:
+        args.output.parent.mkdir(parents=True, exist_ok=True)
+        args.output.write_text(report)
+
+
+if __name__ == "__main__":
+    main()
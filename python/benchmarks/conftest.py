@@ -0,0 +1,5 @@
+import sys
+from pathlib import Path
+
+# Lets the benchmarks `import corpora` regardless of where pytest is run from.
+sys.path.insert(0, str(Path(__file__).resolve().parent))
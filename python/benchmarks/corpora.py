@@ -0,0 +1,74 @@
+"""Benchmark corpora shared by the Rust and Python benchmarks.
+
+Run this file to (re)generate the fixtures in `lize/benches/corpora/`:
+
+    python python/benchmarks/corpora.py
+
+The Rust benchmarks decode these fixtures into `Value`s, and the Python
+benchmarks decode them into objects, so both measure the same data shapes.
+"""
+
+import random
+from pathlib import Path
+from typing import Any, Callable, Dict
+
+import lize
+
+CORPORA_DIR = Path(__file__).resolve().parents[2] / "lize" / "benches" / "corpora"
+
+
+def nested_maps(rng: random.Random) -> Any:
+    return [
+        {
+            "id": i,
+            "name": f"user-{i}",
+            "active": rng.random() < 0.5,
+            "score": rng.uniform(0, 100),
+            "tags": [rng.choice(["red", "green", "blue"]) for _ in range(3)],
+            "address": {"city": rng.choice(["Taipei", "Berlin", "Lima"]), "zip": rng.randrange(99999)},
+        }
+        for i in range(200)
+    ]
+
+
+def string_list(rng: random.Random) -> Any:
+    alphabet = "abcdefghijklmnopqrstuvwxyz "
+    return ["".join(rng.choice(alphabet) for _ in range(rng.randrange(10, 40))) for _ in range(1000)]
+
+
+def numeric_array(rng: random.Random) -> Any:
+    return [rng.uniform(-1e6, 1e6) for _ in range(1000)]
+
+
+def deep_tree(rng: random.Random) -> Any:
+    def node(depth: int) -> Any:
+        if depth == 0:
+            return rng.randrange(200)
+        return [node(depth - 1), node(depth - 1)]
+
+    return node(6)
+
+
+CORPORA: Dict[str, Callable[[random.Random], Any]] = {
+    "nested_maps": nested_maps,
+    "string_list": string_list,
+    "numeric_array": numeric_array,
+    "deep_tree": deep_tree,
+}
+
+
+def load(name: str) -> Any:
+    """Loads a committed corpus as Python objects."""
+    return lize.deserialize((CORPORA_DIR / f"{name}.lize").read_bytes())
+
+
+def main() -> None:
+    CORPORA_DIR.mkdir(parents=True, exist_ok=True)
+    for name, build in CORPORA.items():
+        data = lize.serialize(build(random.Random(0)))
+        (CORPORA_DIR / f"{name}.lize").write_bytes(data)
+        print(f"{name}: {len(data)} bytes")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,84 @@
+"""Measures decoding small records with repeated `deserialize` calls and with
+a `lize.Decoder`.
+
+    python python/benchmarks/decoder.py [--count 100000] [--output results/decoder.txt]
+
+Every record has the same keys, which a `Decoder` builds once and shares;
+`decode_into` also refills one dict instead of building a new one each time.
+"""
+
+import argparse
+import os
+import platform
+import time
+from pathlib import Path
+from typing import Callable, Dict, List
+
+import lize
+
+ROUNDS = 7
+
+
+def records(count: int) -> List[bytes]:
+    return [
+        lize.serialize({"id": i, "host": "web-1", "latency_ms": i % 250 / 10, "status": 200, "ok": True})
+        for i in range(count)
+    ]
+
+
+def strategies() -> Dict[str, Callable[[List[bytes]], None]]:
+    def deserialize(payloads: List[bytes]) -> None:
+        for payload in payloads:
+            lize.deserialize(payload)
+
+    def decode(payloads: List[bytes]) -> None:
+        decoder = lize.Decoder()
+        for payload in payloads:
+            decoder.decode(payload)
+
+    def decode_into(payloads: List[bytes]) -> None:
+        decoder, scratch = lize.Decoder(), {}
+        for payload in payloads:
+            decoder.decode_into(payload, scratch)
+
+    return {"deserialize": deserialize, "Decoder.decode": decode, "decode_into": decode_into}
+
+
+def best_time(run: Callable[[List[bytes]], None], payloads: List[bytes]) -> float:
+    best = float("inf")
+    for _ in range(ROUNDS):
+        start = time.perf_counter()
+        run(payloads)
+        best = min(best, time.perf_counter() - start)
+    return best
+
+
+def main() -> None:
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--count", type=int, default=100_000)
+    parser.add_argument("--output", type=Path, help="also write the results to this file")
+    args = parser.parse_args()
+
+    payloads = records(args.count)
+    lines: List[str] = [
+        f"# python {platform.python_version()}, {platform.machine()}, {os.cpu_count()} cpus, "
+        f"{args.count} records of 5 keys, best of {ROUNDS}",
+        f"{'strategy':<15} {'time':>9} {'records/s':>12} {'speedup':>8}",
+    ]
+    baseline = None
+    for name, run in strategies().items():
+        elapsed = best_time(run, payloads)
+        baseline = baseline or elapsed
+        lines.append(
+            f"{name:<15} {elapsed:>7.3f} s {args.count / elapsed:>12,.0f} {baseline / elapsed:>7.2f}x"
+        )
+
+    report = "\n".join(lines) + "\n"
+    print(report, end="")
+    if args.output is not None:
+        args.output.parent.mkdir(parents=True, exist_ok=True)
+        args.output.write_text(report)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,97 @@
+"""Measures what releasing the GIL during `deserialize` buys under threads.
+
+Each large buffer is decoded by one thread and then by four threads at once,
+with and without `release_gil=True`, and the aggregate throughput is printed:
+
+    python python/benchmarks/gil_release.py [--output results/gil_release.txt]
+
+Only parsing the bytes runs without the GIL; building the Python objects
+still holds it, so the gain is bounded by how much time parsing takes.
+"""
+
+import argparse
+import os
+import platform
+import random
+import time
+from concurrent.futures import ThreadPoolExecutor
+from pathlib import Path
+from typing import Callable, Dict, List
+
+import lize
+
+THREADS = 4
+ROUNDS = 8
+
+
+def floats(rng: random.Random) -> bytes:
+    return lize.serialize([rng.uniform(-1e6, 1e6) for _ in range(200_000)])
+
+
+def strings(rng: random.Random) -> bytes:
+    alphabet = "abcdefghijklmnopqrstuvwxyz "
+    return lize.serialize(
+        ["".join(rng.choice(alphabet) for _ in range(rng.randrange(10, 40))) for _ in range(100_000)]
+    )
+
+
+def records(rng: random.Random) -> bytes:
+    return lize.serialize(
+        [
+            {"id": i, "active": rng.random() < 0.5, "score": rng.uniform(0, 100)}
+            for i in range(50_000)
+        ]
+    )
+
+
+BUFFERS: Dict[str, Callable[[random.Random], bytes]] = {
+    "floats": floats,
+    "strings": strings,
+    "records": records,
+}
+
+
+def throughput(data: bytes, threads: int, release_gil: bool) -> float:
+    """Returns the aggregate decoded megabytes per second over all threads."""
+
+    def work(_: int) -> None:
+        for _ in range(ROUNDS):
+            lize.deserialize(data, release_gil=release_gil)
+
+    with ThreadPoolExecutor(threads) as pool:
+        start = time.perf_counter()
+        list(pool.map(work, range(threads)))
+        elapsed = time.perf_counter() - start
+
+    return len(data) * ROUNDS * threads / elapsed / 1e6
+
+
+def main() -> None:
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--output", type=Path, help="also write the results to this file")
+    args = parser.parse_args()
+
+    lines: List[str] = [
+        f"# python {platform.python_version()}, {platform.machine()}, {os.cpu_count()} cpus, "
+        f"{THREADS} threads, {ROUNDS} rounds",
+        f"{'buffer':<10} {'size':>10} {'gil':>6} {'1 thread':>12} {f'{THREADS} threads':>12} {'speedup':>8}",
+    ]
+    for name, build in BUFFERS.items():
+        data = build(random.Random(name))
+        for release_gil in (False, True):
+            single = throughput(data, 1, release_gil)
+            multi = throughput(data, THREADS, release_gil)
+            lines.append(
+                f"{name:<10} {len(data):>10} {'free' if release_gil else 'held':>6} "
+                f"{single:>9.1f} MB/s {multi:>7.1f} MB/s {multi / single:>7.2f}x"
+            )
+
+    report = "\n".join(lines) + "\n"
+    print(report, end="")
+    if args.output is not None:
+        args.output.parent.mkdir(parents=True, exist_ok=True)
+        args.output.write_text(report)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,64 @@
+"""Measures `deserialize` on large lists, next to `pickle.loads` on the same data.
+
+    python python/benchmarks/large_lists.py [--count 5000000] [--output results/large_lists.txt]
+
+Lists are built at their final length as they're decoded, so this mostly
+measures creating the items themselves.
+"""
+
+import argparse
+import os
+import pickle
+import platform
+import time
+from pathlib import Path
+from typing import Any, Callable, Dict, List
+
+import lize
+
+ROUNDS = 3
+
+
+def payloads(count: int) -> Dict[str, Any]:
+    return {
+        "ints": list(range(count)),
+        "small ints": [i % 200 for i in range(count)],
+        "nested lists": [[i, i + 1, i + 2, i + 3] for i in range(count // 4)],
+        "dicts": [{"id": i, "tags": [i]} for i in range(count // 10)],
+    }
+
+
+def best_time(loads: Callable[[bytes], Any], data: bytes) -> float:
+    best = float("inf")
+    for _ in range(ROUNDS):
+        start = time.perf_counter()
+        loads(data)
+        best = min(best, time.perf_counter() - start)
+    return best
+
+
+def main() -> None:
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--count", type=int, default=5_000_000)
+    parser.add_argument("--output", type=Path, help="also write the results to this file")
+    args = parser.parse_args()
+
+    lines: List[str] = [
+        f"# python {platform.python_version()}, {platform.machine()}, {os.cpu_count()} cpus, "
+        f"{args.count} items, best of {ROUNDS}",
+        f"{'payload':<14} {'lize':>9} {'pickle':>9} {'ratio':>7}",
+    ]
+    for name, value in payloads(args.count).items():
+        ours = best_time(lize.deserialize, lize.serialize(value))
+        theirs = best_time(pickle.loads, pickle.dumps(value, protocol=pickle.HIGHEST_PROTOCOL))
+        lines.append(f"{name:<14} {ours:>7.3f} s {theirs:>7.3f} s {theirs / ours:>6.2f}x")
+
+    report = "\n".join(lines) + "\n"
+    print(report, end="")
+    if args.output is not None:
+        args.output.parent.mkdir(parents=True, exist_ok=True)
+        args.output.write_text(report)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,65 @@
+"""Measures `serialize` on a small RPC-style message, with and without the
+fast path for default options.
+
+    python python/benchmarks/small_messages.py [--count 1000000] [--output results/small_messages.txt]
+
+Passing any keyword argument to `serialize` takes the general path, so
+`canonical=False` measures it on the same message.
+"""
+
+import argparse
+import os
+import platform
+import time
+from pathlib import Path
+from typing import Any, Callable, Dict, List
+
+import lize
+
+MESSAGE = {"id": 7, "ok": True}
+ROUNDS = 3
+
+STRATEGIES: Dict[str, Callable[[Any], bytes]] = {
+    "general path": lambda value: lize.serialize(value, canonical=False),
+    "fast path": lambda value: lize.serialize(value),
+}
+
+
+def best_time(serialize: Callable[[Any], bytes], count: int) -> float:
+    best = float("inf")
+    for _ in range(ROUNDS):
+        start = time.perf_counter()
+        for _ in range(count):
+            serialize(MESSAGE)
+        best = min(best, time.perf_counter() - start)
+    return best
+
+
+def main() -> None:
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--count", type=int, default=1_000_000)
+    parser.add_argument("--output", type=Path, help="also write the results to this file")
+    args = parser.parse_args()
+
+    lines: List[str] = [
+        f"# python {platform.python_version()}, {platform.machine()}, {os.cpu_count()} cpus, "
+        f"{args.count} serializations of {MESSAGE!r}, best of {ROUNDS}",
+        f"{'strategy':<14} {'time':>9} {'ops/s':>12} {'speedup':>8}",
+    ]
+    baseline = None
+    for name, serialize in STRATEGIES.items():
+        elapsed = best_time(serialize, args.count)
+        baseline = baseline or elapsed
+        lines.append(
+            f"{name:<14} {elapsed:>7.3f} s {args.count / elapsed:>12,.0f} {baseline / elapsed:>7.2f}x"
+        )
+
+    report = "\n".join(lines) + "\n"
+    print(report, end="")
+    if args.output is not None:
+        args.output.parent.mkdir(parents=True, exist_ok=True)
+        args.output.write_text(report)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,52 @@
+"""Compares lize against pickle, json and msgpack on the shared corpora.
+
+Requires `pytest-benchmark` (and optionally `msgpack`):
+
+    pytest python/benchmarks --benchmark-columns=mean,ops
+
+The encoded size of each payload is reported in the `size` extra info column.
+"""
+
+import json
+import pickle
+
+import pytest
+
+import lize
+from corpora import CORPORA, load
+
+try:
+    import msgpack
+except ImportError:
+    msgpack = None
+
+
+CODECS = {
+    "lize": (lize.serialize, lize.deserialize),
+    "pickle": (pickle.dumps, pickle.loads),
+    "json": (lambda obj: json.dumps(obj).encode(), json.loads),
+}
+if msgpack is not None:
+    CODECS["msgpack"] = (msgpack.packb, msgpack.unpackb)
+
+
+@pytest.mark.parametrize("codec", list(CODECS))
+@pytest.mark.parametrize("corpus", list(CORPORA))
+def test_serialize(benchmark, corpus, codec):
+    dumps, _ = CODECS[codec]
+    data = load(corpus)
+
+    benchmark.group = f"serialize-{corpus}"
+    result = benchmark(dumps, data)
+    benchmark.extra_info["size"] = len(result)
+
+
+@pytest.mark.parametrize("codec", list(CODECS))
+@pytest.mark.parametrize("corpus", list(CORPORA))
+def test_deserialize(benchmark, corpus, codec):
+    dumps, loads = CODECS[codec]
+    encoded = dumps(load(corpus))
+
+    benchmark.group = f"deserialize-{corpus}"
+    benchmark(loads, encoded)
+    benchmark.extra_info["size"] = len(encoded)
@@ -0,0 +1,86 @@
+"""Measures `serialize` on lists of 1000-byte chunks, untuned and with
+`lize.tune` set to the payload's size.
+
+    python python/benchmarks/tune.py [--megabytes 256] [--output results/tune.txt]
+
+Each payload is serialized until about `--megabytes` have been written. The
+output buffer starts on the stack and doubles each time it fills, so the
+allocations column is how many times it's allocated: once per power of two
+past 128 bytes untuned, and once when tuned.
+"""
+
+import argparse
+import os
+import platform
+import time
+from pathlib import Path
+from typing import Any, Dict, List
+
+import lize
+
+ROUNDS = 3
+STACK_N = 128
+
+PAYLOADS: Dict[str, Any] = {
+    "4 KiB": [b"x" * 1000] * 4,
+    "64 KiB": [b"x" * 1000] * 64,
+    "1 MiB": [b"x" * 1000] * 1_024,
+    "16 MiB": [b"x" * 1000] * 16_384,
+}
+
+
+def allocations(size: int, reserve: int) -> int:
+    if size <= STACK_N:
+        return 0
+    if reserve >= size:
+        return 1
+    return (size - 1).bit_length() - (STACK_N.bit_length() - 1)
+
+
+def best_time(value: Any, count: int) -> float:
+    best = float("inf")
+    for _ in range(ROUNDS):
+        start = time.perf_counter()
+        for _ in range(count):
+            lize.serialize(value)
+        best = min(best, time.perf_counter() - start)
+    return best
+
+
+def main() -> None:
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--megabytes", type=int, default=256)
+    parser.add_argument("--output", type=Path, help="also write the results to this file")
+    args = parser.parse_args()
+
+    lines: List[str] = [
+        f"# python {platform.python_version()}, {platform.machine()}, {os.cpu_count()} cpus, "
+        f"{args.megabytes} MB per payload, best of {ROUNDS}",
+        f"{'payload':<8} {'size':>9} {'tuned':>6} {'allocations':>12} {'time':>9} {'speedup':>8}",
+    ]
+    previous = lize.tune(0)
+    try:
+        for name, value in PAYLOADS.items():
+            size = len(lize.serialize(value))
+            count = max(args.megabytes * 1_000_000 // size, 1)
+            baseline = None
+            for reserve in (0, size):
+                lize.tune(reserve)
+                elapsed = best_time(value, count)
+                baseline = baseline or elapsed
+                lines.append(
+                    f"{name:<8} {size:>9} {'yes' if reserve else 'no':>6} "
+                    f"{allocations(size, reserve):>12} {elapsed:>7.3f} s {baseline / elapsed:>7.2f}x"
+                )
+    finally:
+        lize.tune(previous)
+
+    report = "\n".join(lines) + "\n"
+    print(report, end="")
+    if args.output is not None:
+        args.output.parent.mkdir(parents=True, exist_ok=True)
+        args.output.write_text(report)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,79 @@
+"""Measures writing small records to a file with `lize.Writer`, one frame per
+write and in batches, with and without `fsync`.
+
+    python python/benchmarks/writer.py [--count 100000] [--dir /tmp] [--output results/writer.txt]
+
+The file is opened by the writer from a path. Unbatched `fsync` waits on the
+disk once per record, so on real disks it's by far the slowest; point `--dir`
+at the disk to measure, since a tmpfs makes `fsync` nearly free. With
+`sync="os"`, encoding each record costs far more than the `write` call that
+batching saves, so expect batching to help only with `fsync`.
+"""
+
+import argparse
+import os
+import platform
+import tempfile
+import time
+from pathlib import Path
+from typing import Any, Dict, List
+
+import lize
+
+ROUNDS = 3
+
+STRATEGIES: Dict[str, Dict[str, Any]] = {
+    "unbatched, os": {"batch_size": 1, "sync": "os"},
+    "batch 256, os": {"batch_size": 256, "sync": "os"},
+    "unbatched, fsync": {"batch_size": 1, "sync": "fsync"},
+    "batch 256, fsync": {"batch_size": 256, "sync": "fsync"},
+}
+
+
+def records(count: int) -> List[Dict[str, Any]]:
+    return [{"id": i, "user": f"user-{i % 100}", "action": "login", "ok": i % 7 != 0} for i in range(count)]
+
+
+def best_time(path: str, kwargs: Dict[str, Any], values: List[Dict[str, Any]]) -> float:
+    best = float("inf")
+    for _ in range(ROUNDS):
+        start = time.perf_counter()
+        with lize.Writer(path, **kwargs) as writer:
+            for value in values:
+                writer.write(value)
+        best = min(best, time.perf_counter() - start)
+    return best
+
+
+def main() -> None:
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--count", type=int, default=100_000)
+    parser.add_argument("--dir", help="where to write the file, by default the temporary directory")
+    parser.add_argument("--output", type=Path, help="also write the results to this file")
+    args = parser.parse_args()
+
+    values = records(args.count)
+    lines: List[str] = [
+        f"# python {platform.python_version()}, {platform.machine()}, {os.cpu_count()} cpus, "
+        f"{args.count} records of 4 keys, best of {ROUNDS}",
+        f"{'strategy':<17} {'time':>9} {'records/s':>12} {'speedup':>8}",
+    ]
+    with tempfile.TemporaryDirectory(dir=args.dir) as directory:
+        path = os.path.join(directory, "records.lize")
+        baseline = None
+        for name, kwargs in STRATEGIES.items():
+            elapsed = best_time(path, kwargs, values)
+            baseline = baseline or elapsed
+            lines.append(
+                f"{name:<17} {elapsed:>7.3f} s {args.count / elapsed:>12,.0f} {baseline / elapsed:>7.2f}x"
+            )
+
+    report = "\n".join(lines) + "\n"
+    print(report, end="")
+    if args.output is not None:
+        args.output.parent.mkdir(parents=True, exist_ok=True)
+        args.output.write_text(report)
+
+
+if __name__ == "__main__":
+    main()
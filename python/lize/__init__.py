@@ -1,4 +1,103 @@
-from .lize import Runnable, deserialize, serialize
+from .aio import AsyncReader, AsyncWriter
+from .lize import (
+    DELETE,
+    AllocLimitExceeded,
+    AuthenticationFailed,
+    BrokenRunnable,
+    Cancelled,
+    Decoder,
+    EncodeError,
+    FixedInt,
+    ItemIterator,
+    Problem,
+    Reader,
+    Runnable,
+    Schema,
+    SerializedBuffer,
+    ValidationError,
+    Writer,
+    content_hash,
+    deserialize,
+    deserialize_first,
+    deserialize_typeddict,
+    diagnose,
+    diff,
+    equal,
+    estimate_decoded_size,
+    features,
+    format_version,
+    from_arrow,
+    hexdump,
+    iter_deserialize,
+    load_as,
+    merge,
+    options,
+    read_metadata,
+    redact,
+    register,
+    register_extension,
+    runnable,
+    serialize,
+    serialize_buffer,
+    serialize_map_items,
+    serialize_with_info,
+    to_arrow,
+    to_columns,
+    tune,
+    unwrap_raw,
+    validate_bytes,
+    wrap_raw,
+)
 
-__all__ = ["Runnable", "deserialize", "serialize"]
+__all__ = [
+    "DELETE",
+    "AsyncReader",
+    "AsyncWriter",
+    "AllocLimitExceeded",
+    "AuthenticationFailed",
+    "BrokenRunnable",
+    "Cancelled",
+    "Decoder",
+    "EncodeError",
+    "FixedInt",
+    "ItemIterator",
+    "Problem",
+    "Reader",
+    "Runnable",
+    "Schema",
+    "SerializedBuffer",
+    "ValidationError",
+    "Writer",
+    "content_hash",
+    "deserialize",
+    "deserialize_first",
+    "deserialize_typeddict",
+    "diagnose",
+    "diff",
+    "equal",
+    "estimate_decoded_size",
+    "features",
+    "format_version",
+    "from_arrow",
+    "hexdump",
+    "iter_deserialize",
+    "load_as",
+    "merge",
+    "options",
+    "read_metadata",
+    "redact",
+    "register",
+    "register_extension",
+    "runnable",
+    "serialize",
+    "serialize_buffer",
+    "serialize_map_items",
+    "serialize_with_info",
+    "to_arrow",
+    "to_columns",
+    "tune",
+    "unwrap_raw",
+    "validate_bytes",
+    "wrap_raw",
+]
 __ok__ = True
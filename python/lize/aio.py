@@ -0,0 +1,189 @@
+"""`AsyncReader` and `AsyncWriter`, which read and write files of frames, like
+`Reader` and `Writer`, from asyncio code.
+
+Each one has a thread of its own for the file, so the event loop never waits
+on the disk. The reader decodes there too, ahead of the consumer but at most
+`buffer` values ahead; the writer encodes values as they're written, so later
+changes to them aren't seen, and writes them out in batches.
+"""
+
+import asyncio
+import io
+import os
+from collections import deque
+from concurrent.futures import ThreadPoolExecutor
+from typing import Any, BinaryIO, Deque, Optional, Union
+
+from .lize import Reader, Writer
+
+Path = Union[str, "os.PathLike[str]"]
+
+_END = object()
+
+
+class AsyncReader:
+    """Iterates over the values in a file written by `Writer` or `AsyncWriter`,
+    with `async for`.
+
+    Frames are read and decoded on a background thread, at most `buffer` values
+    ahead of the consumer, so a slow consumer doesn't make it hold the whole
+    file. Takes the same keyword arguments as `deserialize`; `release_gil=True`
+    also lets the event loop run while a large frame is parsed.
+    """
+
+    def __init__(self, path: Path, *, buffer: int = 16, **kwargs: Any) -> None:
+        if buffer < 1:
+            raise ValueError(f"buffer must be at least 1, got {buffer}")
+        self._path = path
+        self._buffer = buffer
+        self._kwargs = kwargs
+        self._executor = ThreadPoolExecutor(max_workers=1, thread_name_prefix="lize-reader")
+        self._file: Optional[BinaryIO] = None
+        self._reader: Optional[Reader] = None
+        self._opening: Optional["asyncio.Future[int]"] = None
+        # Reads already handed to the thread, which runs them in order.
+        self._pending: Deque["asyncio.Future[Any]"] = deque()
+        self._done = False
+
+    def __aiter__(self) -> "AsyncReader":
+        return self
+
+    async def __anext__(self) -> Any:
+        loop = asyncio.get_running_loop()
+        if self._opening is None:
+            self._opening = loop.run_in_executor(self._executor, self._open, 0)
+        await self._opening
+        if self._done:
+            raise StopAsyncIteration
+
+        self._read_ahead(loop)
+        try:
+            # Shielded, so a cancelled consumer finds the value there next time.
+            value = await asyncio.shield(self._pending[0])
+        except asyncio.CancelledError:
+            raise
+        except BaseException:
+            # The reads after a failed one are dropped, and tried again next time.
+            self._pending.popleft()
+            self._cancel_pending()
+            raise
+        self._pending.popleft()
+        if value is _END:
+            self._done = True
+            self._cancel_pending()
+            raise StopAsyncIteration
+        self._read_ahead(loop)
+        return value
+
+    async def seek_to(self, n: int) -> None:
+        """Moves to the `n`th value of the file, counting from 0, or to its end
+        if it has fewer. The values before it are read again but not decoded."""
+        if n < 0:
+            raise ValueError(f"can't seek to a negative position, got {n}")
+        self._cancel_pending()
+        self._done = False
+        self._opening = asyncio.get_running_loop().run_in_executor(self._executor, self._open, n)
+        await self._opening
+
+    async def close(self) -> None:
+        self._cancel_pending()
+        await asyncio.get_running_loop().run_in_executor(self._executor, self._close)
+        self._executor.shutdown(wait=False)
+
+    async def __aenter__(self) -> "AsyncReader":
+        return self
+
+    async def __aexit__(self, *exc_info: Any) -> None:
+        await self.close()
+
+    def _read_ahead(self, loop: asyncio.AbstractEventLoop) -> None:
+        while len(self._pending) < self._buffer:
+            self._pending.append(loop.run_in_executor(self._executor, self._next))
+
+    def _cancel_pending(self) -> None:
+        # Reads the thread hasn't started are dropped; one it's running finishes
+        # before anything submitted later.
+        for future in self._pending:
+            if future.done() and not future.cancelled():
+                # Retrieved, so asyncio doesn't log errors nobody will see.
+                future.exception()
+            future.cancel()
+        self._pending.clear()
+
+    # Run on the thread.
+
+    def _open(self, skip: int) -> int:
+        if self._file is None:
+            self._file = open(self._path, "rb")
+        self._file.seek(0)
+        self._reader = Reader(self._file, **self._kwargs)
+        return self._reader.skip(skip)
+
+    def _next(self) -> Any:
+        assert self._reader is not None
+        return next(self._reader, _END)
+
+    def _close(self) -> None:
+        if self._file is not None:
+            self._file.close()
+
+
+class AsyncWriter:
+    """Writes values as frames to a file from asyncio code, for `Reader` or
+    `AsyncReader` to read.
+
+    Each value is encoded when it's written, and the frames are written to the
+    file and flushed on a background thread each time `batch_size` of them
+    have been collected, or on `flush()` and `close()`. The file is replaced if
+    it exists. Takes `checksum` like `Writer` and the same keyword arguments as
+    `serialize`.
+    """
+
+    def __init__(self, path: Path, *, batch_size: int = 64, **kwargs: Any) -> None:
+        if batch_size < 1:
+            raise ValueError(f"batch_size must be at least 1, got {batch_size}")
+        self._path = path
+        self._batch_size = batch_size
+        self._batch = io.BytesIO()
+        self._writer = Writer(self._batch, **kwargs)
+        self._batched = 0
+        self._executor = ThreadPoolExecutor(max_workers=1, thread_name_prefix="lize-writer")
+        self._file: Optional[BinaryIO] = None
+
+    async def write(self, value: Any) -> None:
+        """Encodes `value`, and writes out the batch if it's full."""
+        self._writer.write(value)
+        self._batched += 1
+        if self._batched >= self._batch_size:
+            await self.flush()
+
+    async def flush(self) -> None:
+        """Writes the values collected so far to the file and flushes it."""
+        data = self._batch.getvalue()
+        self._batch.seek(0)
+        self._batch.truncate()
+        self._batched = 0
+        await asyncio.get_running_loop().run_in_executor(self._executor, self._write, data)
+
+    async def close(self) -> None:
+        await self.flush()
+        await asyncio.get_running_loop().run_in_executor(self._executor, self._close)
+        self._executor.shutdown(wait=False)
+
+    async def __aenter__(self) -> "AsyncWriter":
+        return self
+
+    async def __aexit__(self, *exc_info: Any) -> None:
+        await self.close()
+
+    # Run on the thread.
+
+    def _write(self, data: bytes) -> None:
+        if self._file is None:
+            self._file = open(self._path, "wb")
+        self._file.write(data)
+        self._file.flush()
+
+    def _close(self) -> None:
+        if self._file is not None:
+            self._file.close()
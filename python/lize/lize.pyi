@@ -1,19 +1,653 @@
-from typing import Any, Callable, Generic, NoReturn, TypeVar, Union
+import os
+from array import array
+from collections import deque
+from datetime import datetime, timedelta
+from fractions import Fraction
+from ipaddress import IPv4Address, IPv4Network, IPv6Address, IPv6Network
+from typing import Any, BinaryIO, Callable, ContextManager, Generic, Iterable, Iterator, Literal, Mapping, NoReturn, Sequence, TypeVar, Union, overload
+
+from . import runnable as runnable
 
 Value = Union[
     str,
+    bytes,
+    bytearray,
+    memoryview,
     int,
     float,
+    Fraction,
+    datetime,
+    timedelta,
+    IPv4Address,
+    IPv6Address,
+    IPv4Network,
+    IPv6Network,
     bool,
+    "FixedInt",
     list["Value"],
+    set["Value"],
+    frozenset["Value"],
+    deque["Value"],
     dict["Value", "Value"],
+    Mapping["Value", "Value"],
     None,
     "Runnable[Any]",
     Callable[..., Any],
+    "Delete",
 ]
 
-def serialize(x: Value) -> bytes: ...
-def deserialize(x: bytes) -> Any: ...
+@overload
+def serialize(x: Value, *, with_fingerprint: Literal[True], **kwargs: Any) -> tuple[bytes, int]: ...
+@overload
+def serialize(
+    x: Value,
+    *,
+    with_fingerprint: Literal[False] = False,
+    max_depth: int | None = None,
+    canonical: bool = False,
+    max_output_bytes: int | None = None,
+    check_duplicate_keys: bool = False,
+    release_gil: bool = False,
+    warn_subclasses: bool = False,
+    allow_reduce: bool = False,
+    default: Callable[[Any], Value] | None = None,
+    persistent_id: Callable[[Any], Value | None] | None = None,
+    progress: Callable[[int, int], bool | None] | None = None,
+    progress_interval: int = 8 << 20,
+    parallel: bool = False,
+    rle: bool = False,
+    json_keys: bool = False,
+    strings: Literal["strict", "surrogatepass", "replace"] = "strict",
+    float_quantize: int | Literal["f32"] | None = None,
+    schema: Schema | None = None,
+    key: bytes | None = None,
+    metadata: dict[str, Any] | None = None,
+    authenticate_metadata: bool = False,
+) -> bytes:
+    """Serialize a Python object into bytes.
+
+    Args:
+        with_fingerprint: Return `(bytes, fingerprint)`, where the fingerprint
+            is a 64-bit hash of the object's shape: the types it holds and the
+            keys of its dicts, but not their values. Lists of any length
+            holding the same types, and dicts with the same keys in any order,
+            share it, and ints and floats of any size count as one type each.
+            It's taken before `schema` is applied.
+        max_depth: Maximum nesting of containers. Deeper values raise `ValueError`.
+        canonical: Sort dict entries by key and set items by their encoding, and
+            write `-0.0` as `0.0` and every NaN alike, so equal dicts, sets and
+            floats always produce the same bytes.
+        max_output_bytes: Raise `EncodeError` as soon as the encoded value would
+            take more than this many bytes, before it's encrypted.
+        check_duplicate_keys: Raise `ValueError` if two keys of a dict are
+            written as the same bytes, e.g. objects that `default` turns into
+            the same string, or a `Mapping` whose `items()` repeats a key.
+            `deserialize` would keep only one of them.
+        release_gil: Release the GIL while writing bytes, after the object has been read.
+        warn_subclasses: Warn when a subclass of `str` or `bytes` that isn't
+            registered is written as its base type.
+        allow_reduce: Write other objects, and classes, the way `pickle` does,
+            through `__reduce_ex__`. Dataclasses are written from their fields, and
+            objects with `__slots__` and no `__dict__` from their slots. Reading
+            them back needs `allow_reduce=True` too.
+        default: Called with each object that can't be serialized otherwise,
+            like `json.dumps`'s `default`; what it returns is written instead.
+            Takes precedence over `allow_reduce`, which only applies if it
+            returns something unsupported too. It may call `serialize`,
+            `deserialize` and `register` itself; every call has its own buffers.
+        persistent_id: Called with every object, including dict keys, like
+            `pickle`'s `persistent_id`. If it returns something other than
+            None, that id is written instead of the object, and
+            `deserialize(..., persistent_load=...)` turns it back into one.
+        progress: Called with `(bytes_done, values_done)` each time another
+            `progress_interval` bytes have been written, and once at the end.
+            Returning `False` stops and raises `Cancelled`. It's called with the
+            GIL, which is taken back for it under `release_gil=True`. Can't be
+            combined with `parallel`.
+        progress_interval: How many bytes to write between calls to `progress`.
+        parallel: Write the items of a large top-level list on several threads,
+            without the GIL. The output is the same; objects are still read
+            from Python on the calling thread first.
+        rle: Write lists with long runs of equal items, like sparse data or
+            masks, as `(item, count)` pairs. Payloads have no header, so each
+            such list is marked where it's written, by its extension code.
+            `deserialize` expands them again, applying `keys` to their items
+            as to any list, and refuses a list of runs that adds up to more
+            than 2**28 items.
+        json_keys: Write every dict key as `str(key)`, like `{1: ...}` as
+            `{"1": ...}`, so the decoded value can be passed to `json.dumps`.
+            Raises `ValueError` if two keys of a dict become the same string.
+        strings: What to do with strings holding lone surrogates, e.g. from
+            `os.fsdecode`, which UTF-8 can't encode: raise `UnicodeEncodeError`
+            ("strict"), write them so they decode back exactly
+            ("surrogatepass"), or write each surrogate as "?" ("replace").
+        float_quantize: Round every float, including dict keys, to this many
+            significant decimal digits, or to the nearest f32 with "f32",
+            before writing it. The precision is lost: the decoded floats are
+            the rounded ones. Rounded values an f32 holds exactly take 4 bytes
+            instead of 8, and repeated values compress better.
+        schema: Write `x`, a dict with string keys, as a frame holding just its
+            values in the schema's field order. Only `deserialize` with the
+            same schema can read it.
+        key: A 32-byte key. The output is then an encrypted, authenticated frame
+            that only `deserialize(data, key=key)` can read.
+        metadata: Tags to carry in a frame besides the value, along with the
+            producer, the creation time and the schema hash; see
+            `read_metadata`. Read the frame back with `framed=True`, unless a
+            key or schema already says it's one.
+        authenticate_metadata: With `key`, make tampering with the metadata
+            fail authentication too. By default it can be edited without the key.
+    """
+def serialize_buffer(x: Value, **kwargs: Any) -> SerializedBuffer:
+    """Serialize like `serialize`, returning the bytes without copying them
+    into a `bytes` object. Read them through the buffer protocol, e.g. with
+    `memoryview(result)`.
+    """
+def serialize_with_info(x: Value, **kwargs: Any) -> tuple[bytes, dict[str, int]]:
+    """Serialize like `serialize`, also returning the output's size.
+
+    Takes the same keyword arguments as `serialize`. The dict holds the `size`
+    of the bytes and the `payload_size` of the encoded value inside them,
+    which is smaller when `key` is given.
+    """
+def deserialize(
+    x: bytes | bytearray | memoryview,
+    *,
+    into: dict[Any, Any] | Callable[[Any], Any] | None = None,
+    max_depth: int | None = None,
+    raw_slices: Literal["bytes", "str", "error", "legacy"] = "bytes",
+    lossy: bool = False,
+    maps_as_pairs: bool = False,
+    record: bool = False,
+    preserve_int_width: bool = False,
+    dedupe_values: bool = False,
+    bytes_as: Literal["bytes", "memoryview"] = "bytes",
+    keys: Sequence[str] | None = None,
+    release_gil: bool = False,
+    resolve_annotations: bool = False,
+    warn_subclasses: bool = False,
+    on_bad_runnable: Literal["error", "skip", "placeholder"] = "error",
+    allow_reduce: bool = False,
+    persistent_load: Callable[[Any], Any] | None = None,
+    progress: Callable[[int, int], bool | None] | None = None,
+    progress_interval: int = 8 << 20,
+    max_decoded_size: int | None = None,
+    max_alloc: int | None = None,
+    max_expansion: int | None = None,
+    max_str_len: int | None = None,
+    schema: Schema | None = None,
+    key: bytes | None = None,
+    framed: bool = False,
+) -> Any:
+    """Deserialize bytes into a Python object.
+
+    Args:
+        into: A dict to update with the entries of the dict held by `x`, which
+            is returned, keeping entries `x` doesn't have; or a callable, like
+            `records.append`, to call with each item of the list held by `x`
+            in order, decoding the next only once it returns, so the list is
+            never built, even if it was written with `rle=True` or as packed
+            booleans. The number of items is returned then. If an item
+            can't be decoded or the callable raises, the exception gets a
+            `delivered` attribute with how many items the callable took, and
+            a note saying so on Python 3.11 and newer. Raises `TypeError` if
+            `x` doesn't hold a dict or list to match.
+        max_depth: Maximum nesting of containers. Deeper values raise `ValueError`.
+        raw_slices: How to decode raw byte slices, including `bytes` dict keys.
+            "legacy" reads strings and functions written before format version
+            3, though dict keys are never read as functions.
+        lossy: Replace invalid UTF-8 in strings with U+FFFD instead of raising.
+        maps_as_pairs: Decode maps into lists of `(key, value)` tuples, keeping
+            every entry even when keys collide or aren't hashable.
+        record: Decode maps into immutable records, `collections.namedtuple`s
+            named `Record`, whose entries are read as attributes, like
+            `config.host`. Maps with keys that can't be field names, like
+            non-`str` keys or ones starting with `_`, stay dicts. Can't be
+            combined with `maps_as_pairs`.
+        preserve_int_width: Decode integers as `FixedInt`s, which `serialize`
+            writes back with the width they were read with, so a payload from
+            a Rust producer round-trips to the same bytes.
+        dedupe_values: Build each distinct string, bytes, int and float once
+            and reuse that object wherever the same value appears again, which
+            saves memory on repetitive payloads. Lists and dicts are never shared.
+        bytes_as: Decode byte slices as read-only `memoryview`s over `x` instead
+            of copying them, if `x` is `bytes`; they keep `x` alive. Any other
+            buffer, like a `bytearray` or `mmap`, could be written to while
+            it's decoded, so it's copied once and the views are over the copy.
+            Slices of frames serialized with `key` are still copied.
+        keys: Only decode map entries with these keys, skipping the rest. Applies
+            to the top-level map, or to maps in top-level lists (recursively),
+            but not inside the values that are kept.
+        release_gil: Release the GIL while parsing bytes, before Python objects are built.
+        resolve_annotations: Evaluate the annotations of functions back into types,
+            looking up the modules they name among those already imported.
+            Annotations that can't be resolved, including those naming modules
+            that aren't imported, stay strings.
+        warn_subclasses: Warn when a subclass of `str` or `bytes` can't be found
+            and is read as its base type.
+        on_bad_runnable: What to do with functions whose bytecode can't be loaded,
+            e.g. when written by another Python version. "skip" leaves out their
+            dict entries (or uses None elsewhere), and "placeholder" returns a
+            `BrokenRunnable` instead.
+        progress: Called with `(bytes_done, values_done)` each time another
+            `progress_interval` bytes of `x` have been read, and once at the
+            end, before the Python objects are built. Returning `False` stops
+            and raises `Cancelled`.
+        progress_interval: How many bytes to read between calls to `progress`.
+        allow_reduce: Rebuild objects written with `allow_reduce=True`. Like
+            unpickling, this imports and calls whatever the data names, so only
+            use it on trusted data.
+        persistent_load: Called with each id written by `persistent_id`, to
+            get the object it stands for. Without it, ids raise `ValueError`.
+        max_decoded_size: Raise `ValueError` before decoding if the data is
+            estimated to take more than this many bytes as Python objects, see
+            `estimate_decoded_size`.
+        max_alloc: Raise `AllocLimitExceeded` as soon as the Python objects
+            built so far take more than this many bytes, counted with the same
+            sizes as `estimate_decoded_size`.
+        max_expansion: Raise `ValueError` once the lists written with
+            `rle=True` expand to more than this many values in total, counting
+            every value inside each copy of an item, so nested runs multiply.
+            By default, 2**20 plus 64 for every byte of `x`, at most 2**28.
+            The top-level list of `iter_deserialize` and `into=callable`
+            isn't counted, since it's never built.
+        max_str_len: Raise `ValueError` for any string or bytes longer than this
+            many bytes, checked against its declared length before reading it.
+        schema: Read a frame written with `serialize(..., schema=schema)` back
+            into a dict. Raises `ValueError` if it was written with another
+            schema, or none. Can't be combined with `keys`.
+        key: The 32-byte key the data was serialized with. Raises
+            `AuthenticationFailed` if the data was tampered with or the key is wrong.
+        framed: Read a frame, like one written with `metadata=...`, even
+            without a key or schema.
+    """
+def deserialize_first(x: bytes, **kwargs: Any) -> tuple[Any, bytes]:
+    """Deserialize the first of several values written back to back.
+
+    Returns the value and the bytes after it, which aren't decoded. Takes the
+    same keyword arguments as `deserialize`; with `key=...`, the first frame is
+    read instead.
+    """
+
+class ItemIterator(Iterator[Any]):
+    """The items of a list, decoded one at a time, from `iter_deserialize`."""
+    def __next__(self) -> Any: ...
+    def __length_hint__(self) -> int:
+        """How many items are left."""
+
+def iter_deserialize(x: bytes | bytearray | memoryview, **kwargs: Any) -> ItemIterator:
+    """Iterate over the items of the list held by `x`, decoding each one only
+    when it's reached, so a huge list can be processed without building it.
+    Lists written with `rle=True` or as packed booleans are expanded as
+    they're iterated over too.
+
+    Takes the same keyword arguments as `deserialize`, except `schema`. Raises
+    `TypeError` if `x` doesn't hold a list, and a damaged item raises when
+    it's reached. `x` can't be resized while the iterator is alive.
+    """
+
+D = TypeVar("D", bound=Mapping[str, Any])
+
+def deserialize_typeddict(x: bytes, cls: type[D], **kwargs: Any) -> D:
+    """Deserialize a dict and check it against `cls`, a `typing.TypedDict`.
+
+    Raises `ValidationError` naming the entry if a required key is missing, a
+    key isn't declared, or a value doesn't match its annotation. Nested
+    TypedDicts, unions, `Literal` and the items of lists, tuples, sets and
+    dicts are checked too. Takes the same keyword arguments as `deserialize`.
+    """
+
+L = TypeVar("L")
+
+@overload
+def load_as(x: bytes, tp: type[L], **kwargs: Any) -> L: ...
+@overload
+def load_as(x: bytes, tp: Any, **kwargs: Any) -> Any:
+    """Deserialize `x` and check it against the type hint `tp`, like
+    `list[int]`, `dict[str, float]`, `Optional[Point]` or a TypedDict, in
+    any combination.
+
+    The hint is interpreted once, before the value is checked. Raises
+    `ValidationError` at the first value that doesn't match, with its path
+    like `payload[0]['y']` and the expected type. Takes the same keyword
+    arguments as `deserialize`.
+    """
+def estimate_decoded_size(x: bytes, **kwargs: Any) -> int:
+    """Estimate how many bytes of memory `deserialize(x)` will take, without decoding it.
+
+    The estimate models the size of each kind of Python object and is usually
+    within a factor of two. Takes the same keyword arguments as `deserialize`.
+    """
+def content_hash(
+    x: Value, algorithm: Literal["blake3", "sha256"] = "blake3", **kwargs: Any
+) -> bytes:
+    """Hash what `serialize(x, canonical=True)` would return, without building it.
+
+    Takes the same keyword arguments as `serialize`, except `key`.
+    """
+def equal(a: bytes, b: bytes, **kwargs: Any) -> bool:
+    """Whether `a` and `b` hold the same values, even if they were written
+    differently, without building them as Python objects.
+
+    Integers compare by value whatever width they were written with, and so do
+    floats, with `-0.0` equal to `0.0` and NaN equal to NaN. Dicts and sets
+    compare regardless of order, and lists written with `rle=True` or as
+    packed booleans equal the same lists written plainly. `bytes` never equal
+    `str`, and `int` never equals `float`. Takes the same keyword arguments as
+    `deserialize`.
+    """
+def validate_bytes(data: bytes | bytearray | memoryview) -> bool:
+    """Whether `data` holds exactly one well-formed value, checking every tag,
+    length and string without building any objects. Never raises for
+    malformed data, but ext values such as runnables aren't checked inside.
+    """
+def to_columns(
+    x: bytes, keys: Sequence[str], *, typed: bool = False, **kwargs: Any
+) -> dict[str, list[Any] | array[Any]]:
+    """Decode a list of dicts into `{key: [value of each row]}` for the given keys.
+
+    Only the requested entries are decoded, and the row dicts are never built.
+    Rows missing a key get None. With `typed=True`, columns holding only ints
+    or only floats become `array.array("q")` or `array.array("d")`. Takes the
+    same keyword arguments as `deserialize`.
+    """
+def to_arrow(x: bytes, **kwargs: Any) -> Any:
+    """Decode a list of dicts into a `pyarrow.Table`, with a column per key.
+
+    Columns may hold ints, floats, bools, strs or bytes, with None for nulls
+    and for rows missing the key. Nested lists and dicts raise ValueError.
+    Takes the same keyword arguments as `deserialize`.
+    """
+def from_arrow(table: Any, **kwargs: Any) -> bytes:
+    """Serialize a `pyarrow.Table` or `pyarrow.RecordBatch` as a list of dicts,
+    one per row. Takes the same keyword arguments as `serialize`.
+    """
+def merge(base: bytes, patch: bytes) -> bytes:
+    """Merge a serialized patch dict into a serialized value.
+
+    Dicts are merged recursively: entries set to `DELETE` are removed, `None`
+    sets an entry to None, and entries the patch leaves out are kept. Anything
+    that isn't a dict replaces the value.
+    """
+def diff(old: bytes, new: bytes) -> bytes:
+    """Return the serialized patch that `merge(old, patch)` turns into `new`.
+
+    Entries missing from `new` are set to `DELETE`; unchanged ones are left out.
+    """
+def redact(data: bytes, keys: Sequence[str], replacement: Any = None) -> bytes:
+    """Return the serialized `data` without the dict entries whose key is one
+    of `keys`, at any depth. With a `replacement`, the entries are kept and
+    their values replaced by it instead.
+
+    `data` must hold exactly one bare payload. Raises `ValueError` for a
+    frame, like one written with `key=...`, or for bytes after the value.
+    """
+def wrap_raw(data: bytes | bytearray | memoryview, tag: int = 0) -> bytes:
+    """Wrap bytes from another serializer as an ext value with code `tag`
+    (`0..=255`), without looking inside. Codes 1 to 15 are lize's own, which
+    `deserialize` reads as its own types.
+    """
+def unwrap_raw(data: bytes | bytearray | memoryview) -> tuple[int, memoryview]:
+    """Return the tag and the bytes given to `wrap_raw`, as a read-only
+    memoryview over `data` rather than a copy.
+    """
+def tune(typical_size: int) -> int:
+    """Reserve `typical_size` bytes for the output of every later `serialize`
+    call up front, instead of growing it as it's written, or stop with 0.
+    Returns the previous size.
+    """
+def format_version() -> int: ...
+def features() -> dict[str, bool]:
+    """Which features that depend on the Python version, or on how lize was
+    built, can be used here: `buffer_export` (`serialize_buffer`), `union_type`
+    (`X | Y` hints) and `dataclass_kw_only`. Those that can't raise
+    `NotImplementedError` naming the version they need, or are skipped.
+    """
+def hexdump(bytes: bytes, width: int = 16) -> str: ...
+
+class Problem:
+    """A structural problem found by `diagnose`."""
+
+    offset: int
+    """Where the value that couldn't be read starts."""
+    kind: Literal[
+        "unknown_tag",
+        "invalid_utf8",
+        "invalid_length",
+        "truncated",
+        "trailing_bytes",
+        "unrecoverable",
+    ]
+    message: str
+    path: str
+    """Where the value sits, like `$[3]["name"]`: `[i]` for list items, the key
+    for dict values keyed by a `str` or `int`, `[#i]` for other dict values and
+    `.keys[i]` for the keys themselves."""
+
+def read_metadata(data: bytes) -> dict[str, Any] | None:
+    """Read the metadata of the frame at the start of `data`, without decoding
+    or decrypting the value, or return None if it has none.
+
+    The dict holds `producer`, `created` (a Unix time), `schema` (the schema
+    hash, if the frame has one) and `tags`, as given to `serialize`.
+    """
+def diagnose(data: bytes, limit: int = 100) -> list[Problem]:
+    """Scan `data` for structural problems without decoding it, returning up to
+    `limit` of them, or none if it decodes.
+
+    When a value can't be read, the scan carries on from where the next one
+    seems to start, so one damaged region doesn't hide the others. Damage that
+    still reads as valid values of the wrong kind isn't noticed.
+    """
+
+S = TypeVar("S", bound=type)
+
+def register(cls: S) -> S:
+    """Keep instances of a `str` or `bytes` subclass as that class through
+    `serialize` and `deserialize`. Classes can also opt in by setting
+    `__lize_subclass__ = True`, if their module is imported before decoding.
+    """
+
+def register_extension(
+    tag: int,
+    cls: S,
+    encoder: Callable[[Any], Value],
+    decoder: Callable[[Any], Any],
+) -> S:
+    """Write instances of `cls`, and of its subclasses, as an extension value
+    with code `tag` holding `encoder(obj)`, and read them back as
+    `decoder(payload)`.
+
+    `tag` must be in `240..=255`, which lize keeps free for applications, and
+    can't be taken by another class. Types lize already converts on its own,
+    like subclasses of `dict` or `str`, keep their usual encoding.
+    """
+
+class Delete:
+    """The type of `DELETE`."""
+
+DELETE: Delete
+"""Marks a dict entry to remove when a patch is passed to `merge`.
+
+`deserialize` leaves out dict entries set to it.
+"""
+
+class Options:
+    """The type of `options`."""
+
+    def __getattr__(self, name: str) -> Any:
+        """The default in effect for the option `name`, or `None` if it isn't set."""
+    def __setattr__(self, name: str, value: Any) -> None:
+        """Sets the process-wide default for the option `name`."""
+    def __delattr__(self, name: str) -> None: ...
+    def __call__(self, **kwargs: Any) -> ContextManager[None]:
+        """Sets `kwargs` as the defaults within a `with` block, on top of those
+        already in effect. Other threads and asyncio tasks don't see them."""
+    def snapshot(self) -> dict[str, Any]:
+        """The defaults in effect here, for debugging."""
+    def reset(self) -> None:
+        """Removes every process-wide default."""
+
+options: Options
+"""Defaults for the keyword arguments of `serialize`, `deserialize` and every
+other function, like `lize.options.canonical = True` for the whole process or
+`with lize.options(canonical=True):` for a block.
+
+Keyword arguments win over the innermost block, which wins over the process.
+Each function only applies the defaults it takes, so a default for
+`deserialize` like `lossy` doesn't affect `serialize`. Setting an unknown
+option raises `AttributeError`, and an invalid value raises like it would as a
+keyword argument. `Decoder`, `Reader` and `Writer` read the defaults when
+they're created.
+"""
+
+class FixedInt:
+    """An integer that's written with a fixed width, as returned by
+    `deserialize(x, preserve_int_width=True)`. Compares and hashes like the
+    `int` it holds.
+    """
+    def __init__(self, value: int, width: Literal["small_u8", "u8", "i32", "i64"]) -> None:
+        """Raises ValueError if `value` doesn't fit in `width`; "small_u8" takes
+        0 to 235, in a single byte."""
+    @property
+    def value(self) -> int: ...
+    @property
+    def width(self) -> Literal["small_u8", "u8", "i32", "i64"]: ...
+    def __int__(self) -> int: ...
+    def __index__(self) -> int: ...
+
+class AuthenticationFailed(ValueError):
+    """An encrypted payload was tampered with, or the key is wrong."""
+
+class EncodeError(ValueError):
+    """A value's encoding is longer than `max_output_bytes`."""
+
+class AllocLimitExceeded(ValueError):
+    """Decoding built more bytes of Python objects than `max_alloc`."""
+
+class Cancelled(Exception):
+    """A `progress` callback returned `False`."""
+
+class ValidationError(ValueError):
+    """A decoded value doesn't match the type hint it was loaded as."""
+    path: str
+    """Where the value is, like `payload['points'][0]`."""
+    expected: str
+    """The name of the type it should have been."""
+
+class SerializedBuffer:
+    """Read-only bytes from `serialize_buffer`, exposed through the buffer
+    protocol. They're never modified, and stay alive while any memoryview
+    or export of them exists.
+    """
+    def __len__(self) -> int: ...
+    def __bytes__(self) -> bytes:
+        """A copy of the bytes."""
+    def __buffer__(self, flags: int, /) -> memoryview: ...
+
+class Schema:
+    """The keys of a dict sent often enough that writing them every time is a
+    waste, shared by both sides out of band.
+
+    `serialize(x, schema=schema)` writes only the values of `x`, in field order,
+    and marks the fields it doesn't have as absent. The schema's `hash` goes
+    into the frame, so reading it with a different schema fails.
+    """
+    def __init__(self, fields: Sequence[str]) -> None:
+        """Raises ValueError if a field appears twice."""
+    @property
+    def fields(self) -> list[str]: ...
+    @property
+    def hash(self) -> int:
+        """The 64-bit hash written into frames, which tells schemas apart."""
+
+class Writer:
+    """Writes each value as a frame to a binary file, or to a file it opens
+    from a path, replacing it.
+
+    Frames are collected in batches of `batch_size` and each batch is written
+    in one call. After each batch, `sync` leaves the bytes wherever the file
+    buffers them ("none"), flushes the file to the operating system ("os") or
+    also `fsync`s it, so it survives a power loss ("fsync"). `flush()`,
+    `close()` and leaving a `with` block write the batch so far.
+
+    A batch is also written once its oldest frame is `flush_interval` seconds
+    old, but that's only checked when `write` is called: there's no timer, so
+    frames written before a long pause stay in memory until the next write or
+    `flush()`. Call `flush()` yourself when the stream goes quiet.
+
+    Each frame starts with its length: if a crash cuts the last batch short,
+    `Reader` returns the frames before the cut and then raises `ValueError`
+    about a truncated frame. With `checksum=True`, each frame also carries a
+    CRC-32 of its body, so a frame damaged in any other way raises
+    `ValueError` instead of decoding to the wrong value. Encrypted frames are
+    authenticated anyway, so other damage to them raises
+    `AuthenticationFailed`.
+
+    Takes the same keyword arguments as `serialize`; with `key=...` every
+    frame is encrypted with a fresh nonce.
+    """
+    def __init__(
+        self,
+        file: BinaryIO | str | os.PathLike[str],
+        *,
+        batch_size: int = 1,
+        flush_interval: float | None = None,
+        sync: Literal["none", "os", "fsync"] = "none",
+        checksum: bool = False,
+        **kwargs: Any,
+    ) -> None: ...
+    def write(self, value: Value) -> None: ...
+    def flush(self) -> None:
+        """Writes the batch so far and flushes the file, `fsync`ing it too
+        with `sync="fsync"`."""
+    def close(self) -> None:
+        """Flushes, and closes the file if the writer opened it from a path.
+        Files passed in are left open."""
+    def __enter__(self) -> "Writer": ...
+    def __exit__(self, *exc_info: Any) -> None: ...
+
+def serialize_map_items(
+    items: Iterable[tuple[Value, Value]], len: int, file: BinaryIO, **kwargs: Any
+) -> None:
+    """Write a map of `len` entries to `file`, taking each `(key, value)` pair
+    from `items` as it's written, so the dict is never built.
+
+    Takes the same keyword arguments as `serialize`, except `key`, `schema`,
+    `canonical` and `check_duplicate_keys`. Raises `ValueError` if `items` doesn't have exactly `len`
+    pairs, after writing what it had.
+    """
+
+class Reader:
+    """Iterates over the values in a binary file written by `Writer`.
+
+    Takes the same keyword arguments as `deserialize`.
+    """
+    def __init__(self, file: BinaryIO, **kwargs: Any) -> None: ...
+    def skip(self, n: int) -> int:
+        """Reads past the next `n` values without decoding them. Returns how
+        many there were, fewer than `n` at the end of the file."""
+    def __iter__(self) -> "Reader": ...
+    def __next__(self) -> Any: ...
+
+class Decoder:
+    """Decodes many payloads with the same options, e.g. records from a queue.
+
+    Takes the same keyword arguments as `deserialize`, parsed once. The `str`
+    objects for dict keys are shared with the next payload when it has the
+    same keys in the same order, so records of one shape decode faster.
+    """
+    def __init__(self, **kwargs: Any) -> None: ...
+    def decode(self, x: bytes | bytearray | memoryview) -> Any:
+        """Decodes `x` like `deserialize`."""
+    def decode_into(self, x: bytes | bytearray | memoryview, into: dict[Any, Any]) -> dict[Any, Any]:
+        """Clears `into` and fills it with the dict held by `x`, then returns it.
+
+        Meant for loops that process each record before reading the next: the
+        same dict is refilled every time, so copy anything kept from it. Nested
+        lists and dicts are always new. Raises `TypeError` if `x` doesn't hold
+        a dict.
+        """
 
 T = TypeVar("T")
 
@@ -24,8 +658,47 @@ class Runnable(Generic[T]):
     """
     def __init__(self) -> NoReturn: ...
     @staticmethod
-    def from_pyfn(fn: Callable[..., T]) -> "Runnable[T]": ...
+    def from_pyfn(fn: Callable[..., T], max_payload_bytes: int | None = None) -> "Runnable[T]":
+        """Captures `fn`. With `max_payload_bytes`, encoding it raises `EncodeError`
+        naming the first default or closure variable that would take more bytes."""
     @staticmethod
-    def from_bytes(bytes: bytes) -> "Runnable[T]": ...
-    def run(self, *args: Any, **kwargs: Any) -> T: ...
-    def as_bytes(self) -> bytes: ...
+    def from_bytes(bytes: bytes, resolve_annotations: bool = False) -> "Runnable[T]": ...
+    @property
+    def annotations(self) -> dict[str, Any] | None:
+        """Types when built from a function or decoded with `resolve_annotations=True`,
+        strings otherwise."""
+    def run(self, *args: Any, **kwargs: Any) -> T:
+        """Calls the function, which is built on the first call and reused after."""
+    def run_with_globals(self, globals: dict[str, Any], *args: Any, **kwargs: Any) -> T:
+        """Calls the function with `globals` as its global namespace, e.g. to
+        hand it a database handle or helpers by name. The dict itself is used,
+        so later changes to it are seen, and `__builtins__` is added to it
+        unless it's already set, like `exec` does; set it to `{}` to hide
+        the builtins. `run` keeps using its own function."""
+    def source(self) -> str | None:
+        """The function's source code, or None if `inspect.getsource` couldn't
+        find it. Used to recompile the function when its bytecode is from
+        another Python version or fails to load."""
+    def requires(self) -> list[str]:
+        """The sorted names the function needs from outside, to check before
+        running it: globals and builtins it loads or assigns, including in
+        nested code like comprehensions, and the variables its closure
+        captured. Attribute names, like `path` in `os.path`, aren't included."""
+    def as_bytes(self, portable: bool = False, max_payload_bytes: int | None = None) -> bytes:
+        """Encodes the function. With `portable=True`, raises `ValueError` unless
+        its source was found and is a single `def`, so it can be recompiled on
+        another Python version if the bytecode doesn't load there.
+        `max_payload_bytes` overrides the cap given to `from_pyfn`."""
+    def __sizeof__(self) -> int:
+        """The length of `as_bytes()`."""
+
+class BrokenRunnable:
+    """A function whose bytecode couldn't be loaded, from `on_bad_runnable="placeholder"`."""
+    @property
+    def data(self) -> bytes:
+        """The encoded function, as read by `Runnable.from_bytes`."""
+    @property
+    def error(self) -> Exception:
+        """The exception raised while loading it."""
+    def load(self, resolve_annotations: bool = False) -> Runnable[Any]:
+        """Tries loading the function again, e.g. on a matching interpreter."""
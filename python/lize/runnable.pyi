@@ -0,0 +1,10 @@
+from typing import Any
+
+from .lize import BrokenRunnable as BrokenRunnable
+from .lize import Runnable as Runnable
+
+def from_source(source: str) -> Runnable[Any]:
+    """Builds a `Runnable` from the source of a single `def`. The source is
+    run in a fresh namespace, so decorators and defaults are evaluated, and
+    must leave a plain function under the def's name. It's kept for
+    `source()`, which `inspect` can't find for a function built this way."""
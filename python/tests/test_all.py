@@ -1,6 +1,2780 @@
+import dataclasses
+import os
+import struct
+import sys
+import typing
+from pathlib import Path
+
 import pytest
 import lize
 
 
 def test_sum_as_string():
     assert lize.sum_as_string(1, 1) == "2"
+
+
+def test_mapping_proxy():
+    from types import MappingProxyType
+
+    data = MappingProxyType({"hello": "world", "n": 1})
+    assert lize.deserialize(lize.serialize(data)) == {"hello": "world", "n": 1}
+
+
+def test_custom_mapping():
+    from collections.abc import Mapping
+
+    class Config(Mapping):
+        def __init__(self, **kwargs):
+            self._data = kwargs
+
+        def __getitem__(self, key):
+            return self._data[key]
+
+        def __iter__(self):
+            return iter(self._data)
+
+        def __len__(self):
+            return len(self._data)
+
+    d = lize.deserialize(lize.serialize(Config(host="localhost", port=8080)))
+    assert type(d) is dict
+    assert d == {"host": "localhost", "port": 8080}
+
+
+def test_mapping_and_sequence_likes():
+    from collections import UserDict, UserList
+    from collections.abc import Sequence
+
+    class LastValue(dict):
+        """Holds lists, but reads as their last item, like Django's QueryDict."""
+
+        def __getitem__(self, key):
+            return super().__getitem__(key)[-1]
+
+        def items(self):
+            return [(key, self[key]) for key in self]
+
+    class Keyed:
+        def __init__(self, **kwargs):
+            self._data = kwargs
+
+        def keys(self):
+            return self._data.keys()
+
+        def __getitem__(self, key):
+            return self._data[key]
+
+    class Pair(Sequence):
+        def __getitem__(self, i):
+            return ("left", "right")[i]
+
+        def __len__(self):
+            return 2
+
+    cases = [
+        (UserDict(a=1, b=[2]), {"a": 1, "b": [2]}),
+        (LastValue(a=[1, 2], b=[3]), {"a": 2, "b": 3}),
+        (Keyed(x=UserList([1, 2])), {"x": [1, 2]}),
+        (UserList([UserDict(a=1), Pair()]), [{"a": 1}, ["left", "right"]]),
+    ]
+    for value, expected in cases:
+        decoded = lize.deserialize(lize.serialize(value))
+        assert decoded == expected
+        assert type(decoded) is type(expected)
+
+    # `default` still sees objects that only look like mappings.
+    assert lize.deserialize(lize.serialize(Keyed(a=1), default=lambda obj: "default")) == "default"
+
+
+def test_format_version():
+    assert isinstance(lize.format_version(), int)
+    assert lize.format_version() >= 1
+
+
+def test_hexdump():
+    data = b"\x02\x03\x01\x02hi\x01\x06\x03"
+
+    assert lize.hexdump(data, width=8) == (
+        "00000000  02 03 01 02 68 69 01 06  |....hi..|\n"
+        "00000008  03                       |.|"
+    )
+    assert lize.hexdump(b"") == ""
+
+
+def test_empty_containers():
+    for value in ([], {}, "", b""):
+        data = lize.serialize(value)
+        assert len(data) <= 2
+        decoded = lize.deserialize(data)
+        assert decoded == value and type(decoded) is type(value)
+
+
+def test_bool_lists():
+    mask = [i % 3 == 0 for i in range(1000)]
+    data = lize.serialize(mask)
+    assert len(data) <= 126 + 4
+    assert lize.deserialize(data) == mask
+
+    for value in ([True] * 8, [False] * 9, [True, False] * 4 + [True]):
+        decoded = lize.deserialize(lize.serialize(value))
+        assert decoded == value and all(type(b) is bool for b in decoded)
+
+    # Short and mixed lists are written item by item.
+    assert lize.serialize([True, False]) == b"\x10\x02\x06\x07"
+    assert lize.deserialize(lize.serialize([True] * 8 + [1])) == [True] * 8 + [1]
+    with pytest.raises(ValueError):
+        lize.deserialize(b"\x0e\x07\x12\x01\x08")
+
+
+def test_small_fast_path():
+    class Text(str):
+        pass
+
+    # Without options, small values skip the general path but must write the same bytes.
+    values = [
+        {"id": 7, "ok": True},
+        [0, 235, 236, 255, 256, -1, 2**31 - 1, -(2**31) - 1, 2**63 - 1, 2**63, -(2**63) - 1],
+        [0.5, 0.1, float("inf"), float("nan"), -0.0],
+        ("tuple", b"\x00\xff", None, [], {}),
+        {"nested": [[[[[[[[[[[[[[[[[[1]]]]]]]]]]]]]]]]]]},
+        [True] * 8,
+        [True] * 7,
+        {Text("key"): {1, 2}},
+        "x" * 200,
+        list(range(200)),
+    ]
+    for value in values:
+        assert lize.serialize(value) == lize.serialize(value, canonical=False), value
+
+
+def test_sets():
+    items = [1, "a", b"b", 2.5, None, 300, (1, "x"), frozenset({3})]
+    forward, backward = set(), set()
+    for item in items:
+        forward.add(item)
+    for item in reversed(items):
+        backward.add(item)
+
+    canonical = lize.serialize(forward, canonical=True)
+    assert canonical == lize.serialize(backward, canonical=True)
+    assert lize.serialize({"s": frozenset(items)}, canonical=True) == lize.serialize(
+        {"s": frozenset(reversed(items))}, canonical=True
+    )
+
+    # Tuples come back as lists, which can't be set items.
+    hashable = forward - {(1, "x")}
+    for value in (hashable, frozenset(hashable), set(), frozenset()):
+        for options in ({}, {"canonical": True}):
+            decoded = lize.deserialize(lize.serialize(value, **options))
+            assert decoded == value and type(decoded) is type(value)
+
+
+def test_surrogate_strings():
+    lone = "C:\\Users\\caf\udce9"
+    astral = "emoji \U0001F600 and \U00010348"
+
+    with pytest.raises(UnicodeEncodeError):
+        lize.serialize(lone)
+    with pytest.raises(UnicodeEncodeError):
+        lize.serialize({lone: 1}, strings="strict")
+
+    value = {lone: [lone, astral], "plain": astral}
+    assert lize.deserialize(lize.serialize(value, strings="surrogatepass")) == value
+    assert lize.deserialize(lize.serialize(lone, strings="replace")) == "C:\\Users\\caf?"
+
+    # Strings UTF-8 can encode are written the same way under every option.
+    for strings in ("strict", "surrogatepass", "replace"):
+        assert lize.serialize(astral, strings=strings) == lize.serialize(astral)
+        assert lize.deserialize(lize.serialize(astral, strings=strings)) == astral
+    with pytest.raises(ValueError, match="strings must be one of"):
+        lize.serialize(lone, strings="ignore")
+
+
+def test_raw_slices():
+    # A slice written by a Rust producer, with no Python type prefix.
+    binary = b"\x01\x03\xff\x00\x01"
+    text = b"\x01\x02hi"
+
+    assert lize.deserialize(binary) == b"\xff\x00\x01"
+    assert lize.deserialize(text, raw_slices="str") == "hi"
+
+    with pytest.raises(ValueError):
+        lize.deserialize(text, raw_slices="error")
+
+    with pytest.raises(ValueError):
+        lize.deserialize(binary, raw_slices="str")
+
+    assert lize.deserialize(binary, raw_slices="str", lossy=True) == "�\x00\x01"
+
+
+def test_empty_input():
+    # Like reading an empty file, which says so rather than blaming a value.
+    for empty in (b"", bytearray(), memoryview(b"")):
+        with pytest.raises(ValueError, match="input is empty"):
+            lize.deserialize(empty)
+    with pytest.raises(ValueError, match="input is empty"):
+        lize.deserialize_first(b"")
+    with pytest.raises(ValueError, match="input is empty"):
+        lize.iter_deserialize(b"")
+
+    # A lone byte that isn't a tag, and one that starts a value that isn't there.
+    with pytest.raises(ValueError, match="unknown tag 5"):
+        lize.deserialize(b"\x05")
+    with pytest.raises(ValueError, match="middle of a value"):
+        lize.deserialize(b"\x13")
+
+
+def test_invalid_utf8_string():
+    with pytest.raises(ValueError):
+        lize.deserialize(b"\x0f\x02\xff!")
+
+    # A string as written before format version 3.
+    legacy = b"\x01\x03s\xff!"
+
+    with pytest.raises(ValueError):
+        lize.deserialize(legacy, raw_slices="legacy")
+
+    assert lize.deserialize(legacy, raw_slices="legacy", lossy=True) == "�!"
+
+
+def test_slice_kinds():
+    def double(x: int) -> int:
+        return x * 2
+
+    data = lize.deserialize(lize.serialize(["text", b"\x00bytes", double]))
+
+    assert data[0] == "text"
+    assert data[1] == b"\x00bytes"
+    assert isinstance(data[2], lize.Runnable)
+    assert data[2](21) == 42
+
+
+def test_runnable_envelope_versions():
+    import marshal
+
+    def add(a: int, b: int = 2) -> int:
+        return a + b
+
+    # Written before envelopes were versioned: [code, name, defaults].
+    legacy = lize.Runnable.from_bytes(lize.serialize([marshal.dumps(add.__code__), "add", (2,)]))
+    assert legacy(1) == 3
+    code = len(marshal.dumps(add.__code__))
+    assert repr(legacy) == f"Runnable(<marshal> add(...) -> ?, {code} bytes of code, cached)"
+
+    # Version 1: [1, code, name, defaults, annotations].
+    annotated = lize.Runnable.from_bytes(lize.serialize([1, marshal.dumps(add.__code__), "add", (2,), {"a": "int"}]))
+    assert annotated(1) == 3 and annotated.source() is None
+
+    # Version 2 adds the source and the interpreter's magic number.
+    positional = lize.Runnable.from_bytes(lize.serialize([2, marshal.dumps(add.__code__), "add", (2,), None, "src", None]))
+    assert positional(1) == 3 and positional.source() == "src"
+
+    # Version 3 names its fields, skipping absent ones and unknown ones.
+    named = lize.serialize([3, {"name": "add", "code": marshal.dumps(add.__code__), "future": 1, "defaults": (2,)}])
+    assert lize.Runnable.from_bytes(named)(1) == 3
+    with pytest.raises(ValueError, match="code and a name"):
+        lize.Runnable.from_bytes(lize.serialize([3, {"name": "add"}]))
+    with pytest.raises(ValueError, match="doc"):
+        lize.Runnable.from_bytes(lize.serialize([3, {"name": "add", "code": marshal.dumps(add.__code__), "doc": 1}]))
+
+    extended = lize.Runnable.from_bytes(lize.Runnable.from_pyfn(add).as_bytes())
+    assert repr(extended) == f"Runnable(<marshal> add(a: int, b: int) -> int, {code} bytes of code, not cached)"
+    assert extended(1) == 3
+    assert repr(extended) == f"Runnable(<marshal> add(a: int, b: int) -> int, {code} bytes of code, cached)"
+
+    newer = lize.serialize([4, marshal.dumps(add.__code__), "add", (2,), None, None, None, "future field"])
+    with pytest.raises(ValueError, match="version 4"):
+        lize.Runnable.from_bytes(newer)
+
+    with pytest.raises(ValueError):
+        lize.Runnable.from_bytes(lize.serialize([1, marshal.dumps(add.__code__), "add"]))
+
+    unannotated = lize.deserialize(lize.serialize(lambda x: x + 1))
+    assert unannotated(1) == 2
+
+
+def test_runnable_encoded_once():
+    import importlib.util
+    import inspect
+    import marshal
+
+    def double(x):
+        return x * 2
+
+    # The envelope is written in place, not serialized first and copied in as bytes.
+    fields = {
+        "code": marshal.dumps(double.__code__),
+        "name": "double",
+        "source": inspect.getsource(double),
+        "magic": importlib.util.MAGIC_NUMBER,
+    }
+    envelope = lize.serialize([3, fields])
+    assert lize.serialize(double) == b"\x0e\x03" + envelope
+    assert lize.serialize({"f": [double]}) == b"\x11\x01\x13\x01f\x10\x01\x0e\x03" + envelope
+
+
+def test_runnable_round_trip_matrix():
+    import itertools
+
+    def variant(defaults, kwdefaults, closure, annotations, doc):
+        factor = 3
+        if closure:
+
+            def f(x, y=2, *, z=5):
+                return x * y * z * factor
+
+        else:
+
+            def f(x, y=2, *, z=5):
+                return x * y * z
+
+        f.__defaults__ = (2,) if defaults else None
+        f.__kwdefaults__ = {"z": 5} if kwdefaults else None
+        f.__annotations__ = {"x": int, "return": int} if annotations else {}
+        f.__doc__ = "Multiplies." if doc else None
+        return f
+
+    for flags in itertools.product([False, True], repeat=5):
+        defaults, kwdefaults, closure, annotations, doc = flags
+        f = variant(*flags)
+        args, kwargs = (1,) if defaults else (1, 2), {} if kwdefaults else {"z": 5}
+        expected = f(*args, **kwargs)
+
+        runnable = lize.Runnable.from_bytes(lize.Runnable.from_pyfn(f).as_bytes())
+        again = lize.Runnable.from_bytes(runnable.as_bytes())
+        assert runnable.as_bytes() == again.as_bytes() == lize.Runnable.from_pyfn(f).as_bytes(), flags
+
+        decoded = lize.deserialize(lize.serialize(f))
+        assert decoded(*args, **kwargs) == expected, flags
+        assert runnable.run_with_globals({}, *args, **kwargs) == expected, flags
+        assert decoded.annotations == ({"x": "int", "return": "int"} if annotations else None), flags
+
+        if not defaults:
+            with pytest.raises(TypeError):
+                decoded(1)
+        if not kwdefaults:
+            with pytest.raises(TypeError):
+                decoded(1, 2)
+
+
+def test_runnable_closure():
+    def counter(start):
+        step = 2
+
+        def next_value(n):
+            """Counts up."""
+            return start + step * n
+
+        return next_value
+
+    decoded = lize.deserialize(lize.serialize(counter(10)))
+    assert decoded(3) == 16
+
+    class Opaque:
+        pass
+
+    def holder():
+        opaque = Opaque()
+
+        def get():
+            return opaque
+
+        return get
+
+    with pytest.raises(TypeError, match="closure variable 'opaque' in get"):
+        lize.serialize(holder())
+
+    def recursive():
+        def fact(n):
+            return 1 if n <= 1 else n * fact(n - 1)
+
+        return fact
+
+    with pytest.raises(TypeError, match="closure variable 'fact' in fact") as info:
+        lize.serialize(recursive())
+    assert "closure variables refer back" in str(info.value.__cause__)
+
+
+
+def test_runnable_loop_closures():
+    # Each lambda captures its own value, through a default or a closure cell.
+    defaults = [lambda i=i: i for i in range(3)]
+    cells = [(lambda i: lambda: i)(i) for i in range(3)]
+    nested = [(lambda i: (lambda j: lambda: (i, j))(i * 10))(i) for i in range(3)]
+    for fns, expected in [
+        (defaults, [0, 1, 2]),
+        (cells, [0, 1, 2]),
+        (nested, [(0, 0), (1, 10), (2, 20)]),
+    ]:
+        assert [fn() for fn in lize.deserialize(lize.serialize(fns))] == expected
+        assert [lize.deserialize(lize.serialize(fn))() for fn in fns] == expected
+
+    # Lambdas sharing the comprehension's cell all see its last value, as in Python.
+    shared = [lambda: i for i in range(3)]
+    assert [fn() for fn in lize.deserialize(lize.serialize(shared))] == [2, 2, 2]
+
+def test_runnable_requires():
+    def make(prefix):
+        def build(parts):
+            global counter
+            counter = len(parts)
+            return prefix + os.path.join(*[str(abs(p)) for p in parts])
+
+        return build
+
+    build = make("/")
+    expected = ["abs", "counter", "len", "os", "prefix", "str"]
+    assert lize.Runnable.from_pyfn(build).requires() == expected
+    assert lize.deserialize(lize.serialize(build)).requires() == expected
+    assert lize.Runnable.from_pyfn(lambda x: x + 1).requires() == []
+
+
+def test_runnable_submodule():
+    import lize.runnable
+    from lize.runnable import BrokenRunnable, Runnable, from_source
+
+    assert Runnable is lize.Runnable
+    assert BrokenRunnable is lize.BrokenRunnable
+    assert lize.runnable.__name__ == "lize.runnable"
+    assert lize.runnable is sys.modules["lize.runnable"]
+
+    source = """
+        def scale(x, k=2, *, offset=1):
+            \"\"\"Scales x.\"\"\"
+            return [x * k + offset for _ in range(1)][0]
+    """
+    runnable = from_source(source)
+    assert isinstance(runnable, Runnable)
+    assert runnable.run(3) == 7
+    assert runnable.run(3, k=3, offset=0) == 9
+    assert runnable.source() == source
+    decoded = lize.deserialize(lize.serialize(runnable))
+    assert decoded.run(1) == 3
+    assert decoded.source() == source
+
+    with pytest.raises(ValueError, match="def"):
+        from_source("x = 1")
+    with pytest.raises(TypeError, match="decorators"):
+        from_source("@property\ndef f(self):\n    return 1")
+
+
+def test_runnable_run_with_globals():
+    def query(sql):
+        return db.execute(sql) + suffix  # noqa: F821
+
+    runnable = lize.deserialize(lize.serialize(query))
+
+    class Db:
+        def execute(self, sql):
+            return sql.upper()
+
+    namespace = {"db": Db(), "suffix": "!"}
+    assert runnable.run_with_globals(namespace, "select 1") == "SELECT 1!"
+    assert namespace["__builtins__"] is __import__("builtins")
+    assert "not cached" in repr(runnable)
+
+    # The dict is used live.
+    namespace["suffix"] = "?"
+    assert runnable.run_with_globals(namespace, sql="select 2") == "SELECT 2?"
+
+    # `run` keeps its own empty namespace.
+    with pytest.raises(NameError, match="db"):
+        runnable.run("select 3")
+    assert runnable.run_with_globals(namespace, "x") == "X?"
+
+    def count(items):
+        return len(items)
+
+    hidden = {"__builtins__": {}}
+    with pytest.raises(NameError, match="len"):
+        lize.deserialize(lize.serialize(count)).run_with_globals(hidden, [1])
+    assert hidden == {"__builtins__": {}}
+
+
+def test_runnable_source():
+    import importlib.util
+    import marshal
+
+    def scale(x: int, factor: int = 3) -> int:
+        return x * factor
+
+    runnable = lize.Runnable.from_pyfn(scale)
+    assert runnable.source().lstrip().startswith("def scale(x: int")
+    data = lize.serialize(scale)
+    assert lize.deserialize(data).source() == runnable.source()
+
+    # Bytecode from another Python version, or that fails to load, is
+    # recompiled from the source.
+    other_version = data.replace(importlib.util.MAGIC_NUMBER, b"\x00\x00\r\n")
+    with pytest.warns(UserWarning, match="scale\\(\\) was recompiled from its source"):
+        assert lize.deserialize(other_version)(2) == 6
+    code = marshal.dumps(scale.__code__)
+    with pytest.warns(UserWarning, match="recompiled"):
+        assert lize.deserialize(data.replace(code, bytes(len(code))))(2, 4) == 8
+
+    # Without the source, bytecode from another version is refused.
+    namespace = {}
+    exec("def unsourced(x):\n    return x", namespace)
+    unsourced = lize.serialize(namespace["unsourced"])
+    assert lize.deserialize(unsourced).source() is None
+    with pytest.raises(ValueError, match="another Python version"):
+        lize.deserialize(unsourced.replace(importlib.util.MAGIC_NUMBER, b"\x00\x00\r\n"))
+
+
+def test_runnable_portable():
+    import importlib.util
+
+    def scale(x, factor=3):
+        return x * factor
+
+    portable = lize.Runnable.from_pyfn(scale).as_bytes(portable=True)
+    assert portable == lize.Runnable.from_pyfn(scale).as_bytes()
+    other_version = portable.replace(importlib.util.MAGIC_NUMBER, b"\x00\x00\r\n")
+    with pytest.warns(UserWarning, match="recompiled"):
+        assert lize.Runnable.from_bytes(other_version)(2) == 6
+
+    namespace = {}
+    exec("def unsourced(x):\n    return x", namespace)
+    with pytest.raises(ValueError, match="unsourced\\(\\) can't be written portably: its source can't be found"):
+        lize.Runnable.from_pyfn(namespace["unsourced"]).as_bytes(portable=True)
+
+    square = lambda x: x * x  # noqa: E731
+    with pytest.raises(ValueError, match="isn't a single def"):
+        lize.Runnable.from_pyfn(square).as_bytes(portable=True)
+    assert lize.Runnable.from_pyfn(square).as_bytes()
+
+
+def test_runnable_max_payload_bytes():
+    big = list(range(100_000))
+
+    def lookup(i, cache=big):
+        return cache[i]
+
+    def outer():
+        table = bytes(10_000)
+
+        def inner(i):
+            return table[i]
+
+        return inner
+
+    with pytest.raises(lize.EncodeError, match="the default of parameter 'cache'"):
+        lize.Runnable.from_pyfn(lookup, max_payload_bytes=1000).as_bytes()
+    with pytest.raises(lize.EncodeError, match="the default of parameter 'cache'"):
+        lize.serialize(lize.Runnable.from_pyfn(lookup, max_payload_bytes=1000))
+    with pytest.raises(lize.EncodeError, match="the closure variable 'table'"):
+        lize.Runnable.from_pyfn(outer()).as_bytes(max_payload_bytes=1000)
+
+    capped = lize.Runnable.from_pyfn(lookup, max_payload_bytes=1000)
+    assert capped.as_bytes(max_payload_bytes=1 << 20) == lize.Runnable.from_pyfn(lookup).as_bytes()
+
+    def greet(*, greeting="x" * 2000):
+        return greeting
+
+    with pytest.raises(lize.EncodeError, match="the default of parameter 'greeting'"):
+        lize.Runnable.from_pyfn(greet).as_bytes(max_payload_bytes=1000)
+
+
+def test_runnable_typing_annotations():
+    import datetime
+    import marshal
+    from typing import Any, List, Optional
+
+    def f(a: List[int], b: Optional[str], c: dict[str, Any], d: int | None, e: datetime.date, g: "Undefined") -> list[int]:  # noqa: F821
+        return a
+
+    expected = "f(a: typing.List[int], b: typing.Optional[str], c: dict[str, typing.Any], d: int | None, e: datetime.date, g: Undefined) -> list[int]"
+    data = lize.serialize(f)
+
+    kept = lize.deserialize(data)
+    size = len(marshal.dumps(f.__code__))
+    assert repr(kept) == f"Runnable(<marshal> {expected}, {size} bytes of code, not cached)"
+    assert kept.annotations["a"] == "typing.List[int]"
+    assert kept([7], None, {}, None, datetime.date.today(), None) == [7]
+
+    resolved = lize.deserialize(data, resolve_annotations=True)
+    assert repr(resolved) == f"Runnable(<marshal> {expected}, {size} bytes of code, not cached)"
+    assert resolved.annotations == {
+        "a": List[int],
+        "b": Optional[str],
+        "c": dict[str, Any],
+        "d": int | None,
+        "e": datetime.date,
+        "g": "Undefined",
+        "return": list[int],
+    }
+
+    # Anything that could call code is kept as a string.
+    runnable = lize.Runnable.from_bytes(
+        lize.serialize([1, marshal.dumps(f.__code__), "f", None, {"a": "os.system('true')"}]),
+        resolve_annotations=True,
+    )
+    assert runnable.annotations == {"a": "os.system('true')"}
+
+    # Modules are only looked up among those already imported.
+    import sys
+
+    assert "tabnanny" not in sys.modules
+    runnable = lize.Runnable.from_bytes(
+        lize.serialize([1, marshal.dumps(f.__code__), "f", None, {"a": "tabnanny.NannyNag", "b": "datetime.date"}]),
+        resolve_annotations=True,
+    )
+    assert runnable.annotations == {"a": "tabnanny.NannyNag", "b": datetime.date}
+    assert "tabnanny" not in sys.modules
+
+
+def test_runnable_repr_and_size():
+    import marshal
+
+    def f(x: int) -> int:
+        return x * 2
+
+    runnable = lize.Runnable.from_pyfn(f)
+    assert runnable.__sizeof__() == len(runnable.as_bytes())
+    assert runnable(2) == 4 and runnable(3) == 6
+    assert repr(runnable) == f"Runnable(<marshal> f(x: int) -> int, {len(marshal.dumps(f.__code__))} bytes of code, cached)"
+
+    class Raising(type):
+        def __getattribute__(cls, name):
+            if name in ("__name__", "__qualname__", "__module__", "__origin__"):
+                raise RuntimeError(name)
+            return super().__getattribute__(name)
+
+        def __repr__(cls):
+            raise RuntimeError("no repr")
+
+    class Exotic(metaclass=Raising):
+        pass
+
+    class Nameless:
+        def __getattr__(self, name):
+            raise RuntimeError(name)
+
+        def __repr__(self):
+            raise RuntimeError("no repr")
+
+    def g(a, b):
+        return a
+
+    g.__annotations__ = {"a": Exotic, "b": Nameless(), 3: int, "return": Nameless()}
+    exotic = lize.Runnable.from_pyfn(g)
+    assert repr(exotic) == f"Runnable(<marshal> g(a: ?, b: ?, 3: int) -> ?, {len(marshal.dumps(g.__code__))} bytes of code, not cached)"
+    assert exotic(1, 2) == 1
+
+    # Nothing has been built yet, so there's nothing to run.
+    with pytest.raises(NotImplementedError):
+        lize.Runnable.jit()()
+
+
+def test_on_bad_runnable():
+    import marshal
+
+    # Without its source, corrupted bytecode can't be recompiled.
+    namespace = {}
+    exec("def double(x: int) -> int:\n    return x * 2", namespace)
+    double = namespace["double"]
+
+    code = marshal.dumps(double.__code__)
+    data = lize.serialize({"ok": 1, "fn": double, "fns": [double]})
+    assert code in data
+    corrupted = data.replace(code, b"\xff" * len(code))
+
+    with pytest.raises(ValueError):
+        lize.deserialize(corrupted)
+    with pytest.raises(ValueError):
+        lize.deserialize(corrupted, on_bad_runnable="error")
+
+    assert lize.deserialize(corrupted, on_bad_runnable="skip") == {"ok": 1, "fns": [None]}
+
+    decoded = lize.deserialize(corrupted, on_bad_runnable="placeholder")
+    assert decoded["ok"] == 1
+    broken = decoded["fn"]
+    assert isinstance(broken, lize.BrokenRunnable)
+    assert isinstance(broken.error, ValueError)
+    assert repr(broken).startswith("BrokenRunnable(<")
+    with pytest.raises(ValueError):
+        broken.load()
+
+    # The raw bytes load once they are valid again.
+    fixed = broken.data.replace(b"\xff" * len(code), code)
+    assert lize.Runnable.from_bytes(fixed)(21) == 42
+
+    # Intact functions are unaffected.
+    assert lize.deserialize(data, on_bad_runnable="skip")["fn"](21) == 42
+
+    with pytest.raises(ValueError):
+        lize.deserialize(data, on_bad_runnable="ignore")
+
+
+def test_runnable_nested_defaults():
+    def f(x=[1, 2], y={"k": 1}, z=None):
+        return x, y, z
+
+    decoded = lize.deserialize(lize.serialize(f))
+    assert decoded() == ([1, 2], {"k": 1}, None)
+    assert decoded([3]) == ([3], {"k": 1}, None)
+
+    class Opaque:
+        pass
+
+    def g(a, b=1, c=[Opaque()]):
+        return a
+
+    with pytest.raises(TypeError, match="parameter 'c' in g"):
+        lize.serialize(g)
+
+
+def test_runnable_function_defaults():
+    def backoff(attempt=3):
+        return [attempt * 10, len("ab")]
+
+    def retry(fn=backoff, times=2):
+        return [fn() for _ in range(times)]
+
+    def outer(inner=retry):
+        return inner()
+
+    decoded = lize.deserialize(lize.serialize(outer))
+    assert decoded() == [[30, 2], [30, 2]]
+    assert decoded(lambda: "called") == "called"
+
+    def ping(other=None):
+        return "ping"
+
+    def pong(other=ping):
+        return other()
+
+    ping.__defaults__ = (pong,)
+    with pytest.raises(TypeError, match="parameter 'other' in pong") as info:
+        lize.serialize(pong)
+    assert "refer back" in str(info.value.__cause__.__cause__)
+
+
+def test_bytes_looking_like_tags():
+    # 114 is "r" and 115 is "s", the prefixes of the old format.
+    for data in [b"r", b"rabc", b"s", b"shello"]:
+        assert lize.deserialize(lize.serialize(data)) == data
+
+
+def test_preserve_int_width():
+    import struct
+
+    # As a Rust producer writes them: U8(5), SmallU8(3), I32(7), I64(1), I32(-1),
+    # and a map with an I64 key and a U8 value.
+    data = b"\x10\x06" + b"\x0d\x05" + bytes([20 + 3]) + b"\x0b" + struct.pack("<i", 7)
+    data += b"\x00" + struct.pack("<q", 1) + b"\x0b" + struct.pack("<i", -1)
+    data += b"\x11\x01\x00" + struct.pack("<q", 9) + b"\x0d\xff"
+
+    assert lize.deserialize(data) == [5, 3, 7, 1, -1, {9: 255}]
+    assert lize.serialize(lize.deserialize(data)) != data
+
+    decoded = lize.deserialize(data, preserve_int_width=True)
+    assert decoded == [5, 3, 7, 1, -1, {9: 255}]
+    assert [i.width for i in decoded[:5]] == ["u8", "small_u8", "i32", "i64", "i32"]
+    assert decoded[5][9] == 255 and repr(decoded[0]) == "FixedInt(5, 'u8')"
+    assert lize.serialize(decoded) == data
+
+    assert lize.serialize(lize.FixedInt(7, "i64")) == b"\x00" + struct.pack("<q", 7)
+    with pytest.raises(ValueError):
+        lize.FixedInt(300, "u8")
+
+
+def test_dedupe_values():
+    import tracemalloc
+
+    metrics = [{"name": f"m{i % 50}", "value": 1.5, "meta": {"unit": "ms", "host": "web-01"}} for i in range(2_000)]
+    data = lize.serialize(metrics)
+
+    def decode(**kwargs):
+        tracemalloc.start()
+        decoded = lize.deserialize(data, **kwargs)
+        size = tracemalloc.get_traced_memory()[0]
+        tracemalloc.stop()
+        return decoded, size
+
+    plain, plain_size = decode()
+    shared, shared_size = decode(dedupe_values=True)
+    assert shared == plain == metrics
+
+    def distinct(rows, get):
+        return len({id(get(row)) for row in rows})
+
+    for get in (lambda row: row["meta"]["unit"], lambda row: row["meta"]["host"], lambda row: row["value"]):
+        assert distinct(plain, get) == len(metrics) and distinct(shared, get) == 1
+    assert distinct(shared, lambda row: row["name"]) == 50
+    assert shared_size < plain_size * 0.75
+
+    # Containers are never shared, so changing one leaves the others alone.
+    assert distinct(shared, lambda row: row["meta"]) == len(metrics)
+    shared[0]["meta"]["unit"] = "s"
+    assert shared[1]["meta"]["unit"] == "ms"
+
+    mixed = [1.0, 1, -0.0, 0.0, b"1", "1", 2**40, 2**40]
+    decoded = lize.deserialize(lize.serialize(mixed), dedupe_values=True)
+    assert repr(decoded) == repr(mixed) and decoded[6] is decoded[7]
+    assert lize.deserialize(lize.serialize([b"x", b"x"]), dedupe_values=True, bytes_as="memoryview")[0].obj is not None
+
+
+def test_bytes_as_memoryview():
+    import sys
+
+    value = {"blob": b"\x01\x02\x03" * 100, "parts": [b"ab", b"cd"], "name": "x"}
+    data = bytearray(lize.serialize(value))
+
+    refs = sys.getrefcount(data)
+    decoded = lize.deserialize(data, bytes_as="memoryview")
+    blob = decoded["blob"]
+    assert isinstance(blob, memoryview) and blob.readonly
+    assert blob == value["blob"] and [bytes(p) for p in decoded["parts"]] == [b"ab", b"cd"]
+    assert decoded["name"] == "x"
+
+    # A bytearray could change while it's decoded, so the views are over a
+    # copy of it; later writes don't show, and it can still be resized.
+    assert blob.obj is decoded["parts"][0].obj and blob.obj is not data
+    assert sys.getrefcount(data) == refs
+    start = bytes(data).index(b"ab")
+    data[start] = ord("z")
+    assert bytes(decoded["parts"][0]) == b"ab"
+    data.clear()
+
+    # Bytes can't change, so the views share their memory and keep them alive.
+    frozen = lize.serialize(value)
+    refs = sys.getrefcount(frozen)
+    decoded = lize.deserialize(frozen, bytes_as="memoryview")
+    assert decoded["blob"].obj is frozen and decoded["parts"][0].obj is frozen
+    assert sys.getrefcount(frozen) > refs
+
+    # Writes to the source from Python code that runs while decoding don't
+    # reach values that were already read.
+    data = bytearray(lize.serialize(["abcdefgh"] * 50))
+
+    def scribble(done, values):
+        data[:done] = b"\xff" * done
+
+    assert lize.deserialize(data, progress=scribble, progress_interval=16) == ["abcdefgh"] * 50
+    data = bytearray(lize.serialize(["abcdefgh"] * 50))
+    items = lize.iter_deserialize(data)
+    assert next(items) == "abcdefgh"
+    data[:] = b"\xff" * len(data)
+    assert list(items) == ["abcdefgh"] * 49
+
+    assert lize.deserialize(lize.serialize(b"hi"), bytes_as="memoryview").obj is not None
+    assert lize.deserialize(lize.serialize(b"hi")) == b"hi"
+    with pytest.raises(ValueError):
+        lize.deserialize(data, bytes_as="array")
+
+
+def test_progress():
+    value = [{"id": i, "name": f"item {i}"} for i in range(10_000)]
+    data = lize.serialize(value)
+
+    for kwargs in ({}, {"release_gil": True}):
+        reports = []
+        assert lize.serialize(value, progress=lambda *p: reports.append(p), progress_interval=50_000, **kwargs) == data
+        assert reports[-1] == (len(data), 50_001)
+        assert [done // 50_000 for done, _ in reports[:-1]] == list(range(1, len(data) // 50_000 + 1))
+
+        reports = []
+        assert lize.deserialize(data, progress=lambda *p: reports.append(p), progress_interval=50_000, **kwargs) == value
+        assert reports[-1] == (len(data), 50_001) and len(reports) == len(data) // 50_000 + 1
+
+    # The default interval is 8 MiB, so a small payload only gets the final report.
+    reports = []
+    lize.serialize(value, progress=lambda *p: reports.append(p))
+    assert reports == [(len(data), 50_001)]
+
+
+def test_progress_cancelled():
+    value = list(range(100_000))
+    calls = []
+
+    def stop_after_two(done, values):
+        calls.append(done)
+        return len(calls) < 2
+
+    with pytest.raises(lize.Cancelled):
+        lize.serialize(value, progress=stop_after_two, progress_interval=1_000, release_gil=True)
+    assert len(calls) == 2 and calls[-1] < 3_000
+
+    calls.clear()
+    with pytest.raises(lize.Cancelled):
+        lize.deserialize(lize.serialize(value), progress=stop_after_two, progress_interval=1_000)
+    assert len(calls) == 2
+
+    def broken(done, values):
+        raise KeyError("from the callback")
+
+    with pytest.raises(KeyError, match="from the callback"):
+        lize.serialize(value, progress=broken)
+    with pytest.raises(TypeError, match="progress must be callable"):
+        lize.serialize(value, progress=1)
+    with pytest.raises(TypeError, match="parallel"):
+        lize.serialize(value, progress=print, parallel=True)
+
+
+def test_serialize_buffer():
+    import gc
+
+    if not lize.features()["buffer_export"]:
+        with pytest.raises(NotImplementedError, match="Python 3.11"):
+            lize.serialize_buffer(1)
+        return
+
+    value = {"id": 1, "items": [b"x" * 1000] * 10}
+    data = lize.serialize(value)
+    result = lize.serialize_buffer(value)
+    assert len(result) == len(data) and bytes(result) == data
+
+    view = memoryview(result)
+    assert view.readonly and view.format == "B" and view.tobytes() == data
+    with pytest.raises(TypeError):
+        view[0] = 0
+
+    # The view keeps the bytes alive.
+    del result
+    gc.collect()
+    assert view.tobytes() == data and lize.deserialize(view) == value
+
+    key = b"k" * 32
+    framed = lize.serialize_buffer(value, key=key)
+    assert lize.deserialize(bytes(framed), key=key) == value
+
+
+def test_tune():
+    value = [{"id": i, "name": f"item {i}"} for i in range(1_000)]
+    data = lize.serialize(value)
+
+    previous = lize.tune(len(data))
+    try:
+        assert lize.tune(len(data)) == len(data)
+        assert lize.serialize(value) == data
+        assert lize.serialize({"small": True}) == lize.serialize({"small": True}, canonical=False)
+    finally:
+        lize.tune(previous)
+    with pytest.raises(OverflowError):
+        lize.tune(-1)
+
+
+def test_check_duplicate_keys():
+    import io
+    from collections.abc import Mapping
+
+    class Repeating(Mapping):
+        def __getitem__(self, key):
+            return 1
+
+        def __len__(self):
+            return 2
+
+        def __iter__(self):
+            return iter(["a", "a"])
+
+    class Tag:
+        def __init__(self, name):
+            self.name = name
+
+    # Different objects written as the same key.
+    tagged = {Tag("x"): 1, Tag("x"): 2}
+    for value, kwargs in ((Repeating(), {}), (tagged, {"default": lambda tag: tag.name})):
+        data = lize.serialize(value, **kwargs)
+        assert len(lize.deserialize(data)) == 1
+        with pytest.raises(ValueError, match="map entries 0 and 1 have the same key"):
+            lize.serialize([value], check_duplicate_keys=True, **kwargs)
+
+    # 1 and "1", or NaNs, are different keys.
+    assert lize.serialize({1: 1, "1": 2}, check_duplicate_keys=True) == lize.serialize({1: 1, "1": 2})
+    lize.serialize({float("nan"): 1, float("nan"): 2}, check_duplicate_keys=True)
+    with pytest.raises(TypeError, match="check_duplicate_keys"):
+        lize.serialize_map_items([], 0, io.BytesIO(), check_duplicate_keys=True)
+
+
+def test_serialize_default():
+    class Money:
+        def __init__(self, amount, currency):
+            self.amount, self.currency = amount, currency
+
+    seen = []
+
+    def default(obj):
+        seen.append(obj)
+        if isinstance(obj, Money):
+            return {"amount": obj.amount, "currency": obj.currency}
+        raise TypeError(f"can't serialize {type(obj).__name__}")
+
+    value = {"total": Money(12, "EUR"), "items": [Money(5, "USD"), 3]}
+    data = lize.serialize(value, default=default)
+    assert lize.deserialize(data) == {
+        "total": {"amount": 12, "currency": "EUR"},
+        "items": [{"amount": 5, "currency": "USD"}, 3],
+    }
+    assert len(seen) == 2
+
+    # Errors from `default` propagate, and supported values never reach it.
+    with pytest.raises(TypeError, match="can't serialize object"):
+        lize.serialize([object()], default=default)
+    assert lize.serialize([1, "a"], default=default) == lize.serialize([1, "a"])
+    assert len(seen) == 3
+
+    # What `default` returns isn't passed to it again.
+    with pytest.raises(TypeError, match="allow_reduce"):
+        lize.serialize(Money(1, "EUR"), default=lambda obj: object())
+    with pytest.raises(TypeError, match="callable"):
+        lize.serialize(1, default=3)
+
+
+def test_json_keys():
+    import json
+
+    value = {1: "one", 2.5: [{None: True, "kept": b"x"}], "s": MarkedStr("m")}
+    decoded = lize.deserialize(lize.serialize(value, json_keys=True))
+    assert decoded == {"1": "one", "2.5": [{"None": True, "kept": b"x"}], "s": "m"}
+    assert all(type(key) is str for key in decoded)
+    assert json.loads(json.dumps(lize.deserialize(lize.serialize({7: 1, 8: 2}, json_keys=True)))) == {"7": 1, "8": 2}
+
+    # String keys, even subclasses, are written as they are.
+    keys = list(lize.deserialize(lize.serialize({MarkedStr("k"): 1}, json_keys=True)))
+    assert type(keys[0]) is MarkedStr
+    assert lize.serialize({"a": 1}, json_keys=True) == lize.serialize({"a": 1})
+
+    with pytest.raises(ValueError, match="keys 1 and '1' are both written as '1'"):
+        lize.serialize({1: "int", "1": "str"}, json_keys=True)
+    assert len(lize.deserialize(lize.serialize({1: "int", "1": "str"}))) == 2
+
+
+def test_decoder():
+    decoder = lize.Decoder()
+    first = decoder.decode(lize.serialize({"id": 1, "tags": ["a"]}))
+    second = decoder.decode(lize.serialize({"id": 2, "tags": ["b"]}))
+    assert first == {"id": 1, "tags": ["a"]} and second == {"id": 2, "tags": ["b"]}
+    # Keys are shared between records of the same shape, values aren't.
+    assert all(a is b for a, b in zip(first, second))
+    assert first["tags"] is not second["tags"]
+    assert decoder.decode(lize.serialize({"other": 1, "id": [1]})) == {"other": 1, "id": [1]}
+    assert decoder.decode(lize.serialize([1, "x"])) == [1, "x"]
+
+    scratch = {}
+    records = [{"a": 1, "b": {"c": 2}}, {"b": 3}, {}, {"d": None, "a": [4]}, {"a": 1, "b": {"c": 2}}]
+    for record in records:
+        assert decoder.decode_into(lize.serialize(record), scratch) is scratch
+        assert scratch == record
+        assert list(scratch) == list(record)
+
+    with pytest.raises(TypeError, match="holding a dict"):
+        decoder.decode_into(lize.serialize([1]), scratch)
+    with pytest.raises(TypeError, match="holding a dict"):
+        lize.Decoder(maps_as_pairs=True).decode_into(lize.serialize({"a": 1}), {})
+
+    views = lize.Decoder(bytes_as="memoryview").decode(bytearray(lize.serialize({"k": b"xy"})))
+    assert bytes(views["k"]) == b"xy"
+    assert lize.Decoder(allow_reduce=True).decode(lize.serialize({"p": Point(1, 2)}, allow_reduce=True)) == {"p": Point(1, 2)}
+
+
+def test_bytes_raw():
+    from array import array
+
+    assert lize.serialize(b"\x00\xff\xfe") == b"\x12\x03\x00\xff\xfe"
+    for value in (b"\x00\xff\xfe", b"", b"\x00" * 300, bytes(range(256))):
+        assert lize.deserialize(lize.serialize(value)) == value
+    assert len(lize.serialize(b"x" * 1000)) == 1003
+    assert len(lize.serialize(b"x" * 100_000)) == 100_004
+
+    # Byte buffers are written like bytes, and decode as bytes.
+    data = b"\x00\xff\xfe"
+    for buffer in (bytearray(data), memoryview(data), memoryview(b"\x00?\xff?\xfe")[::2]):
+        assert lize.serialize(buffer) == lize.serialize(data)
+        assert lize.deserialize(lize.serialize({"b": [buffer]})) == {"b": [data]}
+    # Views of wider items are still lists of them.
+    assert lize.deserialize(lize.serialize(memoryview(array("i", [1, -2])))) == [1, -2]
+
+
+def test_float_quantize():
+    import math
+    import random
+    import zlib
+    from array import array
+
+    readings = [20 + random.Random(seed).random() for seed in range(2000)]
+    decoded = lize.deserialize(lize.serialize(readings, float_quantize=3))
+    assert decoded == [float(f"{f:.2e}") for f in readings]
+    assert lize.deserialize(lize.serialize(3.14159, float_quantize=2)) == 3.1
+    assert lize.deserialize(lize.serialize(-0.000123456, float_quantize=3)) == -0.000123
+
+    full = zlib.compress(lize.serialize(readings))
+    quantized = zlib.compress(lize.serialize(readings, float_quantize=3))
+    assert len(quantized) < len(full) / 2
+
+    # Rounded values an f32 holds exactly take 4 bytes.
+    assert len(lize.serialize(20.5001, float_quantize=3)) < len(lize.serialize(20.5001))
+    assert lize.deserialize(lize.serialize(0.1, float_quantize="f32")) == float(array("f", [0.1])[0])
+    assert len(lize.serialize(readings, float_quantize="f32")) < len(lize.serialize(readings))
+
+    special = lize.deserialize(lize.serialize([math.inf, -math.inf, math.nan], float_quantize=2))
+    assert special[:2] == [math.inf, -math.inf] and math.isnan(special[2])
+    assert lize.deserialize(lize.serialize({"n": 7, "b": True}, float_quantize=1)) == {"n": 7, "b": True}
+
+    for bad in (0, 18, "f16", 1.5):
+        with pytest.raises(ValueError, match="float_quantize"):
+            lize.serialize(1.0, float_quantize=bad)
+
+
+def test_rle():
+    sparse = [0] * 10_000
+    for i in (17, 4_000, 9_999):
+        sparse[i] = i
+    data = lize.serialize(sparse, rle=True)
+    assert len(data) == 32 and len(lize.serialize(sparse)) == 10_011
+    assert lize.deserialize(data) == sparse
+
+    mask = [False] * 10_000
+    mask[5_000:5_100] = [True] * 100
+    assert len(lize.serialize(mask, rle=True)) == 18 and len(lize.serialize(mask)) == 1_256
+    assert lize.deserialize(lize.serialize(mask, rle=True)) == mask
+
+    values = [
+        [],
+        [1, 2, 3],
+        [-0.0, 0.0, 0.0, 0.0, 0.0, float("inf")],
+        ["a"] * 300 + [b"a"] * 300 + [None] * 3,
+        [True, 1, 1, 1, 1, 1.0],
+        {"nested": [[1, 2]] * 50, "rows": [{"x": 1}] * 5},
+    ]
+    for value in values:
+        decoded = lize.deserialize(lize.serialize(value, rle=True))
+        assert decoded == value
+        assert repr(decoded) == repr(value)
+
+    # Each copy of a container is its own object.
+    rows = lize.deserialize(lize.serialize([{"x": 1}] * 5, rle=True))
+    rows[0]["x"] = 2
+    assert rows[1] == {"x": 1}
+
+    with pytest.raises(ValueError, match="max_decoded_size"):
+        lize.deserialize(data, max_decoded_size=10_000)
+    assert lize.estimate_decoded_size(lize.serialize([0] * 100_000, rle=True)) > 800_000
+
+
+def test_malformed_runs():
+    # `[1] * count`, with the count as an I64.
+    def runs(count):
+        return bytes.fromhex("0e0d100215") + b"\x00" + count.to_bytes(8, "little", signed=True)
+
+    assert lize.deserialize(runs(3)) == [1, 1, 1]
+    for count in (2**40, 2**62, 2**28 + 1, 0, -1):
+        with pytest.raises(ValueError, match="runs"):
+            lize.deserialize(runs(count))
+        with pytest.raises(ValueError, match="runs"):
+            lize.estimate_decoded_size(runs(count))
+
+    # Runs that add up to too many items, each run small enough on its own.
+    half = (2**27 + 1).to_bytes(8, "little")
+    overflow = bytes.fromhex("0e0d100415") + b"\x00" + half + b"\x15\x00" + half
+    bad = [overflow, bytes.fromhex("0e0d100115"), bytes.fromhex("0e0d1502"), bytes.fromhex("0e0d1002151300")]
+    for data in bad:
+        with pytest.raises(ValueError, match="runs"):
+            lize.deserialize(data)
+
+    # A few bytes of runs can't expand to millions of values by default,
+    # whether in one run, side by side or nested, unless max_expansion allows it.
+    bomb = bytes.fromhex("0e0d10020a00") + (2**28).to_bytes(8, "little")
+    side_by_side = bytes.fromhex("1008") + runs(2**20) * 8
+    nested = bytes.fromhex("0e0d1002") + runs(2**11) + b"\x00" + (2**11).to_bytes(8, "little")
+    for data in (bomb, side_by_side, nested):
+        with pytest.raises(ValueError, match="max_expansion"):
+            lize.deserialize(data)
+    with pytest.raises(ValueError, match="max_expansion"):
+        lize.deserialize(runs(10**6), max_expansion=10**5)
+    with pytest.raises(ValueError, match="max_expansion"):
+        lize.deserialize_first(bomb)
+    with pytest.raises(ValueError, match="max_expansion"):
+        next(lize.iter_deserialize(bytes.fromhex("1001") + nested))
+    assert len(lize.deserialize(nested, max_expansion=2**23)) == 2**11
+    assert lize.deserialize(runs(2**20)) == [1] * 2**20
+
+
+def test_reentrant_hooks():
+    class A:
+        def __init__(self, inner):
+            self.inner = inner
+
+    class B:
+        def __init__(self, depth):
+            self.depth = depth
+
+    def default(obj):
+        # Each hook serializes its own nested payload, which calls it again.
+        if isinstance(obj, A):
+            return {"a": lize.serialize(obj.inner, default=default)}
+        if isinstance(obj, B) and obj.depth:
+            nested = lize.serialize([B(obj.depth - 1), "x" * 200], default=default, rle=True)
+            assert lize.deserialize(nested, max_decoded_size=10_000) is not None
+            return {"b": nested}
+        return {"b": None}
+
+    value = [A({"items": [B(3), A(B(2))], "blob": b"\x00" * 300}), B(1)]
+    data = lize.serialize(value, default=default)
+    decoded = lize.deserialize(data)
+
+    inner = lize.deserialize(decoded[0]["a"])
+    assert inner["blob"] == b"\x00" * 300
+    b3 = lize.deserialize(inner["items"][0]["b"])
+    assert b3[1] == "x" * 200
+    assert lize.deserialize(b3[0]["b"])[0] == {"b": lize.serialize([{"b": None}, "x" * 200])}
+    assert lize.deserialize(lize.deserialize(inner["items"][1]["a"])["b"])[1] == "x" * 200
+    assert lize.serialize(value, default=default) == data
+
+    # Registering from inside a hook applies to the items after it.
+    class Tag(str):
+        pass
+
+    def registering(obj):
+        lize.register(Tag)
+        return [Tag("t"), lize.deserialize(lize.serialize(Tag("u")))]
+
+    decoded = lize.deserialize(lize.serialize([object(), Tag("v")], default=registering))
+    assert decoded == [["t", "u"], "v"]
+    assert type(decoded[0][0]) is Tag and type(decoded[0][1]) is Tag
+    assert type(decoded[1]) is Tag
+
+
+def test_ip_addresses():
+    import ipaddress
+
+    values = [
+        ipaddress.ip_address("192.168.1.20"),
+        ipaddress.ip_address("2001:db8::8a2e:370:7334"),
+        ipaddress.ip_network("10.0.0.0/8"),
+        ipaddress.ip_network("2001:db8::/32"),
+    ]
+    for value in values:
+        decoded = lize.deserialize(lize.serialize(value))
+        assert decoded == value and type(decoded) is type(value)
+
+    # The version and packed bytes, instead of the string form.
+    assert lize.serialize(values[0]) == b"\x0e\x0e\x12\x05\x04\xc0\xa8\x01\x14"
+    assert len(lize.serialize(values[1])) == 21 < len(lize.serialize(str(values[1])))
+    assert lize.deserialize(lize.serialize(values[2])).prefixlen == 8
+
+    hosts = {"web": [values[0], ipaddress.ip_address("10.0.0.1")], "net": values[2]}
+    assert lize.deserialize(lize.serialize(hosts)) == hosts
+    with pytest.raises(ValueError, match="scope ID"):
+        lize.serialize(ipaddress.ip_address("fe80::1%eth0"))
+
+
+def test_raw_passthrough():
+    payload = b"\xff\xfe not utf-8 \x00" * 50
+    wrapped = lize.wrap_raw(payload, tag=200)
+    assert wrapped == b"\x0e\xc8\x12\xbc\x05" + payload
+
+    tag, view = lize.unwrap_raw(wrapped)
+    assert tag == 200 and view == payload
+    # A view over the wrapped bytes, not a copy.
+    assert view.readonly and view.obj is wrapped
+
+    assert lize.unwrap_raw(lize.wrap_raw(bytearray(b"")))[0] == 0
+    assert lize.unwrap_raw(lize.wrap_raw(memoryview(b"abc"), tag=1))[1] == b"abc"
+    with pytest.raises(ValueError, match="wrap_raw"):
+        lize.unwrap_raw(lize.serialize(b"abc"))
+    with pytest.raises(ValueError):
+        lize.unwrap_raw(wrapped[:-1])
+    with pytest.raises(OverflowError):
+        lize.wrap_raw(b"", tag=256)
+
+
+def test_nan_keys():
+    import math
+
+    # Every NaN is its own key, even once decoded with dedupe_values.
+    value = {float("nan"): 1, 2.0: 3, float("nan"): 4}
+    for encode, decode in (({}, {}), ({"canonical": True}, {}), ({}, {"dedupe_values": True})):
+        decoded = lize.deserialize(lize.serialize(value, **encode), **decode)
+        assert len(decoded) == 3
+        assert sorted(v for k, v in decoded.items() if isinstance(k, float) and math.isnan(k)) == [1, 4]
+        assert decoded[2.0] == 3
+
+    # Canonically, NaN sorts after every other float, and is written all the same.
+    data = lize.serialize({float("nan"): 1, float("inf"): 2, -1.5: 3}, canonical=True)
+    assert [v for v in lize.deserialize(data).values()] == [3, 2, 1]
+    assert lize.serialize({float("nan"): 1, 2.0: 3}, canonical=True) == lize.serialize({2.0: 3, float("nan"): 1}, canonical=True)
+
+
+def test_big_int():
+    for n in [2**63, -(2**63) - 1, 10**40, -(10**40)]:
+        assert lize.deserialize(lize.serialize(n)) == n
+
+
+def test_fraction():
+    from fractions import Fraction
+
+    third = lize.deserialize(lize.serialize(Fraction(1, 3)))
+    assert type(third) is Fraction
+    assert third == Fraction(1, 3)
+
+    huge = Fraction(-7, 10**60 + 1)
+    assert lize.deserialize(lize.serialize(huge)) == huge
+
+
+def test_timedelta():
+    from datetime import timedelta
+
+    for delta in (timedelta(days=1, microseconds=5), timedelta(seconds=-1.5), timedelta(days=-10**6)):
+        decoded = lize.deserialize(lize.serialize(delta))
+        assert type(decoded) is timedelta
+        assert decoded == delta
+
+
+def test_datetime():
+    import zoneinfo
+    from datetime import datetime, timedelta, timezone
+
+    new_york = zoneinfo.ZoneInfo("America/New_York")
+    india = timezone(timedelta(hours=5, minutes=30))
+    for dt in (
+        datetime(2021, 11, 7, 1, 30, fold=1, tzinfo=new_york),
+        datetime(2021, 11, 7, 1, 30, fold=1),
+        datetime(2024, 2, 29, 12, 0, 0, 0, tzinfo=india),
+        datetime(2024, 2, 29, 12, 0, 0, 999_999, tzinfo=timezone.utc),
+        datetime(1, 1, 1, tzinfo=timezone(-timedelta(hours=23, minutes=59, microseconds=1))),
+    ):
+        decoded = lize.deserialize(lize.serialize(dt))
+        assert type(decoded) is datetime
+        assert decoded == dt
+        assert (decoded.fold, decoded.microsecond) == (dt.fold, dt.microsecond)
+        assert decoded.tzinfo == dt.tzinfo
+        assert decoded.utcoffset() == dt.utcoffset()
+
+    # The fold picks the offset of the repeated hour: EDT first, then EST.
+    first, second = (lize.deserialize(lize.serialize(datetime(2021, 11, 7, 1, 30, fold=fold, tzinfo=new_york))) for fold in (0, 1))
+    assert first.utcoffset() == timedelta(hours=-4)
+    assert second.utcoffset() == timedelta(hours=-5)
+    assert lize.deserialize(lize.serialize(datetime(2024, 1, 1, tzinfo=timezone.utc))).tzinfo is timezone.utc
+
+
+class MarkedStr(str):
+    __lize_subclass__ = True
+
+
+def test_str_subclasses():
+    import warnings
+
+    class MyStr(str):
+        pass
+
+    class MyBytes(bytes):
+        pass
+
+    # Unregistered subclasses are written as their base type.
+    decoded = lize.deserialize(lize.serialize([MyStr("a"), MyBytes(b"b")]))
+    assert decoded == ["a", b"b"]
+    assert type(decoded[0]) is str and type(decoded[1]) is bytes
+
+    with pytest.warns(UserWarning, match="MyStr"):
+        lize.serialize(MyStr("a"), warn_subclasses=True)
+
+    assert lize.register(MyStr) is MyStr
+    lize.register(MyBytes)
+
+    data = lize.serialize({"k": MyStr("a"), "b": MyBytes(b"b")})
+    decoded = lize.deserialize(data)
+    assert decoded == {"k": "a", "b": b"b"}
+    assert type(decoded["k"]) is MyStr and type(decoded["b"]) is MyBytes
+
+    # Marked classes are found without registering them.
+    decoded = lize.deserialize(lize.serialize(MarkedStr("m")))
+    assert type(decoded) is MarkedStr and decoded == "m"
+
+    with pytest.raises(TypeError):
+        lize.register(int)
+
+    with warnings.catch_warnings():
+        warnings.simplefilter("error")
+        lize.serialize(MyStr("a"), warn_subclasses=True)
+
+
+def test_register_extension():
+    class Point:
+        def __init__(self, x, y):
+            self.x, self.y = x, y
+
+    class Point3(Point):
+        pass
+
+    assert lize.register_extension(250, Point, lambda p: [p.x, p.y], lambda xy: Point(*xy)) is Point
+
+    decoded = lize.deserialize(lize.serialize({"at": Point(1, 2.5), "also": [Point3(-3, 4)]}))
+    assert type(decoded["at"]) is Point and (decoded["at"].x, decoded["at"].y) == (1, 2.5)
+    # Subclasses share the encoder, and decode as the registered class.
+    assert type(decoded["also"][0]) is Point and decoded["also"][0].x == -3
+    # Only the payload is written, rather than the class and `__dict__` `__reduce__` gives.
+    assert len(lize.serialize(Point(1, 2))) == len(lize.serialize([1, 2])) + 2
+
+    with pytest.raises(ValueError, match="reserved"):
+        lize.register_extension(16, Point3, list, list)
+    with pytest.raises(ValueError, match="already registered"):
+        lize.register_extension(250, Point3, list, list)
+    with pytest.raises(ValueError, match="already registered"):
+        lize.register_extension(251, Point, list, list)
+
+    class Loop:
+        pass
+
+    lize.register_extension(252, Loop, lambda loop: loop, lambda payload: Loop())
+    with pytest.raises(TypeError, match="another registered extension"):
+        lize.serialize(Loop())
+
+    with pytest.raises(ValueError, match="Unsupported extension"):
+        lize.deserialize(lize.wrap_raw(b"x", 253))
+
+
+def test_max_depth():
+    data = [[[1]]]
+
+    assert lize.deserialize(lize.serialize(data, max_depth=3), max_depth=3) == data
+
+    with pytest.raises(ValueError):
+        lize.serialize(data, max_depth=2)
+
+    with pytest.raises(ValueError):
+        lize.deserialize(lize.serialize(data), max_depth=2)
+
+
+def test_deeply_nested_dict():
+    depth = 20_000
+
+    data = 1
+    for _ in range(depth):
+        data = {"k": data}
+
+    decoded = lize.deserialize(lize.serialize(data))
+
+    # Compared by hand, since `==` would hit the recursion limit.
+    for _ in range(depth):
+        assert list(decoded) == ["k"]
+        decoded = decoded["k"]
+    assert decoded == 1
+
+
+def test_unknown_option():
+    with pytest.raises(TypeError):
+        lize.serialize(1, not_an_option=True)
+
+    with pytest.raises(TypeError):
+        lize.deserialize(lize.serialize(1), not_an_option=True)
+
+
+def test_canonical():
+    a = {"zeta": 1, "alpha": [2, {"y": 1, "x": 2}], 3: None}
+    b = {3: None, "alpha": [2, {"x": 2, "y": 1}], "zeta": 1}
+
+    assert lize.serialize(a) != lize.serialize(b)
+    assert lize.serialize(a, canonical=True) == lize.serialize(b, canonical=True)
+    assert lize.deserialize(lize.serialize(a, canonical=True)) == a
+
+
+def test_release_gil():
+    value = {"a": [1, 2.5, "three"], "b": None}
+    data = lize.serialize(value, release_gil=True)
+    assert data == lize.serialize(value)
+    assert lize.deserialize(data, release_gil=True) == value
+
+
+def test_keys():
+    rows = [
+        {"id": 1, "status": "open", "detail": {"id": "inner", "note": "kept"}, "name": "a"},
+        [{"id": 2, "name": "b"}, {"name": "c"}],
+        7,
+    ]
+    data = lize.serialize(rows)
+
+    assert lize.deserialize(data, keys=["id", "detail", "absent"]) == [
+        {"id": 1, "detail": {"id": "inner", "note": "kept"}},
+        [{"id": 2}, {}],
+        7,
+    ]
+    assert lize.deserialize(lize.serialize({"id": 1, "name": "x"}), keys=["name"]) == {"name": "x"}
+    assert lize.deserialize(data, keys=[]) == [{}, [{}, {}], 7]
+
+    # Lists of runs are projected like the lists they stand for.
+    secrets = [{"id": 1, "secret": "x"}] * 10
+    assert lize.deserialize(lize.serialize(secrets, rle=True), keys=["id"]) == [{"id": 1}] * 10
+    got = list(lize.iter_deserialize(lize.serialize(secrets, rle=True), keys=["id"]))
+    assert got == [{"id": 1}] * 10
+
+    with pytest.raises(TypeError):
+        lize.deserialize(data, keys="id")
+
+
+def test_maps_as_pairs():
+    # {1: 2, 1: 3, 4: {5: 6}}, with a duplicate key no dict could hold.
+    data = b"\x04\x01\x15\x01\x16\x01\x15\x01\x17\x01\x18\x06\x04\x01\x19\x01\x1a\x05\x05"
+
+    assert lize.deserialize(data) == {1: 3, 4: {5: 6}}
+    assert lize.deserialize(data, maps_as_pairs=True) == [(1, 2), (1, 3), (4, [(5, 6)])]
+
+
+def test_record():
+    config = {
+        "server": {"host": "localhost", "port": 8080, "tls": {"cert": "a.pem", "key": "a.key"}},
+        "workers": [{"name": "w1", "threads": 4}, {"name": "w2", "threads": 8}],
+        "limits": {1: "one", "_private": 2},
+    }
+    cfg = lize.deserialize(lize.serialize(config), record=True)
+
+    assert cfg.server.host == "localhost"
+    assert cfg.server.port == 8080
+    assert cfg.server.tls.cert == "a.pem"
+    assert [w.threads for w in cfg.workers] == [4, 8]
+    assert type(cfg.workers[0]) is type(cfg.workers[1])
+    assert cfg.server._asdict()["port"] == 8080
+    # Keys that can't be attribute names leave the map a dict.
+    assert cfg.limits == {1: "one", "_private": 2}
+    with pytest.raises(AttributeError):
+        cfg.server.host = "example.com"
+
+    assert lize.deserialize(lize.serialize({}), record=True) == ()
+    with pytest.raises(TypeError, match="maps_as_pairs"):
+        lize.deserialize(lize.serialize({}), record=True, maps_as_pairs=True)
+
+
+CROSS_LANGUAGE = Path(__file__).resolve().parents[2] / "lize" / "tests" / "cross_language"
+SAMPLE = {
+    "name": "lize",
+    "version": 3,
+    "tags": ["fast", "small"],
+    "nested": {"ok": True},
+    "ratio": 0.1,
+    "blob": b"\x00\xff",
+}
+
+
+def test_cross_language_to_rust():
+    # Decoded by lize/tests/cross_language.rs.
+    path = CROSS_LANGUAGE / "from_python.lize"
+    if not path.exists() and os.environ.get("LIZE_BLESS"):
+        path.write_bytes(lize.serialize(SAMPLE))
+
+    assert lize.serialize(SAMPLE) == path.read_bytes()
+
+
+def test_cross_language_from_rust():
+    # Written by lize/tests/cross_language.rs.
+    data = lize.deserialize((CROSS_LANGUAGE / "from_rust.lize").read_bytes())
+
+    assert data == SAMPLE
+    assert type(data["nested"]["ok"]) is bool
+
+
+SCHEMA = lize.Schema(["id", "name", "email", "tags", "active"])
+RECORD = {"id": 7, "name": "lize", "tags": ["fast", "small"], "active": True}
+
+
+def test_cross_language_schema_to_rust():
+    # Decoded by lize/tests/cross_language.rs.
+    path = CROSS_LANGUAGE / "schema_from_python.lize"
+    if not path.exists() and os.environ.get("LIZE_BLESS"):
+        path.write_bytes(lize.serialize(RECORD, schema=SCHEMA))
+
+    assert lize.serialize(RECORD, schema=SCHEMA) == path.read_bytes()
+
+
+def test_cross_language_schema_from_rust():
+    # Written by lize/tests/cross_language.rs.
+    data = (CROSS_LANGUAGE / "schema_from_rust.lize").read_bytes()
+
+    assert lize.deserialize(data, schema=SCHEMA) == RECORD
+
+
+def test_cross_language_hash_keys_from_rust():
+    import hashlib
+
+    # Written canonically by lize/tests/cross_language.rs.
+    data = (CROSS_LANGUAGE / "hash_keys_from_rust.lize").read_bytes()
+    decoded = lize.deserialize(data)
+
+    assert len(decoded) == 8 and all(type(key) is bytes for key in decoded)
+    for i in range(8):
+        assert decoded[hashlib.sha256(f"key {i}".encode()).digest()[:16]] == i
+    assert lize.serialize(decoded, canonical=True) == data
+
+    # Keys are never legacy functions, even when they start with `r`.
+    binary = {b"r\x00\xff": 1, b"sabc": 2, b"\xfe": 3}
+    assert lize.deserialize(lize.serialize(binary), raw_slices="legacy") == {b"r\x00\xff": 1, "abc": 2, b"\xfe": 3}
+
+
+def test_metadata():
+    import io
+    import time
+
+    value = {"id": 1, "name": "row"}
+    data = lize.serialize(value, metadata={"source": "nightly"})
+    metadata = lize.read_metadata(data)
+    assert metadata["tags"] == {"source": "nightly"}
+    assert metadata["producer"].startswith("lize ")
+    assert abs(metadata["created"] - time.time()) < 60
+    assert "schema" not in metadata
+    assert lize.deserialize(data, framed=True) == value
+
+    # The payload inside is the one written without metadata.
+    retagged = lize.serialize(value, metadata={"source": "manual"})
+    assert data.endswith(lize.serialize(value)) and retagged.endswith(lize.serialize(value))
+    assert lize.read_metadata(lize.serialize(value, key=bytes(32))) is None
+    with pytest.raises(ValueError, match="not a lize frame"):
+        lize.read_metadata(lize.serialize(value))
+    with pytest.raises(TypeError):
+        lize.serialize(value, metadata=[("source", "nightly")])
+
+    # Readable without the key, and with a schema its hash is recorded.
+    schema = lize.Schema(["id", "name"])
+    key = os.urandom(32)
+    for authenticate in [False, True]:
+        sealed = lize.serialize(value, schema=schema, key=key, metadata={}, authenticate_metadata=authenticate)
+        assert lize.read_metadata(sealed)["schema"] == schema.hash
+        assert lize.deserialize(sealed, schema=schema, key=key) == value
+
+    file = io.BytesIO()
+    writer = lize.Writer(file, metadata={"part": 1})
+    writer.write(value)
+    writer.write([1, 2])
+    assert lize.read_metadata(file.getvalue())["tags"] == {"part": 1}
+    assert list(lize.Reader(io.BytesIO(file.getvalue()))) == [value, [1, 2]]
+
+    # A header claiming almost 4 GiB isn't allocated before the bytes arrive.
+    for lying in (b"LZ\x01\x00\xf0\xff\xff\xff\x00", b"LZ\x01\x04\x00\x00\x00\x00\xf0\xff\xff\xff\x00"):
+        with pytest.raises(ValueError, match="truncated"):
+            next(lize.Reader(io.BytesIO(lying)))
+
+
+def test_schema():
+    import io
+
+    assert SCHEMA.fields == ["id", "name", "email", "tags", "active"]
+    assert repr(lize.Schema(["a", "b"])) == "Schema(['a', 'b'])"
+    assert lize.Schema(["id", "name"]).hash == 0x0088_58BC_0342_5BCB
+
+    # Keys are left out, and `None` is kept apart from a missing field.
+    record = {"id": 1, "name": "a long enough name", "email": None}
+    data, info = lize.serialize_with_info(record, schema=SCHEMA)
+    # The keys took 17 bytes, and each of the two absent fields takes 3.
+    assert info["payload_size"] == len(lize.serialize(record)) - 17 + 2 * 3
+    assert b"email" not in data
+    assert lize.deserialize(data, schema=SCHEMA) == record
+    assert lize.deserialize(lize.serialize({}, schema=SCHEMA), schema=SCHEMA) == {}
+
+    key = bytes(range(32))
+    sealed = lize.serialize(record, schema=SCHEMA, key=key)
+    assert lize.deserialize(sealed, schema=SCHEMA, key=key) == record
+
+    # Other schemas, or none, fail instead of pairing values with the wrong keys.
+    other = lize.Schema(["name", "id", "email", "tags", "active"])
+    with pytest.raises(ValueError, match="written with schema"):
+        lize.deserialize(data, schema=other)
+    with pytest.raises(ValueError, match="but none was given"):
+        next(lize.Reader(io.BytesIO(data)))
+    with pytest.raises(ValueError, match="frame has no schema"):
+        lize.deserialize(lize.serialize(record, key=key), schema=SCHEMA, key=key)
+
+    with pytest.raises(ValueError, match="isn't a field"):
+        lize.serialize({"id": 1, "extra": 2}, schema=SCHEMA)
+    with pytest.raises(ValueError, match="expected a map"):
+        lize.serialize([1], schema=SCHEMA)
+    with pytest.raises(ValueError, match="appears twice"):
+        lize.Schema(["a", "a"])
+    with pytest.raises(TypeError):
+        lize.deserialize(data, schema=SCHEMA, keys=["id"])
+
+    buffer = io.BytesIO()
+    writer = lize.Writer(buffer, schema=SCHEMA)
+    writer.write({"id": 1})
+    writer.write({"name": "b"})
+    assert list(lize.Reader(io.BytesIO(buffer.getvalue()), schema=SCHEMA)) == [
+        {"id": 1},
+        {"name": "b"},
+    ]
+
+    value, rest = lize.deserialize_first(data + data, schema=SCHEMA)
+    assert value == record and rest == data
+
+
+def test_deserialize_first():
+    first = lize.serialize({"id": 1, "tags": ["a", "b"]})
+    second = lize.serialize([2, "two"])
+
+    value, rest = lize.deserialize_first(first + second)
+    assert value == {"id": 1, "tags": ["a", "b"]}
+    assert rest == second
+    assert lize.deserialize(rest) == [2, "two"]
+
+    # The rest isn't decoded, so it may hold anything.
+    value, rest = lize.deserialize_first(first + b"\x05not lize")
+    assert value == {"id": 1, "tags": ["a", "b"]}
+    assert rest == b"\x05not lize"
+
+    value, rest = lize.deserialize_first(second)
+    assert value == [2, "two"] and rest == b""
+
+    key = bytes(range(32))
+    sealed = lize.serialize("secret", key=key) + lize.serialize("more", key=key)
+    value, rest = lize.deserialize_first(sealed, key=key)
+    assert value == "secret"
+    assert lize.deserialize(rest, key=key) == "more"
+
+
+def test_to_columns():
+    from array import array
+
+    rows = [
+        {"id": 1, "name": "a", "score": 0.5, "extra": [1, 2]},
+        {"id": 300, "name": "b", "score": 1.5},
+        {"id": -2, "score": 2.5},
+    ]
+    data = lize.serialize(rows)
+
+    columns = lize.to_columns(data, ["id", "name", "missing"])
+    assert columns == {"id": [1, 300, -2], "name": ["a", "b", None], "missing": [None] * 3}
+
+    typed = lize.to_columns(data, ["id", "score", "name"], typed=True)
+    assert typed["id"] == array("q", [1, 300, -2])
+    assert typed["score"] == array("d", [0.5, 1.5, 2.5])
+    assert typed["name"] == ["a", "b", None]
+
+    assert lize.to_columns(lize.serialize([]), ["id"], typed=True) == {"id": []}
+
+    key = bytes(range(32))
+    assert lize.to_columns(lize.serialize(rows, key=key), ["id"], key=key) == {"id": [1, 300, -2]}
+
+    with pytest.raises(ValueError):
+        lize.to_columns(lize.serialize({"id": 1}), ["id"])
+    with pytest.raises(ValueError):
+        lize.to_columns(lize.serialize([{"id": 1}, 2]), ["id"])
+
+
+def test_structured_arrays():
+    np = pytest.importorskip("numpy")
+
+    dtype = np.dtype([
+        ("id", ">i4"),
+        ("pos", "<f8", (2,)),
+        ("name", "U5"),
+        ("code", "S3"),
+        ("when", ">M8[ms]"),
+        ("inner", [("a", "u1"), ("b", ">f4")]),
+    ])
+    array = np.zeros(3, dtype)
+    array["id"] = [1, -2, 70_000]
+    array["pos"] = [[0.5, 1], [-1.5, 2], [0, 0]]
+    array["name"] = ["ada", "grace", ""]
+    array["code"] = [b"x", b"yz", b"abc"]
+    array["when"] = np.array(["2024-01-01T00:00:00.001", "1970-01-01", "2000-02-29"], "M8[ms]")
+    array["inner"]["a"] = [1, 2, 255]
+    array["inner"]["b"] = [0.25, -1, 3]
+
+    decoded = lize.deserialize(lize.serialize({"rows": array}))["rows"]
+    assert decoded.dtype.names == dtype.names
+    # Fields come back little-endian, at the same offsets.
+    assert decoded.dtype["id"] == np.dtype("<i4")
+    assert decoded.dtype["inner"]["b"] == np.dtype("<f4")
+    assert decoded.dtype.itemsize == dtype.itemsize
+    assert [decoded.dtype.fields[name][1] for name in dtype.names] == [dtype.fields[name][1] for name in dtype.names]
+    for name in ("id", "pos", "name", "code", "when"):
+        assert np.array_equal(decoded[name], array[name])
+    assert decoded["inner"].tolist() == array["inner"].tolist()
+
+    grid = np.zeros((2, 2), [("x", "<i2")])
+    grid["x"] = [[1, 2], [3, 4]]
+    assert lize.deserialize(lize.serialize(grid))["x"].tolist() == [[1, 2], [3, 4]]
+
+    with pytest.raises(TypeError, match="field 'inner.obj'"):
+        lize.serialize(np.zeros(1, [("inner", [("a", "i4"), ("obj", "O")])]))
+
+
+def test_arrow():
+    pa = pytest.importorskip("pyarrow")
+
+    rows = [
+        {"id": 1, "name": "a", "score": 0.5, "blob": b"\x00", "ok": True},
+        {"id": 1 << 40, "name": None, "score": 1.5, "blob": b"", "ok": False},
+        {"id": -2, "name": "c", "blob": b"\xff", "ok": None},
+    ]
+    table = lize.to_arrow(lize.serialize(rows))
+    assert isinstance(table, pa.Table)
+    assert table.column_names == ["id", "name", "score", "blob", "ok"]
+    assert table.column("score").to_pylist() == [0.5, 1.5, None]
+
+    rows[2]["score"] = None
+    assert lize.deserialize(lize.from_arrow(table)) == rows
+    assert lize.deserialize(lize.from_arrow(table.to_batches()[0])) == rows
+
+    with pytest.raises(ValueError):
+        lize.to_arrow(lize.serialize([{"tags": ["a"]}]))
+    with pytest.raises(ValueError):
+        lize.to_arrow(lize.serialize([{"x": 1}, {"x": "one"}]))
+    with pytest.raises(ValueError):
+        lize.from_arrow(pa.table({"tags": [["a"]]}))
+
+
+def test_content_hash():
+    import hashlib
+
+    value = {"b": [1, 2.5, "x"], "a": {"z": None, "y": b"\x00" * 1000}}
+    canonical = lize.serialize(value, canonical=True)
+
+    assert lize.content_hash(value, "sha256") == hashlib.sha256(canonical).digest()
+    assert lize.content_hash(value) == lize.content_hash(dict(reversed(value.items())))
+    assert len(lize.content_hash(value)) == 32
+    assert lize.content_hash(value) != lize.content_hash({**value, "c": 1})
+    assert lize.content_hash(value, release_gil=True) == lize.content_hash(value)
+    assert lize.content_hash({"x": -0.0}) == lize.content_hash({"x": 0.0})
+    nan = struct.unpack("<d", struct.pack("<Q", 0xFFF8_0000_0000_0001))[0]
+    assert lize.content_hash([nan]) == lize.content_hash([float("nan")])
+    assert lize.content_hash({"x": 1.0}) != lize.content_hash({"x": -1.0})
+
+    with pytest.raises(ValueError):
+        lize.content_hash(value, "md5")
+    with pytest.raises(TypeError):
+        lize.content_hash(value, key=bytes(32))
+
+
+class Point:
+    def __init__(self, x, y):
+        self.x, self.y = x, y
+
+    def __reduce__(self):
+        return (Point, (self.x, self.y))
+
+    def __eq__(self, other):
+        return isinstance(other, Point) and (self.x, self.y) == (other.x, other.y)
+
+
+class Bag:
+    def __init__(self, items=()):
+        self.items = list(items)
+
+    def append(self, item):
+        self.items.append(item)
+
+    def __reduce__(self):
+        return (Bag, (), None, iter(self.items))
+
+    def __eq__(self, other):
+        return isinstance(other, Bag) and self.items == other.items
+
+
+class Plain:
+    __slots__ = ("a", "__dict__")
+
+
+@dataclasses.dataclass(slots=True, frozen=True)
+class Frozen:
+    name: str
+    tags: tuple = ()
+    count: int = dataclasses.field(default=0, init=False)
+
+    def __post_init__(self):
+        object.__setattr__(self, "count", len(self.tags))
+
+
+def test_equal():
+    narrow = lize.serialize([{"id": 5, "score": 0.5, "tags": {"a", "b"}}, float("nan")])
+    wide = lize.serialize([
+        {"tags": {"b", "a"}, "score": 0.5, "id": lize.FixedInt(5, "i64")},
+        float("-nan"),
+    ])
+    assert narrow != wide
+    assert lize.equal(narrow, wide)
+    assert lize.equal(bytearray(narrow), memoryview(wide))
+
+    assert not lize.equal(lize.serialize([1, 2]), lize.serialize([2, 1]))
+    assert not lize.equal(lize.serialize(1), lize.serialize(1.0))
+    assert not lize.equal(lize.serialize("a"), lize.serialize(b"a"))
+    assert lize.equal(lize.serialize(-0.0), lize.serialize(0.0))
+    # 0.5 is written as an f32, and here as an f64.
+    assert lize.equal(lize.serialize(0.5), b"\x08" + struct.pack("<d", 0.5))
+
+    # Lists compare by their items, however they were packed.
+    runs = [1, 1, 1, 1, 1, 2]
+    assert lize.equal(lize.serialize(runs), lize.serialize(runs, rle=True))
+    assert not lize.equal(lize.serialize(runs[1:]), lize.serialize(runs, rle=True))
+    mask = [True] * 8 + [False] * 3
+    assert lize.equal(lize.serialize(mask), lize.serialize(mask, rle=True))
+    assert lize.equal(lize.serialize(mask), b"\x10\x0b" + b"\x06" * 8 + b"\x07" * 3)
+
+    # Nesting doesn't run out of stack.
+    def nest(leaf):
+        value = leaf
+        for _ in range(20_000):
+            value = {"k": value}
+        return value
+
+    deep = lize.serialize(nest(1))
+    assert lize.equal(deep, lize.serialize(nest(lize.FixedInt(1, "i64"))))
+    assert not lize.equal(deep, lize.serialize(nest(2)))
+
+    key = bytes(range(32))
+    assert lize.equal(lize.serialize({"a": 1}, key=key), lize.serialize({"a": lize.FixedInt(1, "i32")}, key=key), key=key)
+
+    with pytest.raises(ValueError, match="input is empty"):
+        lize.equal(b"", narrow)
+
+
+def test_persistent_id():
+    class Heavy:
+        def __init__(self, key):
+            self.key = key
+            self.blob = bytes(1_000_000)
+
+    store = {"a": Heavy("a"), "b": Heavy("b")}
+    seen = []
+
+    def persistent_id(obj):
+        seen.append(type(obj))
+        return ("heavy", obj.key) if isinstance(obj, Heavy) else None
+
+    value = {"first": store["a"], "rows": [store["b"], 1, "x"], store["a"]: "as a key"}
+    data = lize.serialize(value, persistent_id=persistent_id)
+    # Only the ids are written, not the blobs, and the ids aren't handed back.
+    assert len(data) < 100
+    assert tuple not in seen and Heavy in seen and int in seen
+
+    loaded = []
+
+    def persistent_load(pid):
+        loaded.append(pid)
+        return store[pid[1]]
+
+    decoded = lize.deserialize(data, persistent_load=persistent_load)
+    assert decoded["first"] is store["a"] and decoded["rows"][0] is store["b"]
+    assert decoded["rows"][1:] == [1, "x"] and decoded[store["a"]] == "as a key"
+    assert sorted(loaded) == [["heavy", "a"], ["heavy", "a"], ["heavy", "b"]]
+
+    # The top-level object can be replaced too.
+    assert lize.deserialize(lize.serialize(store["b"], persistent_id=persistent_id), persistent_load=persistent_load) is store["b"]
+    if lize.features()["buffer_export"]:
+        assert lize.deserialize(lize.serialize_buffer(store["b"], persistent_id=persistent_id), persistent_load=persistent_load) is store["b"]
+
+    with pytest.raises(ValueError, match="persistent_load"):
+        lize.deserialize(data)
+    with pytest.raises(TypeError, match="persistent_id must be callable"):
+        lize.serialize(1, persistent_id=1)
+
+
+def test_frozen_dataclass():
+    value = Frozen("lize", ("a", "b"))
+    decoded = lize.deserialize(lize.serialize(value, allow_reduce=True), allow_reduce=True)
+
+    # Tuples come back as lists, and the constructor runs `__post_init__` again.
+    assert type(decoded) is Frozen and not hasattr(decoded, "__dict__")
+    assert (decoded.name, decoded.tags, decoded.count) == ("lize", ["a", "b"], 2)
+    with pytest.raises(dataclasses.FrozenInstanceError):
+        decoded.name = "other"
+
+
+class Slotted:
+    __slots__ = ("x", "__secret")
+    inits = 0
+
+    def __init__(self, x, secret):
+        Slotted.inits += 1
+        self.x = x
+        self.__secret = secret
+
+    def secret(self):
+        return self.__secret
+
+
+class SlottedChild(Slotted):
+    __slots__ = ("y", "unset", "__weakref__")
+
+    def __init__(self, x, secret, y):
+        super().__init__(x, secret)
+        self.y = y
+
+
+def test_slots():
+    value = SlottedChild(1, "hidden", [2.5])
+    data = lize.serialize({"v": value}, allow_reduce=True)
+    # Written as the class and its slots, not through copyreg.
+    assert b"copyreg" not in data
+    inits = Slotted.inits
+    decoded = lize.deserialize(data, allow_reduce=True)["v"]
+
+    # Rebuilt with `__new__`, without calling `__init__`.
+    assert Slotted.inits == inits
+    assert type(decoded) is SlottedChild and not hasattr(decoded, "__dict__")
+    assert (decoded.x, decoded.secret(), decoded.y) == (1, "hidden", [2.5])
+    assert not hasattr(decoded, "unset")
+
+    # Classes with a `__dict__` as well still go through `__reduce__`.
+    plain = Plain()
+    plain.a, plain.b = 1, "two"
+    assert b"copyreg" in lize.serialize(plain, allow_reduce=True)
+
+
+class Vertex(typing.TypedDict):
+    x: float
+    y: float
+
+
+class Shape(typing.TypedDict, total=False):
+    name: typing.Required[str]
+    points: typing.List[Vertex]
+    kind: typing.Literal["line", "polygon"]
+    color: typing.Optional[typing.Tuple[int, int, int]]
+
+
+def test_typeddict():
+    shape = {"name": "tri", "points": [{"x": 0, "y": 0.5}], "color": (255, 0, 0)}
+    decoded = lize.deserialize_typeddict(lize.serialize(shape), Shape)
+    assert decoded == {**shape, "color": [255, 0, 0]}
+    assert lize.deserialize_typeddict(lize.serialize({"name": "a"}), Shape) == {"name": "a"}
+
+    with pytest.raises(ValueError, match="missing the required key 'name'"):
+        lize.deserialize_typeddict(lize.serialize({"kind": "line"}), Shape)
+    with pytest.raises(ValueError, match=r"payload\['points'\]\[0\]\['y'\] should be float, got str"):
+        lize.deserialize_typeddict(lize.serialize({"name": "a", "points": [{"x": 1, "y": "2"}]}), Shape)
+    for bad in ({"kind": "circle"}, {"color": [1, 2]}, {"size": 3}):
+        with pytest.raises(ValueError):
+            lize.deserialize_typeddict(lize.serialize({"name": "a", **bad}), Shape)
+    with pytest.raises(TypeError):
+        lize.deserialize_typeddict(lize.serialize({}), dict)
+
+
+class Tree(typing.TypedDict):
+    label: str
+    children: "typing.List[Tree]"
+
+
+def test_load_as():
+    assert lize.load_as(lize.serialize([1, 2, 3]), typing.List[int]) == [1, 2, 3]
+    assert lize.load_as(lize.serialize({"a": 1, "b": 2.5}), typing.Dict[str, float]) == {"a": 1, "b": 2.5}
+    assert lize.load_as(lize.serialize(None), typing.Optional[int]) is None
+    assert lize.load_as(lize.serialize([None, 3]), typing.List[typing.Optional[int]]) == [None, 3]
+    shapes = [{"name": "a", "points": [{"x": 1, "y": 2.0}]}, None]
+    assert lize.load_as(lize.serialize(shapes), typing.List[typing.Optional[Shape]]) == shapes
+    tree = {"label": "root", "children": [{"label": "leaf", "children": []}]}
+    assert lize.load_as(lize.serialize(tree), Tree) == tree
+
+    with pytest.raises(lize.ValidationError, match=r"payload\[2\] should be int, got str") as info:
+        lize.load_as(lize.serialize([1, 2, "3"]), typing.List[int])
+    assert (info.value.path, info.value.expected) == ("payload[2]", "int")
+    with pytest.raises(lize.ValidationError) as info:
+        lize.load_as(lize.serialize({"a": [1.5, "x"]}), typing.Dict[str, typing.List[float]])
+    assert (info.value.path, info.value.expected) == ("payload['a'][1]", "float")
+    with pytest.raises(lize.ValidationError) as info:
+        lize.load_as(lize.serialize([{"label": "x", "children": [{"label": 1, "children": []}]}]), typing.List[Tree])
+    assert info.value.path == "payload[0]['children'][0]['label']"
+    with pytest.raises(lize.ValidationError, match="missing the required key 'name'") as info:
+        lize.load_as(lize.serialize({}), Shape)
+    assert (info.value.path, info.value.expected) == ("payload", "Shape")
+    assert issubclass(lize.ValidationError, ValueError)
+
+
+def test_allow_reduce():
+    data = lize.serialize({"p": Point(1, [2.5])}, allow_reduce=True)
+    assert lize.deserialize(data, allow_reduce=True) == {"p": Point(1, [2.5])}
+
+    plain = Plain()
+    plain.a, plain.b = 1, "two"
+    decoded = lize.deserialize(lize.serialize(plain, allow_reduce=True), allow_reduce=True)
+    assert type(decoded) is Plain and (decoded.a, decoded.b) == (1, "two")
+
+    for value in ({1, 2}, frozenset("ab"), Bag([1, "a"])):
+        assert lize.deserialize(lize.serialize(value, allow_reduce=True), allow_reduce=True) == value
+
+    assert lize.deserialize(lize.serialize(Point, allow_reduce=True), allow_reduce=True) is Point
+
+    with pytest.raises(TypeError, match="allow_reduce"):
+        lize.serialize(Point(1, 2))
+    with pytest.raises(ValueError, match="allow_reduce"):
+        lize.deserialize(data)
+
+    class Local:
+        pass
+
+    with pytest.raises(TypeError):
+        lize.serialize(Local(), allow_reduce=True)
+
+
+def test_estimate_decoded_size():
+    import tracemalloc
+
+    corpus = [
+        [str(i) for i in range(5000)],
+        [{"id": i, "name": f"user{i}", "score": i / 2, "tags": ["a", "b"]} for i in range(1000)],
+        [[i * 1000 + j for j in range(10)] for i in range(500)],
+        [bytes(i % 64) for i in range(2000)],
+        [{f"k{j}": None for j in range(30)} for _ in range(100)],
+    ]
+    for value in corpus:
+        data = lize.serialize(value)
+        estimate = lize.estimate_decoded_size(data)
+
+        tracemalloc.start()
+        decoded = lize.deserialize(data)
+        actual = tracemalloc.get_traced_memory()[0]
+        tracemalloc.stop()
+        del decoded
+
+        assert actual / 2 <= estimate <= actual * 2
+
+    data = lize.serialize(corpus[0])
+    with pytest.raises(ValueError, match="max_decoded_size"):
+        lize.deserialize(data, max_decoded_size=1000)
+    assert lize.deserialize(data, max_decoded_size=10**8) == corpus[0]
+    assert lize.deserialize(lize.serialize(1), max_decoded_size=0) == 1
+
+
+def test_max_output_bytes():
+    value = {"name": "lize", "data": b"x" * 1000}
+    data, info = lize.serialize_with_info(value)
+    assert data == lize.serialize(value)
+    assert info == {"size": len(data), "payload_size": len(data)}
+
+    assert lize.serialize(value, max_output_bytes=len(data)) == data
+    with pytest.raises(lize.EncodeError, match="limit of 100 bytes"):
+        lize.serialize(value, max_output_bytes=100)
+    with pytest.raises(ValueError):
+        lize.serialize_with_info(value, max_output_bytes=len(data) - 1)
+
+    key = bytes(range(32))
+    framed, info = lize.serialize_with_info(value, key=key)
+    assert info == {"size": len(framed), "payload_size": len(data)}
+    assert lize.deserialize(framed, key=key) == value
+
+
+def test_parallel():
+    rows = [{"id": i, "name": f"row {i}", "tags": {"b", "a"}, "ok": i % 2 == 0} for i in range(5000)]
+    for value in (rows, rows[:10], {"rows": rows}, 1):
+        for canonical in (False, True):
+            serial = lize.serialize(value, canonical=canonical)
+            assert lize.serialize(value, canonical=canonical, parallel=True) == serial
+
+    with pytest.raises(lize.EncodeError):
+        lize.serialize(rows, parallel=True, max_output_bytes=1000)
+
+
+def test_max_str_len():
+    data = lize.serialize(["abc", b"xyz", {"k": "v"}])
+    assert lize.deserialize(data, max_str_len=3) == ["abc", b"xyz", {"k": "v"}]
+    with pytest.raises(ValueError, match="limit of 2 bytes"):
+        lize.deserialize(data, max_str_len=2)
+
+    # A string declaring 1 GiB over a few bytes fails before anything is read.
+    huge = b"\x13\x80\x80\x80\x80\x04abcd"
+    with pytest.raises(ValueError, match="longer than the limit"):
+        lize.deserialize(huge, max_str_len=1 << 20)
+
+
+def test_max_alloc():
+    # 64 strings of 10 KiB each stay under max_str_len, but not all together.
+    value = ["x" * 10_000] * 64
+    data = lize.serialize(value)
+    assert lize.deserialize(data, max_alloc=1 << 20, max_str_len=10_000) == value
+    with pytest.raises(lize.AllocLimitExceeded, match="max_alloc=300000"):
+        lize.deserialize(data, max_alloc=300_000, max_str_len=10_000)
+    assert issubclass(lize.AllocLimitExceeded, ValueError)
+
+    # Items inside sets are counted too.
+    with pytest.raises(lize.AllocLimitExceeded):
+        lize.deserialize(lize.serialize({str(i) * 5_000 for i in range(10)}), max_alloc=20_000)
+
+    # So are the items packed lists expand to, before they're built. The runs
+    # are spelled out, since writing ten million zeros is slow.
+    def zeros(count):
+        return bytes.fromhex("0e0d1002140b") + count.to_bytes(4, "little")
+
+    assert lize.serialize([0] * 100_000, rle=True) == zeros(100_000)
+    with pytest.raises(lize.AllocLimitExceeded):
+        lize.deserialize(zeros(10_000_000), max_alloc=10_000)
+    bools = lize.serialize([True, False] * 500_000)
+    assert len(bools) < 200_000
+    with pytest.raises(lize.AllocLimitExceeded):
+        lize.deserialize(bools, max_alloc=1_000_000)
+    assert len(lize.deserialize(bools, max_alloc=10_000_000)) == 1_000_000
+
+    # Each value of a Decoder or iter_deserialize gets the whole budget.
+    decoder = lize.Decoder(max_alloc=100_000)
+    for _ in range(3):
+        assert decoder.decode(lize.serialize(value[:5])) == value[:5]
+    assert list(lize.iter_deserialize(lize.serialize([value[:5]] * 3), max_alloc=100_000)) == [value[:5]] * 3
+    with pytest.raises(lize.AllocLimitExceeded):
+        decoder.decode(data)
+
+
+def test_merge_and_diff():
+    base = {"keep": 1, "drop": 2, "nested": {"a": 3, "b": 4}}
+    patch = {
+        "drop": lize.DELETE,
+        "missing": lize.DELETE,
+        "null": None,
+        "nested": {"a": lize.DELETE, "c": 5},
+    }
+    merged = lize.merge(lize.serialize(base), lize.serialize(patch))
+    assert lize.deserialize(merged) == {"keep": 1, "null": None, "nested": {"b": 4, "c": 5}}
+
+    # Deleted entries are dropped by a plain decode, but None is kept.
+    assert lize.deserialize(lize.serialize(patch)) == {"null": None, "nested": {"c": 5}}
+    assert lize.deserialize(lize.serialize([lize.DELETE])) == [lize.DELETE]
+    assert repr(lize.DELETE) == "lize.DELETE"
+
+    new = {"keep": 1, "drop": None, "nested": {"a": 3}, "added": [1, 2]}
+    old_data, new_data = lize.serialize(base), lize.serialize(new)
+    patch_data = lize.diff(old_data, new_data)
+    assert lize.deserialize(patch_data) == {"drop": None, "nested": {}, "added": [1, 2]}
+    assert lize.deserialize(lize.merge(old_data, patch_data)) == new
+    assert lize.deserialize(lize.diff(old_data, old_data)) == {}
+
+    with pytest.raises(ValueError):
+        lize.merge(b"", old_data)
+
+
+def test_validate_bytes():
+    data = lize.serialize({"a": [1, 2.5, "three", b"four", None], "b": {1, 2}})
+    assert lize.validate_bytes(data)
+    assert lize.validate_bytes(memoryview(data))
+
+    for end in range(len(data)):
+        assert not lize.validate_bytes(data[:end])
+    assert not lize.validate_bytes(data + b"\x00")
+    assert not lize.validate_bytes(bytes([16, 1, 5]))
+    assert not lize.validate_bytes(bytes([19, 1, 0xFF]))
+
+
+def test_fingerprint():
+    order = {"id": 1, "tags": ["a"], "price": 9.5, "note": None}
+    data, fingerprint = lize.serialize(order, with_fingerprint=True)
+    assert data == lize.serialize(order)
+    assert isinstance(fingerprint, int) and 0 <= fingerprint < 1 << 64
+
+    def fingerprint_of(value, **kwargs):
+        return lize.serialize(value, with_fingerprint=True, **kwargs)[1]
+
+    same = {"note": None, "price": 0.25, "tags": ["b", "c"], "id": 1 << 70}
+    assert fingerprint_of(same) == fingerprint
+    assert fingerprint_of(same, canonical=True, rle=True) == fingerprint
+    schema = lize.Schema(["id", "tags", "price", "note"])
+    assert fingerprint_of(same, schema=schema) == fingerprint
+
+    for changed in [
+        {**order, "id": "1"},
+        {**order, "tags": [1]},
+        {**order, "tags": ["a", 1]},
+        {**order, "note": "later"},
+        {"id": 1, "tags": ["a"], "cost": 9.5, "note": None},
+        {"id": 1, "tags": ["a"], "price": 9.5},
+        [order],
+    ]:
+        assert fingerprint_of(changed) != fingerprint, changed
+
+
+def test_deque():
+    from collections import deque
+
+    recent = deque(range(10), maxlen=4)
+    recent.appendleft("first")
+    restored = lize.deserialize(lize.serialize(recent))
+    assert type(restored) is deque
+    assert restored.maxlen == 4
+    assert list(restored) == ["first", 6, 7, 8]
+    # Still bounded, so appending drops from the other end.
+    restored.append(9)
+    assert list(restored) == [6, 7, 8, 9]
+
+    for value in [deque(), deque([[1, 2], {"a": None}]), deque([0] * 100 + [1], maxlen=200)]:
+        for kwargs in ({}, {"rle": True}):
+            restored = lize.deserialize(lize.serialize({"queue": value}, **kwargs))["queue"]
+            assert (restored, restored.maxlen) == (value, value.maxlen)
+
+
+def test_deserialize_into():
+    config = {"keep": 1, "a": 0}
+    assert lize.deserialize(lize.serialize({"a": 1, "b": [2]}), into=config) is config
+    assert config == {"keep": 1, "a": 1, "b": [2]}
+    schema = lize.Schema(["a", "c"])
+    lize.deserialize(lize.serialize({"c": "x"}, schema=schema), into=config, schema=schema)
+    assert config == {"keep": 1, "a": 1, "b": [2], "c": "x"}
+
+    records = [{"id": i, "name": f"user-{i}"} for i in range(100_000)]
+    data = lize.serialize(records)
+    received = []
+    assert lize.deserialize(data, into=received.append) == len(records)
+    assert received == records
+
+    # Only one record exists at a time, so a budget far smaller than the
+    # whole list is enough.
+    with pytest.raises(lize.AllocLimitExceeded):
+        lize.deserialize(data, max_alloc=1 << 16)
+    count = 0
+
+    def consume(record):
+        nonlocal count
+        count += 1
+
+    assert lize.deserialize(data, into=consume, max_alloc=1 << 16) == len(records)
+    assert count == len(records)
+
+    def fussy(record):
+        if record["id"] == 5:
+            raise KeyError("five")
+
+    with pytest.raises(KeyError) as raised:
+        lize.deserialize(data, into=fussy)
+    assert raised.value.delivered == 5
+
+    truncated = lize.serialize([1, 2, "three"])[:-2]
+    got = []
+    with pytest.raises(ValueError) as raised:
+        lize.deserialize(truncated, into=got.append)
+    assert (raised.value.delivered, got) == (2, [1, 2])
+    if sys.version_info >= (3, 11):
+        assert raised.value.__notes__ == ["after delivering 2 of 3 items"]
+
+    for into, payload, message in [
+        ({}, [1], "holding a dict"),
+        (got.append, {"a": 1}, "holding a list"),
+        (5, [1], "dict or a callable"),
+    ]:
+        with pytest.raises(TypeError, match=message):
+            lize.deserialize(lize.serialize(payload), into=into)
+
+    # Packed lists are streamed as they expand, rather than built first.
+    mask = [True] * 8 + [False, True]
+    got = []
+    assert lize.deserialize(lize.serialize(mask), into=got.append) == len(mask)
+    assert got == mask
+    rows = [{"x": 1}] * 4 + [0] * 3
+    got = []
+    assert lize.deserialize(lize.serialize(rows, rle=True), into=got.append) == len(rows)
+    assert got == rows and got[0] is not got[1]
+    zeros = bytes.fromhex("0e0d100214") + b"\x00" + (10**8).to_bytes(8, "little")
+
+    def stop_at_three(item):
+        if len(got) == 3:
+            raise KeyError("enough")
+        got.append(item)
+
+    got = []
+    with pytest.raises(KeyError) as raised:
+        lize.deserialize(zeros, into=stop_at_three)
+    assert (raised.value.delivered, got) == (3, [0, 0, 0])
+
+
+def test_redact():
+    users = [
+        {"name": "ada", "password": "hunter2", "auth": {"token": "t1", "kind": "bearer"}},
+        {"name": "bob", "tokens": [{"token": "t2"}]},
+    ]
+    data = lize.serialize(users)
+    redacted = lize.redact(data, ["password", "token"])
+    assert lize.deserialize(redacted) == [
+        {"name": "ada", "auth": {"kind": "bearer"}},
+        {"name": "bob", "tokens": [{}]},
+    ]
+
+    masked = lize.deserialize(lize.redact(data, ["token"], replacement="***"))
+    assert masked[0]["auth"] == {"token": "***", "kind": "bearer"}
+    assert masked[1]["tokens"] == [{"token": "***"}]
+    assert lize.redact(data, []) == data
+
+    with pytest.raises(ValueError):
+        lize.redact(b"", ["token"])
+
+    # Frames and trailing bytes are refused rather than silently lost.
+    with pytest.raises(ValueError, match="frames"):
+        lize.redact(lize.serialize(users, key=bytes(32)), ["password"])
+    with pytest.raises(ValueError, match="frames"):
+        lize.redact(lize.serialize(users, metadata={"tags": {}}), ["password"])
+    with pytest.raises(ValueError, match="after the end"):
+        lize.redact(data + b"junk", ["password"])
+
+
+def test_encryption():
+    key = bytes(range(32))
+    value = {"name": "Ada", "social": "078-05-1120"}
+
+    data = lize.serialize(value, key=key)
+    assert b"078-05-1120" not in data
+    assert data != lize.serialize(value, key=key)
+    assert lize.deserialize(data, key=key) == value
+
+    tampered = bytearray(data)
+    tampered[-1] ^= 1
+    with pytest.raises(lize.AuthenticationFailed):
+        lize.deserialize(bytes(tampered), key=key)
+    with pytest.raises(lize.AuthenticationFailed):
+        lize.deserialize(data, key=bytes(32))
+
+    with pytest.raises(ValueError):
+        lize.deserialize(lize.serialize(value), key=key)
+    with pytest.raises(ValueError, match="32 bytes"):
+        lize.serialize(value, key=b"short")
+
+
+def test_writer_reader():
+    import io
+
+    key = bytes(32)
+    values = [1, "two", {"three": [3.0]}]
+
+    for kwargs in ({}, {"key": key}):
+        file = io.BytesIO()
+        writer = lize.Writer(file, **kwargs)
+        for value in values:
+            writer.write(value)
+
+        file.seek(0)
+        assert list(lize.Reader(file, **kwargs)) == values
+
+    file.seek(0)
+    with pytest.raises(ValueError):
+        list(lize.Reader(file))
+
+    class Broken(io.RawIOBase):
+        def readinto(self, b):
+            raise OSError("disk on fire")
+
+    with pytest.raises(OSError, match="disk on fire"):
+        next(lize.Reader(Broken()))
+
+
+def test_writer_batching():
+    import io
+    import tempfile
+
+    class CountingFile(io.BytesIO):
+        writes = flushes = 0
+
+        def write(self, data):
+            self.writes += 1
+            return super().write(data)
+
+        def flush(self):
+            self.flushes += 1
+
+    records = [{"id": i, "event": "login", "ok": i % 3 != 0} for i in range(100_000)]
+    unbatched, batched = CountingFile(), CountingFile()
+    writer = lize.Writer(unbatched)
+    for record in records:
+        writer.write(record)
+    with lize.Writer(batched, batch_size=256, sync="os") as writer:
+        for record in records:
+            writer.write(record)
+        assert batched.writes == len(records) // 256
+
+    # One call per batch, the last one on leaving the block, and the same frames.
+    assert (unbatched.writes, batched.writes) == (len(records), -(-len(records) // 256))
+    assert batched.flushes == batched.writes
+    assert batched.getvalue() == unbatched.getvalue()
+    batched.seek(0)
+    assert list(lize.Reader(batched)) == records
+
+    file = CountingFile()
+    writer = lize.Writer(file, batch_size=100, flush_interval=0)
+    writer.write(1)
+    writer.write(2)
+    assert file.writes == 2
+    writer.close()
+    writer.close()
+    with pytest.raises(ValueError, match="closed"):
+        writer.write(3)
+
+    directory = tempfile.TemporaryDirectory()
+    path = os.path.join(directory.name, "audit.lize")
+    key = bytes(32)
+    with lize.Writer(path, batch_size=64, sync="fsync", key=key) as writer:
+        for record in records[:1000]:
+            writer.write(record)
+        writer.flush()
+        assert list(lize.Reader(open(path, "rb"), key=key)) == records[:1000]
+        writer.write("after flush")
+    assert len(list(lize.Reader(open(path, "rb"), key=key))) == 1001
+
+    # A batch cut short by a crash leaves a truncated last frame, which the
+    # reader reports after the frames before it.
+    with lize.Writer(path, batch_size=10) as writer:
+        for record in records[:25]:
+            writer.write(record)
+    with open(path, "r+b") as f:
+        f.truncate(os.path.getsize(path) - 3)
+    reader = lize.Reader(open(path, "rb"))
+    assert [next(reader) for _ in range(24)] == records[:24]
+    with pytest.raises(ValueError, match="truncated"):
+        next(reader)
+
+    # With checksum=True, the header ends with a CRC-32 of the body, so other
+    # damage is caught instead of decoding to the wrong value.
+    import zlib
+
+    file = io.BytesIO()
+    with lize.Writer(file, checksum=True) as writer:
+        writer.write({"id": 1, "ok": True})
+    data = bytearray(file.getvalue())
+    (length,) = struct.unpack("<I", data[4:8])
+    body = len(data) - length
+    assert data[3] & 0b10000
+    assert struct.unpack("<I", data[body - 4 : body])[0] == zlib.crc32(data[body:])
+    assert list(lize.Reader(io.BytesIO(data))) == [{"id": 1, "ok": True}]
+    data[-1] ^= 1
+    with pytest.raises(ValueError, match="checksum"):
+        next(lize.Reader(io.BytesIO(data)))
+
+    # A writer that's collected without being closed writes what it holds.
+    writer = lize.Writer(path, batch_size=100)
+    writer.write("last")
+    del writer
+    assert list(lize.Reader(open(path, "rb"))) == ["last"]
+
+    for kwargs, message in [
+        ({"batch_size": 0}, "batch_size"),
+        ({"flush_interval": -1.0}, "flush_interval"),
+        ({"sync": "always"}, "sync"),
+    ]:
+        with pytest.raises(ValueError, match=message):
+            lize.Writer(io.BytesIO(), **kwargs)
+
+
+def test_reader_skip():
+    import io
+
+    file = io.BytesIO()
+    writer = lize.Writer(file)
+    for i in range(5):
+        writer.write(i)
+    file.seek(0)
+    reader = lize.Reader(file)
+    assert reader.skip(2) == 2
+    assert next(reader) == 2
+    assert reader.skip(10) == 2
+    assert list(reader) == []
+
+
+def test_async_reader_writer():
+    import asyncio
+    import tempfile
+
+    directory = tempfile.TemporaryDirectory()
+    path = os.path.join(directory.name, "records.lize")
+    records = [{"id": i, "payload": "x" * (i % 7)} for i in range(200)]
+
+    async def main():
+        async with lize.AsyncWriter(path, batch_size=32, key=bytes(32)) as writer:
+            for record in records:
+                await writer.write(record)
+            # Encoded on write, so later changes aren't seen.
+            changed = {"id": -1}
+            await writer.write(changed)
+            changed["id"] = -2
+        assert list(lize.Reader(open(path, "rb"), key=bytes(32))) == records + [{"id": -1}]
+
+        async with lize.AsyncReader(path, buffer=4, key=bytes(32)) as reader:
+            assert [record async for record in reader] == records + [{"id": -1}]
+            # Iteration has ended until the reader seeks.
+            assert [record async for record in reader] == []
+            await reader.seek_to(150)
+            assert (await reader.__anext__())["id"] == 150
+            await reader.seek_to(3)
+            seen = []
+            async for record in reader:
+                seen.append(record["id"])
+                # The thread stays at most `buffer` values ahead.
+                assert len(reader._pending) <= 4
+                if len(seen) == 3:
+                    break
+            assert seen == [3, 4, 5]
+            await reader.seek_to(10_000)
+            assert [record async for record in reader] == []
+
+        with pytest.raises(ValueError):
+            lize.AsyncReader(path, buffer=0)
+        with pytest.raises(ValueError):
+            async for _ in lize.AsyncReader(path):
+                pass
+        with pytest.raises(FileNotFoundError):
+            async for _ in lize.AsyncReader(os.path.join(directory.name, "missing")):
+                pass
+
+    try:
+        asyncio.run(main())
+    finally:
+        directory.cleanup()
+
+
+def test_serialize_map_items():
+    import io
+
+    n = 100_000
+    file = io.BytesIO()
+    lize.serialize_map_items(((f"k{i}", [i, i * 0.5]) for i in range(n)), n, file)
+    expected = {f"k{i}": [i, i * 0.5] for i in range(n)}
+    assert file.getvalue() == lize.serialize(expected)
+    assert lize.deserialize(file.getvalue()) == expected
+
+    file = io.BytesIO()
+    lize.serialize_map_items(iter([(1, b"x")]), 1, file, rle=True)
+    assert lize.deserialize(file.getvalue()) == {1: b"x"}
+
+    with pytest.raises(ValueError, match="ended after 2 pairs, expected 3"):
+        lize.serialize_map_items(iter([("a", 1), ("b", 2)]), 3, io.BytesIO())
+    with pytest.raises(ValueError, match="more than 1 pairs"):
+        lize.serialize_map_items(iter([("a", 1), ("b", 2)]), 1, io.BytesIO())
+    with pytest.raises(TypeError):
+        lize.serialize_map_items([], 0, io.BytesIO(), canonical=True)
+
+
+
+def test_diagnose():
+    records = [{"id": i, "name": f"record {i:02}"} for i in range(40)]
+    data = lize.serialize(records)
+    assert lize.diagnose(data) == []
+
+    damaged = bytearray(data)
+    # An unknown tag where the 5th record's id starts, and a byte that isn't
+    # UTF-8 in the 31st record's name.
+    first = damaged.index(b"id", damaged.index(b"record 03")) + 2
+    damaged[first] = 5
+    second = damaged.index(b"record 30")
+    damaged[second] = 0xFF
+
+    problems = lize.diagnose(bytes(damaged))
+    assert [(p.offset, p.kind, p.path) for p in problems] == [
+        (first, "unknown_tag", '$[4]["id"]'),
+        (second - 2, "invalid_utf8", '$[30]["name"]'),
+    ]
+    assert "unknown tag 5" in problems[0].message
+    assert "invalid_utf8" in repr(problems[1])
+    assert len(lize.diagnose(bytes(damaged), limit=1)) == 1
+
+    assert [p.kind for p in lize.diagnose(data[:-3])] == ["truncated"]
+    assert [p.kind for p in lize.diagnose(data + b"\x01")] == ["trailing_bytes"]
+
+
+def test_options():
+    value = {"b": 1, "a": 2}
+    plain, canonical = lize.serialize(value), lize.serialize(value, canonical=True)
+    assert plain != canonical
+
+    try:
+        assert lize.options.canonical is None
+        lize.options.canonical = True
+        lize.options.maps_as_pairs = True
+        assert lize.serialize(value) == canonical
+        # Keyword arguments win, and each function only applies its own options.
+        assert lize.serialize(value, canonical=False) == plain
+        assert lize.deserialize(plain) == [("b", 1), ("a", 2)]
+
+        with lize.options(canonical=False, rle=True):
+            assert lize.serialize(value) == plain
+            with lize.options(maps_as_pairs=False):
+                assert lize.options.snapshot() == {"canonical": False, "maps_as_pairs": False, "rle": True}
+                assert lize.deserialize(plain) == value
+            assert lize.options.maps_as_pairs is True
+            assert lize.serialize(value, canonical=True) == canonical
+        assert lize.options.snapshot() == {"canonical": True, "maps_as_pairs": True}
+
+        del lize.options.maps_as_pairs
+        assert lize.deserialize(plain) == value
+
+        with pytest.raises(AttributeError, match="no option"):
+            lize.options.compression = "zstd"
+        with pytest.raises(AttributeError):
+            lize.options(nope=1)
+        with pytest.raises(TypeError):
+            lize.options.canonical = "yes"
+        with pytest.raises(ValueError, match="float_quantize"):
+            lize.options(float_quantize=99)
+    finally:
+        lize.options.reset()
+    assert lize.options.snapshot() == {}
+    assert lize.serialize(value) == plain
+
+
+def test_options_isolation():
+    import asyncio
+    import threading
+
+    value = {"b": 1, "a": 2}
+    plain, canonical = lize.serialize(value), lize.serialize(value, canonical=True)
+
+    # A block in one thread isn't seen by another.
+    entered, checked = threading.Event(), threading.Event()
+    seen = []
+
+    def worker():
+        with lize.options(canonical=True):
+            entered.set()
+            checked.wait()
+            seen.append(lize.serialize(value))
+
+    thread = threading.Thread(target=worker)
+    thread.start()
+    entered.wait()
+    seen.append(lize.serialize(value))
+    checked.set()
+    thread.join()
+    assert seen == [plain, canonical]
+
+    # Nor is a block in one asyncio task seen by another.
+    async def task(ordered, started, release):
+        with lize.options(canonical=ordered):
+            started.set()
+            await release.wait()
+            return lize.serialize(value)
+
+    async def main():
+        started = [asyncio.Event(), asyncio.Event()]
+        release = asyncio.Event()
+        tasks = [asyncio.create_task(task(ordered, event, release)) for ordered, event in zip((True, False), started)]
+        for event in started:
+            await event.wait()
+        assert lize.serialize(value) == plain
+        release.set()
+        return await asyncio.gather(*tasks)
+
+    assert asyncio.run(main()) == [canonical, plain]
+    assert lize.options.snapshot() == {}
+
+
+def test_iter_deserialize():
+    import tracemalloc
+
+    n = 200_000
+    records = [{"id": i, "name": f"record {i}", "tags": ["a", "b"]} for i in range(n)]
+    data = lize.serialize(records)
+
+    items = lize.iter_deserialize(data)
+    assert items.__length_hint__() == n
+    tracemalloc.start()
+    count = 0
+    for expected, item in zip(records, items):
+        assert item == expected
+        count += 1
+    peak = tracemalloc.get_traced_memory()[1]
+    tracemalloc.stop()
+    assert count == n and next(items, None) is None
+    # Only about one record is alive at a time, rather than all of them.
+    assert peak < 100_000
+
+    assert list(lize.iter_deserialize(bytearray(lize.serialize([1, b"x"])))) == [1, b"x"]
+    assert list(lize.iter_deserialize(lize.serialize([]))) == []
+    key = bytes(range(32))
+    assert list(lize.iter_deserialize(lize.serialize([{"a": 1}], key=key), key=key, maps_as_pairs=True)) == [[("a", 1)]]
+    with pytest.raises(TypeError, match="holding a list"):
+        lize.iter_deserialize(lize.serialize({"a": 1}))
+
+    damaged = lize.iter_deserialize(lize.serialize(["ok", "bad"])[:-1])
+    assert next(damaged) == "ok"
+    with pytest.raises(ValueError):
+        next(damaged)
+
+    # Packed lists are expanded an item at a time too.
+    mask = [True] * 8 + [False] * 5
+    bits = lize.iter_deserialize(lize.serialize(mask))
+    assert bits.__length_hint__() == 13
+    assert list(bits) == mask and bits.__length_hint__() == 0
+    zeros = lize.iter_deserialize(bytes.fromhex("0e0d100214") + b"\x00" + (10**8).to_bytes(8, "little"))
+    assert zeros.__length_hint__() == 10**8
+    assert [next(zeros) for _ in range(3)] == [0, 0, 0]
+    assert zeros.__length_hint__() == 10**8 - 3
+    rows = [{"x": 1}] * 3 + [None] * 2 + [{"x": 1}]
+    items = list(lize.iter_deserialize(lize.serialize(rows, rle=True)))
+    assert items == rows and items[0] is not items[1]
+    with pytest.raises(ValueError, match="runs"):
+        lize.iter_deserialize(bytes.fromhex("0e0d10021514"))
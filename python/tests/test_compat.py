@@ -0,0 +1,76 @@
+"""Which features depend on the interpreter or on how lize was built, checked
+against the running interpreter, so every version in the CI matrix asserts
+its own row."""
+
+import dataclasses
+import sys
+import typing
+
+import pytest
+import lize
+
+# The oldest version each feature works on, in builds that have it at all.
+SINCE = {
+    "buffer_export": (3, 9),
+    "union_type": (3, 10),
+    "dataclass_kw_only": (3, 10),
+}
+
+
+def test_features_match_interpreter():
+    features = lize.features()
+    assert set(features) == set(SINCE)
+    for name, since in SINCE.items():
+        if sys.version_info < since:
+            assert not features[name], name
+    # Only abi3 builds lack anything the interpreter has.
+    assert features["union_type"] == (sys.version_info >= (3, 10))
+    assert features["dataclass_kw_only"] == (sys.version_info >= (3, 10))
+
+
+def test_buffer_export():
+    if lize.features()["buffer_export"]:
+        assert bytes(memoryview(lize.serialize_buffer([1, 2]))) == lize.serialize([1, 2])
+    else:
+        with pytest.raises(NotImplementedError, match="Python 3.11 or newer"):
+            lize.serialize_buffer([1, 2])
+
+
+def test_union_type():
+    if not lize.features()["union_type"]:
+        pytest.skip("X | Y needs Python 3.10")
+    hint = eval("int | None")
+    assert lize.load_as(lize.serialize(None), hint) is None
+    assert lize.load_as(lize.serialize([1, None]), typing.List[hint]) == [1, None]
+
+
+@dataclasses.dataclass
+class Point:
+    x: int
+    y: int
+
+
+if sys.version_info >= (3, 10):
+
+    @dataclasses.dataclass(kw_only=True)
+    class Named:
+        x: int
+
+
+def test_dataclasses():
+    # Reading `kw_only` from the fields of a dataclass fails before 3.10.
+    assert lize.deserialize(lize.serialize(Point(1, 2), allow_reduce=True), allow_reduce=True) == Point(1, 2)
+    if lize.features()["dataclass_kw_only"]:
+        assert lize.deserialize(lize.serialize(Named(x=1), allow_reduce=True), allow_reduce=True) == Named(x=1)
+
+
+def test_builds_agree():
+    # What the abi3 wrappers read through Python attributes round-trips the same.
+    import datetime
+
+    delta = datetime.timedelta(days=-1, seconds=5, microseconds=7)
+    moment = datetime.datetime(2024, 2, 29, 23, 59, 59, 999999, fold=1, tzinfo=datetime.timezone(-delta))
+    assert lize.deserialize(lize.serialize([delta, moment])) == [delta, moment]
+    assert lize.deserialize(lize.serialize(lambda x: x + 1))(1) == 2
+    assert lize.validate_bytes(bytearray(lize.serialize("text")))
+    assert lize.deserialize(memoryview(lize.serialize("text"))) == "text"
@@ -0,0 +1,86 @@
+//! `serialize_buffer`, which hands out the encoded bytes through the buffer
+//! protocol instead of copying them into a `bytes` object.
+//!
+//! The bytes live in a [`SerializedBuffer`] that's never written to or resized
+//! after it's built, and every export holds a reference to it, so the bytes
+//! live as long as any `memoryview` or C extension reading them.
+//!
+//! abi3 builds can't export buffers, see [`compat::BUFFER_EXPORT`].
+
+#[cfg(not(feature = "abi3"))]
+use std::os::raw::c_int;
+
+#[cfg(not(feature = "abi3"))]
+use pyo3::ffi;
+use pyo3::{
+    prelude::*,
+    types::{PyBytes, PyDict},
+};
+
+use crate::{compat, encode_buffer, options::EncodeOptions, persistent};
+
+/// Read-only bytes written by `serialize_buffer`.
+#[pyclass(module = "lize", frozen)]
+pub struct SerializedBuffer {
+    bytes: Vec<u8>,
+}
+
+#[pymethods]
+impl SerializedBuffer {
+    fn __len__(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// A copy of the bytes.
+    fn __bytes__<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.bytes)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("SerializedBuffer(<{} bytes>)", self.bytes.len())
+    }
+
+    /// Exports the bytes as a read-only, one-dimensional buffer of `B`s.
+    #[cfg(not(feature = "abi3"))]
+    unsafe fn __getbuffer__(
+        slf: Bound<'_, Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        let bytes = &slf.get().bytes;
+        // SAFETY: `PyBuffer_FillInfo` takes a reference to `slf` for the view,
+        // which keeps `bytes` alive, and nothing mutates them after `new`. It
+        // fails for writable requests, since the buffer is marked read-only.
+        let result = unsafe {
+            ffi::PyBuffer_FillInfo(
+                view,
+                slf.as_ptr(),
+                bytes.as_ptr() as *mut _,
+                bytes.len() as ffi::Py_ssize_t,
+                1,
+                flags,
+            )
+        };
+        match result {
+            -1 => Err(PyErr::fetch(slf.py())),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Serializes `value` like `serialize`, into a buffer that can be read
+/// without copying it again.
+#[pyfunction]
+#[pyo3(signature = (value, **kwargs))]
+pub fn serialize_buffer(
+    py: Python<'_>,
+    value: &Bound<'_, PyAny>,
+    kwargs: Option<&Bound<'_, PyDict>>,
+) -> PyResult<SerializedBuffer> {
+    compat::BUFFER_EXPORT.require(py, "serialize_buffer")?;
+    let options = EncodeOptions::from_kwargs(kwargs)?;
+    let (bytes, _, _) = encode_buffer(py, persistent::extract(value, &options)?, &options)?;
+    Ok(SerializedBuffer {
+        bytes: bytes.into_vec(),
+    })
+}
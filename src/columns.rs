@@ -0,0 +1,134 @@
+//! `to_columns`, which pivots a list of dicts into a dict of columns without
+//! building the dicts.
+
+use std::collections::HashMap;
+
+use lize_sys::Value;
+use pyo3::{
+    exceptions,
+    prelude::*,
+    types::{PyBytes, PyDict, PyList},
+};
+
+use crate::{decode_value, error::into_py_err, lize_to_py, open_payload, options::DecodeOptions};
+
+/// Decodes a list of dicts into `{key: [value of each row]}` for the given keys.
+///
+/// Only the requested entries are decoded; the rest of each row is skipped.
+/// Rows missing a key get `None`. With `typed=True`, columns whose values are
+/// all integers or all floats become `array.array("q")` or `array.array("d")`.
+#[pyfunction]
+#[pyo3(signature = (data, keys, *, typed = false, **kwargs))]
+pub fn to_columns<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    keys: Vec<String>,
+    typed: bool,
+    kwargs: Option<&Bound<'_, PyDict>>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let mut options = DecodeOptions::from_kwargs(kwargs)?;
+    options.core.keys = Some(keys.clone());
+
+    let payload = open_payload(data, &options)?;
+    let value = decode_value(py, &payload, &options)?;
+    let Value::Vector(rows) = &value else {
+        return Err(exceptions::PyValueError::new_err(
+            "to_columns expects a list of dicts",
+        ));
+    };
+
+    let index: HashMap<&str, usize> = keys
+        .iter()
+        .enumerate()
+        .map(|(i, k)| (k.as_str(), i))
+        .collect();
+    let mut columns: Vec<Vec<Option<&Value<'_>>>> = vec![vec![None; rows.len()]; keys.len()];
+
+    for (row, item) in rows.iter().enumerate() {
+        let Value::HashMap(pairs) = item else {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "to_columns expects a list of dicts, but item {} isn't one",
+                row
+            )));
+        };
+
+        for (k, v) in pairs {
+            if let Some(&column) = k.as_str().and_then(|k| index.get(k)) {
+                columns[column][row] = Some(v);
+            }
+        }
+    }
+
+    let result = PyDict::new(py);
+    for (key, column) in keys.iter().zip(&columns) {
+        let typed_column = if typed {
+            typed_array(py, column)?
+        } else {
+            None
+        };
+        let column = match typed_column {
+            Some(array) => array,
+            None => {
+                let items = column
+                    .iter()
+                    .map(|value| match value {
+                        Some(value) => lize_to_py(py, value, &options).map_err(into_py_err),
+                        None => Ok(py.None()),
+                    })
+                    .collect::<PyResult<Vec<_>>>()?;
+                PyList::new(py, items)?.into_any()
+            }
+        };
+        result.set_item(key, column)?;
+    }
+
+    Ok(result)
+}
+
+/// Packs a column into an `array.array` if every row holds an integer, or
+/// every row holds a float.
+fn typed_array<'py>(
+    py: Python<'py>,
+    column: &[Option<&Value<'_>>],
+) -> PyResult<Option<Bound<'py, PyAny>>> {
+    if column.is_empty() {
+        return Ok(None);
+    }
+
+    let int = |value: &Value| match value {
+        Value::I64(i) => Some(*i),
+        Value::I32(i) => Some(*i as i64),
+        Value::U8(u) | Value::SmallU8(u) => Some(*u as i64),
+        _ => None,
+    };
+    let float = |value: &Value| match value {
+        Value::F64(f) => Some(*f),
+        Value::F32(f) => Some(*f as f64),
+        _ => None,
+    };
+
+    let (typecode, bytes) = if let Some(ints) = column
+        .iter()
+        .map(|value| value.and_then(int))
+        .collect::<Option<Vec<_>>>()
+    {
+        (
+            "q",
+            ints.iter()
+                .flat_map(|i| i.to_ne_bytes())
+                .collect::<Vec<_>>(),
+        )
+    } else if let Some(floats) = column
+        .iter()
+        .map(|value| value.and_then(float))
+        .collect::<Option<Vec<_>>>()
+    {
+        ("d", floats.iter().flat_map(|f| f.to_ne_bytes()).collect())
+    } else {
+        return Ok(None);
+    };
+
+    let array = py.import("array")?.getattr("array")?.call1((typecode,))?;
+    array.call_method1("frombytes", (PyBytes::new(py, &bytes),))?;
+    Ok(Some(array))
+}
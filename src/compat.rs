@@ -0,0 +1,246 @@
+//! What depends on the interpreter lize runs on, or on how it was built.
+//!
+//! With the `abi3` feature, the module is built once against the stable ABI
+//! of Python 3.9 and loads on any later version, but pyo3 leaves out what
+//! isn't part of that ABI: the `datetime` types, functions and the buffer
+//! protocol. The wrappers here read those through their Python attributes in
+//! such builds, and through the C API otherwise.
+//!
+//! Features that need more than the oldest interpreter or build lize supports
+//! are listed in [`FEATURES`], which `lize.features()` reports on.
+
+#[cfg(not(feature = "abi3"))]
+use pyo3::types::{PyDateAccess, PyDateTime, PyDelta, PyDeltaAccess, PyFunction, PyTimeAccess};
+use pyo3::{exceptions, prelude::*, types::PyDict};
+#[cfg(feature = "abi3")]
+use pyo3::{intern, sync::GILOnceCell, types::PyType};
+
+/// Something lize can only do on newer interpreters, or in some builds.
+pub struct Feature {
+    pub name: &'static str,
+    /// The oldest Python version it works on.
+    pub since: (u8, u8),
+    /// Whether this build has it at all.
+    pub built: bool,
+}
+
+impl Feature {
+    /// Whether it can be used on the running interpreter.
+    pub fn available(&self, py: Python<'_>) -> bool {
+        self.built && py.version_info() >= self.since
+    }
+
+    /// Fails with `NotImplementedError`, naming the version `what` needs, if
+    /// the feature can't be used.
+    pub fn require(&self, py: Python<'_>, what: &str) -> PyResult<()> {
+        if self.available(py) {
+            return Ok(());
+        }
+        let (major, minor) = self.since;
+        Err(exceptions::PyNotImplementedError::new_err(match self.built {
+            true => format!("{} needs Python {}.{} or newer", what, major, minor),
+            false => format!(
+                "{} needs lize built for Python {}.{} or newer, not for the stable ABI of Python 3.9",
+                what, major, minor
+            ),
+        }))
+    }
+}
+
+/// `serialize_buffer`, exporting bytes through the buffer protocol, which the
+/// stable ABI only has from Python 3.11.
+pub const BUFFER_EXPORT: Feature = Feature {
+    name: "buffer_export",
+    since: if cfg!(feature = "abi3") {
+        (3, 11)
+    } else {
+        (3, 9)
+    },
+    built: cfg!(not(feature = "abi3")),
+};
+
+/// `X | Y` type hints in `load_as` and `deserialize_typeddict`.
+pub const UNION_TYPE: Feature = Feature {
+    name: "union_type",
+    since: (3, 10),
+    built: true,
+};
+
+/// Dataclasses with keyword-only fields, which are written through `__reduce__`
+/// rather than from their fields.
+pub const DATACLASS_KW_ONLY: Feature = Feature {
+    name: "dataclass_kw_only",
+    since: (3, 10),
+    built: true,
+};
+
+pub const FEATURES: [&Feature; 3] = [&BUFFER_EXPORT, &UNION_TYPE, &DATACLASS_KW_ONLY];
+
+/// Returns which of the features that depend on the interpreter or the build
+/// can be used here, by name.
+#[pyfunction]
+pub fn features(py: Python<'_>) -> PyResult<Bound<'_, PyDict>> {
+    let features = PyDict::new(py);
+    for feature in FEATURES {
+        features.set_item(feature.name, feature.available(py))?;
+    }
+    Ok(features)
+}
+
+/// A `datetime.timedelta`, or an instance of a subclass.
+#[derive(Debug)]
+pub struct TimeDelta(Py<PyAny>);
+
+impl<'py> FromPyObject<'py> for TimeDelta {
+    #[cfg(not(feature = "abi3"))]
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        Ok(Self(ob.downcast::<PyDelta>()?.clone().into_any().unbind()))
+    }
+
+    #[cfg(feature = "abi3")]
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        static CLASS: GILOnceCell<Py<PyType>> = GILOnceCell::new();
+        if !ob.is_instance(CLASS.import(ob.py(), "datetime", "timedelta")?)? {
+            return Err(exceptions::PyTypeError::new_err("expected a timedelta"));
+        }
+        Ok(Self(ob.clone().unbind()))
+    }
+}
+
+impl<'py> IntoPyObject<'py> for TimeDelta {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        Ok(self.0.into_bound(py))
+    }
+}
+
+impl TimeDelta {
+    /// Its `(days, seconds, microseconds)`, normalized as Python keeps them.
+    #[cfg(not(feature = "abi3"))]
+    pub fn parts(&self, py: Python<'_>) -> PyResult<(i32, i32, i32)> {
+        let delta = self.0.bind(py).downcast::<PyDelta>()?;
+        Ok((
+            delta.get_days(),
+            delta.get_seconds(),
+            delta.get_microseconds(),
+        ))
+    }
+
+    #[cfg(feature = "abi3")]
+    pub fn parts(&self, py: Python<'_>) -> PyResult<(i32, i32, i32)> {
+        let delta = self.0.bind(py);
+        Ok((
+            delta.getattr(intern!(py, "days"))?.extract()?,
+            delta.getattr(intern!(py, "seconds"))?.extract()?,
+            delta.getattr(intern!(py, "microseconds"))?.extract()?,
+        ))
+    }
+}
+
+/// A `timedelta` of the given parts, which are normalized.
+#[cfg(not(feature = "abi3"))]
+pub fn timedelta(
+    py: Python<'_>,
+    days: i32,
+    seconds: i32,
+    micros: i32,
+) -> PyResult<Bound<'_, PyAny>> {
+    Ok(PyDelta::new(py, days, seconds, micros, true)?.into_any())
+}
+
+#[cfg(feature = "abi3")]
+pub fn timedelta(
+    py: Python<'_>,
+    days: i32,
+    seconds: i32,
+    micros: i32,
+) -> PyResult<Bound<'_, PyAny>> {
+    static CLASS: GILOnceCell<Py<PyType>> = GILOnceCell::new();
+    CLASS
+        .import(py, "datetime", "timedelta")?
+        .call1((days, seconds, micros))
+}
+
+/// The `[year, month, day, hour, minute, second, microsecond, fold]` of a
+/// `datetime.datetime`.
+#[cfg(not(feature = "abi3"))]
+pub fn datetime_fields(dt: &Bound<'_, PyAny>) -> PyResult<[i64; 8]> {
+    let dt = dt.downcast::<PyDateTime>()?;
+    Ok([
+        dt.get_year() as i64,
+        dt.get_month() as i64,
+        dt.get_day() as i64,
+        dt.get_hour() as i64,
+        dt.get_minute() as i64,
+        dt.get_second() as i64,
+        dt.get_microsecond() as i64,
+        dt.get_fold() as i64,
+    ])
+}
+
+#[cfg(feature = "abi3")]
+pub fn datetime_fields(dt: &Bound<'_, PyAny>) -> PyResult<[i64; 8]> {
+    let mut fields = [0; 8];
+    let names = [
+        "year",
+        "month",
+        "day",
+        "hour",
+        "minute",
+        "second",
+        "microsecond",
+        "fold",
+    ];
+    for (field, name) in fields.iter_mut().zip(names) {
+        *field = dt.getattr(name)?.extract()?;
+    }
+    Ok(fields)
+}
+
+/// A plain Python function, `types.FunctionType`.
+#[derive(Debug)]
+pub struct Function(Py<PyAny>);
+
+impl<'py> FromPyObject<'py> for Function {
+    #[cfg(not(feature = "abi3"))]
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        Ok(Self(
+            ob.downcast::<PyFunction>()?.clone().into_any().unbind(),
+        ))
+    }
+
+    #[cfg(feature = "abi3")]
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        static CLASS: GILOnceCell<Py<PyType>> = GILOnceCell::new();
+        // `FunctionType` can't be subclassed.
+        if !ob
+            .get_type()
+            .is(CLASS.import(ob.py(), "types", "FunctionType")?)
+        {
+            return Err(exceptions::PyTypeError::new_err(format!(
+                "'{}' object cannot be converted to 'function'",
+                ob.get_type().name()?
+            )));
+        }
+        Ok(Self(ob.clone().unbind()))
+    }
+}
+
+impl<'py> IntoPyObject<'py> for Function {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        Ok(self.0.into_bound(py))
+    }
+}
+
+impl Function {
+    pub fn bind<'py>(&self, py: Python<'py>) -> &Bound<'py, PyAny> {
+        self.0.bind(py)
+    }
+}
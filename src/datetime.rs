@@ -0,0 +1,147 @@
+//! `datetime.datetime`, which is stored as an [`ext::DATETIME`] value holding
+//! its fields, its `fold` and its time zone.
+//!
+//! Only `datetime` itself is handled. Subclasses, like `pandas.Timestamp`,
+//! are left to the other conversions. Time zones from `zoneinfo` are kept by
+//! their key; any other is kept as its offset from UTC at that time.
+
+use anyhow::Result;
+use lize_sys::{ext, Value};
+use pyo3::{
+    exceptions,
+    prelude::*,
+    sync::GILOnceCell,
+    types::{PyDict, PyType},
+};
+
+use crate::compat;
+
+fn class(py: Python<'_>) -> PyResult<&Bound<'_, PyType>> {
+    static CLASS: GILOnceCell<Py<PyType>> = GILOnceCell::new();
+    CLASS.import(py, "datetime", "datetime")
+}
+
+/// A `datetime.datetime`, naive or aware.
+#[derive(Debug)]
+pub struct DateTime(Py<PyAny>);
+
+impl<'py> FromPyObject<'py> for DateTime {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        if !ob.get_type().is(class(ob.py())?) {
+            return Err(exceptions::PyTypeError::new_err("expected a datetime"));
+        }
+        Ok(Self(ob.clone().unbind()))
+    }
+}
+
+impl<'py> IntoPyObject<'py> for DateTime {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        Ok(self.0.into_bound(py))
+    }
+}
+
+/// The smallest integer value holding `i`, as the rest of the module writes them.
+fn int(i: i64) -> Value<'static> {
+    match i {
+        0..=235 => Value::SmallU8(i as u8),
+        236..=255 => Value::U8(i as u8),
+        _ => match i32::try_from(i) {
+            Ok(i) => Value::I32(i),
+            Err(_) => Value::I64(i),
+        },
+    }
+}
+
+impl DateTime {
+    /// `[year, month, day, hour, minute, second, microsecond, fold, zone]`,
+    /// where `zone` is `None` for naive datetimes, a `zoneinfo` key, or the
+    /// offset from UTC in microseconds.
+    pub fn to_lize(&self, py: Python<'_>) -> Result<Value<'static>> {
+        let dt = self.0.bind(py);
+        let zone = match dt.getattr("tzinfo")? {
+            tz if tz.is_none() => Value::Optional(None),
+            tz if is_zoneinfo(&tz)? && !tz.getattr("key")?.is_none() => {
+                Value::StrLike(tz.getattr("key")?.extract()?)
+            }
+            _ => match dt.call_method0("utcoffset")? {
+                // A time zone without an offset makes the datetime naive.
+                offset if offset.is_none() => Value::Optional(None),
+                offset => {
+                    let (days, seconds, micros) =
+                        offset.extract::<compat::TimeDelta>()?.parts(py)?;
+                    let seconds = days as i64 * 86_400 + seconds as i64;
+                    int(seconds * 1_000_000 + micros as i64)
+                }
+            },
+        };
+
+        let mut fields: Vec<_> = compat::datetime_fields(dt)?.into_iter().map(int).collect();
+        fields.push(zone);
+        Ok(Value::Ext(ext::DATETIME, Box::new(Value::Vector(fields))))
+    }
+}
+
+fn is_zoneinfo(tz: &Bound<'_, PyAny>) -> PyResult<bool> {
+    static ZONEINFO: GILOnceCell<Py<PyType>> = GILOnceCell::new();
+    tz.is_instance(ZONEINFO.import(tz.py(), "zoneinfo", "ZoneInfo")?)
+}
+
+/// Rebuilds a datetime from the fields [`DateTime::to_lize`] wrote.
+pub fn from_lize(py: Python<'_>, fields: &[Value<'_>]) -> PyResult<Py<PyAny>> {
+    let invalid = || exceptions::PyValueError::new_err("Invalid datetime");
+    let [year, month, day, hour, minute, second, microsecond, fold, zone] = fields else {
+        return Err(invalid());
+    };
+    let field = |value: &Value<'_>| match value {
+        Value::SmallU8(u) | Value::U8(u) => Ok(*u as i64),
+        Value::I32(i) => Ok(*i as i64),
+        Value::I64(i) => Ok(*i),
+        _ => Err(invalid()),
+    };
+
+    let tzinfo = match zone {
+        Value::Optional(None) => None,
+        Value::Str(key) => Some(zoneinfo(py, key)?),
+        Value::StrLike(key) => Some(zoneinfo(py, key)?),
+        offset => {
+            let micros = field(offset)?;
+            let offset = compat::timedelta(
+                py,
+                micros.div_euclid(86_400_000_000) as i32,
+                (micros.rem_euclid(86_400_000_000) / 1_000_000) as i32,
+                micros.rem_euclid(1_000_000) as i32,
+            )?;
+            Some(
+                py.import("datetime")?
+                    .getattr("timezone")?
+                    .call1((offset,))?,
+            )
+        }
+    };
+
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("tzinfo", tzinfo)?;
+    kwargs.set_item("fold", field(fold)?)?;
+    Ok(class(py)?
+        .call(
+            (
+                field(year)?,
+                field(month)?,
+                field(day)?,
+                field(hour)?,
+                field(minute)?,
+                field(second)?,
+                field(microsecond)?,
+            ),
+            Some(&kwargs),
+        )?
+        .unbind())
+}
+
+fn zoneinfo<'py>(py: Python<'py>, key: &str) -> PyResult<Bound<'py, PyAny>> {
+    py.import("zoneinfo")?.getattr("ZoneInfo")?.call1((key,))
+}
@@ -0,0 +1,136 @@
+//! `Decoder`, for loops that decode many payloads of the same shape.
+//!
+//! Its options are parsed once, and the `str` objects built for dict keys are
+//! kept and shared with the next payload, so records with the same keys don't
+//! build them again. `decode_into` also reuses the top-level dict.
+
+use std::sync::Arc;
+
+use lize_sys::Value;
+use pyo3::{
+    exceptions,
+    prelude::*,
+    types::{PyDict, PyString},
+};
+
+use crate::{
+    decode_value,
+    error::into_py_err,
+    lize_to_py_with, open_payload,
+    options::{BytesAs, DecodeOptions},
+    view,
+};
+
+/// How many keys a `Decoder` keeps, so payloads with huge dicts don't leave
+/// it holding all their keys.
+const MAX_KEYS: usize = 4096;
+
+/// What a `Decoder` reuses between payloads.
+#[derive(Default)]
+pub struct Scratch {
+    /// The keys of the last payload, in the order they were read. Records of
+    /// the same shape have the same keys in the same order, so the `n`th key
+    /// read is compared with the `n`th key kept rather than hashed.
+    keys: Vec<(Box<str>, Py<PyAny>)>,
+    next: usize,
+}
+
+impl Scratch {
+    /// The `str` for the next dict key, `key`, reused from the last payload if
+    /// it had the same key at the same place.
+    pub fn key(&mut self, py: Python<'_>, key: &str) -> Py<PyAny> {
+        let slot = self.next;
+        self.next += 1;
+        match self.keys.get(slot) {
+            Some((kept, built)) if **kept == *key => return built.clone_ref(py),
+            _ => {}
+        }
+
+        let built = PyString::new(py, key).into_any().unbind();
+        if slot < self.keys.len() {
+            self.keys[slot] = (key.into(), built.clone_ref(py));
+        } else if slot < MAX_KEYS {
+            self.keys.push((key.into(), built.clone_ref(py)));
+        }
+        built
+    }
+}
+
+/// Decodes payloads with the same options, sharing the dict keys they have in
+/// common.
+///
+/// Takes the same keyword arguments as `deserialize`.
+#[pyclass(module = "lize")]
+pub struct Decoder {
+    options: DecodeOptions,
+    scratch: Scratch,
+}
+
+#[pymethods]
+impl Decoder {
+    #[new]
+    #[pyo3(signature = (**kwargs))]
+    fn new(kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        Ok(Self {
+            options: DecodeOptions::from_kwargs(kwargs)?,
+            scratch: Scratch::default(),
+        })
+    }
+
+    /// Decodes `bytes` like `deserialize`.
+    fn decode(&mut self, bytes: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        self.decode_to(bytes, None)
+    }
+
+    /// Decodes `bytes`, which must hold a dict, into `into` after clearing it,
+    /// and returns `into`.
+    fn decode_into<'py>(
+        &mut self,
+        bytes: &Bound<'py, PyAny>,
+        into: Bound<'py, PyDict>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let py = bytes.py();
+        let dict = self.decode_to(bytes, Some(into))?;
+        Ok(dict.into_bound(py).downcast_into()?)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Decoder(<{} keys>)", self.scratch.keys.len())
+    }
+}
+
+impl Decoder {
+    fn decode_to(
+        &mut self,
+        bytes: &Bound<'_, PyAny>,
+        into: Option<Bound<'_, PyDict>>,
+    ) -> PyResult<Py<PyAny>> {
+        let py = bytes.py();
+        let buffer = view::immutable(bytes)?;
+        let mut with_view = None;
+        if self.options.bytes_as == BytesAs::Memoryview {
+            let mut options = self.options.clone();
+            options.view = Some(Arc::new(view::SourceView::new(&buffer, buffer.as_bytes())?));
+            with_view = Some(options);
+        }
+        let options = with_view.as_ref().unwrap_or(&self.options);
+
+        self.scratch.next = 0;
+        if let Some(budget) = &options.max_alloc {
+            budget.reset();
+        }
+        let payload = open_payload(buffer.as_bytes(), options)?;
+        let value = decode_value(py, &payload, options)?;
+        if into.is_some()
+            && (options.maps_as_pairs || options.record || !matches!(value, Value::HashMap(_)))
+        {
+            return Err(exceptions::PyTypeError::new_err(
+                "decode_into needs a payload holding a dict",
+            ));
+        }
+        if let Some(into) = &into {
+            into.clear();
+        }
+        lize_to_py_with(py, &value, options, Some(&mut self.scratch), into).map_err(into_py_err)
+    }
+}
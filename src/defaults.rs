@@ -0,0 +1,214 @@
+//! `lize.options`, defaults for the keyword arguments of every entry point.
+//!
+//! Defaults are set for the whole process with `lize.options.canonical = True`,
+//! or for a block with `with lize.options(canonical=True):`, which keeps them
+//! in a `contextvars.ContextVar` so other threads and asyncio tasks don't see
+//! them. Keyword arguments win over the block, which wins over the process.
+//!
+//! Each entry point applies the defaults it has an option for, so a decoding
+//! default like `lossy` doesn't make `serialize` raise.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use pyo3::{
+    exceptions,
+    prelude::*,
+    pybacked::PyBackedStr,
+    sync::GILOnceCell,
+    types::{PyDict, PyTuple},
+};
+
+use crate::options::{DecodeOptions, EncodeOptions};
+
+/// Whether any process-wide default is set.
+static GLOBAL_SET: AtomicBool = AtomicBool::new(false);
+
+/// How many `with lize.options(...)` blocks are open, in any context, so entry
+/// points only look up the context when one might be.
+static OPEN_BLOCKS: AtomicUsize = AtomicUsize::new(0);
+
+fn global(py: Python<'_>) -> &Bound<'_, PyDict> {
+    static GLOBAL: GILOnceCell<Py<PyDict>> = GILOnceCell::new();
+    GLOBAL.get_or_init(py, || PyDict::new(py).unbind()).bind(py)
+}
+
+/// The `ContextVar` holding the defaults of the open blocks, merged, or
+/// `None` outside of them.
+fn context_var(py: Python<'_>) -> PyResult<&Bound<'_, PyAny>> {
+    static VAR: GILOnceCell<Py<PyAny>> = GILOnceCell::new();
+    let var = VAR.get_or_try_init(py, || -> PyResult<_> {
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("default", py.None())?;
+        Ok(py
+            .import("contextvars")?
+            .getattr("ContextVar")?
+            .call(("lize.options",), Some(&kwargs))?
+            .unbind())
+    })?;
+    Ok(var.bind(py))
+}
+
+/// The defaults of the open blocks in the current context, if any.
+fn block(py: Python<'_>) -> PyResult<Option<Bound<'_, PyDict>>> {
+    if OPEN_BLOCKS.load(Ordering::Acquire) == 0 {
+        return Ok(None);
+    }
+    let current = context_var(py)?.call_method0("get")?;
+    Ok(current.downcast_into::<PyDict>().ok())
+}
+
+/// Whether any default might apply, for fast paths that skip options.
+pub fn any() -> bool {
+    GLOBAL_SET.load(Ordering::Acquire) || OPEN_BLOCKS.load(Ordering::Acquire) > 0
+}
+
+/// The defaults in effect: the process-wide ones, updated with those of the
+/// open blocks.
+fn effective(py: Python<'_>) -> PyResult<Bound<'_, PyDict>> {
+    let merged = global(py).copy()?;
+    if let Some(block) = block(py)? {
+        merged.update(block.as_mapping())?;
+    }
+    Ok(merged)
+}
+
+/// Calls `set` with each default in effect, which returns `false` for those
+/// that don't apply.
+pub fn apply(mut set: impl FnMut(&str, &Bound<'_, PyAny>) -> PyResult<bool>) -> PyResult<()> {
+    if !any() {
+        return Ok(());
+    }
+    Python::with_gil(|py| {
+        for (key, value) in effective(py)? {
+            set(&key.extract::<PyBackedStr>()?, &value)?;
+        }
+        Ok(())
+    })
+}
+
+/// Whether some entry point has an option named `name`.
+fn is_option(py: Python<'_>, name: &str) -> bool {
+    // Options that aren't `None`able fail to extract it, but are still known.
+    let none = py.None().into_bound(py);
+    !matches!(EncodeOptions::default().set(name, &none), Ok(false))
+        || !matches!(DecodeOptions::default().set(name, &none), Ok(false))
+}
+
+/// Raises unless `name` is an option of some entry point and `value` is valid
+/// for every entry point that has it.
+fn check(name: &str, value: &Bound<'_, PyAny>) -> PyResult<()> {
+    if !is_option(value.py(), name) {
+        return Err(exceptions::PyAttributeError::new_err(format!(
+            "lize has no option {:?}",
+            name
+        )));
+    }
+    EncodeOptions::default().set(name, value)?;
+    DecodeOptions::default().set(name, value)?;
+    Ok(())
+}
+
+/// The type of `lize.options`.
+#[pyclass(module = "lize", frozen)]
+pub struct Options;
+
+#[pymethods]
+impl Options {
+    /// The default in effect for `name`, or `None` if it isn't set.
+    fn __getattr__(&self, py: Python<'_>, name: &str) -> PyResult<Py<PyAny>> {
+        if !is_option(py, name) {
+            return Err(exceptions::PyAttributeError::new_err(format!(
+                "lize has no option {:?}",
+                name
+            )));
+        }
+        Ok(match effective(py)?.get_item(name)? {
+            Some(value) => value.unbind(),
+            None => py.None(),
+        })
+    }
+
+    /// Sets the process-wide default for `name`.
+    fn __setattr__(&self, name: &str, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        check(name, value)?;
+        global(value.py()).set_item(name, value)?;
+        GLOBAL_SET.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Removes the process-wide default for `name`.
+    fn __delattr__(&self, py: Python<'_>, name: &str) -> PyResult<()> {
+        let global = global(py);
+        if global.contains(name)? {
+            global.del_item(name)?;
+        }
+        GLOBAL_SET.store(!global.is_empty(), Ordering::Release);
+        Ok(())
+    }
+
+    /// Returns a context manager setting `kwargs` as the defaults in its block,
+    /// on top of those already in effect.
+    #[pyo3(signature = (**kwargs))]
+    fn __call__(&self, py: Python<'_>, kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<Block> {
+        let overrides = PyDict::new(py);
+        for (key, value) in kwargs.into_iter().flatten() {
+            check(&key.extract::<PyBackedStr>()?, &value)?;
+            overrides.set_item(key, value)?;
+        }
+        Ok(Block {
+            overrides: overrides.unbind(),
+            token: None,
+        })
+    }
+
+    /// The defaults in effect here, as a dict.
+    fn snapshot<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        effective(py)
+    }
+
+    /// Removes every process-wide default. Those of open blocks stay.
+    fn reset(&self, py: Python<'_>) {
+        global(py).clear();
+        GLOBAL_SET.store(false, Ordering::Release);
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!("lize.options({})", effective(py)?.repr()?))
+    }
+}
+
+/// A `with lize.options(...)` block.
+#[pyclass(module = "lize")]
+pub struct Block {
+    overrides: Py<PyDict>,
+    /// The token restoring the previous defaults, while the block is open.
+    token: Option<Py<PyAny>>,
+}
+
+#[pymethods]
+impl Block {
+    fn __enter__(&mut self, py: Python<'_>) -> PyResult<()> {
+        if self.token.is_some() {
+            return Err(exceptions::PyRuntimeError::new_err(
+                "this lize.options block is already open",
+            ));
+        }
+        let merged = match block(py)? {
+            Some(outer) => outer.copy()?,
+            None => PyDict::new(py),
+        };
+        merged.update(self.overrides.bind(py).as_mapping())?;
+        self.token = Some(context_var(py)?.call_method1("set", (merged,))?.unbind());
+        OPEN_BLOCKS.fetch_add(1, Ordering::AcqRel);
+        Ok(())
+    }
+
+    #[pyo3(signature = (*_exc_info))]
+    fn __exit__(&mut self, py: Python<'_>, _exc_info: &Bound<'_, PyTuple>) -> PyResult<bool> {
+        if let Some(token) = self.token.take() {
+            OPEN_BLOCKS.fetch_sub(1, Ordering::AcqRel);
+            context_var(py)?.call_method1("reset", (token,))?;
+        }
+        Ok(false)
+    }
+}
@@ -0,0 +1,48 @@
+//! `diagnose`, which lists every structural problem in a payload instead of
+//! raising at the first, for tooling that inspects damaged data.
+
+use pyo3::prelude::*;
+
+/// A problem `diagnose` found in a payload.
+#[pyclass(module = "lize", frozen)]
+pub struct Problem {
+    /// Where the value that couldn't be read starts.
+    #[pyo3(get)]
+    offset: usize,
+    /// What went wrong: `"unknown_tag"`, `"invalid_utf8"`, `"invalid_length"`,
+    /// `"truncated"`, `"trailing_bytes"` or `"unrecoverable"`.
+    #[pyo3(get)]
+    kind: &'static str,
+    #[pyo3(get)]
+    message: String,
+    /// Where the value sits, like `$[3]["name"]`.
+    #[pyo3(get)]
+    path: String,
+}
+
+#[pymethods]
+impl Problem {
+    fn __repr__(&self) -> String {
+        format!(
+            "Problem(offset={}, kind={:?}, path={:?}, message={:?})",
+            self.offset, self.kind, self.path, self.message
+        )
+    }
+}
+
+/// Scans `data` for structural problems without decoding it, returning up to
+/// `limit` of them. When a value can't be read, the scan carries on from where
+/// the next one seems to start, so one damaged region doesn't hide the rest.
+#[pyfunction]
+#[pyo3(signature = (data, limit = 100))]
+pub fn diagnose(data: &[u8], limit: usize) -> Vec<Problem> {
+    lize_sys::diagnose(data, limit)
+        .into_iter()
+        .map(|problem| Problem {
+            offset: problem.offset,
+            kind: problem.kind.name(),
+            message: problem.message,
+            path: problem.path,
+        })
+        .collect()
+}
@@ -0,0 +1,69 @@
+use pyo3::{create_exception, exceptions, PyErr};
+
+create_exception!(
+    lize,
+    AuthenticationFailed,
+    exceptions::PyValueError,
+    "An encrypted payload was tampered with, or the key is wrong."
+);
+
+create_exception!(
+    lize,
+    EncodeError,
+    exceptions::PyValueError,
+    "A value's encoding is longer than max_output_bytes."
+);
+
+create_exception!(
+    lize,
+    Cancelled,
+    exceptions::PyException,
+    "A progress callback returned False."
+);
+
+create_exception!(
+    lize,
+    AllocLimitExceeded,
+    exceptions::PyValueError,
+    "Decoding built more bytes of Python objects than max_alloc."
+);
+
+create_exception!(
+    lize,
+    ValidationError,
+    exceptions::PyValueError,
+    "A decoded value doesn't match the type hint it was loaded as."
+);
+
+/// Converts an error from the core into the matching Python exception.
+///
+/// Errors caused by the input (see [`lize_sys::Error`]) become `ValueError`s,
+/// except failed authentication, which is an [`AuthenticationFailed`], and
+/// output over `max_output_bytes`, which is an [`EncodeError`], and a
+/// progress callback asking to stop, which is a [`Cancelled`]. Python
+/// errors pass through, and anything else is a `RuntimeError`.
+pub fn into_py_err(err: anyhow::Error) -> PyErr {
+    let err = match err.downcast::<PyErr>() {
+        Ok(err) => return err,
+        Err(err) => err,
+    };
+
+    // Errors raised by Python file objects come back wrapped in an `io::Error`.
+    let err = match err.downcast::<std::io::Error>() {
+        Ok(io) if io.get_ref().is_some_and(|inner| inner.is::<PyErr>()) => {
+            return *io.into_inner().unwrap().downcast::<PyErr>().unwrap();
+        }
+        Ok(io) => anyhow::Error::from(io),
+        Err(err) => err,
+    };
+
+    match err.downcast_ref::<lize_sys::Error>() {
+        Some(core @ lize_sys::Error::AuthenticationFailed) => {
+            AuthenticationFailed::new_err(core.to_string())
+        }
+        Some(core @ lize_sys::Error::OutputTooLarge(_)) => EncodeError::new_err(core.to_string()),
+        Some(core @ lize_sys::Error::Cancelled) => Cancelled::new_err(core.to_string()),
+        Some(core) => exceptions::PyValueError::new_err(core.to_string()),
+        None => exceptions::PyRuntimeError::new_err(format!("{:?}", err)),
+    }
+}
@@ -0,0 +1,222 @@
+//! Estimates of how much memory a payload takes once decoded into Python
+//! objects, from [`Stats`] gathered without decoding it.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use lize_sys::{packed, Stats, Value};
+use pyo3::{exceptions, prelude::*, types::PyDict};
+
+use crate::{
+    error::{into_py_err, AllocLimitExceeded},
+    open_payload,
+    options::DecodeOptions,
+};
+
+/// Bytes taken by each kind of object on a 64-bit CPython, including allocator
+/// rounding. Small integers, booleans and `None` are shared, so they're free.
+const INT: usize = 32;
+const FLOAT: usize = 24;
+const STR: usize = 52;
+const BYTES: usize = 36;
+const LIST: usize = 56;
+const POINTER: usize = 8;
+const EXT: usize = 64;
+const EMPTY_DICT: usize = 64;
+
+/// Estimates the bytes of Python objects the value counted by `stats` decodes into.
+pub fn estimate(stats: &Stats) -> usize {
+    let maps: usize = stats
+        .map_sizes
+        .iter()
+        .map(|(&len, &count)| dict_size(len) * count)
+        .sum();
+
+    stats.ints * INT
+        + stats.floats * FLOAT
+        + stats.strs * STR
+        + stats.str_bytes
+        + stats.slices * BYTES
+        + stats.slice_bytes
+        + stats.vectors * LIST
+        + stats.items * POINTER
+        + (stats.run_items + stats.bits) * POINTER
+        + stats.exts * EXT
+        + maps
+}
+
+/// The size of a dict built from `len` entries: its header, an index table
+/// that's at most two-thirds full, and a 24-byte entry per usable slot.
+fn dict_size(len: usize) -> usize {
+    if len == 0 {
+        return EMPTY_DICT;
+    }
+
+    let mut slots = 8;
+    while len > slots * 2 / 3 {
+        slots *= 2;
+    }
+    96 + slots + slots * 2 / 3 * 24
+}
+
+/// The bytes of Python objects `value` decodes into on its own: a container's
+/// header and item pointers, but not its items.
+pub fn size_of(value: &Value<'_>) -> usize {
+    match value {
+        Value::I32(_) | Value::I64(_) => INT,
+        Value::F32(_) | Value::F64(_) => FLOAT,
+        Value::Str(s) => STR + s.len(),
+        Value::StrLike(s) => STR + s.len(),
+        Value::Slice(s) => BYTES + s.len(),
+        Value::SliceLike(s) => BYTES + s.len(),
+        Value::Vector(v) => LIST + v.len() * POINTER,
+        Value::HashMap(h) => dict_size(h.len()),
+        Value::Ext(..) => EXT,
+        Value::Bool(_) | Value::U8(_) | Value::SmallU8(_) | Value::Optional(_) => 0,
+    }
+}
+
+/// `max_alloc=...`, the bytes of Python objects one decode may build, counted
+/// as they're built.
+#[derive(Debug)]
+pub struct Budget {
+    max: usize,
+    used: AtomicUsize,
+}
+
+impl Budget {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Starts counting again, for the next value of a `Decoder` or `Reader`.
+    pub fn reset(&self) {
+        self.used.store(0, Ordering::Relaxed);
+    }
+
+    /// Counts `value`, raising `AllocLimitExceeded` once the total is over the limit.
+    pub fn charge(&self, value: &Value<'_>) -> PyResult<()> {
+        self.charge_bytes(size_of(value))
+    }
+
+    /// Counts the slots of `len` items a packed list expands to, which
+    /// [`Budget::charge`] doesn't see in the value itself.
+    pub fn charge_items(&self, len: usize) -> PyResult<()> {
+        self.charge_bytes(len.saturating_mul(POINTER))
+    }
+
+    fn charge_bytes(&self, size: usize) -> PyResult<()> {
+        let used = self
+            .used
+            .fetch_add(size, Ordering::Relaxed)
+            .saturating_add(size);
+        if used > self.max {
+            return Err(AllocLimitExceeded::new_err(format!(
+                "decoding built more than max_alloc={} bytes of Python objects",
+                self.max
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// How many values runs may expand to for each byte of the payload, on top of
+/// [`EXPANSION_BASE`], unless `max_expansion=...` says otherwise.
+const EXPANSION_PER_BYTE: usize = 64;
+const EXPANSION_BASE: usize = 1 << 20;
+
+/// `max_expansion=...`, how many values the runs in one payload may expand to
+/// in total. Each copy of a run's item counts every value in it, so nested
+/// runs multiply.
+#[derive(Debug, Default)]
+pub struct Expansion {
+    max: Option<usize>,
+    limit: AtomicUsize,
+    used: AtomicUsize,
+}
+
+impl Expansion {
+    pub fn new(max: Option<usize>) -> Self {
+        Self {
+            max,
+            ..Self::default()
+        }
+    }
+
+    /// Starts counting for a payload of `len` bytes. Without `max_expansion`,
+    /// the limit grows with the payload, up to [`packed::MAX_RUNS_LEN`].
+    pub fn start(&self, len: usize) {
+        let limit = self.max.unwrap_or_else(|| {
+            len.saturating_mul(EXPANSION_PER_BYTE)
+                .saturating_add(EXPANSION_BASE)
+                .min(packed::MAX_RUNS_LEN)
+        });
+        self.limit.store(limit, Ordering::Relaxed);
+        self.used.store(0, Ordering::Relaxed);
+    }
+
+    /// Counts `count` copies of `item`, raising `ValueError` once the total
+    /// is over the limit.
+    pub fn charge(&self, item: &Value<'_>, count: usize) -> PyResult<()> {
+        let size = count.saturating_mul(values_in(item));
+        let used = self
+            .used
+            .fetch_add(size, Ordering::Relaxed)
+            .saturating_add(size);
+        let limit = self.limit.load(Ordering::Relaxed);
+        if used > limit {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "runs expand to more than {} values, pass max_expansion=... to allow more",
+                limit
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The number of values in `value`, itself included, with runs and packed
+/// booleans counted as they're encoded.
+fn values_in(value: &Value<'_>) -> usize {
+    let mut count = 0;
+    let mut stack = vec![value];
+    while let Some(value) = stack.pop() {
+        count += 1;
+        match value {
+            Value::Vector(items) => stack.extend(items),
+            Value::HashMap(pairs) => stack.extend(pairs.iter().flat_map(|(k, v)| [k, v])),
+            Value::Ext(_, inner) => stack.push(inner),
+            Value::Optional(Some(inner)) => stack.push(inner),
+            _ => {}
+        }
+    }
+    count
+}
+
+/// Raises a `ValueError` if `payload` would decode into more than
+/// `max_decoded_size` bytes of Python objects.
+pub fn check(payload: &[u8], options: &DecodeOptions) -> PyResult<()> {
+    let Some(max) = options.max_decoded_size else {
+        return Ok(());
+    };
+
+    let size = estimate(&Stats::of(payload).map_err(into_py_err)?);
+    if size > max {
+        return Err(exceptions::PyValueError::new_err(format!(
+            "payload would decode to about {} bytes of Python objects, more than max_decoded_size={}",
+            size, max
+        )));
+    }
+    Ok(())
+}
+
+/// Estimates how many bytes of Python objects `data` decodes into, without
+/// decoding it. The estimate is usually within a factor of two.
+#[pyfunction]
+#[pyo3(signature = (data, **kwargs))]
+pub fn estimate_decoded_size(data: &[u8], kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<usize> {
+    let options = DecodeOptions::from_kwargs(kwargs)?;
+    let payload = open_payload(data, &options)?;
+    Ok(estimate(&Stats::of(&payload).map_err(into_py_err)?))
+}
@@ -0,0 +1,144 @@
+//! `register_extension`, which stores instances of an application's own class
+//! as a [`Value::Ext`] under one of the codes in [`ext::USER`], holding
+//! whatever its encoder returns.
+//!
+//! Only objects lize has no conversion of its own for are looked up, so
+//! registering a subclass of `dict` or `str`, say, changes nothing. Instances
+//! of subclasses of a registered class are encoded by its encoder too.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+use lize_sys::{ext, Value};
+use pyo3::{
+    exceptions,
+    prelude::*,
+    sync::GILOnceCell,
+    types::{PyDict, PyTuple, PyType},
+};
+
+use crate::{lize_to_py, options::DecodeOptions, options::EncodeOptions, py_to_lize, PyValue};
+
+/// Set once anything is registered, so encoding skips the lookups until then.
+static ANY: AtomicBool = AtomicBool::new(false);
+
+/// `(code, encoder)` by class.
+fn encoders(py: Python<'_>) -> &Bound<'_, PyDict> {
+    static ENCODERS: GILOnceCell<Py<PyDict>> = GILOnceCell::new();
+    ENCODERS
+        .get_or_init(py, || PyDict::new(py).unbind())
+        .bind(py)
+}
+
+/// `(class, decoder)` by code.
+fn decoders(py: Python<'_>) -> &Bound<'_, PyDict> {
+    static DECODERS: GILOnceCell<Py<PyDict>> = GILOnceCell::new();
+    DECODERS
+        .get_or_init(py, || PyDict::new(py).unbind())
+        .bind(py)
+}
+
+/// Registers `cls` under the ext code `tag`, which must be in `240..=255`.
+/// Its instances are written as `encoder(obj)`, and read back as
+/// `decoder(payload)`. Returns the class.
+#[pyfunction]
+pub fn register_extension<'py>(
+    tag: u8,
+    cls: &Bound<'py, PyType>,
+    encoder: &Bound<'py, PyAny>,
+    decoder: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyType>> {
+    let py = cls.py();
+    if !ext::USER.contains(&tag) {
+        return Err(exceptions::PyValueError::new_err(format!(
+            "tag {} is reserved for lize's own types, use one in {}..={}",
+            tag,
+            ext::USER.start(),
+            ext::USER.end()
+        )));
+    }
+    if let Some(taken) = decoders(py).get_item(tag)? {
+        let (other, _): (Bound<'_, PyType>, Bound<'_, PyAny>) = taken.extract()?;
+        if !other.is(cls) {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "tag {} is already registered for {}",
+                tag, other
+            )));
+        }
+    }
+    if let Some(taken) = encoders(py).get_item(cls)? {
+        let (other, _): (u8, Bound<'_, PyAny>) = taken.extract()?;
+        if other != tag {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "{} is already registered under tag {}",
+                cls, other
+            )));
+        }
+    }
+
+    encoders(py).set_item(cls, (tag, encoder))?;
+    decoders(py).set_item(tag, (cls, decoder))?;
+    ANY.store(true, Ordering::Relaxed);
+    Ok(cls.clone())
+}
+
+/// The code and encoder for `obj`, from the closest registered class in its
+/// MRO.
+fn lookup<'py>(obj: &Bound<'py, PyAny>) -> PyResult<Option<(u8, Bound<'py, PyAny>)>> {
+    if !ANY.load(Ordering::Relaxed) {
+        return Ok(None);
+    }
+    let encoders = encoders(obj.py());
+    let mro = obj.get_type().getattr("__mro__")?;
+    for cls in mro.downcast::<PyTuple>()?.iter() {
+        if let Some(entry) = encoders.get_item(cls)? {
+            return Ok(Some(entry.extract()?));
+        }
+    }
+    Ok(None)
+}
+
+/// Whether `obj`'s class, or one of its bases, is registered.
+pub fn is_registered(obj: &Bound<'_, PyAny>) -> PyResult<bool> {
+    Ok(lookup(obj)?.is_some())
+}
+
+/// Writes `obj` with its registered encoder, or returns `None` if its class
+/// isn't registered.
+pub fn to_lize<'py>(
+    py: Python<'py>,
+    obj: &Bound<'py, PyAny>,
+    options: &EncodeOptions,
+) -> Result<Option<Value<'py>>> {
+    let Some((code, encoder)) = lookup(obj)? else {
+        return Ok(None);
+    };
+    let payload = encoder.call1((obj,))?;
+    // Would be handed to the same encoder again, which could go on forever.
+    if lookup(&payload)?.is_some() {
+        return Err(exceptions::PyTypeError::new_err(format!(
+            "the encoder for {} returned another registered extension, {}",
+            obj.get_type(),
+            payload.get_type()
+        ))
+        .into());
+    }
+    let payload = py_to_lize(py, payload.extract::<PyValue>()?, options)?;
+    Ok(Some(Value::Ext(code, Box::new(payload))))
+}
+
+/// Decodes `payload` and hands it to the decoder registered for `code`, or
+/// returns `None` if there's none.
+pub fn from_lize(
+    py: Python<'_>,
+    code: u8,
+    payload: &Value<'_>,
+    options: &DecodeOptions,
+) -> Result<Option<Py<PyAny>>> {
+    let Some(entry) = decoders(py).get_item(code)? else {
+        return Ok(None);
+    };
+    let (_, decoder): (Bound<'_, PyType>, Bound<'_, PyAny>) = entry.extract()?;
+    let payload = lize_to_py(py, payload, options)?;
+    Ok(Some(decoder.call1((payload,))?.unbind()))
+}
@@ -0,0 +1,122 @@
+//! `lize.FixedInt`, the integers `deserialize(..., preserve_int_width=True)`
+//! returns, so decoding and re-encoding a payload from a Rust producer gives
+//! back the same bytes instead of whichever width Python would pick.
+
+use lize_sys::Value;
+use pyo3::{basic::CompareOp, exceptions, prelude::*, IntoPyObjectExt};
+
+/// How an integer was encoded, named after its [`Value`] variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Width {
+    SmallU8,
+    U8,
+    I32,
+    I64,
+}
+
+impl Width {
+    fn name(self) -> &'static str {
+        match self {
+            Self::SmallU8 => "small_u8",
+            Self::U8 => "u8",
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+        }
+    }
+}
+
+/// An integer that remembers the width it was encoded with, and is written
+/// back with it. Compares and hashes like the `int` it holds.
+#[pyclass(module = "lize", frozen)]
+pub struct FixedInt {
+    /// The integer itself.
+    #[pyo3(get)]
+    value: i64,
+    width: Width,
+}
+
+#[pymethods]
+impl FixedInt {
+    /// Wraps `value`, which must fit in `width`: one of `"small_u8"` (`0..=235`,
+    /// a single byte), `"u8"`, `"i32"` or `"i64"`.
+    #[new]
+    fn new(value: i64, width: &str) -> PyResult<Self> {
+        let (width, fits) = match width {
+            "small_u8" => (Width::SmallU8, (0..=235).contains(&value)),
+            "u8" => (Width::U8, u8::try_from(value).is_ok()),
+            "i32" => (Width::I32, i32::try_from(value).is_ok()),
+            "i64" => (Width::I64, true),
+            other => {
+                return Err(exceptions::PyValueError::new_err(format!(
+                    "width must be one of 'small_u8', 'u8', 'i32' or 'i64', got {:?}",
+                    other
+                )))
+            }
+        };
+        if !fits {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "{} doesn't fit in {}",
+                value,
+                width.name()
+            )));
+        }
+        Ok(Self { value, width })
+    }
+
+    /// The width it's written with: `"small_u8"`, `"u8"`, `"i32"` or `"i64"`.
+    #[getter]
+    fn width(&self) -> &'static str {
+        self.width.name()
+    }
+
+    fn __int__(&self) -> i64 {
+        self.value
+    }
+
+    fn __index__(&self) -> i64 {
+        self.value
+    }
+
+    fn __hash__(&self, py: Python<'_>) -> PyResult<isize> {
+        self.value.into_pyobject(py)?.hash()
+    }
+
+    fn __richcmp__(&self, other: &Bound<'_, PyAny>, op: CompareOp) -> PyResult<Py<PyAny>> {
+        let py = other.py();
+        let other = match other.downcast::<Self>() {
+            Ok(fixed) => fixed.get().value,
+            Err(_) => match other.extract::<i64>() {
+                Ok(other) => other,
+                Err(_) => return Ok(py.NotImplemented()),
+            },
+        };
+        op.matches(self.value.cmp(&other)).into_py_any(py)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("FixedInt({}, '{}')", self.value, self.width.name())
+    }
+}
+
+impl FixedInt {
+    /// Wraps a decoded integer, or returns `None` if `value` isn't one.
+    pub fn from_lize(value: &Value<'_>) -> Option<Self> {
+        let (value, width) = match value {
+            Value::SmallU8(u) => (*u as i64, Width::SmallU8),
+            Value::U8(u) => (*u as i64, Width::U8),
+            Value::I32(i) => (*i as i64, Width::I32),
+            Value::I64(i) => (*i, Width::I64),
+            _ => return None,
+        };
+        Some(Self { value, width })
+    }
+
+    pub fn to_lize(&self) -> Value<'static> {
+        match self.width {
+            Width::SmallU8 => Value::SmallU8(self.value as u8),
+            Width::U8 => Value::U8(self.value as u8),
+            Width::I32 => Value::I32(self.value as i32),
+            Width::I64 => Value::I64(self.value),
+        }
+    }
+}
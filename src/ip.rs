@@ -0,0 +1,109 @@
+//! Addresses and networks from `ipaddress`, which are stored as
+//! [`ext::IP_ADDRESS`] and [`ext::IP_NETWORK`] values holding their packed bytes.
+//!
+//! Only the four classes themselves are handled. Subclasses, like
+//! `IPv4Interface`, are left to the other conversions.
+
+use anyhow::Result;
+use lize_sys::{ext, Value};
+use pyo3::{exceptions, prelude::*, sync::GILOnceCell, types::PyType};
+
+/// `IPv4Address`, `IPv6Address`, `IPv4Network` and `IPv6Network`.
+fn classes(py: Python<'_>) -> PyResult<&[Py<PyType>; 4]> {
+    static CLASSES: GILOnceCell<[Py<PyType>; 4]> = GILOnceCell::new();
+    CLASSES.get_or_try_init(py, || {
+        let module = py.import("ipaddress")?;
+        let class = |name| -> PyResult<Py<PyType>> {
+            Ok(module.getattr(name)?.downcast_into::<PyType>()?.unbind())
+        };
+        Ok([
+            class("IPv4Address")?,
+            class("IPv6Address")?,
+            class("IPv4Network")?,
+            class("IPv6Network")?,
+        ])
+    })
+}
+
+/// An `ipaddress` address or network.
+#[derive(Debug)]
+pub struct Ip {
+    value: Py<PyAny>,
+    network: bool,
+}
+
+impl<'py> FromPyObject<'py> for Ip {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let cls = ob.get_type();
+        match classes(ob.py())?.iter().position(|c| cls.is(c)) {
+            Some(i) => Ok(Self {
+                value: ob.clone().unbind(),
+                network: i >= 2,
+            }),
+            None => Err(exceptions::PyTypeError::new_err(
+                "expected an IP address or network",
+            )),
+        }
+    }
+}
+
+impl<'py> IntoPyObject<'py> for Ip {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        Ok(self.value.into_bound(py))
+    }
+}
+
+impl Ip {
+    /// The IP version, `4` or `6`, followed by the packed address, and for
+    /// networks the prefix length.
+    pub fn to_lize(&self, py: Python<'_>) -> Result<Value<'static>> {
+        let ob = self.value.bind(py);
+        let address = match self.network {
+            true => ob.getattr("network_address")?,
+            false => ob.clone(),
+        };
+        if !address.getattr("scope_id").map_or(true, |id| id.is_none()) {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "can't serialize {}, since its scope ID isn't kept",
+                ob.repr()?
+            ))
+            .into());
+        }
+
+        let mut bytes = vec![ob.getattr("version")?.extract::<u8>()?];
+        bytes.extend(address.getattr("packed")?.extract::<Vec<u8>>()?);
+        let code = match self.network {
+            true => {
+                bytes.push(ob.getattr("prefixlen")?.extract()?);
+                ext::IP_NETWORK
+            }
+            false => ext::IP_ADDRESS,
+        };
+        Ok(Value::Ext(code, Box::new(Value::SliceLike(bytes))))
+    }
+}
+
+/// Rebuilds an address, or a network if `network` is set, from what
+/// [`Ip::to_lize`] wrote.
+pub fn from_lize(py: Python<'_>, bytes: &[u8], network: bool) -> PyResult<Py<PyAny>> {
+    let prefix_len = network as usize;
+    let packed = match bytes {
+        [4, rest @ ..] if rest.len() == 4 + prefix_len => rest,
+        [6, rest @ ..] if rest.len() == 16 + prefix_len => rest,
+        _ => return Err(exceptions::PyValueError::new_err("Invalid IP address")),
+    };
+
+    let ipaddress = py.import("ipaddress")?;
+    let (packed, prefix) = packed.split_at(packed.len() - prefix_len);
+    let address = ipaddress.call_method1("ip_address", (packed,))?;
+    if !network {
+        return Ok(address.unbind());
+    }
+    Ok(ipaddress
+        .call_method1("ip_network", ((address, prefix[0]),))?
+        .unbind())
+}
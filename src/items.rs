@@ -0,0 +1,273 @@
+//! `iter_deserialize` and `deserialize(..., into=callable)`, which decode the
+//! items of a top-level list one at a time, so a huge list can be processed
+//! without building all of it.
+
+use std::sync::Arc;
+
+use lize_sys::{ext, packed, Error, Value};
+use pyo3::{exceptions, prelude::*, types::PyBool, types::PyBytes, types::PyDict};
+
+use crate::{
+    error::into_py_err,
+    lize_to_py, open_payload,
+    options::{BytesAs, DecodeOptions},
+    view,
+};
+
+/// The bytes being iterated over, which nothing can write to in the meantime.
+enum Payload {
+    Bytes(Py<PyBytes>),
+    /// The payload of a frame, decrypted.
+    Opened(Vec<u8>),
+}
+
+impl Payload {
+    fn as_bytes<'a>(&'a self, py: Python<'a>) -> &'a [u8] {
+        match self {
+            Self::Bytes(bytes) => bytes.as_bytes(py),
+            Self::Opened(payload) => payload,
+        }
+    }
+}
+
+/// The items of the list a payload holds, read one at a time, whether it's
+/// written plainly or packed as [`ext::RUNS`] or [`ext::BITS`].
+enum Items {
+    /// A plain list, whose next item starts `offset` bytes into the payload.
+    Encoded { offset: usize, remaining: usize },
+    /// Runs, decoded up front since they're what's small, with how many
+    /// copies of the first one are left. Each copy is converted on its own.
+    Runs {
+        runs: std::vec::IntoIter<(Value<'static>, usize)>,
+        current: Option<(Value<'static>, usize)>,
+        remaining: usize,
+    },
+    /// Packed booleans, `len` of them, the next one at `next`.
+    Bits {
+        packed: Vec<u8>,
+        next: usize,
+        len: usize,
+    },
+}
+
+impl Items {
+    /// Reads the start of the list held by `payload`, or returns `None` if it
+    /// holds something else.
+    fn open(payload: &[u8], options: &DecodeOptions) -> lize_sys::Result<Option<Self>> {
+        options.expansion.start(payload.len());
+        if let Some((len, items)) = Value::read_vector_head(payload)? {
+            return Ok(Some(Self::Encoded {
+                offset: payload.len() - items.len(),
+                remaining: len,
+            }));
+        }
+        let Some((code @ (ext::RUNS | ext::BITS), rest)) = Value::read_ext_head(payload)? else {
+            return Ok(None);
+        };
+        let (value, rest) = Value::deserialize_first(rest, &options.core)?;
+        if !rest.is_empty() {
+            return Err(Error::TrailingBytes(rest.len()).into());
+        }
+
+        if code == ext::BITS {
+            let len = packed::bits(&value)?.len();
+            let packed = value.as_slice().unwrap_or_default().to_vec();
+            return Ok(Some(Self::Bits {
+                packed,
+                next: 0,
+                len,
+            }));
+        }
+        let runs: Vec<_> = packed::runs(&value)?
+            .into_iter()
+            .map(|(item, count)| (item.clone().into_owned(), count))
+            .collect();
+        Ok(Some(Self::Runs {
+            remaining: runs.iter().map(|(_, n)| n).sum(),
+            runs: runs.into_iter(),
+            current: None,
+        }))
+    }
+
+    /// How many items are left.
+    fn remaining(&self) -> usize {
+        match self {
+            Self::Encoded { remaining, .. } | Self::Runs { remaining, .. } => *remaining,
+            Self::Bits { next, len, .. } => len - next,
+        }
+    }
+
+    /// Converts the next item, reading it from `payload` if it's a plain list.
+    fn next(
+        &mut self,
+        py: Python<'_>,
+        payload: &[u8],
+        options: &DecodeOptions,
+    ) -> lize_sys::Result<Option<Py<PyAny>>> {
+        if self.remaining() == 0 {
+            return Ok(None);
+        }
+        if let Some(budget) = &options.max_alloc {
+            budget.reset();
+        }
+        let item = match self {
+            Self::Encoded { offset, remaining } => {
+                let (item, rest) = Value::deserialize_first(&payload[*offset..], &options.core)?;
+                let item = lize_to_py(py, &item, options)?;
+                *offset = payload.len() - rest.len();
+                *remaining -= 1;
+                item
+            }
+            Self::Runs {
+                runs,
+                current,
+                remaining,
+            } => {
+                if current.as_ref().is_none_or(|(_, left)| *left == 0) {
+                    *current = runs.next();
+                }
+                let (value, left) = current.as_mut().expect("items are left");
+                *left -= 1;
+                *remaining -= 1;
+                lize_to_py(py, value, options)?
+            }
+            Self::Bits { packed, next, .. } => {
+                let bit = packed::bits(&Value::Slice(packed))?.nth(*next);
+                *next += 1;
+                PyBool::new(py, bit.expect("items are left"))
+                    .to_owned()
+                    .into_any()
+                    .unbind()
+            }
+        };
+        Ok(Some(item))
+    }
+
+    /// How many bytes of `payload` follow a plain list once every item is
+    /// read, which should be none. Packed lists are checked when opened.
+    fn trailing(&self, payload: &[u8]) -> usize {
+        match self {
+            Self::Encoded { offset, .. } => payload.len() - offset,
+            _ => 0,
+        }
+    }
+}
+
+/// Iterates over the items of the list held by a payload, decoding each one
+/// when it's asked for. Returned by `iter_deserialize`.
+#[pyclass(module = "lize")]
+pub struct ItemIterator {
+    payload: Payload,
+    items: Items,
+    options: DecodeOptions,
+}
+
+#[pymethods]
+impl ItemIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<Py<PyAny>>> {
+        let payload = self.payload.as_bytes(py);
+        self.items
+            .next(py, payload, &self.options)
+            .map_err(into_py_err)
+    }
+
+    /// How many items are left.
+    fn __length_hint__(&self) -> usize {
+        self.items.remaining()
+    }
+}
+
+/// Returns an iterator over the items of the list held by `bytes`, which
+/// decodes each item only when it's reached.
+///
+/// Takes the same keyword arguments as `deserialize`, except `schema`.
+#[pyfunction]
+#[pyo3(signature = (bytes, **kwargs))]
+pub fn iter_deserialize(
+    bytes: &Bound<'_, PyAny>,
+    kwargs: Option<&Bound<'_, PyDict>>,
+) -> PyResult<ItemIterator> {
+    let py = bytes.py();
+    let mut options = DecodeOptions::from_kwargs(kwargs)?;
+    if options.schema.is_some() {
+        return Err(exceptions::PyTypeError::new_err(
+            "iter_deserialize can't be combined with a schema",
+        ));
+    }
+
+    let buffer = view::immutable(bytes)?;
+    if options.bytes_as == BytesAs::Memoryview {
+        options.view = Some(Arc::new(view::SourceView::new(&buffer, buffer.as_bytes())?));
+    }
+    let payload = match open_payload(buffer.as_bytes(), &options)? {
+        std::borrow::Cow::Owned(opened) => Payload::Opened(opened),
+        std::borrow::Cow::Borrowed(_) => Payload::Bytes(buffer.unbind()),
+    };
+
+    let Some(items) = Items::open(payload.as_bytes(py), &options).map_err(into_py_err)? else {
+        return Err(exceptions::PyTypeError::new_err(
+            "iter_deserialize needs a payload holding a list",
+        ));
+    };
+    Ok(ItemIterator {
+        payload,
+        items,
+        options,
+    })
+}
+
+/// Decodes the items of the list held by `payload` one at a time, calling
+/// `sink` with each before decoding the next, and returns how many there were.
+///
+/// If decoding an item or `sink` fails, the error is raised with a `delivered`
+/// attribute, the number of items `sink` took before it, and a note saying so
+/// where exceptions take notes.
+pub fn deliver(
+    py: Python<'_>,
+    payload: &[u8],
+    sink: &Bound<'_, PyAny>,
+    options: &DecodeOptions,
+) -> PyResult<usize> {
+    if options.schema.is_some() {
+        return Err(exceptions::PyTypeError::new_err(
+            "into=callable can't be combined with a schema",
+        ));
+    }
+    let Some(mut items) = Items::open(payload, options).map_err(into_py_err)? else {
+        return Err(exceptions::PyTypeError::new_err(
+            "into=callable needs a payload holding a list",
+        ));
+    };
+
+    let len = items.remaining();
+    for delivered in 0..len {
+        let mut next = || -> PyResult<()> {
+            let item = items.next(py, payload, options).map_err(into_py_err)?;
+            sink.call1((item.expect("items are left"),))?;
+            Ok(())
+        };
+        next().map_err(|err| stopped(py, err, delivered, len))?;
+    }
+    let trailing = items.trailing(payload);
+    if trailing > 0 {
+        let err = into_py_err(Error::TrailingBytes(trailing).into());
+        return Err(stopped(py, err, len, len));
+    }
+    Ok(len)
+}
+
+/// Tells `err` how many of the `len` items were delivered before it.
+fn stopped(py: Python<'_>, err: PyErr, delivered: usize, len: usize) -> PyErr {
+    let value = err.value(py);
+    // Exceptions that take no attributes or notes are raised as they are.
+    let _ = value.setattr("delivered", delivered);
+    if value.hasattr("add_note").unwrap_or(false) {
+        let note = format!("after delivering {} of {} items", delivered, len);
+        let _ = value.call_method1("add_note", (note,));
+    }
+    err
+}
@@ -1,15 +1,164 @@
+mod buffer;
+mod columns;
+mod compat;
+mod datetime;
+mod decoder;
+mod defaults;
+mod diagnose;
+mod error;
+mod estimate;
+mod extension;
+mod int_width;
+mod ip;
+mod items;
+mod metadata;
+mod options;
+mod patch;
+mod persistent;
+mod raw;
+mod record;
+mod redact;
+mod reduce;
+mod runnable;
+mod schema;
+mod small;
+mod stream;
+mod structured;
+mod subclass;
+#[cfg(feature = "arrow")]
+mod table;
+mod tune;
+mod typeddict;
+mod view;
+
 use core::str;
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{hash_map::Entry, BTreeSet, HashMap},
+    sync::Arc,
+};
 
 use anyhow::{Context, Result};
 
-use lize_sys::{SmallVec, Value, STACK_N};
+use lize_sys::{ext, frame, packed, SerializeOptions, SmallVec, Value, FORMAT_VERSION, STACK_N};
 use pyo3::{
     exceptions,
     prelude::*,
-    types::{PyBytes, PyDict, PyFunction, PyNone, PyString, PyTuple},
+    sync::GILOnceCell,
+    types::{
+        PyBool, PyBytes, PyDict, PyFrozenSet, PyInt, PyList, PyMapping, PyNone, PySet, PyString,
+        PyTuple, PyType,
+    },
     IntoPyObjectExt,
 };
 
+use error::{into_py_err, EncodeError};
+use options::{
+    BytesAs, DecodeOptions, EncodeOptions, OnBadRunnable, Progress, Quantize, RawSlices, Strings,
+};
+
+/// Declares a wrapper around instances of a Python class that isn't exposed by pyo3,
+/// such as `fractions.Fraction`, so it can be used as a [`PyValue`] variant.
+macro_rules! py_instance {
+    ($(#[$meta:meta])* $name:ident, $module:literal, $class:literal) => {
+        $(#[$meta])*
+        #[derive(Debug)]
+        pub struct $name(Py<PyAny>);
+
+        impl $name {
+            fn class(py: Python<'_>) -> PyResult<&Bound<'_, PyType>> {
+                static CLASS: GILOnceCell<Py<PyType>> = GILOnceCell::new();
+                CLASS.import(py, $module, $class)
+            }
+        }
+
+        impl<'py> FromPyObject<'py> for $name {
+            fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+                if ob.is_instance(Self::class(ob.py())?)? {
+                    Ok(Self(ob.clone().unbind()))
+                } else {
+                    Err(exceptions::PyTypeError::new_err(concat!(
+                        "expected ",
+                        $module,
+                        ".",
+                        $class
+                    )))
+                }
+            }
+        }
+
+        impl<'py> IntoPyObject<'py> for $name {
+            type Target = PyAny;
+            type Output = Bound<'py, PyAny>;
+            type Error = PyErr;
+
+            fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+                Ok(self.0.into_bound(py))
+            }
+        }
+    };
+}
+
+/// A `float` that converts to an `f32` and back without losing precision.
+#[derive(Debug)]
+pub struct ExactF32(f32);
+
+impl<'py> FromPyObject<'py> for ExactF32 {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let f = ob.extract::<f64>()?;
+        if (f as f32) as f64 == f {
+            Ok(Self(f as f32))
+        } else {
+            Err(exceptions::PyValueError::new_err(
+                "float doesn't fit in an f32",
+            ))
+        }
+    }
+}
+
+impl<'py> IntoPyObject<'py> for ExactF32 {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        Ok(self.0.into_pyobject(py)?.into_any())
+    }
+}
+
+py_instance!(
+    /// A `fractions.Fraction`.
+    Fraction,
+    "fractions",
+    "Fraction"
+);
+
+py_instance!(
+    /// A `collections.deque`.
+    Deque,
+    "collections",
+    "deque"
+);
+
+/// The function a [`Runnable`] runs, built on its first call and reused after.
+#[pyclass(module = "lize", frozen)]
+pub struct FunctionCache(GILOnceCell<Py<PyAny>>);
+
+impl FunctionCache {
+    fn new(py: Python<'_>, function: Option<Py<PyAny>>) -> PyResult<Py<Self>> {
+        let cell = GILOnceCell::new();
+        if let Some(function) = function {
+            let _ = cell.set(py, function);
+        }
+        Py::new(py, Self(cell))
+    }
+
+    fn get<'a>(&'a self, py: Python<'_>) -> Option<&'a Py<PyAny>> {
+        self.0.get(py)
+    }
+}
+
 #[pyclass]
 pub enum Runnable {
     /// Coming soon (tm)
@@ -19,9 +168,21 @@ pub enum Runnable {
         bytes: Py<PyAny>,
         name: Py<PyAny>,
         annotations: Py<PyAny>,
-        runnable: Option<Py<PyAny>>,
+        runnable: Py<FunctionCache>,
         defaults: Py<PyAny>,
+        /// `__kwdefaults__`, a dict or `None`.
+        kwdefaults: Py<PyAny>,
+        /// `__closure__`, a tuple of cells or `None`.
         closure: Py<PyAny>,
+        /// `__doc__`, a string or `None`.
+        doc: Py<PyAny>,
+        /// The function's source code, or `None` if it couldn't be retrieved.
+        source_code: Py<PyAny>,
+        /// `importlib.util.MAGIC_NUMBER` of the interpreter that compiled the
+        /// bytecode, or `None` if it's unknown.
+        magic: Py<PyAny>,
+        /// The most bytes each default or closure variable may encode to.
+        max_payload_bytes: Option<usize>,
     },
 }
 
@@ -32,8 +193,16 @@ impl Runnable {
         Self::JustInTime()
     }
 
+    /// Captures `fn`. With `max_payload_bytes`, encoding it raises
+    /// `EncodeError` if any of its defaults or closure variables would take
+    /// more than that many bytes.
     #[staticmethod]
-    pub fn from_pyfn(py: Python<'_>, r#fn: Py<PyFunction>) -> PyResult<Self> {
+    #[pyo3(signature = (r#fn, max_payload_bytes = None))]
+    pub fn from_pyfn(
+        py: Python<'_>,
+        r#fn: compat::Function,
+        max_payload_bytes: Option<usize>,
+    ) -> PyResult<Self> {
         let function = r#fn.bind(py);
         let marshal = py.import("marshal")?;
 
@@ -42,14 +211,25 @@ impl Runnable {
             .call1((function.getattr("__code__")?,))?
             .unbind();
 
+        // Functions typed into a REPL or built by `exec` have no source file.
+        let source = match py.import("inspect")?.call_method1("getsource", (function,)) {
+            Ok(source) => source.unbind(),
+            Err(_) => py.None(),
+        };
+
         Ok(Self::Marshal {
             marshal: marshal.unbind(),
             bytes,
             name: function.getattr("__name__")?.unbind(),
-            annotations: function.getattr("__annotations__")?.unbind(),
+            annotations: type_hints(function)?.unbind(),
             defaults: function.getattr("__defaults__")?.unbind(),
+            kwdefaults: function.getattr("__kwdefaults__")?.unbind(),
             closure: function.getattr("__closure__")?.unbind(),
-            runnable: None,
+            doc: function.getattr("__doc__")?.unbind(),
+            runnable: FunctionCache::new(py, None)?,
+            source_code: source,
+            magic: magic_number(py)?.unbind(),
+            max_payload_bytes,
         })
     }
 
@@ -61,36 +241,38 @@ impl Runnable {
         kwargs: Option<&Bound<'_, PyDict>>,
     ) -> PyResult<Py<PyAny>> {
         match self {
-            Runnable::JustInTime() => todo!(),
-            Runnable::Marshal {
-                marshal,
-                bytes,
-                name,
-                annotations,
-                defaults,
-                closure,
-                runnable,
-            } => {
-                if let Some(r) = runnable {
-                    return r.call(py, args, kwargs);
+            Runnable::JustInTime() => Err(exceptions::PyNotImplementedError::new_err(
+                "just-in-time runnables have no code yet",
+            )),
+            Runnable::Marshal { runnable, .. } => {
+                if let Some(function) = runnable.get().get(py) {
+                    return function.call(py, args, kwargs);
                 }
 
-                let code = marshal.getattr(py, "loads")?.call1(py, (bytes,))?;
-                let types = py.import("types")?;
-                let ft = types.getattr("FunctionType")?.call1((
-                    code,
-                    PyDict::new(py),
-                    name,
-                    defaults,
-                    closure,
-                ))?;
-                ft.setattr("__annotations__", annotations)?;
-
-                Ok(ft.call(args, kwargs)?.unbind())
+                let ft = self.build_function(&PyDict::new(py))?;
+                let function = runnable.get().0.get_or_init(py, || ft.unbind());
+                function.call(py, args, kwargs)
             }
         }
     }
 
+    /// Calls the function with `globals` as its global namespace, instead of
+    /// the empty one `run` uses. The dict itself is used, so the function sees
+    /// later changes to it, and `__builtins__` is added to it unless it's
+    /// already set, like `exec` does. The function `run` caches isn't touched.
+    #[pyo3(signature = (globals, *args, **kwargs))]
+    pub fn run_with_globals(
+        &self,
+        py: Python<'_>,
+        globals: &Bound<'_, PyDict>,
+        args: Py<PyTuple>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Py<PyAny>> {
+        self.build_function(globals)?
+            .unbind()
+            .call(py, args, kwargs)
+    }
+
     #[pyo3(name = "__call__", signature = (*args, **kwargs))]
     pub fn __call__(
         &self,
@@ -101,13 +283,99 @@ impl Runnable {
         self.run(py, args, kwargs)
     }
 
-    pub fn as_bytes(&self, py: Python<'_>) -> PyResult<Py<PyBytes>> {
+    /// The function's annotations: types when built from a function or
+    /// decoded with `resolve_annotations=True`, strings otherwise.
+    #[getter]
+    pub fn annotations(&self, py: Python<'_>) -> Py<PyAny> {
+        match self {
+            Self::JustInTime() => py.None(),
+            Self::Marshal { annotations, .. } => annotations.clone_ref(py),
+        }
+    }
+
+    /// The function's source code, kept so it can be read and recompiled on
+    /// another Python version, or `None` if it couldn't be retrieved.
+    pub fn source(&self, py: Python<'_>) -> Py<PyAny> {
+        match self {
+            Self::JustInTime() => py.None(),
+            Self::Marshal { source_code, .. } => source_code.clone_ref(py),
+        }
+    }
+
+    /// The sorted names the function needs from outside: the globals and
+    /// builtins its code, or code nested in it like comprehensions, loads or
+    /// assigns, and the variables its closure captured. Attribute names, like
+    /// `path` in `os.path`, aren't included.
+    pub fn requires(&self, py: Python<'_>) -> PyResult<Vec<String>> {
+        let code = self.load_code(py)?;
+        let get_instructions = py.import("dis")?.getattr("get_instructions")?;
+        let code_type = py.import("types")?.getattr("CodeType")?;
+
+        // Variables from an enclosing function are the top-level code's free
+        // variables; those of nested code come from it.
+        let freevars = code.getattr("co_freevars")?.extract::<Vec<String>>()?;
+        let mut names = BTreeSet::from_iter(freevars);
+        let mut stack = vec![code];
+        while let Some(code) = stack.pop() {
+            for instruction in get_instructions.call1((&code,))?.try_iter()? {
+                let instruction = instruction?;
+                let opname = instruction.getattr("opname")?.extract::<String>()?;
+                if matches!(
+                    opname.as_str(),
+                    "LOAD_GLOBAL" | "LOAD_NAME" | "STORE_GLOBAL" | "DELETE_GLOBAL"
+                ) {
+                    names.insert(instruction.getattr("argval")?.extract()?);
+                }
+            }
+            for constant in code.getattr("co_consts")?.try_iter()? {
+                let constant = constant?;
+                if constant.is_instance(&code_type)? {
+                    stack.push(constant);
+                }
+            }
+        }
+        Ok(names.into_iter().collect())
+    }
+
+    /// Encodes the function. With `portable=True`, raises `ValueError` unless
+    /// the source kept with it compiles, so it's sure to load on any Python
+    /// version rather than only the one that wrote it. `max_payload_bytes`
+    /// overrides the cap given to `from_pyfn`.
+    #[pyo3(signature = (portable = false, max_payload_bytes = None))]
+    pub fn as_bytes(
+        &self,
+        py: Python<'_>,
+        portable: bool,
+        max_payload_bytes: Option<usize>,
+    ) -> PyResult<Py<PyBytes>> {
         match self {
-            Self::JustInTime() => todo!(),
-            Self::Marshal { .. } => {
-                println!("working...");
-                let value = self.as_lize(py)?;
-                println!("ok");
+            Self::JustInTime() => Err(exceptions::PyNotImplementedError::new_err(
+                "just-in-time runnables can't be serialized yet",
+            )),
+            Self::Marshal {
+                name, source_code, ..
+            } => {
+                if portable {
+                    let unportable = |reason: &str| {
+                        exceptions::PyValueError::new_err(format!(
+                            "{}() can't be written portably: {}",
+                            name, reason
+                        ))
+                    };
+                    let source = source_code.extract::<Option<String>>(py)?.ok_or_else(|| {
+                        unportable(
+                            "its source can't be found, e.g. because it was typed \
+                             into a REPL; define it in a file or use lize.runnable.from_source",
+                        )
+                    })?;
+                    compile_source(py, &source).map_err(|err| {
+                        let error = unportable("its source isn't a single def, like a lambda's");
+                        error.set_cause(py, Some(err));
+                        error
+                    })?;
+                }
+
+                let value = self.as_lize(py, max_payload_bytes)?;
 
                 let mut buffer = SmallVec::<[u8; STACK_N]>::new();
                 value.serialize_into(&mut buffer)?;
@@ -119,146 +387,1488 @@ impl Runnable {
     }
 
     #[staticmethod]
-    pub fn from_bytes(py: Python<'_>, bytes: &[u8]) -> PyResult<Self> {
-        let value = Value::deserialize_from(bytes)?;
-        match value {
-            Value::Vector(vec) => {
-                if vec.len() != 3 {
-                    return Err(exceptions::PyValueError::new_err(
-                        "Invalid marshal'd object for lize",
-                    ));
-                }
-
-                let bytes = vec[0].as_slice().unwrap();
-                let name = str::from_utf8(vec[1].as_slice().unwrap())?;
-                let defaults = lize_to_py(py, &vec[2])?;
+    #[pyo3(signature = (bytes, resolve_annotations = false))]
+    pub fn from_bytes(py: Python<'_>, bytes: &[u8], resolve_annotations: bool) -> PyResult<Self> {
+        let value = Value::deserialize_from(bytes).map_err(into_py_err)?;
+        Self::from_lize(py, &value, resolve_annotations)
+    }
 
-                let marshal = py.import("marshal")?;
+    /// Shows the signature, the size of the bytecode and whether the function
+    /// has been built yet. Never raises, whatever the annotations are, and
+    /// never serializes the defaults or closure, which may be huge.
+    pub fn __repr__(&self, py: Python<'_>) -> String {
+        let Self::Marshal {
+            bytes,
+            name,
+            annotations,
+            runnable,
+            ..
+        } = self
+        else {
+            return String::from("Runnable(<jit>)");
+        };
 
-                Ok(Self::Marshal {
-                    marshal: marshal.unbind(),
-                    bytes: PyBytes::new(py, bytes).unbind().into_any(),
-                    name: PyString::new(py, name).unbind().into_any(),
-                    annotations: py.None(),
-                    runnable: None,
-                    defaults,
-                    closure: py.None(),
-                })
+        let signature = match annotations.bind(py).downcast_exact::<PyDict>() {
+            Ok(ann) => {
+                let key = |k: &Bound<'_, PyAny>| {
+                    k.extract::<String>().unwrap_or_else(|_| annotation_name(k))
+                };
+                let parameters = ann
+                    .iter()
+                    .filter(|(k, _)| key(k) != "return")
+                    .map(|(k, v)| format!("{}: {}", key(&k), annotation_name(&v)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let returns = match ann.get_item("return") {
+                    Ok(Some(v)) => annotation_name(&v),
+                    _ => String::from("?"),
+                };
+                format!("({}) -> {}", parameters, returns)
             }
-            _ => Err(exceptions::PyValueError::new_err("Invalid marshal")),
+            Err(_) => String::from("(...) -> ?"),
+        };
+        let size = match bytes.bind(py).len() {
+            Ok(len) => len.to_string(),
+            Err(_) => String::from("?"),
+        };
+        let state = match runnable.get().get(py) {
+            Some(_) => "cached",
+            None => "not cached",
+        };
+
+        format!(
+            "Runnable(<marshal> {}{}, {} bytes of code, {})",
+            name.bind(py),
+            signature,
+            size,
+            state
+        )
+    }
+
+    /// The length of `as_bytes()`, the encoded code, name, defaults and annotations.
+    pub fn __sizeof__(&self, py: Python<'_>) -> PyResult<usize> {
+        match self {
+            Self::JustInTime() => Ok(0),
+            Self::Marshal { .. } => Ok(self.as_bytes(py, false, None)?.as_bytes(py).len()),
         }
     }
+}
+
+/// A function whose bytecode couldn't be loaded, read with `on_bad_runnable="placeholder"`.
+#[pyclass(module = "lize", frozen)]
+pub struct BrokenRunnable {
+    /// The encoded function, as read by `Runnable.from_bytes`.
+    #[pyo3(get)]
+    data: Py<PyBytes>,
+    /// The exception raised while loading it.
+    #[pyo3(get)]
+    error: Py<PyAny>,
+}
+
+#[pymethods]
+impl BrokenRunnable {
+    /// Tries loading the function again, e.g. on a matching interpreter.
+    #[pyo3(signature = (resolve_annotations = false))]
+    pub fn load(&self, py: Python<'_>, resolve_annotations: bool) -> PyResult<Runnable> {
+        Runnable::from_bytes(py, self.data.as_bytes(py), resolve_annotations)
+    }
 
     pub fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
-        match self {
-            Self::JustInTime() => todo!(),
-            Self::Marshal {
-                marshal: _,
-                bytes: _,
+        Ok(format!(
+            "BrokenRunnable(<{} bytes>, error={})",
+            self.data.as_bytes(py).len(),
+            self.error.bind(py).repr()?
+        ))
+    }
+}
+
+/// The layout of the [`ext::RUNNABLE`] envelope written by [`Runnable::as_lize`].
+///
+/// - `0`: `[code, name, defaults]`, written before envelopes were versioned.
+/// - `1`: `[1, code, name, defaults, annotations]`, where `annotations` maps argument
+///   names to type names, or is `None` if there are none.
+/// - `2`: `[2, code, name, defaults, annotations, source, magic]`, adding the
+///   source code or `None`, and the `importlib.util.MAGIC_NUMBER` of the
+///   interpreter that compiled `code`.
+/// - `3`: `[3, fields]`, where `fields` maps `code`, `name`, `defaults`,
+///   `kwdefaults`, `closure` (the values of its cells), `annotations`, `doc`,
+///   `source` and `magic`, written in that order, to their values. Only `code`
+///   and `name` are required; the others are left out when the function doesn't
+///   have them, and names a reader doesn't know are skipped, so new fields
+///   don't need a new version.
+const RUNNABLE_ENVELOPE_VERSION: u8 = 3;
+
+/// The fields of an [`ext::RUNNABLE`] envelope, whatever its version.
+#[derive(Default)]
+struct Envelope<'v, 'a> {
+    code: &'a [u8],
+    name: &'a str,
+    defaults: Option<&'v Value<'a>>,
+    kwdefaults: Option<&'v Value<'a>>,
+    closure: Option<&'v Value<'a>>,
+    annotations: Option<&'v Value<'a>>,
+    doc: Option<&'a str>,
+    source: Option<&'a str>,
+    magic: Option<&'a [u8]>,
+}
+
+impl<'v, 'a> Envelope<'v, 'a> {
+    fn parse(value: &'v Value<'a>) -> PyResult<Self> {
+        let invalid = || exceptions::PyValueError::new_err("Invalid marshal'd object for lize");
+
+        let Value::Vector(fields) = value else {
+            return Err(invalid());
+        };
+        let version = match fields.first() {
+            // Legacy envelopes start with the code itself.
+            Some(Value::Slice(_)) => 0,
+            Some(Value::SmallU8(version)) => *version,
+            _ => return Err(invalid()),
+        };
+        if version > RUNNABLE_ENVELOPE_VERSION {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "Runnable envelope version {} is newer than the supported version {}",
+                version, RUNNABLE_ENVELOPE_VERSION
+            )));
+        }
+
+        let (code, name, defaults, annotations, source, magic) = match (version, fields.as_slice())
+        {
+            (0, [code, name, defaults]) => (code, name, defaults, None, None, None),
+            (1, [_, code, name, defaults, annotations]) => {
+                (code, name, defaults, Some(annotations), None, None)
+            }
+            (2, [_, code, name, defaults, annotations, source, magic]) => (
+                code,
                 name,
-                annotations,
-                ..
-            } => {
-                if let Ok(ann) = annotations.bind(py).downcast_exact::<PyDict>() {
-                    let py_ann = ann
-                        .iter()
-                        .filter(|(k, _)| k.extract::<&str>().unwrap() != "return")
-                        .map(|(k, v)| {
-                            format!(
-                                "{}: {}",
-                                k.extract::<&str>().unwrap_or("?"),
-                                v.getattr("__name__")
-                                    .map(|v| v.to_string())
-                                    .unwrap_or(String::from("?"))
-                            )
-                        })
-                        .collect::<Vec<_>>()
-                        .join(", ");
-
-                    let result = format!(
-                        "Runnable(<marshal> {}({}) -> {})",
-                        name.bind(py).to_string(),
-                        py_ann,
-                        ann.get_item("return")?
-                            .map(|v| v
-                                .getattr("__name__")
-                                .map(|v| v.to_string())
-                                .unwrap_or(String::from("?")))
-                            .unwrap_or(String::from("?")),
-                    );
-
-                    Ok(result)
-                } else {
-                    Ok(format!(
-                        "Runnable(<marshal> {}(...) -> ?)",
-                        name.bind(py).to_string()
-                    ))
+                defaults,
+                Some(annotations),
+                Some(source),
+                Some(magic),
+            ),
+            (3, [_, Value::HashMap(pairs)]) => return Self::parse_fields(pairs),
+            _ => return Err(invalid()),
+        };
+        let (Some(code), Some(name)) = (code.as_slice(), name.as_str()) else {
+            return Err(invalid());
+        };
+
+        Ok(Self {
+            code,
+            name,
+            defaults: present(defaults),
+            annotations: annotations.and_then(present),
+            source: source.map(optional_str).transpose()?.flatten(),
+            magic: magic
+                .and_then(present)
+                .map(|magic| magic.as_slice().ok_or_else(invalid))
+                .transpose()?,
+            ..Self::default()
+        })
+    }
+
+    /// Reads the map of a version 3 envelope.
+    fn parse_fields(pairs: &'v [(Value<'a>, Value<'a>)]) -> PyResult<Self> {
+        let invalid = |field| {
+            exceptions::PyValueError::new_err(format!("Invalid Runnable field {:?}", field))
+        };
+
+        let mut envelope = Self::default();
+        let (mut has_code, mut has_name) = (false, false);
+        for (key, value) in pairs {
+            let Some(key) = key.as_str() else {
+                return Err(invalid("?"));
+            };
+            match key {
+                "code" => {
+                    envelope.code = value.as_slice().ok_or_else(|| invalid(key))?;
+                    has_code = true;
+                }
+                "name" => {
+                    envelope.name = value.as_str().ok_or_else(|| invalid(key))?;
+                    has_name = true;
                 }
+                "defaults" => envelope.defaults = present(value),
+                "kwdefaults" => envelope.kwdefaults = present(value),
+                "closure" => envelope.closure = present(value),
+                "annotations" => envelope.annotations = present(value),
+                "doc" => envelope.doc = optional_str(value).map_err(|_| invalid(key))?,
+                "source" => envelope.source = optional_str(value).map_err(|_| invalid(key))?,
+                "magic" => envelope.magic = Some(value.as_slice().ok_or_else(|| invalid(key))?),
+                // Written by a newer version, which can add fields without
+                // bumping the envelope version.
+                _ => {}
             }
         }
+        if !has_code || !has_name {
+            return Err(exceptions::PyValueError::new_err(
+                "Runnable fields need at least a code and a name",
+            ));
+        }
+        Ok(envelope)
     }
 }
 
-impl<'a> Runnable {
-    fn as_lize(&'a self, py: Python<'a>) -> PyResult<Value<'a>> {
-        match self {
-            Self::JustInTime() => todo!(),
-            Self::Marshal {
-                marshal: _,
-                bytes,
-                name,
-                annotations: _,
-                runnable: _,
-                defaults,
-                closure: _,
-            } => Ok(Value::Vector(vec![
-                Value::Slice(bytes.extract::<&[u8]>(py)?),          // bytes
-                Value::Slice(name.extract::<&str>(py)?.as_bytes()), // name
-                py_to_lize(py, defaults.extract(py)?)?,             // defaults
-            ])),
+/// `value`, unless it's `None`.
+fn present<'v, 'a>(value: &'v Value<'a>) -> Option<&'v Value<'a>> {
+    match value {
+        Value::Optional(None) => None,
+        value => Some(value),
+    }
+}
+
+/// A string, or `None`.
+fn optional_str<'a>(value: &Value<'a>) -> PyResult<Option<&'a str>> {
+    present(value)
+        .map(|value| {
+            value.as_str().ok_or_else(|| {
+                exceptions::PyValueError::new_err("Invalid marshal'd object for lize")
+            })
+        })
+        .transpose()
+}
+
+impl Runnable {
+    /// Builds the [`ext::RUNNABLE`] envelope, checking the defaults and
+    /// closure variables against `max_payload_bytes`, or the cap the function
+    /// was captured with.
+    fn as_lize<'py>(
+        &self,
+        py: Python<'py>,
+        max_payload_bytes: Option<usize>,
+    ) -> PyResult<Value<'py>> {
+        let Self::Marshal {
+            bytes,
+            name,
+            annotations,
+            defaults,
+            kwdefaults,
+            closure,
+            doc,
+            source_code,
+            magic,
+            max_payload_bytes: cap,
+            ..
+        } = self
+        else {
+            return Err(exceptions::PyNotImplementedError::new_err(
+                "just-in-time runnables can't be serialized yet",
+            ));
+        };
+        let max_payload_bytes = max_payload_bytes.or(*cap);
+
+        let mut fields = vec![
+            (
+                "code",
+                Value::SliceLike(bytes.bind(py).downcast::<PyBytes>()?.as_bytes().to_vec()),
+            ),
+            ("name", Value::StrLike(name.extract::<String>(py)?)),
+        ];
+        let attributes = [
+            ("defaults", "defaults", defaults),
+            ("kwdefaults", "keyword defaults", kwdefaults),
+            ("closure", "closure variables", closure),
+        ];
+        for (field, what, attribute) in attributes {
+            if let Some(value) = self.convert_guarded(py, what, attribute, max_payload_bytes)? {
+                fields.push((field, value));
+            }
+        }
+
+        let mut pairs = vec![];
+        if let Ok(annotations) = annotations.bind(py).downcast::<PyDict>() {
+            for (k, v) in annotations {
+                pairs.push((
+                    Value::StrLike(k.extract()?),
+                    Value::StrLike(annotation_name(&v)),
+                ));
+            }
+        }
+        if !pairs.is_empty() {
+            fields.push(("annotations", Value::HashMap(pairs)));
+        }
+        // A class's docstring may not be a string; it's not worth failing for.
+        if let Ok(Some(doc)) = doc.extract::<Option<String>>(py) {
+            fields.push(("doc", Value::StrLike(doc)));
+        }
+        if let Some(source) = source_code.extract::<Option<String>>(py)? {
+            fields.push(("source", Value::StrLike(source)));
+        }
+        if let Some(magic) = magic.extract::<Option<Vec<u8>>>(py)? {
+            fields.push(("magic", Value::SliceLike(magic)));
+        }
+
+        Ok(Value::Vector(vec![
+            Value::SmallU8(RUNNABLE_ENVELOPE_VERSION),
+            Value::HashMap(
+                fields
+                    .into_iter()
+                    .map(|(field, value)| (Value::Str(field), value))
+                    .collect(),
+            ),
+        ]))
+    }
+
+    /// Converts `__defaults__`, `__kwdefaults__` or `__closure__`, or returns
+    /// `None` if the function doesn't have it.
+    ///
+    /// Functions among them are converted too, but not one whose own
+    /// attribute leads back to it, which would never end.
+    fn convert_guarded<'py>(
+        &self,
+        py: Python<'py>,
+        what: &str,
+        attribute: &Py<PyAny>,
+        max_payload_bytes: Option<usize>,
+    ) -> PyResult<Option<Value<'py>>> {
+        thread_local! {
+            /// The attributes of the functions being converted.
+            static CONVERTING: RefCell<Vec<usize>> = const { RefCell::new(vec![]) };
+        }
+
+        let Self::Marshal { name, .. } = self else {
+            return Ok(None);
+        };
+        // `None` is shared by every function without them.
+        if attribute.is_none(py) {
+            return Ok(None);
+        }
+        let id = attribute.as_ptr() as usize;
+        if CONVERTING.with_borrow(|ids| ids.contains(&id)) {
+            return Err(exceptions::PyTypeError::new_err(format!(
+                "can't serialize {}(), since its {} refer back to it",
+                name.bind(py),
+                what
+            )));
+        }
+
+        CONVERTING.with_borrow_mut(|ids| ids.push(id));
+        let result = self.convert_attribute(py, attribute.bind(py), max_payload_bytes);
+        CONVERTING.with_borrow_mut(|ids| ids.pop());
+        result.map(Some)
+    }
+
+    /// Converts the items of `__defaults__` or `__closure__` into a vector, or
+    /// `__kwdefaults__` into a map, naming the parameter or variable whose
+    /// value can't be converted or encodes to more than `max_payload_bytes`.
+    fn convert_attribute<'py>(
+        &self,
+        py: Python<'py>,
+        attribute: &Bound<'py, PyAny>,
+        max_payload_bytes: Option<usize>,
+    ) -> PyResult<Value<'py>> {
+        let Self::Marshal {
+            marshal,
+            bytes,
+            name,
+            ..
+        } = self
+        else {
+            return Ok(Value::Optional(None));
+        };
+        let convert = |value: Bound<'py, PyAny>| {
+            value
+                .extract::<PyValue>()
+                .map_err(anyhow::Error::from)
+                .and_then(|value| py_to_lize(py, value, &EncodeOptions::default()))
+        };
+        let fail = |what: String, cause: anyhow::Error| {
+            let err = exceptions::PyTypeError::new_err(format!(
+                "can't serialize {} in {}()",
+                what,
+                name.bind(py)
+            ));
+            err.set_cause(py, Some(into_py_err(cause)));
+            err
+        };
+        // Measured by writing nowhere, stopping as soon as the cap is passed.
+        let measure = |value: &Value<'_>, what: &dyn Fn() -> PyResult<String>| {
+            let Some(max) = max_payload_bytes else {
+                return Ok(());
+            };
+            let options = SerializeOptions::new().max_output_bytes(max);
+            match value.serialize_to(&options, &mut std::io::sink()) {
+                Ok(()) => Ok(()),
+                Err(err) if err.downcast_ref() == Some(&lize_sys::Error::OutputTooLarge(max)) => {
+                    Err(EncodeError::new_err(format!(
+                        "can't serialize {}(), since {} takes more than max_payload_bytes={}",
+                        name.bind(py),
+                        what()?,
+                        max
+                    )))
+                }
+                Err(err) => Err(into_py_err(err)),
+            }
+        };
+
+        if let Ok(kwdefaults) = attribute.downcast::<PyDict>() {
+            let mut pairs = vec![];
+            for (parameter, default) in kwdefaults {
+                let what = || Ok(format!("the default of parameter {}", parameter.repr()?));
+                match convert(default) {
+                    Ok(value) => {
+                        measure(&value, &what)?;
+                        pairs.push((Value::StrLike(parameter.extract()?), value));
+                    }
+                    Err(cause) => return Err(fail(what()?, cause)),
+                }
+            }
+            return Ok(Value::HashMap(pairs));
+        }
+
+        let Ok(items) = attribute.downcast::<PyTuple>() else {
+            return Ok(Value::Optional(None));
+        };
+        let code = || marshal.bind(py).call_method1("loads", (bytes,));
+        let mut converted = vec![];
+        for (i, item) in items.iter().enumerate() {
+            // Closures are tuples of cells, defaults tuples of values.
+            let cell = item.is_instance(&py.import("types")?.getattr("CellType")?)?;
+            let what = || -> PyResult<String> {
+                if cell {
+                    let variable = code()?.getattr("co_freevars")?.get_item(i)?;
+                    return Ok(format!("the closure variable {}", variable.repr()?));
+                }
+                // Defaults belong to the last positional parameters.
+                let code = code()?;
+                let argcount = code.getattr("co_argcount")?.extract::<usize>()?;
+                let parameter = code
+                    .getattr("co_varnames")?
+                    .get_item(argcount - items.len() + i)?;
+                Ok(format!("the default of parameter {}", parameter.repr()?))
+            };
+            let value = match cell {
+                true => item.getattr("cell_contents").map_err(anyhow::Error::from),
+                false => Ok(item),
+            };
+            match value.and_then(convert) {
+                Ok(value) => {
+                    measure(&value, &what)?;
+                    converted.push(value);
+                }
+                Err(cause) => return Err(fail(what()?, cause)),
+            }
+        }
+
+        Ok(Value::Vector(converted))
+    }
+
+    /// Reads a function back from the payload of an [`ext::RUNNABLE`] value.
+    ///
+    /// Annotations are kept as strings unless `resolve_annotations` is set.
+    fn from_lize(py: Python<'_>, value: &Value<'_>, resolve_annotations: bool) -> PyResult<Self> {
+        let envelope = Envelope::parse(value)?;
+        let options = DecodeOptions::default();
+        let to_py = |value: Option<&Value<'_>>| match value {
+            Some(value) => lize_to_py(py, value, &options).map_err(into_py_err),
+            None => Ok(py.None()),
+        };
+
+        let marshal = py.import("marshal")?;
+
+        // `__defaults__` is a tuple, which is written as a vector and read back as a list.
+        let mut defaults = to_py(envelope.defaults)?;
+        if let Ok(list) = defaults.downcast_bound::<PyList>(py) {
+            defaults = list.to_tuple().into_any().unbind();
+        }
+        let kwdefaults = to_py(envelope.kwdefaults)?;
+        // `__closure__` holds a cell for each variable.
+        let mut closure = to_py(envelope.closure)?;
+        if let Ok(list) = closure.downcast_bound::<PyList>(py) {
+            let cell = py.import("types")?.getattr("CellType")?;
+            let cells = list
+                .iter()
+                .map(|value| cell.call1((value,)))
+                .collect::<PyResult<Vec<_>>>()?;
+            closure = PyTuple::new(py, cells)?.into_any().unbind();
+        }
+        let annotations = to_py(envelope.annotations)?;
+        if resolve_annotations {
+            if let Ok(annotations) = annotations.downcast_bound::<PyDict>(py) {
+                for (k, v) in annotations.iter() {
+                    if let Some(resolved) = resolve_annotation(&v) {
+                        annotations.set_item(k, resolved)?;
+                    }
+                }
+            }
+        }
+        let string = |s: Option<&str>| {
+            s.map_or_else(|| py.None(), |s| PyString::new(py, s).unbind().into_any())
+        };
+
+        let runnable = Self::Marshal {
+            marshal: marshal.unbind(),
+            bytes: PyBytes::new(py, envelope.code).unbind().into_any(),
+            name: PyString::new(py, envelope.name).unbind().into_any(),
+            annotations,
+            runnable: FunctionCache::new(py, None)?,
+            defaults,
+            kwdefaults,
+            closure,
+            doc: string(envelope.doc),
+            source_code: string(envelope.source),
+            magic: envelope
+                .magic
+                .map_or_else(|| py.None(), |m| PyBytes::new(py, m).unbind().into_any()),
+            max_payload_bytes: None,
+        };
+
+        // Loaded here, so bytecode from another interpreter without the source to
+        // fall back on fails while decoding rather than on the first call.
+        runnable.load_code(py)?;
+        Ok(runnable)
+    }
+
+    /// Builds the function against `globals`, adding `__builtins__` to it if
+    /// it's missing.
+    fn build_function<'py>(&self, globals: &Bound<'py, PyDict>) -> PyResult<Bound<'py, PyAny>> {
+        let py = globals.py();
+        let Self::Marshal {
+            name,
+            annotations,
+            defaults,
+            kwdefaults,
+            closure,
+            doc,
+            ..
+        } = self
+        else {
+            return Err(exceptions::PyNotImplementedError::new_err(
+                "just-in-time runnables have no code yet",
+            ));
+        };
+
+        let code = self.load_code(py)?;
+        // Without `__builtins__`, functions built while another
+        // loaded function runs can't find them, e.g. its defaults.
+        if !globals.contains("__builtins__")? {
+            globals.set_item("__builtins__", py.import("builtins")?)?;
+        }
+        let types = py.import("types")?;
+        let ft = types
+            .getattr("FunctionType")?
+            .call1((code, globals, name, defaults, closure))?;
+        ft.setattr("__annotations__", annotations)?;
+        ft.setattr("__kwdefaults__", kwdefaults)?;
+        ft.setattr("__doc__", doc)?;
+        Ok(ft)
+    }
+
+    /// Loads the marshalled bytecode, or compiles the source instead if the
+    /// bytecode is from another Python version or fails to load.
+    fn load_code<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let Self::Marshal {
+            marshal,
+            bytes,
+            name,
+            source_code,
+            magic,
+            ..
+        } = self
+        else {
+            return Err(exceptions::PyNotImplementedError::new_err(
+                "just-in-time runnables have no code yet",
+            ));
+        };
+
+        let loaded = if magic.is_none(py) || magic.bind(py).eq(magic_number(py)?)? {
+            marshal.bind(py).call_method1("loads", (bytes,))
+        } else {
+            Err(exceptions::PyValueError::new_err(
+                "the bytecode was compiled by another Python version",
+            ))
+        };
+
+        match (loaded, source_code.extract::<Option<String>>(py)?) {
+            (Ok(code), _) => Ok(code),
+            (Err(err), Some(source)) => {
+                let code = compile_source(py, &source).inspect_err(|compile_err| {
+                    compile_err.set_cause(py, Some(err.clone_ref(py)))
+                })?;
+                PyErr::warn(
+                    py,
+                    &py.get_type::<exceptions::PyUserWarning>(),
+                    &std::ffi::CString::new(format!(
+                        "{}() was recompiled from its source, since its bytecode couldn't be loaded: {}",
+                        name, err
+                    ))?,
+                    1,
+                )?;
+                Ok(code)
+            }
+            (Err(err), None) => Err(err),
+        }
+    }
+}
+
+/// `importlib.util.MAGIC_NUMBER`, which changes whenever the bytecode does.
+fn magic_number(py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+    py.import("importlib.util")?.getattr("MAGIC_NUMBER")
+}
+
+/// Compiles the function defined by `source` without running the `def`, so
+/// its decorators, defaults and annotations aren't evaluated.
+fn compile_source<'py>(py: Python<'py>, source: &str) -> PyResult<Bound<'py, PyAny>> {
+    let ast = py.import("ast")?;
+    let dedented = py.import("textwrap")?.call_method1("dedent", (source,))?;
+    let tree = ast.call_method1("parse", (dedented,))?;
+
+    let definition = tree.getattr("body")?.get_item(0)?;
+    if !definition.is_instance(&ast.getattr("FunctionDef")?)?
+        && !definition.is_instance(&ast.getattr("AsyncFunctionDef")?)?
+    {
+        return Err(exceptions::PyValueError::new_err(
+            "only functions defined with def can be recompiled from their source",
+        ));
+    }
+    definition.setattr("decorator_list", PyList::empty(py))?;
+
+    // The function's code is a constant of the module defining it.
+    let name = definition.getattr("name")?;
+    let module = py
+        .import("builtins")?
+        .call_method1("compile", (tree, "<lize source>", "exec"))?;
+    for constant in module.getattr("co_consts")?.try_iter()? {
+        let constant = constant?;
+        if constant.hasattr("co_code")? && constant.getattr("co_name")?.eq(&name)? {
+            return Ok(constant);
         }
     }
+    Err(exceptions::PyValueError::new_err(format!(
+        "the source doesn't define {}()",
+        name
+    )))
+}
+
+/// The annotations of `function`, with string forward references evaluated
+/// where `typing.get_type_hints` can, or its raw `__annotations__` otherwise.
+fn type_hints<'py>(function: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyAny>> {
+    let py = function.py();
+    match py
+        .import("typing")?
+        .getattr("get_type_hints")?
+        .call1((function,))
+    {
+        Ok(hints) => Ok(hints),
+        Err(_) => function.getattr("__annotations__"),
+    }
+}
+
+/// How an annotation is shown and stored: a string as is, a class by its
+/// (qualified, unless builtin) name, and typing constructs such as
+/// `list[int]` or `typing.Optional[str]` by their `repr`, which evaluates
+/// back to them.
+fn annotation_name(annotation: &Bound<'_, PyAny>) -> String {
+    if let Ok(s) = annotation.extract::<String>() {
+        return s;
+    }
+
+    // Generic aliases such as `list[int]` pass as classes, but have an origin.
+    if annotation.is_instance_of::<PyType>() && !annotation.hasattr("__origin__").unwrap_or(false) {
+        let name = |attr| annotation.getattr(attr).and_then(|v| v.extract::<String>());
+        if let Ok(qualname) = name("__qualname__") {
+            return match name("__module__") {
+                Ok(module) if module != "builtins" => format!("{}.{}", module, qualname),
+                _ => qualname,
+            };
+        }
+    }
+
+    annotation
+        .repr()
+        .map(|v| v.to_string())
+        .unwrap_or(String::from("?"))
+}
+
+/// Evaluates an annotation written by [`annotation_name`] back into a type,
+/// looking the modules it names up among the loaded ones. Nothing is
+/// imported, so payloads can't trigger imports. Returns `None` if that fails,
+/// or if the string is more than names, attributes, subscripts, unions and
+/// literals.
+fn resolve_annotation(annotation: &Bound<'_, PyAny>) -> Option<Py<PyAny>> {
+    let py = annotation.py();
+    let source = annotation.extract::<String>().ok()?;
+
+    // Without parentheses or dunders, evaluating can't call arbitrary code.
+    let allowed = |c: char| c.is_alphanumeric() || "_.[], |'\"-".contains(c);
+    if !source.chars().all(allowed) || source.contains("__") {
+        return None;
+    }
+
+    let globals = PyDict::new(py);
+    let modules = py.import("sys").ok()?.getattr("modules").ok()?;
+    let names = source.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'));
+    for name in names.filter(|name| name.contains('.')) {
+        // A submodule that isn't loaded isn't an attribute of its parent
+        // either, so evaluating fails and the string is kept.
+        let root = name.split('.').next()?;
+        if let Ok(module) = modules.get_item(root) {
+            globals.set_item(root, module).ok()?;
+        }
+    }
+
+    py.import("builtins")
+        .ok()?
+        .getattr("eval")
+        .ok()?
+        .call1((source, globals))
+        .ok()
+        .map(Bound::unbind)
 }
 
 #[derive(Debug, FromPyObject, IntoPyObject)]
 pub enum PyValue {
+    /// Checked first, since `bool`s are also `int`s.
+    Bool(bool),
+    /// Checked before `int`s, which it converts to.
+    Fixed(Py<int_width::FixedInt>),
+    /// Checked before `str` and `bytes`, which would also accept their subclasses.
+    Subclass(subclass::Subclass),
     Str(String),
+    /// A `str` holding lone surrogates, which doesn't convert to a `String`.
+    Surrogates(Py<PyString>),
+    Bytes(Py<PyBytes>),
+    ByteBuffer(view::ByteBuffer),
     U8(u8),
     Int32(i32),
     Int(i64),
-    Float32(f32),
+    /// An `int` that doesn't fit in an `i64`.
+    BigInt(Py<PyInt>),
+    /// Checked before floats, since fractions can be converted to them.
+    Fraction(Fraction),
+    TimeDelta(compat::TimeDelta),
+    DateTime(datetime::DateTime),
+    Float32(ExactF32),
     Float(f64),
-    Bool(bool),
-    Vec(Vec<Py<PyAny>>),
     Map(Py<PyDict>),
+    /// Any other `collections.abc.Mapping`, e.g. `os.environ` or `MappingProxyType`.
+    /// Checked before `Vec` since custom mappings may also pass as sequences.
+    Mapping(Py<PyMapping>),
+    Set(Py<PySet>),
+    FrozenSet(Py<PyFrozenSet>),
+    /// Checked before `Vec`, since networks can be indexed like sequences.
+    Ip(ip::Ip),
+    /// A numpy array with named fields. Other arrays are left to `Object`.
+    StructuredArray(structured::StructuredArray),
+    /// Checked before `Vec`, which would drop its `maxlen`.
+    Deque(Deque),
+    Vec(Vec<Py<PyAny>>),
     Run(Py<Runnable>),
-    Callable(Py<PyFunction>),
+    Callable(compat::Function),
     #[allow(dead_code)]
     None(Py<PyNone>),
+    Delete(Py<patch::Delete>),
+    /// The id `persistent_id` gave for an object, see [`persistent::extract`].
+    Persistent(Py<persistent::PersistentId>),
+    /// Anything else, which is only written with `default` or `allow_reduce`.
+    Object(Py<PyAny>),
+}
+
+/// Serializes `value`. With `with_fingerprint=True`, returns the bytes along
+/// with a hash of the value's shape: the types and dict keys it holds.
+#[pyfunction]
+#[pyo3(signature = (value, *, with_fingerprint = false, **kwargs))]
+pub fn serialize<'py>(
+    py: Python<'py>,
+    value: &Bound<'py, PyAny>,
+    with_fingerprint: bool,
+    kwargs: Option<&Bound<'_, PyDict>>,
+) -> PyResult<Bound<'py, PyAny>> {
+    if !with_fingerprint && kwargs.is_none_or(|kwargs| kwargs.is_empty()) && !defaults::any() {
+        if let Some(bytes) = small::serialize(value) {
+            return Ok(bytes.into_any());
+        }
+        return Ok(encode(py, value.extract()?, &EncodeOptions::default())?
+            .0
+            .into_any());
+    }
+
+    let mut options = EncodeOptions::from_kwargs(kwargs)?;
+    options.fingerprint = with_fingerprint;
+    let (bytes, _, fingerprint) = encode(py, persistent::extract(value, &options)?, &options)?;
+    match fingerprint {
+        Some(fingerprint) => Ok((bytes, fingerprint).into_pyobject(py)?.into_any()),
+        None => Ok(bytes.into_any()),
+    }
 }
 
+/// Like `serialize`, but also returns a dict with the `size` of the bytes and
+/// the `payload_size` of the value inside them, which is smaller when framed.
 #[pyfunction]
-pub fn serialize(py: Python<'_>, value: PyValue) -> Result<Bound<'_, PyBytes>> {
-    let lz = py_to_lize(py, value)?;
+#[pyo3(signature = (value, **kwargs))]
+pub fn serialize_with_info<'py>(
+    py: Python<'py>,
+    value: &Bound<'py, PyAny>,
+    kwargs: Option<&Bound<'_, PyDict>>,
+) -> PyResult<(Bound<'py, PyBytes>, Bound<'py, PyDict>)> {
+    let options = EncodeOptions::from_kwargs(kwargs)?;
+    let (bytes, payload_size, _) = encode(py, persistent::extract(value, &options)?, &options)?;
+
+    let info = PyDict::new(py);
+    info.set_item("size", bytes.as_bytes().len())?;
+    info.set_item("payload_size", payload_size)?;
+    Ok((bytes, info))
+}
+
+/// Serializes `value`, returning the bytes, the length of the payload before
+/// it's framed and, if `options.fingerprint` is set, the fingerprint of its
+/// shape.
+fn encode<'py>(
+    py: Python<'py>,
+    value: PyValue,
+    options: &EncodeOptions,
+) -> PyResult<(Bound<'py, PyBytes>, usize, Option<u64>)> {
+    let (buf, payload_size, fingerprint) = encode_buffer(py, value, options)?;
+    Ok((PyBytes::new(py, &buf), payload_size, fingerprint))
+}
+
+/// Like [`encode`], leaving the bytes in the buffer they were written to.
+fn encode_buffer(
+    py: Python<'_>,
+    value: PyValue,
+    options: &EncodeOptions,
+) -> PyResult<(SmallVec<[u8; STACK_N]>, usize, Option<u64>)> {
+    let mut lz = py_to_lize(py, value, options).map_err(into_py_err)?;
+    // Taken before a schema turns the top-level dict into a list.
+    let fingerprint = match options.fingerprint {
+        true => Some(lz.shape_fingerprint().map_err(into_py_err)?),
+        false => None,
+    };
+    if let Some(schema) = &options.schema {
+        lz = schema.encode(lz).map_err(into_py_err)?;
+    }
     let mut buf = SmallVec::<[u8; STACK_N]>::new();
-    lz.serialize_into(&mut buf)?;
+    tune::reserve(&mut buf);
+    // The converted value holds no Python objects, so threads can write it
+    // without the GIL.
+    if let Some(callback) = &options.progress.callback {
+        if options.parallel {
+            return Err(exceptions::PyTypeError::new_err(
+                "progress can't be combined with parallel=True",
+            ));
+        }
+        let write = |buf: &mut SmallVec<[u8; STACK_N]>| {
+            let report = Progress::report(callback);
+            lz.serialize_with_progress(&options.core, buf, options.progress.interval(), report)
+        };
+        match options.release_gil {
+            true => py.allow_threads(|| write(&mut buf)),
+            false => write(&mut buf),
+        }
+    } else if options.parallel {
+        py.allow_threads(|| lz.serialize_parallel(&options.core, &mut buf))
+    } else if options.release_gil {
+        py.allow_threads(|| lz.serialize_with(&options.core, &mut buf))
+    } else {
+        lz.serialize_with(&options.core, &mut buf)
+    }
+    .map_err(into_py_err)?;
+
+    if options.is_framed() {
+        let framed = seal_payload(py, &buf, options)?;
+        return Ok((SmallVec::from_vec(framed), buf.len(), fingerprint));
+    }
+
+    let payload_size = buf.len();
+    Ok((buf, payload_size, fingerprint))
+}
+
+/// Wraps `payload` in a frame with the key, schema and metadata in `options`.
+fn seal_payload(py: Python<'_>, payload: &[u8], options: &EncodeOptions) -> PyResult<Vec<u8>> {
+    let key = options.key.as_ref().map(|key| &key.0);
+    let schema = options.schema.as_deref();
+    match (metadata::build(py, options)?, schema) {
+        (Some(metadata), _) => frame::seal_with_metadata(payload, key, schema, &metadata),
+        (None, Some(schema)) => frame::seal_with_schema(payload, key, schema),
+        (None, None) => frame::seal(payload, key),
+    }
+    .map_err(into_py_err)
+}
+
+/// Hashes what `serialize(value, canonical=True)` returns, without building it.
+#[pyfunction]
+#[pyo3(signature = (value, algorithm = "blake3", **kwargs))]
+pub fn content_hash<'py>(
+    py: Python<'py>,
+    value: PyValue,
+    algorithm: &str,
+    kwargs: Option<&Bound<'_, PyDict>>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let options = EncodeOptions::from_kwargs(kwargs)?;
+    if options.key.is_some() {
+        return Err(exceptions::PyTypeError::new_err(
+            "content_hash doesn't take a key, since encrypted payloads differ every time",
+        ));
+    }
+    if options.schema.is_some() {
+        return Err(exceptions::PyTypeError::new_err(
+            "content_hash doesn't take a schema",
+        ));
+    }
+
+    let sha256 = match algorithm {
+        "blake3" => false,
+        "sha256" => true,
+        _ => {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "unknown algorithm {:?}, expected \"blake3\" or \"sha256\"",
+                algorithm
+            )))
+        }
+    };
+
+    let lz = py_to_lize(py, value, &options).map_err(into_py_err)?;
+    let hash = |lz: &Value| -> Result<Vec<u8>> {
+        if sha256 {
+            let mut hasher = sha2::Sha256::default();
+            lz.hash_canonical(&mut hasher)?;
+            Ok(sha2::Digest::finalize(hasher).to_vec())
+        } else {
+            let mut hasher = blake3::Hasher::new();
+            lz.hash_canonical(&mut hasher)?;
+            Ok(hasher.finalize().as_bytes().to_vec())
+        }
+    };
+
+    let digest = if options.release_gil {
+        py.allow_threads(|| hash(&lz))
+    } else {
+        hash(&lz)
+    }
+    .map_err(into_py_err)?;
 
-    let bytes = PyBytes::new(py, &buf);
-    Ok(bytes)
+    Ok(PyBytes::new(py, &digest))
 }
 
+/// Whether `a` and `b` hold the same values, compared with
+/// [`Value::logical_eq`] rather than byte for byte, and without building any
+/// Python objects.
 #[pyfunction]
-pub fn deserialize(py: Python<'_>, bytes: &[u8]) -> Result<Py<PyAny>> {
-    let lize_value = Value::deserialize_from(bytes)?;
-    let value = lize_to_py(py, &lize_value)?;
+#[pyo3(signature = (a, b, **kwargs))]
+pub fn equal(
+    a: &Bound<'_, PyAny>,
+    b: &Bound<'_, PyAny>,
+    kwargs: Option<&Bound<'_, PyDict>>,
+) -> PyResult<bool> {
+    let options = DecodeOptions::from_kwargs(kwargs)?;
+    let (a, b) = (view::Buffer::get(a)?, view::Buffer::get(b)?);
+    let a = open_payload(a.as_bytes(), &options)?;
+    let b = open_payload(b.as_bytes(), &options)?;
+    Value::logical_eq_encoded(&a, &b, &options.core).map_err(into_py_err)
+}
+
+/// Whether `data` holds exactly one well-formed value, checked without
+/// building it.
+#[pyfunction]
+pub fn validate_bytes(data: &Bound<'_, PyAny>) -> PyResult<bool> {
+    let buffer = view::Buffer::get(data)?;
+    Ok(Value::validate_encoded(buffer.as_bytes()).is_ok())
+}
+
+/// Deserializes `bytes`. With `into=`, a dict is updated with the entries of
+/// the dict held by `bytes` and returned, and a callable is called with each
+/// item of the list held by `bytes` as it's decoded, returning how many there
+/// were.
+#[pyfunction]
+#[pyo3(signature = (bytes, *, into = None, **kwargs))]
+pub fn deserialize(
+    py: Python<'_>,
+    bytes: &Bound<'_, PyAny>,
+    into: Option<&Bound<'_, PyAny>>,
+    kwargs: Option<&Bound<'_, PyDict>>,
+) -> PyResult<Py<PyAny>> {
+    let mut options = DecodeOptions::from_kwargs(kwargs)?;
+    let buffer = view::immutable(bytes)?;
+    if options.bytes_as == BytesAs::Memoryview {
+        options.view = Some(Arc::new(view::SourceView::new(&buffer, buffer.as_bytes())?));
+    }
+
+    let payload = open_payload(buffer.as_bytes(), &options)?;
+    let Some(into) = into else {
+        return decode_payload(py, &payload, &options);
+    };
+    if let Ok(dict) = into.downcast::<PyDict>() {
+        let value = decode_value(py, &payload, &options)?;
+        if options.maps_as_pairs || options.record || !matches!(value, Value::HashMap(_)) {
+            return Err(exceptions::PyTypeError::new_err(
+                "into=dict needs a payload holding a dict, and can't be combined with maps_as_pairs or record",
+            ));
+        }
+        return lize_to_py_with(py, &value, &options, None, Some(dict.clone()))
+            .map_err(into_py_err);
+    }
+    if into.is_callable() {
+        return items::deliver(py, &payload, into, &options)?.into_py_any(py);
+    }
+    Err(exceptions::PyTypeError::new_err(format!(
+        "into must be a dict or a callable, got {}",
+        into.get_type().name()?
+    )))
+}
+
+/// Deserializes the first value in `bytes`, returning it and the bytes after it.
+#[pyfunction]
+#[pyo3(signature = (bytes, **kwargs))]
+pub fn deserialize_first<'py>(
+    py: Python<'py>,
+    bytes: &[u8],
+    kwargs: Option<&Bound<'_, PyDict>>,
+) -> PyResult<(Py<PyAny>, Bound<'py, PyBytes>)> {
+    let options = DecodeOptions::from_kwargs(kwargs)?;
+
+    // A frame says how long it is, so only its payload needs decoding.
+    if options.is_framed() {
+        let (payload, rest) = open_frame(bytes, &options)?;
+        let value = decode_payload(py, &payload, &options)?;
+        return Ok((value, PyBytes::new(py, rest)));
+    }
+
+    estimate::check(bytes, &options)?;
+    options.expansion.start(bytes.len());
+    let (lize_value, rest) = if options.release_gil {
+        py.allow_threads(|| Value::deserialize_first(bytes, &options.core))
+    } else {
+        Value::deserialize_first(bytes, &options.core)
+    }
+    .map_err(into_py_err)?;
+    let value = lize_to_py(py, &lize_value, &options).map_err(into_py_err)?;
+    Ok((value, PyBytes::new(py, rest)))
+}
+
+/// The payload of `bytes`: the bytes themselves, or the frame they hold if a
+/// key or schema is set.
+fn open_payload<'b>(bytes: &'b [u8], options: &DecodeOptions) -> PyResult<Cow<'b, [u8]>> {
+    if !options.is_framed() {
+        return Ok(Cow::Borrowed(bytes));
+    }
+
+    let (payload, rest) = open_frame(bytes, options)?;
+    if !rest.is_empty() {
+        return Err(exceptions::PyValueError::new_err(
+            "unexpected bytes after the frame",
+        ));
+    }
+    Ok(payload)
+}
+
+/// Reads the frame at the start of `bytes` with the key and schema in `options`,
+/// returning its payload and the bytes after it.
+fn open_frame<'b>(bytes: &'b [u8], options: &DecodeOptions) -> PyResult<(Cow<'b, [u8]>, &'b [u8])> {
+    let key = options.key.as_ref().map(|key| &key.0);
+    match &options.schema {
+        Some(schema) => frame::open_with_schema(bytes, key, schema),
+        None => frame::open(bytes, key),
+    }
+    .map_err(into_py_err)
+}
+
+/// Parses a payload, without the GIL if the options say so.
+fn decode_value<'p>(
+    py: Python<'_>,
+    payload: &'p [u8],
+    options: &DecodeOptions,
+) -> PyResult<Value<'p>> {
+    estimate::check(payload, options)?;
+    options.expansion.start(payload.len());
+    let Some(schema) = &options.schema else {
+        return parse_value(py, payload, options);
+    };
+
+    // `keys` would pick entries out of the list of values instead.
+    if options.core.keys.is_some() {
+        return Err(exceptions::PyTypeError::new_err(
+            "keys can't be combined with a schema",
+        ));
+    }
+    schema
+        .decode(parse_value(py, payload, options)?)
+        .map_err(into_py_err)
+}
+
+fn parse_value<'p>(
+    py: Python<'_>,
+    payload: &'p [u8],
+    options: &DecodeOptions,
+) -> PyResult<Value<'p>> {
+    let parse = || match &options.progress.callback {
+        Some(callback) => Value::deserialize_with_progress(
+            payload,
+            &options.core,
+            options.progress.interval(),
+            Progress::report(callback),
+        ),
+        None => Value::deserialize_with(payload, &options.core),
+    };
+    if options.release_gil {
+        py.allow_threads(parse)
+    } else {
+        parse()
+    }
+    .map_err(into_py_err)
+}
+
+fn decode_payload(py: Python<'_>, payload: &[u8], options: &DecodeOptions) -> PyResult<Py<PyAny>> {
+    let lize_value = decode_value(py, payload, options)?;
+    let value = lize_to_py(py, &lize_value, options).map_err(into_py_err)?;
     Ok(value)
 }
 
-fn py_to_lize(py: Python<'_>, value: PyValue) -> Result<Value<'_>> {
+/// A `list` or `dict` whose items are still being converted.
+struct PendingPy<'py> {
+    is_map: bool,
+    /// Whether a list may be written as runs, see [`encode_runs`].
+    rle: bool,
+    /// Converted items, with keys and values alternating for maps.
+    done: Vec<Value<'py>>,
+    rest: std::vec::IntoIter<PyValue>,
+}
+
+impl<'py> PendingPy<'py> {
+    fn finish(self) -> Value<'py> {
+        if !self.is_map {
+            return match self.rle {
+                true => encode_runs(self.done),
+                false => Value::Vector(self.done),
+            };
+        }
+
+        let mut items = self.done.into_iter();
+        let mut pairs = Vec::with_capacity(items.len() / 2);
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+            pairs.push((key, value));
+        }
+        Value::HashMap(pairs)
+    }
+}
+
+/// Converts a Python value, keeping nested containers on an explicit stack
+/// so deeply nested ones don't overflow the call stack.
+fn py_to_lize<'py>(py: Python<'py>, value: PyValue, options: &EncodeOptions) -> Result<Value<'py>> {
+    let mut stack: Vec<PendingPy<'_>> = vec![];
+    let mut next = value;
+
+    loop {
+        let mut value = match container_items(py, &next, options)? {
+            Some((is_map, items)) => {
+                let mut rest = items.into_iter();
+                match rest.next() {
+                    Some(item) => {
+                        stack.push(PendingPy {
+                            is_map,
+                            rle: options.rle,
+                            done: Vec::with_capacity(rest.len() + 1),
+                            rest,
+                        });
+                        next = item;
+                        continue;
+                    }
+                    None => PendingPy {
+                        is_map,
+                        rle: options.rle,
+                        done: vec![],
+                        rest,
+                    }
+                    .finish(),
+                }
+            }
+            None => scalar_to_lize(py, next, options)?,
+        };
+
+        // Hand the value to its parents, closing every container it completes.
+        loop {
+            let Some(parent) = stack.last_mut() else {
+                return Ok(value);
+            };
+            parent.done.push(value);
+
+            if let Some(item) = parent.rest.next() {
+                next = item;
+                break;
+            }
+            value = stack.pop().map(PendingPy::finish).unwrap();
+        }
+    }
+}
+
+/// Converts the keys of one map, writing them as `str(key)` with `json_keys`.
+struct MapKeys<'py> {
+    /// Each key written so far by its string, to catch two keys that become
+    /// the same string. `None` unless `json_keys` is set.
+    seen: Option<Bound<'py, PyDict>>,
+}
+
+impl<'py> MapKeys<'py> {
+    fn new(py: Python<'py>, options: &EncodeOptions) -> Self {
+        Self {
+            seen: options.json_keys.then(|| PyDict::new(py)),
+        }
+    }
+
+    fn convert(&mut self, key: &Bound<'py, PyAny>, options: &EncodeOptions) -> PyResult<PyValue> {
+        let Some(seen) = &self.seen else {
+            return persistent::extract(key, options);
+        };
+
+        let text = key.str()?;
+        if let Some(other) = seen.get_item(&text)? {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "keys {} and {} are both written as {} with json_keys=True",
+                other.repr()?,
+                key.repr()?,
+                text.repr()?
+            )));
+        }
+        seen.set_item(&text, key)?;
+
+        // Strings, including subclasses, are kept as they are.
+        match key.is_instance_of::<PyString>() {
+            true => key.extract(),
+            false => text.extract(),
+        }
+    }
+}
+
+/// Returns whether `value` is a map and its items, with keys and values
+/// alternating, or `None` if it isn't a container.
+fn container_items(
+    py: Python<'_>,
+    value: &PyValue,
+    options: &EncodeOptions,
+) -> Result<Option<(bool, Vec<PyValue>)>> {
+    Ok(Some(match value {
+        // Subclasses may override `items`, like Django's `QueryDict`, so only
+        // exact dicts are read directly.
+        PyValue::Map(m) if m.bind(py).is_exact_instance_of::<PyDict>() => {
+            let binding = m.bind(py);
+            let mut items = Vec::with_capacity(binding.len() * 2);
+            let mut keys = MapKeys::new(py, options);
+
+            for (k, v) in binding {
+                items
+                    .push(keys.convert(&k, options).with_context(|| {
+                        format!("Failed to extract key for dict {:?}", binding)
+                    })?);
+                items.push(
+                    persistent::extract(&v, options).with_context(|| {
+                        format!("Failed to extract value for dict {:?}", binding)
+                    })?,
+                );
+            }
+
+            (true, items)
+        }
+        PyValue::Map(m) => (true, mapping_items(m.bind(py), options)?),
+        PyValue::Mapping(m) => (true, mapping_items(m.bind(py), options)?),
+        PyValue::Object(obj) if is_duck_mapping(obj.bind(py), options)? => {
+            (true, mapping_items(obj.bind(py), options)?)
+        }
+        // Lists of booleans are packed into bits, so they're written as a scalar,
+        // unless they're mostly long runs.
+        PyValue::Vec(v) if is_bool_mask(py, v) && !(options.rle && is_sparse_mask(py, v)) => {
+            return Ok(None)
+        }
+        PyValue::Vec(v) => (
+            false,
+            v.iter()
+                .map(|item| persistent::extract(item.bind(py), options))
+                .collect::<PyResult<_>>()?,
+        ),
+        _ => return Ok(None),
+    }))
+}
+
+/// The keys and values of any mapping, alternating, read through its `items`,
+/// or through `keys` and `__getitem__` if it has no `items`.
+fn mapping_items(mapping: &Bound<'_, PyAny>, options: &EncodeOptions) -> Result<Vec<PyValue>> {
+    let py = mapping.py();
+    let mut items = vec![];
+    let mut keys = MapKeys::new(py, options);
+    let context = || format!("Failed to extract item for mapping {:?}", mapping);
+
+    if mapping.hasattr("items")? {
+        for item in mapping.call_method0("items")?.try_iter()? {
+            let (k, v) = item?
+                .extract::<(Bound<PyAny>, Bound<PyAny>)>()
+                .with_context(context)?;
+            items.push(keys.convert(&k, options)?);
+            items.push(persistent::extract(&v, options)?);
+        }
+    } else {
+        for k in mapping.call_method0("keys")?.try_iter()? {
+            let k = k?;
+            let v = mapping.get_item(&k).with_context(context)?;
+            items.push(keys.convert(&k, options)?);
+            items.push(persistent::extract(&v, options)?);
+        }
+    }
+
+    Ok(items)
+}
+
+/// Whether `obj` has `keys` and `__getitem__` like a mapping, without being a
+/// `collections.abc.Mapping`, and should be written as one. `default` and
+/// [`extension::register_extension`] take precedence.
+fn is_duck_mapping(obj: &Bound<'_, PyAny>, options: &EncodeOptions) -> PyResult<bool> {
+    if options.default.is_some() || extension::is_registered(obj)? {
+        return Ok(false);
+    }
+    let cls = obj.get_type();
+    Ok(cls.hasattr("keys")? && cls.hasattr("__getitem__")?)
+}
+
+/// The fewest booleans a list needs before packing them saves space.
+const MIN_BITS: usize = 8;
+
+/// Whether a list holds enough booleans, and only booleans, to be packed.
+fn is_bool_mask(py: Python<'_>, items: &[Py<PyAny>]) -> bool {
+    items.len() >= MIN_BITS
+        && items
+            .iter()
+            .all(|item| item.bind(py).is_instance_of::<PyBool>())
+}
+
+/// Whether the runs of a boolean mask take fewer bytes than its packed bits,
+/// counting two bytes per run.
+fn is_sparse_mask(py: Python<'_>, items: &[Py<PyAny>]) -> bool {
+    let truthy = |item: &Py<PyAny>| item.bind(py).is_truthy().unwrap_or(false);
+    let runs = 1 + items
+        .windows(2)
+        .filter(|pair| truthy(&pair[0]) != truthy(&pair[1]))
+        .count();
+    runs * 2 < items.len() / 8
+}
+
+/// Collapses runs of equal adjacent items into an [`ext::RUNS`] value, if
+/// there are fewer than half as many runs as items.
+fn encode_runs(items: Vec<Value<'_>>) -> Value<'_> {
+    let same = |a: &Value, b: &Value| a.cmp_structural(b) == std::cmp::Ordering::Equal;
+    let runs = 1 + items
+        .windows(2)
+        .filter(|pair| !same(&pair[0], &pair[1]))
+        .count();
+    if items.is_empty() || runs * 2 >= items.len() {
+        return Value::Vector(items);
+    }
+
+    let mut counted: Vec<(Value, usize)> = Vec::with_capacity(runs);
+    for item in items {
+        match counted.last_mut() {
+            Some((last, count)) if same(last, &item) => *count += 1,
+            _ => counted.push((item, 1)),
+        }
+    }
+
+    let mut runs = Vec::with_capacity(counted.len() * 2);
+    for (item, count) in counted {
+        runs.push(item);
+        runs.push(match count {
+            0..=235 => Value::SmallU8(count as u8),
+            236..=255 => Value::U8(count as u8),
+            _ => match i32::try_from(count) {
+                Ok(count) => Value::I32(count),
+                Err(_) => Value::I64(count as i64),
+            },
+        });
+    }
+    Value::Ext(ext::RUNS, Box::new(Value::Vector(runs)))
+}
+
+/// Packs booleans into an [`ext::BITS`] value.
+fn pack_bits<'py>(py: Python<'py>, items: &[Py<PyAny>]) -> Value<'py> {
+    let mut bytes = vec![0; 1 + items.len().div_ceil(8)];
+    bytes[0] = ((8 - items.len() % 8) % 8) as u8;
+    for (i, item) in items.iter().enumerate() {
+        if item.bind(py).is_truthy().unwrap_or(false) {
+            bytes[1 + i / 8] |= 1 << (i % 8);
+        }
+    }
+    Value::Ext(ext::BITS, Box::new(Value::SliceLike(bytes)))
+}
+
+/// Expands the `[item, count, ...]` of an [`ext::RUNS`] value into a list,
+/// growing it as the runs expand rather than trusting the counts up front.
+/// Containers are converted once per copy, so the copies aren't shared, and
+/// each copy counts against `max_expansion`.
+fn decode_runs(py: Python<'_>, payload: &Value<'_>, options: &DecodeOptions) -> Result<Py<PyAny>> {
+    let list = PyList::empty(py);
+    for (item, count) in packed::runs(payload)? {
+        if let Some(budget) = &options.max_alloc {
+            budget.charge_items(count)?;
+        }
+        options.expansion.charge(item, count)?;
+        match item {
+            Value::Vector(_) | Value::HashMap(_) | Value::Ext(..) => {
+                for _ in 0..count {
+                    list.append(lize_to_py(py, item, options)?)?;
+                }
+            }
+            _ => {
+                let item = lize_to_py(py, item, options)?;
+                for _ in 0..count {
+                    list.append(&item)?;
+                }
+            }
+        }
+    }
+    Ok(list.into_any().unbind())
+}
+
+/// Converts the items of a set into an ext value with `code`. Canonical
+/// output sorts them by their encoding, since sets iterate in hash order.
+fn set_to_lize<'py>(
+    py: Python<'py>,
+    items: impl Iterator<Item = Bound<'py, PyAny>>,
+    code: u8,
+    options: &EncodeOptions,
+) -> Result<Value<'py>> {
+    let mut items = items
+        .map(|item| py_to_lize(py, item.extract()?, options))
+        .collect::<Result<Vec<_>>>()?;
+
+    if options.core.canonical {
+        let mut keyed = items
+            .into_iter()
+            .map(|item| {
+                let mut bytes = Vec::new();
+                item.serialize_to(&options.core, &mut bytes)?;
+                Ok((bytes, item))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        keyed.sort_by(|a, b| a.0.cmp(&b.0));
+        items = keyed.into_iter().map(|(_, item)| item).collect();
+    }
+
+    Ok(Value::Ext(code, Box::new(Value::Vector(items))))
+}
+
+/// Rounds `f` with `quantize`, writing it as an `f32` if that holds the
+/// result exactly.
+fn quantized<'py>(quantize: Quantize, f: f64) -> Value<'py> {
+    let f = quantize.round(f);
+    if (f as f32) as f64 == f || f.is_nan() {
+        Value::F32(f as f32)
+    } else {
+        Value::F64(f)
+    }
+}
+
+/// Converts a Python value that isn't a `list` or `dict`.
+fn scalar_to_lize<'py>(
+    py: Python<'py>,
+    value: PyValue,
+    options: &EncodeOptions,
+) -> Result<Value<'py>> {
     match value {
         PyValue::Bool(b) => Ok(Value::Bool(b)),
-        PyValue::Float32(f) => Ok(Value::F32(f)),
-        PyValue::Float(f) => Ok(Value::F64(f)),
+        PyValue::Fixed(fixed) => Ok(fixed.get().to_lize()),
+        PyValue::Float32(f) => match options.float_quantize {
+            Some(quantize) => Ok(quantized(quantize, f.0 as f64)),
+            None => Ok(Value::F32(f.0)),
+        },
+        PyValue::Float(f) => match options.float_quantize {
+            Some(quantize) => Ok(quantized(quantize, f)),
+            None => Ok(Value::F64(f)),
+        },
         PyValue::U8(u) => {
             if u <= 235 {
                 Ok(Value::SmallU8(u))
@@ -268,53 +1878,433 @@ fn py_to_lize(py: Python<'_>, value: PyValue) -> Result<Value<'_>> {
         }
         PyValue::Int32(i) => Ok(Value::I32(i)),
         PyValue::Int(i) => Ok(Value::I64(i)),
-        PyValue::Str(s) => Ok(Value::SliceLike(format!("s{}", s).into())),
-        PyValue::Map(m) => {
-            let binding = m.bind(py);
-            let mut lize_value = vec![];
+        PyValue::BigInt(i) => {
+            let i = i.bind(py);
+            let bits = i.call_method0("bit_length")?.extract::<usize>()?;
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("signed", true)?;
 
-            for (k, v) in binding {
-                let key = py_to_lize(
-                    py,
-                    k.extract()
-                        .context(format!("Failed to extract key for dict {:?}", binding))?,
-                )?;
-                let val = py_to_lize(
+            let bytes = i
+                .call_method("to_bytes", (bits / 8 + 1, "little"), Some(&kwargs))?
+                .extract::<Vec<u8>>()?;
+            Ok(Value::Ext(ext::BIG_INT, Box::new(Value::SliceLike(bytes))))
+        }
+        PyValue::Fraction(f) => {
+            let f = f.0.bind(py);
+            let numerator = py_to_lize(py, f.getattr("numerator")?.extract()?, options)?;
+            let denominator = py_to_lize(py, f.getattr("denominator")?.extract()?, options)?;
+            Ok(Value::Ext(
+                ext::FRACTION,
+                Box::new(Value::Vector(vec![numerator, denominator])),
+            ))
+        }
+        PyValue::TimeDelta(d) => {
+            let (days, seconds, micros) = d.parts(py)?;
+            let int = |i: i32| {
+                py_to_lize(
                     py,
-                    v.extract()
-                        .context(format!("Failed to extract value for dict {:?}", binding))?,
-                )?;
-                lize_value.push((key, val));
+                    u8::try_from(i).map_or(PyValue::Int32(i), PyValue::U8),
+                    options,
+                )
+            };
+            Ok(Value::Ext(
+                ext::TIMEDELTA,
+                Box::new(Value::Vector(vec![int(days)?, int(seconds)?, int(micros)?])),
+            ))
+        }
+        PyValue::DateTime(dt) => dt.to_lize(py),
+        PyValue::Subclass(s) => s.to_lize(py, options.warn_subclasses),
+        PyValue::Ip(ip) => ip.to_lize(py),
+        PyValue::StructuredArray(array) => array.to_lize(py, options),
+        PyValue::Str(s) => Ok(Value::StrLike(s)),
+        PyValue::Surrogates(s) => surrogates_to_lize(s.bind(py), options.strings),
+        PyValue::Bytes(b) => Ok(Value::SliceLike(b.as_bytes(py).to_vec())),
+        PyValue::ByteBuffer(b) => Ok(Value::SliceLike(b.0)),
+        PyValue::None(_) => Ok(Value::Optional(None)),
+        PyValue::Delete(_) => Ok(Value::delete()),
+        PyValue::Persistent(id) => id.get().to_lize(py, options),
+        PyValue::Vec(v) => Ok(pack_bits(py, &v)),
+        PyValue::Set(set) => set_to_lize(py, set.bind(py).iter(), ext::SET, options),
+        PyValue::FrozenSet(set) => set_to_lize(py, set.bind(py).iter(), ext::FROZEN_SET, options),
+        PyValue::Deque(d) => {
+            let d = d.0.bind(py);
+            let items = py_to_lize(py, PyValue::Vec(d.extract()?), options)?;
+            let maxlen = py_to_lize(py, d.getattr("maxlen")?.extract()?, options)?;
+            Ok(Value::Ext(
+                ext::DEQUE,
+                Box::new(Value::Vector(vec![items, maxlen])),
+            ))
+        }
+        PyValue::Map(_) | PyValue::Mapping(_) => unreachable!(),
+        PyValue::Run(runnable) => {
+            let value = runnable.bind(py).get().as_lize(py, None)?;
+            Ok(Value::Ext(ext::RUNNABLE, Box::new(value)))
+        }
+        PyValue::Callable(callable) => {
+            let value = Runnable::from_pyfn(py, callable, None)?.as_lize(py, None)?;
+            Ok(Value::Ext(ext::RUNNABLE, Box::new(value)))
+        }
+        PyValue::Object(obj) => {
+            if let Some(value) = extension::to_lize(py, obj.bind(py), options)? {
+                return Ok(value);
+            }
+            match &options.default {
+                Some(default) => match default.bind(py).call1((obj,))?.extract()? {
+                    // Isn't handed to `default` again, which could go on forever.
+                    PyValue::Object(obj) => reduce::to_lize(py, obj.bind(py), options),
+                    value => py_to_lize(py, value, options),
+                },
+                None => reduce::to_lize(py, obj.bind(py), options),
             }
+        }
+    }
+}
+
+/// Writes a `str` holding lone surrogates the way `strings=` asks.
+fn surrogates_to_lize<'py>(s: &Bound<'py, PyString>, strings: Strings) -> Result<Value<'py>> {
+    let encode =
+        |errors| -> Result<Vec<u8>> { Ok(s.call_method1("encode", ("utf-8", errors))?.extract()?) };
+    match strings {
+        // Raises the `UnicodeEncodeError` naming the surrogate.
+        Strings::Strict => Ok(Value::StrLike(s.to_cow()?.into_owned())),
+        Strings::SurrogatePass => Ok(Value::Ext(
+            ext::SURROGATE_STR,
+            Box::new(Value::SliceLike(encode("surrogatepass")?)),
+        )),
+        Strings::Replace => Ok(Value::StrLike(String::from_utf8(encode("replace")?)?)),
+    }
+}
 
-            Ok(Value::HashMap(lize_value))
+fn decode_str(bytes: &[u8], options: &DecodeOptions) -> Result<String> {
+    if options.lossy {
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    } else {
+        Ok(str::from_utf8(bytes)
+            .map_err(|e| {
+                exceptions::PyValueError::new_err(format!(
+                    "Invalid UTF-8 in string ({}), pass lossy=True to replace it",
+                    e
+                ))
+            })?
+            .to_owned())
+    }
+}
+
+/// The items of a vector, or the keys and values of a map, alternating.
+enum Items<'v, 'a> {
+    Vector(std::slice::Iter<'v, Value<'a>>),
+    Map {
+        pairs: std::slice::Iter<'v, (Value<'a>, Value<'a>)>,
+        value: Option<&'v Value<'a>>,
+    },
+}
+
+impl<'v, 'a> Iterator for Items<'v, 'a> {
+    type Item = &'v Value<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Vector(items) => items.next(),
+            Self::Map { pairs, value } => value.take().or_else(|| {
+                let (k, v) = pairs.next()?;
+                *value = Some(v);
+                Some(k)
+            }),
         }
-        PyValue::None(_) => Ok(Value::Optional(None)),
-        PyValue::Vec(mut v) => {
-            let mut lize_value = vec![];
+    }
+}
+
+/// Where the converted items of a container go as they're ready.
+enum Filling<'py> {
+    /// A list created at its final length, filled in place.
+    List(Bound<'py, PyList>, usize),
+    /// Entries of a map, waiting on their value if the key is `Some`.
+    Dict(Bound<'py, PyDict>, Option<Option<Py<PyAny>>>),
+    Pairs(Vec<Bound<'py, PyTuple>>, Option<Option<Py<PyAny>>>),
+}
+
+/// A vector or map whose items are still being converted.
+struct PendingLize<'py, 'v, 'a> {
+    filling: Filling<'py>,
+    rest: Items<'v, 'a>,
+}
 
-            for item in v.drain(..) {
-                lize_value.push(py_to_lize(py, item.extract::<PyValue>(py)?)?);
+impl<'py, 'v, 'a> PendingLize<'py, 'v, 'a> {
+    /// Starts converting `value`, or returns `None` if it isn't a container.
+    /// A map that becomes a dict fills `into` if it's given, on top of the
+    /// entries it already has.
+    fn new(
+        py: Python<'py>,
+        value: &'v Value<'a>,
+        options: &DecodeOptions,
+        into: Option<Bound<'py, PyDict>>,
+    ) -> Result<Option<Self>> {
+        let (filling, rest) = match value {
+            // Filled with `None` first, since the list may be seen from Python,
+            // e.g. by the garbage collector, while items are converted.
+            Value::Vector(items) => (
+                Filling::List(PyList::new(py, items.iter().map(|_| py.None()))?, 0),
+                Items::Vector(items.iter()),
+            ),
+            Value::HashMap(pairs) => (
+                match (options.maps_as_pairs, into) {
+                    (true, _) => Filling::Pairs(Vec::with_capacity(pairs.len()), None),
+                    (false, Some(dict)) => Filling::Dict(dict, None),
+                    (false, None) => Filling::Dict(PyDict::new(py), None),
+                },
+                Items::Map {
+                    pairs: pairs.iter(),
+                    value: None,
+                },
+            ),
+            _ => return Ok(None),
+        };
+        Ok(Some(Self { filling, rest }))
+    }
+
+    /// Whether the next item is the key of a map entry.
+    fn wants_key(&self) -> bool {
+        matches!(
+            self.filling,
+            Filling::Dict(_, None) | Filling::Pairs(_, None)
+        )
+    }
+
+    /// Adds the next item, which is `None` if it was skipped.
+    fn push(&mut self, py: Python<'py>, item: Option<Py<PyAny>>) -> Result<()> {
+        let (key, value) = match &mut self.filling {
+            Filling::List(list, index) => {
+                list.set_item(*index, item.unwrap_or_else(|| py.None()))?;
+                *index += 1;
+                return Ok(());
             }
+            Filling::Dict(_, key) | Filling::Pairs(_, key) => match key.take() {
+                Some(key) => (key, item),
+                None => {
+                    *key = Some(item);
+                    return Ok(());
+                }
+            },
+        };
 
-            Ok(Value::Vector(lize_value))
+        // Entries with a skipped key or value are left out, and so are those
+        // marked `DELETE`, which only mean something to `merge`.
+        let (Some(key), Some(value)) = (key, value) else {
+            return Ok(());
+        };
+        if patch::is_sentinel(py, &value) {
+            return Ok(());
         }
-        PyValue::Run(runnable) => {
-            let binding = runnable.bind(py);
-            let mut data = binding.get().as_lize(py)?.serialize()?;
-            data.insert(0, b'r');
-            Ok(Value::SliceLike(data))
+        match &mut self.filling {
+            Filling::Dict(dict, _) => dict.set_item(key, value)?,
+            Filling::Pairs(pairs, _) => pairs.push(PyTuple::new(py, [key, value])?),
+            Filling::List(..) => unreachable!(),
         }
-        PyValue::Callable(callable) => {
-            let runnable = Runnable::from_pyfn(py, callable)?;
-            let mut data = runnable.as_lize(py)?.serialize()?;
-            data.insert(0, b'r');
-            Ok(Value::SliceLike(data))
+        Ok(())
+    }
+
+    /// Returns the finished container, with a map made into a record if
+    /// `records` is given.
+    fn finish(self, py: Python<'py>, records: Option<&mut record::Records>) -> Result<Py<PyAny>> {
+        Ok(match self.filling {
+            Filling::List(list, _) => list.into_any().unbind(),
+            Filling::Dict(dict, _) => match records {
+                Some(records) => records.make(dict)?,
+                None => dict.into_any().unbind(),
+            },
+            Filling::Pairs(pairs, _) => PyList::new(py, pairs)?.into_any().unbind(),
+        })
+    }
+}
+
+/// A scalar that always converts to the same immutable Python object, which
+/// `dedupe_values` builds once and shares. Named after its [`Value`] variants.
+#[derive(PartialEq, Eq, Hash)]
+enum Shared<'v> {
+    Str(&'v str),
+    Slice(&'v [u8]),
+    SmallU8(u8),
+    U8(u8),
+    I32(i32),
+    I64(i64),
+    /// The bits of the float, so `0.0` and `-0.0` stay apart.
+    F32(u32),
+    F64(u64),
+}
+
+impl<'v> Shared<'v> {
+    fn of(value: &'v Value<'_>, options: &DecodeOptions) -> Option<Self> {
+        Some(match value {
+            Value::Str(s) => Self::Str(s),
+            Value::StrLike(s) => Self::Str(s),
+            // Memoryviews are left alone, and so are legacy slices, which may be functions.
+            Value::Slice(sl) => Self::Slice(Self::shareable_slice(sl, options)?),
+            Value::SliceLike(sl) => Self::Slice(Self::shareable_slice(sl, options)?),
+            Value::SmallU8(u) => Self::SmallU8(*u),
+            Value::U8(u) => Self::U8(*u),
+            Value::I32(i) => Self::I32(*i),
+            Value::I64(i) => Self::I64(*i),
+            // NaN keys must stay apart, and Python only tells them apart by identity.
+            Value::F32(f) if !f.is_nan() => Self::F32(f.to_bits()),
+            Value::F64(f) if !f.is_nan() => Self::F64(f.to_bits()),
+            _ => return None,
+        })
+    }
+
+    /// Memoryviews are left alone, and so are legacy slices, which may be functions.
+    fn shareable_slice<'s>(slice: &'s [u8], options: &DecodeOptions) -> Option<&'s [u8]> {
+        match (options.bytes_as, options.raw_slices) {
+            (BytesAs::Bytes, RawSlices::Bytes | RawSlices::Str) => Some(slice),
+            _ => None,
+        }
+    }
+}
+
+/// Converts a value to Python, keeping nested containers on an explicit stack
+/// so deeply nested ones don't overflow the call stack.
+fn lize_to_py(
+    py: Python<'_>,
+    lize_value: &Value<'_>,
+    options: &DecodeOptions,
+) -> Result<Py<PyAny>> {
+    lize_to_py_with(py, lize_value, options, None, None)
+}
+
+/// [`lize_to_py`], sharing the dict keys in `scratch` between calls as a
+/// `Decoder` does, and filling `into` with a top-level map if it's given.
+fn lize_to_py_with<'py>(
+    py: Python<'py>,
+    lize_value: &Value<'_>,
+    options: &DecodeOptions,
+    mut scratch: Option<&mut decoder::Scratch>,
+    mut into: Option<Bound<'py, PyDict>>,
+) -> Result<Py<PyAny>> {
+    let mut stack: Vec<PendingLize<'_, '_, '_>> = vec![];
+    let mut next = lize_value;
+    let mut shared: Option<HashMap<Shared<'_>, Py<PyAny>>> =
+        options.dedupe_values.then(HashMap::new);
+    let mut records = options.record.then(record::Records::default);
+
+    loop {
+        let is_key = stack.last().is_some_and(PendingLize::wants_key);
+        let key = match (&mut scratch, next) {
+            (Some(scratch), Value::Str(key)) if is_key => Some(scratch.key(py, key)),
+            (Some(scratch), Value::StrLike(key)) if is_key => Some(scratch.key(py, key)),
+            _ => None,
+        };
+        // Keys shared through `scratch` are built once for every payload.
+        if let (Some(budget), None) = (&options.max_alloc, &key) {
+            budget.charge(next)?;
+        }
+        let mut value = match PendingLize::new(py, next, options, into.take())? {
+            Some(mut pending) => match pending.rest.next() {
+                Some(item) => {
+                    stack.push(pending);
+                    next = item;
+                    continue;
+                }
+                None => Some(pending.finish(py, records.as_mut())?),
+            },
+            None if key.is_some() => key,
+            // Keys are never functions, so a legacy key that starts like one
+            // is binary.
+            None if is_key && is_legacy_function(next, options) => Some(
+                PyBytes::new(py, next.as_slice().unwrap_or_default())
+                    .into_any()
+                    .unbind(),
+            ),
+            None => match (&mut shared, Shared::of(next, options)) {
+                (Some(shared), Some(key)) => Some(match shared.entry(key) {
+                    Entry::Occupied(entry) => entry.get().clone_ref(py),
+                    Entry::Vacant(entry) => {
+                        entry.insert(scalar_to_py(py, next, options)?).clone_ref(py)
+                    }
+                }),
+                _ => item_to_py(py, next, options)?,
+            },
+        };
+
+        // Hand the value to its parents, closing every container it completes.
+        loop {
+            let Some(parent) = stack.last_mut() else {
+                return Ok(value.unwrap_or_else(|| py.None()));
+            };
+            parent.push(py, value)?;
+
+            if let Some(item) = parent.rest.next() {
+                next = item;
+                break;
+            }
+            value = Some(stack.pop().unwrap().finish(py, records.as_mut())?);
         }
     }
 }
 
-fn lize_to_py(py: Python<'_>, lize_value: &Value<'_>) -> Result<Py<PyAny>> {
+/// Whether `value` is a function written before format version 3, which
+/// `raw_slices="legacy"` reads from slices starting with `r`.
+fn is_legacy_function(value: &Value<'_>, options: &DecodeOptions) -> bool {
+    matches!(options.raw_slices, RawSlices::Legacy)
+        && matches!(value, Value::Slice(sl) if sl.first() == Some(&b'r'))
+}
+
+/// Converts a value that isn't a vector or map to Python, or returns `None`
+/// for a function that can't be loaded and should be skipped.
+fn item_to_py(
+    py: Python<'_>,
+    lize_value: &Value<'_>,
+    options: &DecodeOptions,
+) -> Result<Option<Py<PyAny>>> {
+    let loaded = match lize_value {
+        Value::Ext(ext::RUNNABLE, envelope) => {
+            Runnable::from_lize(py, envelope, options.resolve_annotations)
+        }
+        Value::Slice(sl) if is_legacy_function(lize_value, options) => {
+            Runnable::from_bytes(py, &sl[1..], options.resolve_annotations)
+        }
+        _ => return Ok(Some(scalar_to_py(py, lize_value, options)?)),
+    };
+
+    match (loaded, options.on_bad_runnable) {
+        (Ok(runnable), _) => Ok(Some(runnable.into_py_any(py)?)),
+        (Err(err), OnBadRunnable::Error) => Err(err.into()),
+        (Err(_), OnBadRunnable::Skip) => Ok(None),
+        (Err(err), OnBadRunnable::Placeholder) => {
+            // The bytes `Runnable.from_bytes` reads, so loading can be retried.
+            let data = match lize_value {
+                Value::Ext(_, envelope) => envelope.serialize()?.to_vec(),
+                Value::Slice(sl) => sl[1..].to_vec(),
+                _ => unreachable!(),
+            };
+            let broken = BrokenRunnable {
+                data: PyBytes::new(py, &data).unbind(),
+                error: err.into_value(py).into_any(),
+            };
+            Ok(Some(broken.into_py_any(py)?))
+        }
+    }
+}
+
+/// `value`, with owned bytes borrowed as a [`Value::Slice`] so ext payloads
+/// match the same way however they were built.
+fn borrow_slice<'v>(value: &'v Value<'_>) -> Cow<'v, Value<'v>> {
+    match value {
+        Value::SliceLike(bytes) => Cow::Owned(Value::Slice(bytes)),
+        value => Cow::Borrowed(value),
+    }
+}
+
+/// Converts a value that isn't a vector or map to Python.
+fn scalar_to_py(
+    py: Python<'_>,
+    lize_value: &Value<'_>,
+    options: &DecodeOptions,
+) -> Result<Py<PyAny>> {
+    if options.preserve_int_width {
+        if let Some(fixed) = int_width::FixedInt::from_lize(lize_value) {
+            return Ok(Py::new(py, fixed)?.into_any());
+        }
+    }
+
     match lize_value {
         Value::Bool(b) => Ok(PyValue::Bool(*b).into_py_any(py)?),
 
@@ -327,51 +2317,341 @@ fn lize_to_py(py: Python<'_>, lize_value: &Value<'_>) -> Result<Py<PyAny>> {
         Value::I32(i) => Ok(PyValue::Int(*i as i64).into_py_any(py)?),
         Value::I64(i) => Ok(PyValue::Int(*i).into_py_any(py)?),
 
-        Value::Slice(sl) => {
-            if let Ok(s) = str::from_utf8(&sl[0..1]) {
-                if s == "s" {
-                    Ok(PyValue::Str(String::from_utf8_lossy(&sl[1..]).to_string())
-                        .into_py_any(py)?)
-                } else if s == "r" {
-                    Ok(Runnable::from_bytes(py, &sl[1..])?.into_py_any(py)?)
+        Value::Slice(sl) => match options.raw_slices {
+            RawSlices::Bytes => match options.bytes_as {
+                BytesAs::Bytes => Ok(PyBytes::new(py, sl).into_py_any(py)?),
+                BytesAs::Memoryview => {
+                    Ok(view::memoryview(py, options.view.as_deref(), sl)?.unbind())
+                }
+            },
+            RawSlices::Str => Ok(PyValue::Str(decode_str(sl, options)?).into_py_any(py)?),
+            RawSlices::Error => Err(exceptions::PyValueError::new_err(
+                "Found a raw slice, pass raw_slices='bytes' or 'str' to decode it",
+            )
+            .into()),
+            RawSlices::Legacy => match sl.first() {
+                Some(b's') => Ok(PyValue::Str(decode_str(&sl[1..], options)?).into_py_any(py)?),
+                Some(b'r') => Ok(item_to_py(py, lize_value, options)?.unwrap_or_else(|| py.None())),
+                _ => Ok(PyBytes::new(py, sl).into_py_any(py)?),
+            },
+        },
+        Value::Str(s) => Ok(PyString::new(py, s).into_py_any(py)?),
+        Value::StrLike(s) => Ok(PyString::new(py, s).into_py_any(py)?),
+        // Only built by Rust producers, and read like the slice it encodes as.
+        Value::SliceLike(sl) => scalar_to_py(py, &Value::Slice(sl), options),
+
+        Value::Ext(code, value) => match (*code, borrow_slice(value).as_ref()) {
+            (ext::BIG_INT, Value::Slice(bytes)) => {
+                let kwargs = PyDict::new(py);
+                kwargs.set_item("signed", true)?;
+
+                Ok(py
+                    .get_type::<PyInt>()
+                    .call_method(
+                        "from_bytes",
+                        (PyBytes::new(py, bytes), "little"),
+                        Some(&kwargs),
+                    )?
+                    .unbind())
+            }
+            (ext::RUNNABLE, _) => {
+                Ok(item_to_py(py, lize_value, options)?.unwrap_or_else(|| py.None()))
+            }
+            (ext::FRACTION, Value::Vector(v)) if v.len() == 2 => {
+                let numerator = lize_to_py(py, &v[0], options)?;
+                let denominator = lize_to_py(py, &v[1], options)?;
+                Ok(Fraction::class(py)?
+                    .call1((numerator, denominator))?
+                    .unbind())
+            }
+            (ext::DEQUE, Value::Vector(v)) if v.len() == 2 => {
+                let items = lize_to_py(py, &v[0], options)?;
+                let maxlen = lize_to_py(py, &v[1], options)?;
+                Ok(Deque::class(py)?.call1((items, maxlen))?.unbind())
+            }
+            (ext::SUBCLASS, Value::Vector(v)) if v.len() == 2 => {
+                let Some(name) = v[0].as_str() else {
+                    return Err(exceptions::PyValueError::new_err("Invalid subclass name").into());
+                };
+                let base = scalar_to_py(py, &v[1], options)?;
+                Ok(subclass::from_lize(
+                    py,
+                    name,
+                    base,
+                    options.warn_subclasses,
+                )?)
+            }
+            (ext::REDUCE, Value::Vector(v)) => Ok(reduce::from_lize(py, v, options)?),
+            (ext::RUNS, _) => decode_runs(py, value, options),
+            (ext::DATETIME, Value::Vector(v)) => Ok(datetime::from_lize(py, v)?),
+            (ext::STRUCTURED_ARRAY, Value::Vector(_)) => structured::from_lize(py, value),
+            (ext::PERSISTENT_ID, _) => persistent::from_lize(py, value, options),
+            (ext::IP_ADDRESS | ext::IP_NETWORK, Value::Slice(bytes)) => {
+                Ok(ip::from_lize(py, bytes, *code == ext::IP_NETWORK)?)
+            }
+            (ext::SET | ext::FROZEN_SET, Value::Vector(v)) => {
+                let items = v
+                    .iter()
+                    .map(|item| lize_to_py(py, item, options))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(if *code == ext::SET {
+                    PySet::new(py, items)?.into_any().unbind()
                 } else {
-                    Ok(PyValue::Str(s.to_string()).into_py_any(py)?)
+                    PyFrozenSet::new(py, items)?.into_any().unbind()
+                })
+            }
+            (ext::SURROGATE_STR, Value::Slice(bytes)) => Ok(PyBytes::new(py, bytes)
+                .call_method1("decode", ("utf-8", "surrogatepass"))?
+                .unbind()),
+            (ext::DELETE, _) => Ok(patch::sentinel(py)?.clone().into_any().unbind()),
+            (ext::BITS, _) => {
+                let bits = packed::bits(value)?;
+                if let Some(budget) = &options.max_alloc {
+                    budget.charge_items(bits.len())?;
                 }
-            } else {
-                Err(anyhow::anyhow!("Invalid slice"))
+                Ok(PyList::new(py, bits)?.into_any().unbind())
             }
-        }
-        Value::SliceLike(_) => unreachable!(),
-
-        Value::HashMap(m) => {
-            let map = PyDict::new(py);
-            for (k, v) in m {
-                let k = lize_to_py(py, k)?;
-                let v = lize_to_py(py, v)?;
-                map.set_item(k, v)?;
+            (ext::TIMEDELTA, Value::Vector(v)) if v.len() == 3 => {
+                let int = |v: &Value| -> Result<i32> {
+                    Ok(lize_to_py(py, v, options)?.extract::<i32>(py)?)
+                };
+                Ok(compat::timedelta(py, int(&v[0])?, int(&v[1])?, int(&v[2])?)?.unbind())
             }
-
-            Ok(PyValue::Map(map.unbind()).into_py_any(py)?)
-        }
+            _ => match extension::from_lize(py, *code, value, options)? {
+                Some(obj) => Ok(obj),
+                None => Err(exceptions::PyValueError::new_err(format!(
+                    "Unsupported extension value with code {}",
+                    code
+                ))
+                .into()),
+            },
+        },
 
         Value::Optional(_) => Ok(py.None().into_py_any(py)?),
-        Value::Vector(v) => {
-            let mut vec = vec![];
-            for item in v {
-                vec.push(lize_to_py(py, item)?);
-            }
+        Value::Vector(_) | Value::HashMap(_) => unreachable!(),
+    }
+}
 
-            Ok(PyValue::Vec(vec).into_py_any(py)?)
-        }
+/// Formats a buffer as a classic hexdump: offset, hex bytes and an ASCII gutter.
+#[pyfunction]
+#[pyo3(signature = (bytes, width = 16))]
+pub fn hexdump(bytes: &[u8], width: usize) -> PyResult<String> {
+    if width == 0 {
+        return Err(exceptions::PyValueError::new_err("width must be positive"));
     }
+
+    let lines = bytes
+        .chunks(width)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex = chunk
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let ascii = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect::<String>();
+
+            format!(
+                "{:08x}  {:<hex_width$}  |{}|",
+                i * width,
+                hex,
+                ascii,
+                hex_width = width * 3 - 1
+            )
+        })
+        .collect::<Vec<_>>();
+
+    Ok(lines.join("\n"))
+}
+
+/// The version of the wire format this module reads and writes.
+#[pyfunction]
+pub fn format_version() -> u8 {
+    FORMAT_VERSION
 }
 
 /// A Python module implemented in Rust.
 #[pymodule]
 fn lize(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(serialize, m)?)?;
+    m.add_function(wrap_pyfunction!(serialize_with_info, m)?)?;
+    m.add_function(wrap_pyfunction!(buffer::serialize_buffer, m)?)?;
     m.add_function(wrap_pyfunction!(deserialize, m)?)?;
+    m.add_function(wrap_pyfunction!(deserialize_first, m)?)?;
+    m.add_function(wrap_pyfunction!(items::iter_deserialize, m)?)?;
+    m.add_function(wrap_pyfunction!(typeddict::deserialize_typeddict, m)?)?;
+    m.add_function(wrap_pyfunction!(typeddict::load_as, m)?)?;
+    m.add_function(wrap_pyfunction!(content_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(equal, m)?)?;
+    m.add_function(wrap_pyfunction!(validate_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(estimate::estimate_decoded_size, m)?)?;
+    m.add_function(wrap_pyfunction!(columns::to_columns, m)?)?;
+    #[cfg(feature = "arrow")]
+    m.add_function(wrap_pyfunction!(table::to_arrow, m)?)?;
+    #[cfg(feature = "arrow")]
+    m.add_function(wrap_pyfunction!(table::from_arrow, m)?)?;
+    m.add_function(wrap_pyfunction!(format_version, m)?)?;
+    m.add_function(wrap_pyfunction!(compat::features, m)?)?;
+    m.add_function(wrap_pyfunction!(hexdump, m)?)?;
+    m.add_function(wrap_pyfunction!(diagnose::diagnose, m)?)?;
+    m.add_function(wrap_pyfunction!(metadata::read_metadata, m)?)?;
+    m.add_function(wrap_pyfunction!(subclass::register, m)?)?;
+    m.add_function(wrap_pyfunction!(extension::register_extension, m)?)?;
+    m.add_function(wrap_pyfunction!(tune::tune, m)?)?;
+    m.add_function(wrap_pyfunction!(patch::merge, m)?)?;
+    m.add_function(wrap_pyfunction!(patch::diff, m)?)?;
+    m.add_function(wrap_pyfunction!(redact::redact, m)?)?;
+    m.add_function(wrap_pyfunction!(raw::wrap_raw, m)?)?;
+    m.add_function(wrap_pyfunction!(raw::unwrap_raw, m)?)?;
+    m.add_function(wrap_pyfunction!(stream::serialize_map_items, m)?)?;
     m.add_class::<Runnable>()?;
+    m.add_class::<BrokenRunnable>()?;
+    m.add_class::<int_width::FixedInt>()?;
+    m.add_class::<patch::Delete>()?;
+    m.add_class::<schema::Schema>()?;
+    m.add_class::<buffer::SerializedBuffer>()?;
+    runnable::register(m)?;
+    m.add("DELETE", patch::sentinel(m.py())?)?;
+    m.add("options", defaults::Options)?;
+    m.add_class::<stream::Writer>()?;
+    m.add_class::<stream::Reader>()?;
+    m.add_class::<decoder::Decoder>()?;
+    m.add_class::<diagnose::Problem>()?;
+    m.add_class::<items::ItemIterator>()?;
+    m.add(
+        "AuthenticationFailed",
+        m.py().get_type::<error::AuthenticationFailed>(),
+    )?;
+    m.add("EncodeError", m.py().get_type::<error::EncodeError>())?;
+    m.add("Cancelled", m.py().get_type::<error::Cancelled>())?;
+    m.add(
+        "AllocLimitExceeded",
+        m.py().get_type::<error::AllocLimitExceeded>(),
+    )?;
+    m.add(
+        "ValidationError",
+        m.py().get_type::<error::ValidationError>(),
+    )?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slice_like() -> Result<()> {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let value = Value::Vector(vec![
+                Value::SliceLike(b"owned".to_vec()),
+                Value::Ext(ext::BIG_INT, Box::new(Value::SliceLike(vec![0, 1]))),
+            ]);
+            let options = DecodeOptions {
+                raw_slices: RawSlices::Bytes,
+                ..Default::default()
+            };
+
+            let expected = PyList::new(py, [b"owned".into_py_any(py)?, 256.into_py_any(py)?])?;
+            assert!(lize_to_py(py, &value, &options)?.bind(py).eq(&expected)?);
+
+            let bytes = value.serialize()?;
+            let decoded = Value::deserialize_from(&bytes)?;
+            assert!(lize_to_py(py, &decoded, &options)?.bind(py).eq(&expected)?);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_structured_array_without_numpy() -> Result<()> {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            // Arrays are only rebuilt as dicts of lists when numpy is missing.
+            if py.import("numpy").is_ok() {
+                return Ok(());
+            }
+
+            let field = |name: &'static str, offset, shape: Vec<Value<'static>>, dtype, column| {
+                Value::Vector(vec![
+                    Value::Str(name),
+                    Value::SmallU8(offset),
+                    Value::Vector(shape),
+                    dtype,
+                    column,
+                ])
+            };
+            let floats = [0.5f32, 1.0, -1.5, 2.0]
+                .iter()
+                .flat_map(|f| f.to_le_bytes())
+                .collect();
+            let nested = Value::Vector(vec![
+                Value::SmallU8(1),
+                Value::Vector(vec![field(
+                    "flag",
+                    0,
+                    vec![],
+                    Value::Str("|b1"),
+                    Value::SliceLike(vec![1, 0]),
+                )]),
+            ]);
+            let value = Value::Ext(
+                ext::STRUCTURED_ARRAY,
+                Box::new(Value::Vector(vec![
+                    Value::Vector(vec![Value::SmallU8(2)]),
+                    Value::Vector(vec![
+                        Value::SmallU8(15),
+                        Value::Vector(vec![
+                            field(
+                                "id",
+                                0,
+                                vec![],
+                                Value::Str("<i4"),
+                                Value::SliceLike(
+                                    [1i32, -2].iter().flat_map(|i| i.to_le_bytes()).collect(),
+                                ),
+                            ),
+                            field(
+                                "pos",
+                                4,
+                                vec![Value::SmallU8(2)],
+                                Value::Str("<f4"),
+                                Value::SliceLike(floats),
+                            ),
+                            field(
+                                "tag",
+                                12,
+                                vec![],
+                                Value::Str("|S2"),
+                                Value::SliceLike(b"abc\0".to_vec()),
+                            ),
+                            field("inner", 14, vec![], nested, Value::Optional(None)),
+                        ]),
+                    ]),
+                ])),
+            );
+
+            let bytes = value.serialize()?;
+            let decoded = Value::deserialize_from(&bytes)?;
+            let decoded = lize_to_py(py, &decoded, &DecodeOptions::default())?;
+            let expected = py.eval(
+                c"{'id': [1, -2], 'pos': [[0.5, 1.0], [-1.5, 2.0]], 'tag': [b'ab', b'c'], 'inner': {'flag': [True, False]}}",
+                None,
+                None,
+            )?;
+            assert!(decoded.bind(py).eq(&expected)?);
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,53 @@
+//! `serialize(..., metadata={...})`, which frames the payload with a metadata
+//! map, and `read_metadata`, which reads it back without decoding the body.
+//!
+//! The map holds `producer`, `created` and, with a schema, `schema`, which are
+//! filled in here, and `tags`, the dict given by the caller.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lize_sys::frame;
+use pyo3::{prelude::*, types::PyDict};
+
+use crate::{
+    error::into_py_err,
+    lize_to_py,
+    options::{DecodeOptions, EncodeOptions},
+    py_to_lize,
+};
+
+/// The metadata to frame a payload written with `options` with, if it asks
+/// for any.
+pub fn build(py: Python<'_>, options: &EncodeOptions) -> PyResult<Option<frame::Metadata>> {
+    let Some(tags) = &options.metadata else {
+        return Ok(None);
+    };
+
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64());
+    let map = PyDict::new(py);
+    map.set_item("producer", concat!("lize ", env!("CARGO_PKG_VERSION")))?;
+    map.set_item("created", created)?;
+    if let Some(schema) = &options.schema {
+        map.set_item("schema", schema.hash())?;
+    }
+    map.set_item("tags", tags.bind(py))?;
+
+    let value = py_to_lize(py, map.extract()?, &EncodeOptions::default()).map_err(into_py_err)?;
+    let metadata = frame::Metadata::new(&value).map_err(into_py_err)?;
+    Ok(Some(metadata.authenticated(options.authenticate_metadata)))
+}
+
+/// Returns the metadata map of the frame at the start of `data`, or `None` if
+/// it has none. The body isn't decoded, nor decrypted, so no key is needed.
+#[pyfunction]
+pub fn read_metadata(py: Python<'_>, data: &[u8]) -> PyResult<Option<Py<PyAny>>> {
+    let framed = frame::Framed::parse(data).map_err(into_py_err)?;
+    let Some(value) = framed.metadata().map_err(into_py_err)? else {
+        return Ok(None);
+    };
+    Ok(Some(
+        lize_to_py(py, &value, &DecodeOptions::default()).map_err(into_py_err)?,
+    ))
+}
@@ -0,0 +1,439 @@
+//! Keyword arguments accepted by the Python entry points.
+//!
+//! Every entry point parses its `**kwargs` through [`EncodeOptions::from_kwargs`] or
+//! [`DecodeOptions::from_kwargs`], so each option is translated in exactly one place.
+//! Both start from the defaults set with `lize.options`, see [`defaults`].
+
+use std::sync::Arc;
+
+use lize_sys::{frame, DeserializeOptions, SerializeOptions};
+use pyo3::{
+    exceptions,
+    prelude::*,
+    pybacked::PyBackedStr,
+    types::{PyBool, PyBytes, PyDict},
+};
+
+use crate::{
+    defaults,
+    estimate::{Budget, Expansion},
+    schema::Schema,
+    view::SourceView,
+};
+
+/// What to do with raw slices, i.e. Python `bytes` or byte strings written by Rust producers.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum RawSlices {
+    /// Decode them as `bytes`.
+    #[default]
+    Bytes,
+    /// Decode them as UTF-8 `str`.
+    Str,
+    /// Refuse to decode them.
+    Error,
+    /// Read them the way payloads from before format version 3 were written:
+    /// an `s` prefix for strings and an `r` prefix for functions.
+    Legacy,
+}
+
+impl<'py> FromPyObject<'py> for RawSlices {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        match &*ob.extract::<PyBackedStr>()? {
+            "bytes" => Ok(Self::Bytes),
+            "str" => Ok(Self::Str),
+            "error" => Ok(Self::Error),
+            "legacy" => Ok(Self::Legacy),
+            other => Err(exceptions::PyValueError::new_err(format!(
+                "raw_slices must be one of 'bytes', 'str', 'error' or 'legacy', got {:?}",
+                other
+            ))),
+        }
+    }
+}
+
+/// How to write strings that UTF-8 can't encode, i.e. that hold lone
+/// surrogates. Named after the codec error handlers that do the same.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strings {
+    /// Raise a `UnicodeEncodeError`.
+    #[default]
+    Strict,
+    /// Write them as [`ext::SURROGATE_STR`](lize_sys::ext::SURROGATE_STR),
+    /// which decodes back to the same string.
+    SurrogatePass,
+    /// Write each surrogate as `?`.
+    Replace,
+}
+
+impl<'py> FromPyObject<'py> for Strings {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        match &*ob.extract::<PyBackedStr>()? {
+            "strict" => Ok(Self::Strict),
+            "surrogatepass" => Ok(Self::SurrogatePass),
+            "replace" => Ok(Self::Replace),
+            other => Err(exceptions::PyValueError::new_err(format!(
+                "strings must be one of 'strict', 'surrogatepass' or 'replace', got {:?}",
+                other
+            ))),
+        }
+    }
+}
+
+/// What to decode byte slices into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BytesAs {
+    /// Copies, as `bytes`.
+    #[default]
+    Bytes,
+    /// Read-only memoryviews over the buffer passed to `deserialize`.
+    Memoryview,
+}
+
+impl<'py> FromPyObject<'py> for BytesAs {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        match &*ob.extract::<PyBackedStr>()? {
+            "bytes" => Ok(Self::Bytes),
+            "memoryview" => Ok(Self::Memoryview),
+            other => Err(exceptions::PyValueError::new_err(format!(
+                "bytes_as must be one of 'bytes' or 'memoryview', got {:?}",
+                other
+            ))),
+        }
+    }
+}
+
+/// What to do with functions whose bytecode can't be loaded, e.g. because they
+/// were written by another Python version.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnBadRunnable {
+    /// Raise, failing the whole call.
+    #[default]
+    Error,
+    /// Leave out the map entry holding the function, or use `None` elsewhere.
+    Skip,
+    /// Use a `BrokenRunnable` holding the raw bytes and the error.
+    Placeholder,
+}
+
+impl<'py> FromPyObject<'py> for OnBadRunnable {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        match &*ob.extract::<PyBackedStr>()? {
+            "error" => Ok(Self::Error),
+            "skip" => Ok(Self::Skip),
+            "placeholder" => Ok(Self::Placeholder),
+            other => Err(exceptions::PyValueError::new_err(format!(
+                "on_bad_runnable must be one of 'error', 'skip' or 'placeholder', got {:?}",
+                other
+            ))),
+        }
+    }
+}
+
+/// How `float_quantize` rounds floats before they're written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantize {
+    /// To this many significant decimal digits, from 1 to 17.
+    Digits(usize),
+    /// To the nearest `f32`, written in 4 bytes.
+    F32,
+}
+
+impl Quantize {
+    /// Rounds `f`. Infinities and NaNs are returned as they are.
+    pub fn round(self, f: f64) -> f64 {
+        match self {
+            _ if !f.is_finite() => f,
+            // Formatting rounds to the nearest decimal, which parses back to
+            // the closest f64.
+            Self::Digits(digits) => format!("{:.*e}", digits - 1, f).parse().unwrap_or(f),
+            Self::F32 => f as f32 as f64,
+        }
+    }
+}
+
+impl<'py> FromPyObject<'py> for Quantize {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        if let Ok("f32") = ob.extract::<PyBackedStr>().as_deref() {
+            return Ok(Self::F32);
+        }
+        match ob.extract::<usize>() {
+            Ok(digits @ 1..=17) => Ok(Self::Digits(digits)),
+            _ => Err(exceptions::PyValueError::new_err(format!(
+                "float_quantize must be a number of digits from 1 to 17 or 'f32', got {}",
+                ob.repr()?
+            ))),
+        }
+    }
+}
+
+/// A `bytes` key of exactly 32 bytes, for encrypted frames.
+#[derive(Debug, Clone, Copy)]
+pub struct Key(pub frame::Key);
+
+impl<'py> FromPyObject<'py> for Key {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let bytes = ob.downcast::<PyBytes>()?.as_bytes();
+        bytes.try_into().map(Self).map_err(|_| {
+            exceptions::PyValueError::new_err(format!("key must be 32 bytes, got {}", bytes.len()))
+        })
+    }
+}
+
+/// Options for turning Python objects into lize values.
+#[derive(Debug, Clone, Default)]
+pub struct EncodeOptions {
+    pub core: SerializeOptions,
+    /// Release the GIL while writing bytes, once the Python objects have been read.
+    pub release_gil: bool,
+    /// Warn when a subclass of `str` or `bytes` is written as its base type.
+    pub warn_subclasses: bool,
+    /// Write other objects through `__reduce_ex__`, like `pickle`.
+    pub allow_reduce: bool,
+    /// Write the items of a large top-level list on several threads.
+    pub parallel: bool,
+    /// Write lists with long runs of equal items as [`ext::RUNS`](lize_sys::ext::RUNS).
+    pub rle: bool,
+    /// Write every map key as `str(key)`, so the payload converts to JSON.
+    pub json_keys: bool,
+    pub strings: Strings,
+    /// Round every float before writing it.
+    pub float_quantize: Option<Quantize>,
+    /// Called with objects that can't be written otherwise, like `json.dumps`'s
+    /// `default`, to get a value to write in their place.
+    pub default: Option<Arc<Py<PyAny>>>,
+    /// Called with every object. Objects it returns an id for, rather than
+    /// `None`, are written as that id, like with `pickle`'s `persistent_id`.
+    pub persistent_id: Option<Arc<Py<PyAny>>>,
+    pub progress: Progress,
+    /// Write a top-level dict as the list of its values in a frame, see `lize.Schema`.
+    pub schema: Option<Arc<lize_sys::Schema>>,
+    /// Write an encrypted frame instead of a bare payload.
+    pub key: Option<Key>,
+    /// Write a frame carrying these tags in its metadata, see `lize.read_metadata`.
+    pub metadata: Option<Arc<Py<PyDict>>>,
+    /// Authenticate the metadata along with the header of an encrypted frame.
+    pub authenticate_metadata: bool,
+    /// Also hash the shape of the value, see `Value::shape_fingerprint`. Set
+    /// by `serialize` rather than passed as a keyword argument.
+    pub fingerprint: bool,
+}
+
+impl EncodeOptions {
+    pub fn from_kwargs(kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        let mut options = Self::default();
+        defaults::apply(|key, value| options.set(key, value))?;
+        for (key, value) in kwargs.into_iter().flatten() {
+            let key = key.extract::<PyBackedStr>()?;
+            if !options.set(&key, &value)? {
+                return Err(unexpected_keyword(&key));
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// Sets the option named `key`, returning `false` if there's none.
+    pub fn set(&mut self, key: &str, value: &Bound<'_, PyAny>) -> PyResult<bool> {
+        match key {
+            "max_depth" => self.core.max_depth = value.extract()?,
+            "canonical" => self.core.canonical = value.extract()?,
+            "max_output_bytes" => self.core.max_output_bytes = value.extract()?,
+            "check_duplicate_keys" => self.core.check_duplicate_keys = value.extract()?,
+            "release_gil" => self.release_gil = value.extract()?,
+            "warn_subclasses" => self.warn_subclasses = value.extract()?,
+            "allow_reduce" => self.allow_reduce = value.extract()?,
+            "parallel" => self.parallel = value.extract()?,
+            "rle" => self.rle = value.extract()?,
+            "json_keys" => self.json_keys = value.extract()?,
+            "strings" => self.strings = value.extract()?,
+            "float_quantize" => self.float_quantize = value.extract()?,
+            "default" => self.default = extract_callable("default", value)?,
+            "persistent_id" => self.persistent_id = extract_callable("persistent_id", value)?,
+            "progress" => self.progress.callback = extract_callable("progress", value)?,
+            "progress_interval" => self.progress.interval = Some(value.extract()?),
+            "schema" => self.schema = extract_schema(value)?,
+            "key" => self.key = value.extract()?,
+            "metadata" => {
+                self.metadata = value
+                    .extract::<Option<Bound<'_, PyDict>>>()?
+                    .map(|tags| Arc::new(tags.unbind()))
+            }
+            "authenticate_metadata" => self.authenticate_metadata = value.extract()?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Whether the output is a frame rather than a bare payload.
+    pub fn is_framed(&self) -> bool {
+        self.key.is_some() || self.schema.is_some() || self.metadata.is_some()
+    }
+}
+
+/// Options for turning lize values back into Python objects.
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    pub core: DeserializeOptions,
+    pub raw_slices: RawSlices,
+    pub bytes_as: BytesAs,
+    /// The buffer being decoded, which `bytes_as="memoryview"` slices. Set by
+    /// `deserialize` rather than passed as a keyword argument.
+    pub view: Option<Arc<SourceView>>,
+    /// Replace invalid UTF-8 with U+FFFD instead of raising.
+    pub lossy: bool,
+    /// Decode maps into lists of `(key, value)` tuples, keeping duplicate and unhashable keys.
+    pub maps_as_pairs: bool,
+    /// Decode maps into immutable records read through attributes.
+    pub record: bool,
+    /// Decode integers as `FixedInt`s, which are written back with the same width.
+    pub preserve_int_width: bool,
+    /// Build each distinct string, byte string, int and float once, and share
+    /// it wherever it appears again.
+    pub dedupe_values: bool,
+    /// Release the GIL while parsing bytes, before any Python object is built.
+    pub release_gil: bool,
+    /// Evaluate function annotations back into types instead of keeping them as strings.
+    pub resolve_annotations: bool,
+    /// Warn when a subclass of `str` or `bytes` is read as its base type.
+    pub warn_subclasses: bool,
+    pub on_bad_runnable: OnBadRunnable,
+    /// Rebuild objects written with `allow_reduce`, which imports and calls
+    /// whatever the payload names.
+    pub allow_reduce: bool,
+    /// Called with each id written by `persistent_id`, to get the object it
+    /// stands for.
+    pub persistent_load: Option<Arc<Py<PyAny>>>,
+    pub progress: Progress,
+    /// Refuse payloads estimated to decode into more bytes of Python objects.
+    pub max_decoded_size: Option<usize>,
+    /// Stop decoding once the Python objects built so far take more bytes.
+    pub max_alloc: Option<Arc<Budget>>,
+    /// Stop expanding runs once they've expanded to this many values.
+    pub expansion: Arc<Expansion>,
+    /// Read a frame written with the same `lize.Schema`, and turn its list of
+    /// values back into a dict.
+    pub schema: Option<Arc<lize_sys::Schema>>,
+    /// Read an encrypted frame instead of a bare payload.
+    pub key: Option<Key>,
+    /// Read a frame even without a key or schema, e.g. one carrying metadata.
+    pub framed: bool,
+}
+
+impl DecodeOptions {
+    pub fn from_kwargs(kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        let mut options = Self::default();
+        defaults::apply(|key, value| options.set(key, value))?;
+        for (key, value) in kwargs.into_iter().flatten() {
+            let key = key.extract::<PyBackedStr>()?;
+            if !options.set(&key, &value)? {
+                return Err(unexpected_keyword(&key));
+            }
+        }
+        if options.record && options.maps_as_pairs {
+            return Err(exceptions::PyTypeError::new_err(
+                "record can't be combined with maps_as_pairs",
+            ));
+        }
+
+        Ok(options)
+    }
+
+    /// Sets the option named `key`, returning `false` if there's none.
+    pub fn set(&mut self, key: &str, value: &Bound<'_, PyAny>) -> PyResult<bool> {
+        match key {
+            "max_depth" => self.core.max_depth = value.extract()?,
+            "raw_slices" => self.raw_slices = value.extract()?,
+            "bytes_as" => self.bytes_as = value.extract()?,
+            "lossy" => self.lossy = value.extract()?,
+            "maps_as_pairs" => self.maps_as_pairs = value.extract()?,
+            "record" => self.record = value.extract()?,
+            "preserve_int_width" => self.preserve_int_width = value.extract()?,
+            "dedupe_values" => self.dedupe_values = value.extract()?,
+            "progress" => self.progress.callback = extract_callable("progress", value)?,
+            "progress_interval" => self.progress.interval = Some(value.extract()?),
+            "keys" => self.core.keys = value.extract()?,
+            "release_gil" => self.release_gil = value.extract()?,
+            "resolve_annotations" => self.resolve_annotations = value.extract()?,
+            "warn_subclasses" => self.warn_subclasses = value.extract()?,
+            "on_bad_runnable" => self.on_bad_runnable = value.extract()?,
+            "allow_reduce" => self.allow_reduce = value.extract()?,
+            "persistent_load" => self.persistent_load = extract_callable("persistent_load", value)?,
+            "max_decoded_size" => self.max_decoded_size = value.extract()?,
+            "max_alloc" => {
+                self.max_alloc = value
+                    .extract::<Option<usize>>()?
+                    .map(Budget::new)
+                    .map(Arc::new)
+            }
+            "max_expansion" => self.expansion = Arc::new(Expansion::new(value.extract()?)),
+            "max_str_len" => self.core.max_str_len = value.extract()?,
+            "schema" => self.schema = extract_schema(value)?,
+            "key" => self.key = value.extract()?,
+            "framed" => self.framed = value.extract()?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Whether the input is a frame rather than a bare payload.
+    pub fn is_framed(&self) -> bool {
+        self.key.is_some() || self.schema.is_some() || self.framed
+    }
+}
+
+/// `progress=...` and `progress_interval=...`, a callback reporting how many
+/// bytes and values have been written or read, every 8 MiB by default.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    pub callback: Option<Arc<Py<PyAny>>>,
+    pub interval: Option<usize>,
+}
+
+impl Progress {
+    pub fn interval(&self) -> usize {
+        self.interval.unwrap_or(8 << 20)
+    }
+
+    /// Calls `callback` with `(bytes, values)`, taking the GIL if it was
+    /// released, and fails with [`lize_sys::Error::Cancelled`] if it returns
+    /// `False`.
+    pub fn report(
+        callback: &Py<PyAny>,
+    ) -> impl FnMut(lize_sys::Progress) -> anyhow::Result<()> + Send + '_ {
+        move |progress| {
+            Python::with_gil(|py| {
+                let carry_on = callback.call1(py, (progress.bytes, progress.values))?;
+                match carry_on.bind(py).downcast::<PyBool>() {
+                    Ok(carry_on) if !carry_on.is_true() => Err(lize_sys::Error::Cancelled.into()),
+                    _ => Ok(()),
+                }
+            })
+        }
+    }
+}
+
+fn extract_callable(name: &str, ob: &Bound<'_, PyAny>) -> PyResult<Option<Arc<Py<PyAny>>>> {
+    if ob.is_none() {
+        return Ok(None);
+    }
+    if !ob.is_callable() {
+        return Err(exceptions::PyTypeError::new_err(format!(
+            "{} must be callable, got {}",
+            name,
+            ob.get_type().name()?
+        )));
+    }
+    Ok(Some(Arc::new(ob.clone().unbind())))
+}
+
+/// A `lize.Schema`, or `None`.
+fn extract_schema(ob: &Bound<'_, PyAny>) -> PyResult<Option<Arc<lize_sys::Schema>>> {
+    if ob.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(ob.downcast::<Schema>()?.get().inner.clone()))
+}
+
+fn unexpected_keyword(name: &str) -> PyErr {
+    exceptions::PyTypeError::new_err(format!("got an unexpected keyword argument {:?}", name))
+}
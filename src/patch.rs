@@ -0,0 +1,59 @@
+//! `lize.DELETE`, `merge` and `diff`, for patching serialized dicts.
+//!
+//! A patch is a dict whose entries replace those of the dict it's merged into.
+//! `DELETE` removes an entry, while `None` sets it to `None`.
+
+use lize_sys::Value;
+use pyo3::{prelude::*, sync::GILOnceCell, types::PyBytes};
+
+use crate::error::into_py_err;
+
+/// The type of `lize.DELETE`, which marks a dict entry to remove in a patch.
+#[pyclass(module = "lize", frozen)]
+pub struct Delete;
+
+#[pymethods]
+impl Delete {
+    fn __repr__(&self) -> &'static str {
+        "lize.DELETE"
+    }
+
+    fn __reduce__(&self) -> &'static str {
+        "DELETE"
+    }
+}
+
+/// The only instance of [`Delete`].
+pub fn sentinel(py: Python<'_>) -> PyResult<&Bound<'_, Delete>> {
+    static DELETE: GILOnceCell<Py<Delete>> = GILOnceCell::new();
+    Ok(DELETE.get_or_try_init(py, || Py::new(py, Delete))?.bind(py))
+}
+
+/// Whether `value` is `lize.DELETE`.
+pub fn is_sentinel(py: Python<'_>, value: &Py<PyAny>) -> bool {
+    sentinel(py).is_ok_and(|delete| delete.as_ptr() == value.as_ptr())
+}
+
+/// Merges the serialized `patch` into the serialized `base`, returning the result.
+///
+/// Dicts are merged recursively. Entries set to `DELETE` in the patch are
+/// removed, and anything that isn't a dict replaces what's in `base`.
+#[pyfunction]
+pub fn merge<'py>(py: Python<'py>, base: &[u8], patch: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    let mut value = Value::deserialize_from(base).map_err(into_py_err)?;
+    value.merge(Value::deserialize_from(patch).map_err(into_py_err)?);
+    Ok(PyBytes::new(py, &value.serialize().map_err(into_py_err)?))
+}
+
+/// Returns the serialized patch that `merge` turns `old` into `new` with.
+///
+/// Entries missing from `new` are set to `DELETE`, and unchanged ones are left out.
+#[pyfunction]
+pub fn diff<'py>(py: Python<'py>, old: &[u8], new: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    let old = Value::deserialize_from(old).map_err(into_py_err)?;
+    let new = Value::deserialize_from(new).map_err(into_py_err)?;
+    Ok(PyBytes::new(
+        py,
+        &old.diff(&new).serialize().map_err(into_py_err)?,
+    ))
+}
@@ -0,0 +1,56 @@
+//! `persistent_id` and `persistent_load`, which, like `pickle`'s, let an
+//! application write its own id in place of an object and resolve the id back
+//! to an object when reading. Ids are stored as [`ext::PERSISTENT_ID`] values.
+
+use anyhow::Result;
+use lize_sys::{ext, Value};
+use pyo3::{exceptions, prelude::*};
+
+use crate::{
+    lize_to_py,
+    options::{DecodeOptions, EncodeOptions},
+    py_to_lize, PyValue,
+};
+
+/// The id `persistent_id` gave for an object, which is written instead of it.
+#[pyclass(frozen)]
+#[derive(Debug)]
+pub struct PersistentId(Py<PyAny>);
+
+/// Extracts `obj`, or the id `persistent_id` gives for it, if any.
+pub fn extract(obj: &Bound<'_, PyAny>, options: &EncodeOptions) -> PyResult<PyValue> {
+    if let Some(persistent_id) = &options.persistent_id {
+        let id = persistent_id.bind(obj.py()).call1((obj,))?;
+        if !id.is_none() {
+            return Ok(PyValue::Persistent(Py::new(
+                obj.py(),
+                PersistentId(id.unbind()),
+            )?));
+        }
+    }
+    obj.extract()
+}
+
+impl PersistentId {
+    pub fn to_lize<'py>(&self, py: Python<'py>, options: &EncodeOptions) -> Result<Value<'py>> {
+        // The id is written as it is, even if `persistent_id` has an id for it.
+        let options = EncodeOptions {
+            persistent_id: None,
+            ..options.clone()
+        };
+        let id = py_to_lize(py, self.0.bind(py).extract()?, &options)?;
+        Ok(Value::Ext(ext::PERSISTENT_ID, Box::new(id)))
+    }
+}
+
+/// Resolves the id in a [`ext::PERSISTENT_ID`] value with `persistent_load`.
+pub fn from_lize(py: Python<'_>, id: &Value<'_>, options: &DecodeOptions) -> Result<Py<PyAny>> {
+    let Some(persistent_load) = &options.persistent_load else {
+        return Err(exceptions::PyValueError::new_err(
+            "found a persistent id, but no persistent_load was given to resolve it",
+        )
+        .into());
+    };
+    let id = lize_to_py(py, id, options)?;
+    Ok(persistent_load.bind(py).call1((id,))?.unbind())
+}
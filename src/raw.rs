@@ -0,0 +1,60 @@
+//! `wrap_raw` and `unwrap_raw`, which pass bytes from another serializer
+//! through lize as an [`Value::Ext`] holding a slice, without looking inside.
+
+use lize_sys::Value;
+use pyo3::{
+    exceptions,
+    prelude::*,
+    types::{PyBytes, PyTuple},
+};
+
+use crate::{
+    error::into_py_err,
+    view::{self, Buffer, SourceView},
+};
+
+/// Wraps `data` as an ext value with code `tag`, copying it once.
+#[pyfunction]
+#[pyo3(signature = (data, tag=0))]
+pub fn wrap_raw<'py>(
+    py: Python<'py>,
+    data: &Bound<'py, PyAny>,
+    tag: u8,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let data = Buffer::get(data)?;
+    let data = data.as_bytes();
+
+    let mut head = Vec::with_capacity(11);
+    Value::write_ext_head(&mut head, tag).map_err(into_py_err)?;
+    Value::write_slice_head(&mut head, data.len()).map_err(into_py_err)?;
+    PyBytes::new_with(py, head.len() + data.len(), |buffer| {
+        let (start, rest) = buffer.split_at_mut(head.len());
+        start.copy_from_slice(&head);
+        rest.copy_from_slice(data);
+        Ok(())
+    })
+}
+
+/// The tag and a read-only memoryview over the bytes given to `wrap_raw`,
+/// which shares the buffer of `data`.
+#[pyfunction]
+pub fn unwrap_raw<'py>(py: Python<'py>, data: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyTuple>> {
+    let buffer = Buffer::get(data)?;
+    let bytes = buffer.as_bytes();
+
+    let value = Value::deserialize_from(bytes).map_err(into_py_err)?;
+    let (tag, slice) = match &value {
+        Value::Ext(tag, inner) => match inner.as_ref() {
+            Value::Slice(slice) => (*tag, *slice),
+            _ => return Err(not_raw()),
+        },
+        _ => return Err(not_raw()),
+    };
+
+    let view = SourceView::new(data, bytes)?;
+    (tag, view::memoryview(py, Some(&view), slice)?).into_pyobject(py)
+}
+
+fn not_raw() -> PyErr {
+    exceptions::PyValueError::new_err("expected bytes from wrap_raw")
+}
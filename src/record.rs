@@ -0,0 +1,66 @@
+//! `record=True`, which decodes maps into immutable records whose entries are
+//! read as attributes, like `config.host`.
+//!
+//! A record is an instance of a `collections.namedtuple` class named `Record`,
+//! built once per distinct list of keys in a payload. Maps whose keys can't
+//! all be field names, e.g. ones that aren't `str`, start with `_` or are
+//! keywords, stay dicts.
+
+use std::collections::HashMap;
+
+use pyo3::{
+    intern,
+    prelude::*,
+    sync::GILOnceCell,
+    types::{PyDict, PyString, PyTuple},
+};
+
+/// The `Record` classes built while decoding one payload, by their fields,
+/// or `None` for keys that can't be fields.
+#[derive(Default)]
+pub struct Records {
+    classes: HashMap<Vec<String>, Option<Py<PyAny>>>,
+}
+
+impl Records {
+    /// Turns `dict` into a record, or returns it as it is if its keys can't
+    /// be fields.
+    pub fn make(&mut self, dict: Bound<'_, PyDict>) -> PyResult<Py<PyAny>> {
+        let py = dict.py();
+        let Some(fields) = dict
+            .keys()
+            .iter()
+            .map(|key| Some(key.downcast::<PyString>().ok()?.to_cow().ok()?.into_owned()))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(dict.into_any().unbind());
+        };
+
+        let class = match self.classes.get(&fields) {
+            Some(class) => class,
+            None => {
+                let class = build_class(py, &fields)?;
+                self.classes.entry(fields).or_insert(class)
+            }
+        };
+        match class {
+            Some(class) => Ok(class.call1(py, PyTuple::new(py, dict.values())?)?),
+            None => Ok(dict.into_any().unbind()),
+        }
+    }
+}
+
+/// Builds the `Record` class for `fields`, or returns `None` if
+/// `namedtuple` refuses them.
+fn build_class(py: Python<'_>, fields: &[String]) -> PyResult<Option<Py<PyAny>>> {
+    static NAMEDTUPLE: GILOnceCell<Py<PyAny>> = GILOnceCell::new();
+    let namedtuple = NAMEDTUPLE.import(py, "collections", "namedtuple")?;
+
+    let kwargs = PyDict::new(py);
+    kwargs.set_item(intern!(py, "module"), "lize")?;
+    match namedtuple.call(("Record", fields), Some(&kwargs)) {
+        Ok(class) => Ok(Some(class.unbind())),
+        Err(err) if err.is_instance_of::<pyo3::exceptions::PyValueError>(py) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
@@ -0,0 +1,50 @@
+//! `redact`, which rewrites a serialized value without some of its dict
+//! entries, using [`RedactKeys`] so nothing is copied but the bytes.
+
+use lize_sys::{
+    frame, DeserializeOptions, Error, RedactKeys, SerializeOptions, SmallVec, Value, STACK_N,
+};
+use pyo3::{exceptions, prelude::*, types::PyBytes};
+
+use crate::{error::into_py_err, options::EncodeOptions, py_to_lize};
+
+/// Returns `data` with every dict entry whose key is one of `keys` left out,
+/// at any depth, or with its value replaced by `replacement` if one is given.
+///
+/// `data` must be a single bare payload. Frames are refused rather than read
+/// as a payload that happens to start with their magic, and so are bytes
+/// after the value, which would otherwise be lost.
+#[pyfunction]
+#[pyo3(signature = (data, keys, replacement = None))]
+pub fn redact<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    keys: Vec<String>,
+    replacement: Option<&Bound<'py, PyAny>>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let mut interceptor = RedactKeys::new(keys);
+    if let Some(replacement) = replacement {
+        let value = py_to_lize(py, replacement.extract()?, &EncodeOptions::default())
+            .map_err(into_py_err)?;
+        interceptor = interceptor.replacement(value.into_owned());
+    }
+
+    if data.starts_with(&frame::MAGIC) {
+        return Err(exceptions::PyValueError::new_err(
+            "redact works on bare payloads, not frames; deserialize the frame and serialize it again instead",
+        ));
+    }
+    let (value, rest) =
+        Value::deserialize_first(data, &DeserializeOptions::default()).map_err(into_py_err)?;
+    if !rest.is_empty() {
+        return Err(into_py_err(Error::TrailingBytes(rest.len()).into()));
+    }
+    let mut buffer = SmallVec::<[u8; STACK_N]>::new();
+    value
+        .serialize_with(
+            &SerializeOptions::new().interceptor(interceptor),
+            &mut buffer,
+        )
+        .map_err(into_py_err)?;
+    Ok(PyBytes::new(py, &buffer))
+}
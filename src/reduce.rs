@@ -0,0 +1,336 @@
+//! Objects without a dedicated encoding, written through the pickle protocol
+//! (`__reduce_ex__`) as [`ext::REDUCE`] values when `allow_reduce=True`.
+//!
+//! Dataclasses are written as a call to their class with their fields instead,
+//! so frozen ones without a `__dict__` are rebuilt through their constructor.
+//! Other objects with `__slots__` and no `__dict__` are written as their class
+//! and the values of their slots by name, and rebuilt by `__new__` and setting
+//! each slot.
+//!
+//! Rebuilding one imports and calls whatever the payload names, so reading them
+//! also requires `allow_reduce=True`.
+
+use anyhow::Result;
+use lize_sys::{ext, Value};
+use pyo3::{
+    exceptions,
+    prelude::*,
+    types::{PyDict, PyList, PyString, PyTuple, PyType},
+};
+
+use crate::{
+    compat, lize_to_py,
+    options::{DecodeOptions, EncodeOptions},
+    py_to_lize,
+};
+
+/// The protocol passed to `__reduce_ex__`, the default of `pickle`.
+const PROTOCOL: u8 = 4;
+
+/// Converts `obj` through its reduce tuple, or by name if it's a class.
+pub fn to_lize<'py>(
+    py: Python<'py>,
+    obj: &Bound<'py, PyAny>,
+    options: &EncodeOptions,
+) -> Result<Value<'py>> {
+    if !options.allow_reduce {
+        return Err(exceptions::PyTypeError::new_err(format!(
+            "can't serialize {}, pass allow_reduce=True to write it with __reduce__",
+            obj.get_type().name()?
+        ))
+        .into());
+    }
+
+    if obj.is_instance_of::<PyType>() {
+        return Ok(reduced(vec![Value::StrLike(global_name(obj)?)]));
+    }
+
+    // Dataclasses are rebuilt by calling the class with their fields, which also
+    // works for frozen ones with `__slots__` and no `__dict__`.
+    if let Some(args) = dataclass_args(py, obj)? {
+        return Ok(reduced(vec![
+            Value::StrLike(global_name(&obj.get_type())?),
+            py_to_lize(py, args.into_any().extract()?, options)?,
+            Value::Optional(None),
+            Value::Vector(vec![]),
+            Value::HashMap(vec![]),
+        ]));
+    }
+
+    if let Some(slots) = slot_values(py, obj)? {
+        return Ok(reduced(vec![
+            Value::StrLike(global_name(&obj.get_type())?),
+            py_to_lize(py, slots.into_any().extract()?, options)?,
+        ]));
+    }
+
+    let reduced_obj = obj.call_method1("__reduce_ex__", (PROTOCOL,))?;
+
+    // A string names a global in the object's module, like a singleton.
+    if let Ok(name) = reduced_obj.downcast::<PyString>() {
+        let module = obj.getattr("__module__")?;
+        return Ok(reduced(vec![Value::StrLike(format!(
+            "{}.{}",
+            module, name
+        ))]));
+    }
+
+    let parts = reduced_obj.downcast::<PyTuple>().map_err(PyErr::from)?;
+    let part = |i: usize| {
+        parts
+            .get_item(i)
+            .unwrap_or_else(|_| py.None().into_bound(py))
+    };
+    if !part(5).is_none() {
+        return Err(exceptions::PyTypeError::new_err(format!(
+            "can't serialize {}, since its __reduce__ sets a state setter",
+            obj.get_type().name()?
+        ))
+        .into());
+    }
+
+    let list_items = match part(3) {
+        items if items.is_none() => PyList::empty(py),
+        items => PyList::new(py, items.try_iter()?.collect::<PyResult<Vec<_>>>()?)?,
+    };
+    let dict_items = PyDict::new(py);
+    if !part(4).is_none() {
+        for item in part(4).try_iter()? {
+            let (key, value) = item?.extract::<(Bound<PyAny>, Bound<PyAny>)>()?;
+            dict_items.set_item(key, value)?;
+        }
+    }
+
+    Ok(reduced(vec![
+        Value::StrLike(global_name(&part(0))?),
+        py_to_lize(py, part(1).extract()?, options)?,
+        py_to_lize(py, part(2).extract()?, options)?,
+        py_to_lize(py, list_items.into_any().extract()?, options)?,
+        py_to_lize(py, dict_items.into_any().extract()?, options)?,
+    ]))
+}
+
+/// The arguments to rebuild a dataclass instance with, read from its fields
+/// with `getattr`. `None` if `obj` isn't one, if its class defines its own
+/// `__reduce__`, or if it has keyword-only fields.
+fn dataclass_args<'py>(
+    py: Python<'py>,
+    obj: &Bound<'py, PyAny>,
+) -> PyResult<Option<Bound<'py, PyList>>> {
+    let dataclasses = py.import("dataclasses")?;
+    if !dataclasses
+        .call_method1("is_dataclass", (obj,))?
+        .is_truthy()?
+    {
+        return Ok(None);
+    }
+
+    if overrides_reduce(py, &obj.get_type())? {
+        return Ok(None);
+    }
+
+    let args = PyList::empty(py);
+    for field in dataclasses.call_method1("fields", (obj,))?.try_iter()? {
+        let field = field?;
+        if !field.getattr("init")?.is_truthy()? {
+            continue;
+        }
+        if compat::DATACLASS_KW_ONLY.available(py) && field.getattr("kw_only")?.is_truthy()? {
+            return Ok(None);
+        }
+        args.append(obj.getattr(field.getattr("name")?.downcast::<PyString>()?)?)?;
+    }
+    Ok(Some(args))
+}
+
+/// The set slots of an object with `__slots__` and no `__dict__`, by name,
+/// from its class and every base. `None` if `obj` isn't one, if its class
+/// defines how it's pickled itself, or if it subclasses a built-in type.
+fn slot_values<'py>(
+    py: Python<'py>,
+    obj: &Bound<'py, PyAny>,
+) -> PyResult<Option<Bound<'py, PyDict>>> {
+    let class = obj.get_type();
+    if obj.hasattr("__dict__")? || overrides_reduce(py, &class)? || class.hasattr("__setstate__")? {
+        return Ok(None);
+    }
+
+    // Every class but `object` must declare `__slots__`, so that a subclass
+    // of a built-in like `tuple` isn't written as its slots alone.
+    let object = py.get_type::<PyAny>();
+    let values = PyDict::new(py);
+    for base in class.mro() {
+        let base = base.downcast_into::<PyType>()?;
+        if base.is(&object) {
+            continue;
+        }
+        let Some(slots) = base.getattr("__dict__")?.get_item("__slots__").ok() else {
+            return Ok(None);
+        };
+        let names = match slots.downcast::<PyString>() {
+            Ok(name) => vec![name.to_cow()?.into_owned()],
+            Err(_) => slots
+                .try_iter()?
+                .map(|name| name?.extract())
+                .collect::<PyResult<_>>()?,
+        };
+        for name in names {
+            if name == "__dict__" || name == "__weakref__" {
+                continue;
+            }
+            // Private names are stored mangled with the name of the class
+            // declaring them.
+            let name = if name.starts_with("__") && !name.ends_with("__") {
+                format!(
+                    "_{}{}",
+                    base.name()?.to_cow()?.trim_start_matches('_'),
+                    name
+                )
+            } else {
+                name
+            };
+            // Slots that were never set are left out.
+            if let Ok(value) = obj.getattr(name.as_str()) {
+                values.set_item(name, value)?;
+            }
+        }
+    }
+    Ok(Some(values))
+}
+
+/// Whether `class` defines its own `__reduce__`, `__reduce_ex__` or
+/// `__getstate__`, which would decide what it's written as.
+fn overrides_reduce(py: Python<'_>, class: &Bound<'_, PyType>) -> PyResult<bool> {
+    let base = py.get_type::<PyAny>();
+    for method in ["__reduce__", "__reduce_ex__", "__getstate__"] {
+        match (class.getattr(method), base.getattr(method)) {
+            (Ok(method), Ok(inherited)) if method.is(&inherited) => {}
+            (Err(_), Err(_)) => {}
+            _ => return Ok(true),
+        }
+    }
+    Ok(false)
+}
+
+fn reduced(items: Vec<Value<'_>>) -> Value<'_> {
+    Value::Ext(ext::REDUCE, Box::new(Value::Vector(items)))
+}
+
+/// `module.QualName` of a class or function, which must be importable.
+fn global_name(obj: &Bound<'_, PyAny>) -> PyResult<String> {
+    let qualname = obj.getattr("__qualname__")?.extract::<String>()?;
+    if qualname.contains("<locals>") {
+        return Err(exceptions::PyTypeError::new_err(format!(
+            "can't serialize {}, which is defined inside a function",
+            qualname
+        )));
+    }
+    Ok(format!("{}.{}", obj.getattr("__module__")?, qualname))
+}
+
+/// Rebuilds an object from the items of an [`ext::REDUCE`] value.
+pub fn from_lize(py: Python<'_>, items: &[Value], options: &DecodeOptions) -> Result<Py<PyAny>> {
+    if !options.allow_reduce {
+        return Err(exceptions::PyValueError::new_err(
+            "found an object written with __reduce__, pass allow_reduce=True to rebuild it",
+        )
+        .into());
+    }
+
+    let invalid = || exceptions::PyValueError::new_err("Invalid reduced object");
+    let target = import_global(
+        py,
+        items.first().and_then(Value::as_str).ok_or_else(invalid)?,
+    )?;
+
+    // Maps in the reduce tuple are passed to Python as dicts.
+    let options = DecodeOptions {
+        maps_as_pairs: false,
+        record: false,
+        ..options.clone()
+    };
+    let decode = |value: &Value| -> Result<Bound<PyAny>> {
+        Ok(lize_to_py(py, value, &options)?.into_bound(py))
+    };
+
+    let [_, args, state, list_items, dict_items] = items else {
+        return match items {
+            [_] => Ok(target.unbind()),
+            [_, slots] => {
+                let class = target.downcast::<PyType>().map_err(PyErr::from)?;
+                let obj = class.call_method1("__new__", (class,))?;
+                for (name, value) in decode(slots)?.downcast::<PyDict>().map_err(PyErr::from)? {
+                    obj.setattr(name.downcast::<PyString>().map_err(PyErr::from)?, value)?;
+                }
+                Ok(obj.unbind())
+            }
+            _ => Err(invalid().into()),
+        };
+    };
+
+    let args = PyTuple::new(py, decode(args)?.downcast::<PyList>().map_err(PyErr::from)?)?;
+    let obj = target.call1(args)?;
+
+    let state = decode(state)?;
+    if !state.is_none() {
+        set_state(&obj, state)?;
+    }
+    for item in decode(list_items)?.try_iter()? {
+        obj.call_method1("append", (item?,))?;
+    }
+    for (key, value) in decode(dict_items)?
+        .downcast::<PyDict>()
+        .map_err(PyErr::from)?
+    {
+        obj.set_item(key, value)?;
+    }
+
+    Ok(obj.unbind())
+}
+
+/// Applies a reduce tuple's state the way `pickle` does.
+fn set_state<'py>(obj: &Bound<'py, PyAny>, state: Bound<'py, PyAny>) -> PyResult<()> {
+    if let Ok(setstate) = obj.getattr("__setstate__") {
+        setstate.call1((state,))?;
+        return Ok(());
+    }
+
+    // Classes with `__slots__` pass `(dict state, slot state)`.
+    let (state, slot_state) = match state.downcast::<PyList>() {
+        Ok(pair) if pair.len() == 2 => (pair.get_item(0)?, Some(pair.get_item(1)?)),
+        _ => (state, None),
+    };
+    if state.is_truthy()? {
+        obj.getattr("__dict__")?.call_method1("update", (state,))?;
+    }
+    if let Some(slot_state) = slot_state.filter(|s| !s.is_none()) {
+        for (name, value) in slot_state.downcast::<PyDict>()? {
+            obj.setattr(name.downcast::<PyString>()?, value)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Imports `module.QualName`, where the module is the longest importable prefix.
+fn import_global<'py>(py: Python<'py>, path: &str) -> PyResult<Bound<'py, PyAny>> {
+    let importlib = py.import("importlib")?;
+    for (i, _) in path.match_indices('.').rev() {
+        let module = match importlib.call_method1("import_module", (&path[..i],)) {
+            Ok(module) => module,
+            Err(err) if err.is_instance_of::<exceptions::PyModuleNotFoundError>(py) => continue,
+            Err(err) => return Err(err),
+        };
+
+        let mut target = module;
+        for attr in path[i + 1..].split('.') {
+            target = target.getattr(attr)?;
+        }
+        return Ok(target);
+    }
+
+    Err(exceptions::PyValueError::new_err(format!(
+        "can't import {:?}",
+        path
+    )))
+}
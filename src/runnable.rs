@@ -0,0 +1,58 @@
+//! The `lize.runnable` submodule, which gathers [`Runnable`] and the helpers
+//! for building one. `lize.Runnable` stays available as well.
+
+use pyo3::{
+    exceptions,
+    prelude::*,
+    types::{PyDict, PyString},
+};
+
+use crate::{compat, compile_source, BrokenRunnable, Runnable};
+
+/// Builds a `Runnable` from the source of a single `def`, which is run in a
+/// fresh namespace so its decorators and defaults are evaluated. The source is
+/// kept, since `inspect` can't find it for a function built this way.
+#[pyfunction]
+pub fn from_source(py: Python<'_>, source: &str) -> PyResult<Runnable> {
+    let name = compile_source(py, source)?.getattr("co_name")?;
+
+    let builtins = py.import("builtins")?;
+    let namespace = PyDict::new(py);
+    namespace.set_item("__builtins__", &builtins)?;
+    let dedented = py.import("textwrap")?.call_method1("dedent", (source,))?;
+    builtins.call_method1("exec", (dedented, &namespace))?;
+
+    let function = namespace
+        .get_item(&name)?
+        .and_then(|function| function.extract::<compat::Function>().ok())
+        .ok_or_else(|| {
+            exceptions::PyTypeError::new_err(format!(
+                "{}() isn't a function once its decorators are applied",
+                name
+            ))
+        })?;
+
+    let mut runnable = Runnable::from_pyfn(py, function, None)?;
+    if let Runnable::Marshal { source_code, .. } = &mut runnable {
+        *source_code = PyString::new(py, source).into_any().unbind();
+    }
+    Ok(runnable)
+}
+
+/// Adds the `runnable` submodule to `parent`, and to `sys.modules` so that
+/// `from lize.runnable import Runnable` finds it.
+pub fn register(parent: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = parent.py();
+    let module = PyModule::new(py, "runnable")?;
+    module.add_class::<Runnable>()?;
+    module.add_class::<BrokenRunnable>()?;
+    module.add_function(wrap_pyfunction!(from_source, &module)?)?;
+    parent.add_submodule(&module)?;
+
+    // Set after `add_submodule`, which names the attribute after the module.
+    module.setattr("__name__", "lize.runnable")?;
+    py.import("sys")?
+        .getattr("modules")?
+        .set_item("lize.runnable", &module)?;
+    Ok(())
+}
@@ -0,0 +1,42 @@
+//! `lize.Schema`, the field names of a dict that `serialize(..., schema=...)`
+//! writes as a list of values, leaving the keys out.
+
+use std::sync::Arc;
+
+use pyo3::prelude::*;
+
+use crate::error::into_py_err;
+
+/// The keys of a dict sent often enough that writing them every time is a
+/// waste. Both sides must create it with the same fields, in the same order.
+#[pyclass(module = "lize", frozen)]
+pub struct Schema {
+    pub inner: Arc<lize_sys::Schema>,
+}
+
+#[pymethods]
+impl Schema {
+    #[new]
+    fn new(fields: Vec<String>) -> PyResult<Self> {
+        let inner = lize_sys::Schema::new(fields).map_err(into_py_err)?;
+        Ok(Self {
+            inner: Arc::new(inner),
+        })
+    }
+
+    #[getter]
+    fn fields(&self) -> Vec<String> {
+        self.inner.fields().to_vec()
+    }
+
+    /// The hash written into frames, which tells schemas apart.
+    #[getter]
+    fn hash(&self) -> u64 {
+        self.inner.hash()
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        let fields = self.inner.fields().into_pyobject(py)?;
+        Ok(format!("Schema({})", fields.repr()?))
+    }
+}
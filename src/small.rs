@@ -0,0 +1,125 @@
+//! A fast path for `serialize` with default options on small messages, like
+//! RPC heartbeats, where converting to [`PyValue`](crate::PyValue)s and then to
+//! a [`Value`] costs far more than writing the bytes.
+//!
+//! Values made only of exact `dict`s, `list`s, `tuple`s, `str`s, `bytes`,
+//! `int`s, `float`s, `bool`s and `None` are written straight into a
+//! [`STACK_N`]-byte array on the stack. Anything else, or anything longer,
+//! gives up and leaves it to the general path, which writes the same bytes.
+
+use std::borrow::Cow;
+
+use lize_sys::{SerializeOptions, Value, STACK_N};
+use pyo3::{
+    prelude::*,
+    types::{PyBool, PyBytes, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple},
+};
+
+use crate::MIN_BITS;
+
+/// How deep containers may nest before giving up, so the fast path's
+/// recursion stays shallow.
+const MAX_DEPTH: usize = 16;
+
+/// Serializes `value` if it fits in [`STACK_N`] bytes and holds only the
+/// types above, or returns `None`.
+pub fn serialize<'py>(value: &Bound<'py, PyAny>) -> Option<Bound<'py, PyBytes>> {
+    let mut array = [0; STACK_N];
+    let mut rest = &mut array[..];
+    write(value, &mut rest, 0)?;
+    let len = STACK_N - rest.len();
+    Some(PyBytes::new(value.py(), &array[..len]))
+}
+
+/// Writes `value` to the front of `out`, or returns `None` if it doesn't fit
+/// or needs the general path.
+fn write(value: &Bound<'_, PyAny>, out: &mut &mut [u8], depth: usize) -> Option<()> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+
+    if let Ok(dict) = value.downcast_exact::<PyDict>() {
+        // Every key and value takes at least a byte.
+        if dict.len() * 2 > out.len() {
+            return None;
+        }
+        Value::write_map_head(out, dict.len()).ok()?;
+        for (key, item) in dict {
+            write(&key, out, depth + 1)?;
+            write(&item, out, depth + 1)?;
+        }
+        return Some(());
+    }
+
+    if let Ok(list) = value.downcast_exact::<PyList>() {
+        return write_items(list.len(), || list.iter(), out, depth);
+    }
+    if let Ok(tuple) = value.downcast_exact::<PyTuple>() {
+        return write_items(tuple.len(), || tuple.iter(), out, depth);
+    }
+
+    scalar(value)?
+        .serialize_to(&SerializeOptions::default(), out)
+        .ok()
+}
+
+/// Writes the `len` items of a list or tuple as a vector.
+fn write_items<'py, I: Iterator<Item = Bound<'py, PyAny>>>(
+    len: usize,
+    items: impl Fn() -> I,
+    out: &mut &mut [u8],
+    depth: usize,
+) -> Option<()> {
+    // Every item takes at least a byte, and lists of booleans are packed into
+    // bits by the general path.
+    if len > out.len() || (len >= MIN_BITS && items().all(|item| item.is_instance_of::<PyBool>())) {
+        return None;
+    }
+
+    Value::write_vector_head(out, len).ok()?;
+    for item in items() {
+        write(&item, out, depth + 1)?;
+    }
+    Some(())
+}
+
+/// The value an exact `str`, `bytes`, `int`, `float`, `bool` or `None` is
+/// written as, borrowing the bytes of strings.
+fn scalar<'a>(value: &'a Bound<'_, PyAny>) -> Option<Value<'a>> {
+    if value.is_none() {
+        return Some(Value::Optional(None));
+    }
+    if let Ok(b) = value.downcast_exact::<PyBool>() {
+        return Some(Value::Bool(b.is_true()));
+    }
+    if let Ok(s) = value.downcast_exact::<PyString>() {
+        // Only borrowed where the string's UTF-8 can be, outside abi3 builds.
+        return match s.to_cow().ok()? {
+            Cow::Borrowed(s) => Some(Value::Str(s)),
+            Cow::Owned(s) => Some(Value::StrLike(s)),
+        };
+    }
+    if let Ok(b) = value.downcast_exact::<PyBytes>() {
+        return Some(Value::Slice(b.as_bytes()));
+    }
+    if value.is_exact_instance_of::<PyInt>() {
+        // The same widths `PyValue` picks: `U8`, `Int32`, then `Int`.
+        let i = value.extract::<i64>().ok()?;
+        return Some(match i {
+            0..=235 => Value::SmallU8(i as u8),
+            236..=255 => Value::U8(i as u8),
+            _ => match i32::try_from(i) {
+                Ok(i) => Value::I32(i),
+                Err(_) => Value::I64(i),
+            },
+        });
+    }
+    if let Ok(f) = value.downcast_exact::<PyFloat>() {
+        let f = f.value();
+        return Some(match (f as f32) as f64 == f {
+            true => Value::F32(f as f32),
+            false => Value::F64(f),
+        });
+    }
+    None
+}
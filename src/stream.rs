@@ -0,0 +1,433 @@
+//! `Writer` and `Reader`, which store values as frames in files or Python file objects,
+//! and `serialize_map_items`, which streams a map into one.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use lize_sys::{frame, Value};
+use pyo3::{
+    exceptions, intern,
+    prelude::*,
+    pybacked::PyBackedStr,
+    types::{PyBytes, PyDict, PyTuple},
+};
+
+use crate::{
+    decode_value,
+    error::into_py_err,
+    lize_to_py, metadata,
+    options::{DecodeOptions, EncodeOptions},
+    py_to_lize, PyValue,
+};
+
+/// A binary Python file object, e.g. from `open(path, "rb")` or `io.BytesIO`.
+///
+/// Errors raised by the file are carried inside the [`io::Error`], and are
+/// re-raised as they are by [`into_py_err`].
+#[derive(Debug)]
+pub struct PyFile(Py<PyAny>);
+
+impl Read for PyFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Python::with_gil(|py| {
+            let data = self
+                .0
+                .call_method1(py, "read", (buf.len(),))
+                .map_err(io::Error::other)?;
+            let data = data.extract::<&[u8]>(py).map_err(io::Error::other)?;
+
+            let n = data.len().min(buf.len());
+            buf[..n].copy_from_slice(&data[..n]);
+            Ok(n)
+        })
+    }
+}
+
+impl Write for PyFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Python::with_gil(|py| {
+            let written = self
+                .0
+                .call_method1(py, "write", (PyBytes::new(py, buf),))
+                .map_err(io::Error::other)?;
+            // Raw files may write less than asked, buffered ones return `None` or everything.
+            Ok(written
+                .extract::<Option<usize>>(py)
+                .ok()
+                .flatten()
+                .unwrap_or(buf.len()))
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Python::with_gil(|py| {
+            self.0
+                .call_method0(py, "flush")
+                .map(|_| ())
+                .map_err(io::Error::other)
+        })
+    }
+}
+
+/// When a [`Writer`] makes the frames it has written durable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SyncMode {
+    /// Leaves the bytes wherever the file buffers them until it's flushed.
+    #[default]
+    None,
+    /// Hands each batch to the operating system, by flushing the file.
+    Os,
+    /// Flushes each batch and `fsync`s the file, so it survives a power loss.
+    Fsync,
+}
+
+impl<'py> FromPyObject<'py> for SyncMode {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        match &*ob.extract::<PyBackedStr>()? {
+            "none" => Ok(Self::None),
+            "os" => Ok(Self::Os),
+            "fsync" => Ok(Self::Fsync),
+            other => Err(exceptions::PyValueError::new_err(format!(
+                "sync must be one of 'none', 'os' or 'fsync', got {:?}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Where a [`Writer`] writes its batches.
+#[derive(Debug)]
+enum Sink {
+    /// A file object it was given, which is left open.
+    Python(PyFile),
+    /// A file it opened from a path, which it closes. It isn't buffered, and
+    /// is written without the GIL.
+    Path(File),
+}
+
+impl Sink {
+    fn write_all(&mut self, py: Python<'_>, bytes: &[u8]) -> PyResult<()> {
+        match self {
+            Self::Python(file) => file.write_all(bytes).map_err(into_os_err),
+            Self::Path(file) => py
+                .allow_threads(|| file.write_all(bytes))
+                .map_err(into_os_err),
+        }
+    }
+
+    fn sync(&mut self, py: Python<'_>, mode: SyncMode) -> PyResult<()> {
+        match (self, mode) {
+            (_, SyncMode::None) | (Self::Path(_), SyncMode::Os) => Ok(()),
+            (Self::Python(file), SyncMode::Os) => file.flush().map_err(into_os_err),
+            (Self::Python(file), SyncMode::Fsync) => {
+                file.flush().map_err(into_os_err)?;
+                let fileno = file.0.call_method0(py, intern!(py, "fileno"))?;
+                py.import("os")?.call_method1("fsync", (fileno,))?;
+                Ok(())
+            }
+            (Self::Path(file), SyncMode::Fsync) => {
+                py.allow_threads(|| file.sync_all()).map_err(into_os_err)
+            }
+        }
+    }
+}
+
+/// Re-raises errors from Python file objects as they are, and others as the
+/// matching `OSError`.
+fn into_os_err(err: io::Error) -> PyErr {
+    match err.get_ref().is_some_and(|inner| inner.is::<PyErr>()) {
+        true => into_py_err(err.into()),
+        false => err.into(),
+    }
+}
+
+/// Writes each value as a frame to a binary file, or to a file it opens from
+/// a path, replacing it.
+///
+/// Frames are collected in batches of `batch_size`, each written in one call,
+/// and a batch is also written once its oldest frame is `flush_interval`
+/// seconds old, checked on each write rather than by a timer. `sync` says
+/// what happens after each batch is written. `flush()`, `close()` and leaving
+/// a `with` block write the batch so far. With `checksum=True`, each frame
+/// carries a CRC-32 of its body, which readers check.
+///
+/// Takes the same keyword arguments as `serialize`; with `key=...` every frame
+/// is encrypted with a fresh nonce.
+#[pyclass(module = "lize")]
+pub struct Writer {
+    /// Seals frames into the batch.
+    inner: frame::Writer<Vec<u8>>,
+    /// `None` once the writer is closed.
+    sink: Option<Sink>,
+    options: EncodeOptions,
+    batch_size: usize,
+    flush_interval: Option<Duration>,
+    sync: SyncMode,
+    batched: usize,
+    /// When the first frame of the batch was written.
+    started: Option<Instant>,
+}
+
+#[pymethods]
+impl Writer {
+    #[new]
+    #[pyo3(signature = (file, *, batch_size = 1, flush_interval = None, sync = SyncMode::None, checksum = false, **kwargs))]
+    fn new(
+        file: Bound<'_, PyAny>,
+        batch_size: usize,
+        flush_interval: Option<f64>,
+        sync: SyncMode,
+        checksum: bool,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        if batch_size < 1 {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "batch_size must be at least 1, got {}",
+                batch_size
+            )));
+        }
+        let flush_interval = flush_interval
+            .map(|seconds| {
+                Duration::try_from_secs_f64(seconds).map_err(|_| {
+                    exceptions::PyValueError::new_err(format!(
+                        "flush_interval must be a number of seconds of at least 0, got {}",
+                        seconds
+                    ))
+                })
+            })
+            .transpose()?;
+
+        let options = EncodeOptions::from_kwargs(kwargs)?;
+        let metadata = metadata::build(file.py(), &options)?;
+
+        let mut inner = frame::Writer::new(vec![])
+            .with_options(options.core.clone())
+            .with_checksum(checksum);
+        if let Some(key) = options.key {
+            inner = inner.with_key(key.0);
+        }
+        if let Some(schema) = &options.schema {
+            inner = inner.with_schema(schema);
+        }
+        if let Some(metadata) = metadata {
+            inner = inner.with_metadata(metadata);
+        }
+
+        let sink = match file.extract::<PathBuf>() {
+            Ok(path) => Sink::Path(File::create(path)?),
+            Err(_) => Sink::Python(PyFile(file.unbind())),
+        };
+
+        Ok(Self {
+            inner,
+            sink: Some(sink),
+            options,
+            batch_size,
+            flush_interval,
+            sync,
+            batched: 0,
+            started: None,
+        })
+    }
+
+    pub fn write(&mut self, py: Python<'_>, value: PyValue) -> PyResult<()> {
+        if self.sink.is_none() {
+            return Err(exceptions::PyValueError::new_err(
+                "write to a closed Writer",
+            ));
+        }
+        let mut value = py_to_lize(py, value, &self.options).map_err(into_py_err)?;
+        if let Some(schema) = &self.options.schema {
+            value = schema.encode(value).map_err(into_py_err)?;
+        }
+        self.inner.write(&value).map_err(into_py_err)?;
+
+        self.batched += 1;
+        let started = *self.started.get_or_insert_with(Instant::now);
+        let due = self
+            .flush_interval
+            .is_some_and(|interval| started.elapsed() >= interval);
+        if self.batched >= self.batch_size || due {
+            self.write_batch(py)?;
+            let sync = self.sync;
+            self.sink_mut()?.sync(py, sync)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the batch so far and flushes the file, `fsync`ing it too with
+    /// `sync="fsync"`.
+    pub fn flush(&mut self, py: Python<'_>) -> PyResult<()> {
+        self.write_batch(py)?;
+        let sync = self.sync.max(SyncMode::Os);
+        self.sink_mut()?.sync(py, sync)
+    }
+
+    /// Flushes, and closes the file if the writer opened it. Closing again
+    /// does nothing.
+    pub fn close(&mut self, py: Python<'_>) -> PyResult<()> {
+        if self.sink.is_none() {
+            return Ok(());
+        }
+        let flushed = self.flush(py);
+        self.sink = None;
+        flushed
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    #[pyo3(signature = (*_exc_info))]
+    fn __exit__(&mut self, py: Python<'_>, _exc_info: &Bound<'_, PyTuple>) -> PyResult<bool> {
+        self.close(py)?;
+        Ok(false)
+    }
+}
+
+impl Writer {
+    fn sink_mut(&mut self) -> PyResult<&mut Sink> {
+        self.sink
+            .as_mut()
+            .ok_or_else(|| exceptions::PyValueError::new_err("flush of a closed Writer"))
+    }
+
+    /// Writes the frames collected so far in one call. They're dropped even
+    /// if it fails, since part of them may have been written.
+    fn write_batch(&mut self, py: Python<'_>) -> PyResult<()> {
+        let mut batch = std::mem::take(self.inner.get_mut());
+        self.batched = 0;
+        self.started = None;
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let written = self.sink_mut()?.write_all(py, &batch);
+        batch.clear();
+        *self.inner.get_mut() = batch;
+        written
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        // Like a Python file, a writer that's collected without being closed
+        // writes what it holds, but can't raise errors anymore.
+        if self.sink.is_some() {
+            Python::with_gil(|py| {
+                let _ = self.close(py);
+            });
+        }
+    }
+}
+
+/// Writes a map of `len` entries to a binary file, taking each `(key, value)`
+/// pair from `items` as it's written, so the dict never has to exist.
+///
+/// Takes the same keyword arguments as `serialize`, except those that need the
+/// whole map: `key`, `schema`, `metadata`, `canonical` and `check_duplicate_keys`. Raises `ValueError` if `items`
+/// doesn't have exactly `len` pairs, after writing what it had.
+#[pyfunction]
+#[pyo3(signature = (items, len, file, **kwargs))]
+pub fn serialize_map_items(
+    py: Python<'_>,
+    items: &Bound<'_, PyAny>,
+    len: usize,
+    file: Py<PyAny>,
+    kwargs: Option<&Bound<'_, PyDict>>,
+) -> PyResult<()> {
+    let options = EncodeOptions::from_kwargs(kwargs)?;
+    if options.is_framed() || options.core.canonical || options.core.check_duplicate_keys {
+        return Err(exceptions::PyTypeError::new_err(
+            "serialize_map_items doesn't take key, schema, metadata, canonical or check_duplicate_keys",
+        ));
+    }
+
+    let mut out = BufWriter::new(PyFile(file));
+    Value::write_map_head(&mut out, len).map_err(into_py_err)?;
+
+    let mut items = items.try_iter()?;
+    for written in 0..len {
+        let Some(item) = items.next() else {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "items ended after {} pairs, expected {}",
+                written, len
+            )));
+        };
+        let (key, value) = item?.extract::<(PyValue, PyValue)>()?;
+        for part in [key, value] {
+            let part = py_to_lize(py, part, &options).map_err(into_py_err)?;
+            part.serialize_to(&options.core, &mut out)
+                .map_err(into_py_err)?;
+        }
+    }
+    out.flush().map_err(|err| into_py_err(err.into()))?;
+
+    if items.next().is_some() {
+        return Err(exceptions::PyValueError::new_err(format!(
+            "items has more than {} pairs",
+            len
+        )));
+    }
+    Ok(())
+}
+
+/// Iterates over the values in a binary file written by `Writer`.
+///
+/// Takes the same keyword arguments as `deserialize`.
+#[pyclass(module = "lize")]
+pub struct Reader {
+    inner: frame::Reader<PyFile>,
+    options: DecodeOptions,
+}
+
+#[pymethods]
+impl Reader {
+    #[new]
+    #[pyo3(signature = (file, **kwargs))]
+    fn new(file: Py<PyAny>, kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        let options = DecodeOptions::from_kwargs(kwargs)?;
+
+        let mut inner = frame::Reader::new(PyFile(file));
+        if let Some(key) = options.key {
+            inner = inner.with_key(key.0);
+        }
+        if let Some(schema) = &options.schema {
+            inner = inner.with_schema(schema);
+        }
+
+        Ok(Self { inner, options })
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Reads past the next `n` values without decoding them, and returns how
+    /// many there were, which is less than `n` at the end of the file.
+    pub fn skip(&mut self, n: usize) -> PyResult<usize> {
+        for skipped in 0..n {
+            if self.inner.next_payload().map_err(into_py_err)?.is_none() {
+                return Ok(skipped);
+            }
+        }
+        Ok(n)
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<Py<PyAny>>> {
+        let Some(payload) = self.inner.next_payload().map_err(into_py_err)? else {
+            return Ok(None);
+        };
+
+        if let Some(budget) = &self.options.max_alloc {
+            budget.reset();
+        }
+        let value = decode_value(py, &payload, &self.options)?;
+        let value = lize_to_py(py, &value, &self.options).map_err(into_py_err)?;
+        Ok(Some(value))
+    }
+}
@@ -0,0 +1,340 @@
+//! numpy structured arrays, whose dtype has named fields, which are stored as
+//! an [`ext::STRUCTURED_ARRAY`] value holding each field as a column of bytes.
+//!
+//! Columns are written little-endian, whatever the byte order of the dtype.
+//! They're read back into an array of the same dtype if numpy can be
+//! imported, or else into a dict of lists, one per field.
+
+use anyhow::Result;
+use lize_sys::{ext, Value};
+use pyo3::{
+    exceptions,
+    prelude::*,
+    pybacked::PyBackedStr,
+    sync::GILOnceCell,
+    types::{PyBytes, PyComplex, PyDict, PyList, PyString, PyTuple, PyType},
+    IntoPyObjectExt,
+};
+
+use crate::{lize_to_py, options::DecodeOptions, options::EncodeOptions, py_to_lize};
+
+/// `numpy.ndarray`, once numpy has been imported by someone else. Nothing can
+/// be an array before that, so it isn't imported just to check.
+fn class(py: Python<'_>) -> PyResult<Option<&Bound<'_, PyType>>> {
+    static CLASS: GILOnceCell<Py<PyType>> = GILOnceCell::new();
+    if CLASS.get(py).is_none() && !py.import("sys")?.getattr("modules")?.contains("numpy")? {
+        return Ok(None);
+    }
+    CLASS.import(py, "numpy", "ndarray").map(Some)
+}
+
+/// A numpy array whose dtype has named fields.
+#[derive(Debug)]
+pub struct StructuredArray(Py<PyAny>);
+
+impl<'py> FromPyObject<'py> for StructuredArray {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let is_structured = match class(ob.py())? {
+            Some(class) => {
+                ob.is_instance(class)? && !ob.getattr("dtype")?.getattr("names")?.is_none()
+            }
+            None => false,
+        };
+        if !is_structured {
+            return Err(exceptions::PyTypeError::new_err(
+                "expected a structured array",
+            ));
+        }
+        Ok(Self(ob.clone().unbind()))
+    }
+}
+
+impl<'py> IntoPyObject<'py> for StructuredArray {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        Ok(self.0.into_bound(py))
+    }
+}
+
+impl StructuredArray {
+    /// `[shape, structure]`, where a structure is `[itemsize, fields]` and each
+    /// field is `[name, offset, shape, dtype, column]`. `dtype` is either the
+    /// little-endian dtype string, with `column` holding its bytes in C order,
+    /// or the structure of a nested dtype, with `column` being `None`.
+    pub fn to_lize<'py>(&self, py: Python<'py>, options: &EncodeOptions) -> Result<Value<'py>> {
+        let array = self.0.bind(py);
+        let payload = PyList::new(
+            py,
+            [
+                array.getattr("shape")?,
+                structure(array, &array.getattr("dtype")?, "")?.into_any(),
+            ],
+        )?;
+        let payload = py_to_lize(py, payload.extract()?, options)?;
+        Ok(Value::Ext(ext::STRUCTURED_ARRAY, Box::new(payload)))
+    }
+}
+
+/// The structure of `dtype`, with the columns of `array`, which has it.
+/// `path` names the field `array` is, for errors.
+fn structure<'py>(
+    array: &Bound<'py, PyAny>,
+    dtype: &Bound<'py, PyAny>,
+    path: &str,
+) -> PyResult<Bound<'py, PyList>> {
+    let py = array.py();
+    let numpy = py.import("numpy")?;
+    let fields = PyList::empty(py);
+    let by_name = dtype.getattr("fields")?;
+
+    for name in dtype.getattr("names")?.downcast::<PyTuple>()? {
+        let name = name.downcast_into::<PyString>()?;
+        let path = match path {
+            "" => name.to_string(),
+            _ => format!("{}.{}", path, name),
+        };
+        let field = by_name.get_item(&name)?;
+        let (field_dtype, offset) = (field.get_item(0)?, field.get_item(1)?);
+        let base = field_dtype.getattr("base")?;
+        let column = array.get_item(&name)?;
+
+        let (dtype, data) = if !base.getattr("names")?.is_none() {
+            (
+                structure(&column, &base, &path)?.into_any(),
+                py.None().into_bound(py),
+            )
+        } else {
+            if base.getattr("hasobject")?.is_truthy()? {
+                return Err(exceptions::PyTypeError::new_err(format!(
+                    "can't serialize field '{}' of a structured array, since its dtype {} holds Python objects",
+                    path, base
+                )));
+            }
+            let little = base.call_method1("newbyteorder", ("<",))?;
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("dtype", &little)?;
+            let data = numpy
+                .call_method("ascontiguousarray", (&column,), Some(&kwargs))?
+                .call_method0("tobytes")?;
+            (little.getattr("str")?, data)
+        };
+
+        fields.append((name, offset, field_dtype.getattr("shape")?, dtype, data))?;
+    }
+
+    PyList::new(py, [dtype.getattr("itemsize")?, fields.into_any()])
+}
+
+/// Rebuilds an array from the `[shape, structure]` payload
+/// [`StructuredArray::to_lize`] wrote, or a dict of lists without numpy.
+pub fn from_lize(py: Python<'_>, payload: &Value<'_>) -> Result<Py<PyAny>> {
+    let payload = lize_to_py(py, payload, &DecodeOptions::default())?;
+    let [shape, structure] = &payload.extract::<Vec<Bound<'_, PyAny>>>(py)?[..] else {
+        return Err(invalid().into());
+    };
+    let shape: Vec<usize> = shape.extract()?;
+
+    Ok(match py.import("numpy") {
+        Ok(numpy) => {
+            let array = numpy.call_method1(
+                "zeros",
+                (PyTuple::new(py, &shape)?, dtype(&numpy, structure)?),
+            )?;
+            fill(&numpy, &array, structure)?;
+            array.unbind()
+        }
+        Err(_) => columns(py, &shape, structure)?.into_any().unbind(),
+    })
+}
+
+fn invalid() -> PyErr {
+    exceptions::PyValueError::new_err("Invalid structured array")
+}
+
+/// A field of a structure: its name, offset, shape, dtype and column.
+type Field<'py> = (
+    Bound<'py, PyString>,
+    usize,
+    Vec<usize>,
+    Bound<'py, PyAny>,
+    Bound<'py, PyAny>,
+);
+
+/// The itemsize and fields of a structure.
+fn fields<'py>(structure: &Bound<'py, PyAny>) -> PyResult<(usize, Vec<Field<'py>>)> {
+    let [itemsize, fields] = &structure.extract::<Vec<Bound<'py, PyAny>>>()?[..] else {
+        return Err(invalid());
+    };
+    let fields = fields
+        .extract::<Vec<Vec<Bound<'py, PyAny>>>>()?
+        .into_iter()
+        .map(|field| match <[_; 5]>::try_from(field) {
+            Ok([name, offset, shape, dtype, column]) => Ok((
+                name.downcast_into()?,
+                offset.extract()?,
+                shape.extract()?,
+                dtype,
+                column,
+            )),
+            Err(_) => Err(invalid()),
+        })
+        .collect::<PyResult<_>>()?;
+    Ok((itemsize.extract()?, fields))
+}
+
+/// The numpy dtype of a structure.
+fn dtype<'py>(
+    numpy: &Bound<'py, PyModule>,
+    structure: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = numpy.py();
+    let (itemsize, fields) = fields(structure)?;
+    let (names, formats, offsets) = (PyList::empty(py), PyList::empty(py), PyList::empty(py));
+
+    for (name, offset, shape, field_dtype, _) in fields {
+        let base = match field_dtype.is_instance_of::<PyString>() {
+            true => numpy.getattr("dtype")?.call1((field_dtype,))?,
+            false => dtype(numpy, &field_dtype)?,
+        };
+        // Only plain bytes can be rebuilt, never references to objects.
+        if base.getattr("hasobject")?.is_truthy()? {
+            return Err(invalid());
+        }
+        names.append(name)?;
+        match shape.is_empty() {
+            true => formats.append(base)?,
+            false => formats.append((base, PyTuple::new(py, shape)?))?,
+        }
+        offsets.append(offset)?;
+    }
+
+    let spec = PyDict::new(py);
+    spec.set_item("names", names)?;
+    spec.set_item("formats", formats)?;
+    spec.set_item("offsets", offsets)?;
+    spec.set_item("itemsize", itemsize)?;
+    numpy.getattr("dtype")?.call1((spec,))
+}
+
+/// Copies the columns of a structure into `array`, which has its dtype.
+fn fill(
+    numpy: &Bound<'_, PyModule>,
+    array: &Bound<'_, PyAny>,
+    structure: &Bound<'_, PyAny>,
+) -> PyResult<()> {
+    for (name, _, _, field_dtype, column) in fields(structure)?.1 {
+        let target = array.get_item(&name)?;
+        if field_dtype.is_instance_of::<PyString>() {
+            let column = numpy
+                .call_method1("frombuffer", (column, field_dtype))?
+                .call_method1("reshape", (target.getattr("shape")?,))?;
+            array.set_item(&name, column)?;
+        } else {
+            fill(numpy, &target, &field_dtype)?;
+        }
+    }
+    Ok(())
+}
+
+/// A dict with a list per field, nested like `shape` and any shape of the
+/// field itself. Nested dtypes give nested dicts.
+fn columns<'py>(
+    py: Python<'py>,
+    shape: &[usize],
+    structure: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    for (name, _, field_shape, field_dtype, column) in fields(structure)?.1 {
+        let shape = [shape, &field_shape].concat();
+        let value = match field_dtype.extract::<PyBackedStr>() {
+            Ok(dtype) => {
+                let items = scalars(py, &dtype, column.downcast::<PyBytes>()?.as_bytes())?;
+                if items.len() != shape.iter().product::<usize>() {
+                    return Err(invalid());
+                }
+                nest(py, &items, &shape)?
+            }
+            Err(_) => columns(py, &shape, &field_dtype)?.into_any(),
+        };
+        dict.set_item(name, value)?;
+    }
+    Ok(dict)
+}
+
+/// Splits `items` into lists of lists, following `shape`.
+fn nest<'py>(
+    py: Python<'py>,
+    items: &[Bound<'py, PyAny>],
+    shape: &[usize],
+) -> PyResult<Bound<'py, PyAny>> {
+    let Some((&n, rest)) = shape.split_first() else {
+        return Ok(items[0].clone());
+    };
+    let chunk = rest.iter().product::<usize>();
+    let list = PyList::empty(py);
+    for i in 0..n {
+        list.append(nest(py, &items[i * chunk..(i + 1) * chunk], rest)?)?;
+    }
+    Ok(list.into_any())
+}
+
+/// The elements of a column with the little-endian dtype `dtype`, as Python
+/// values. Dtypes without an obvious Python equivalent give each element's
+/// bytes.
+fn scalars<'py>(py: Python<'py>, dtype: &str, bytes: &[u8]) -> PyResult<Vec<Bound<'py, PyAny>>> {
+    let mut chars = dtype.chars();
+    let (Some('<' | '|'), Some(kind)) = (chars.next(), chars.next()) else {
+        return Err(invalid());
+    };
+    let digits = chars.as_str().split('[').next().unwrap_or_default();
+    let count: usize = digits.parse().map_err(|_| invalid())?;
+    let size = if kind == 'U' { count * 4 } else { count };
+    if size == 0 || !bytes.len().is_multiple_of(size) {
+        return Err(invalid());
+    }
+
+    let int = |b: &[u8]| {
+        let mut buf = [0; 8];
+        buf[..b.len()].copy_from_slice(b);
+        // Sign-extends the narrower integers.
+        let shift = 64 - 8 * b.len() as u32;
+        (i64::from_le_bytes(buf) << shift) >> shift
+    };
+    let uint = |b: &[u8]| {
+        let mut buf = [0; 8];
+        buf[..b.len()].copy_from_slice(b);
+        u64::from_le_bytes(buf)
+    };
+    let float = |b: &[u8]| match b.len() {
+        4 => f32::from_le_bytes(b.try_into().unwrap()) as f64,
+        _ => f64::from_le_bytes(b.try_into().unwrap()),
+    };
+
+    bytes
+        .chunks_exact(size)
+        .map(|b| {
+            Ok(match (kind, size) {
+                ('b', 1) => (b[0] != 0).into_bound_py_any(py)?,
+                ('i' | 'M' | 'm', 1 | 2 | 4 | 8) => int(b).into_bound_py_any(py)?,
+                ('u', 1 | 2 | 4 | 8) => uint(b).into_bound_py_any(py)?,
+                ('f', 4 | 8) => float(b).into_bound_py_any(py)?,
+                ('c', 8 | 16) => {
+                    let (real, imag) = b.split_at(size / 2);
+                    PyComplex::from_doubles(py, float(real), float(imag)).into_any()
+                }
+                // numpy drops trailing NULs from both.
+                ('S', _) => {
+                    let end = b.iter().rposition(|&c| c != 0).map_or(0, |i| i + 1);
+                    PyBytes::new(py, &b[..end]).into_any()
+                }
+                ('U', _) => PyBytes::new(py, b)
+                    .call_method1("decode", ("utf-32-le",))?
+                    .call_method1("rstrip", ("\0",))?,
+                _ => PyBytes::new(py, b).into_any(),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,170 @@
+//! Subclasses of `str` and `bytes`, which are stored as [`ext::SUBCLASS`] values
+//! so they decode to the same class.
+//!
+//! A class is preserved if it was passed to `lize.register`, or if it sets
+//! `__lize_subclass__ = True`. Other subclasses are written as their base type.
+
+use anyhow::Result;
+use lize_sys::{ext, Value};
+use pyo3::{
+    exceptions,
+    prelude::*,
+    sync::GILOnceCell,
+    types::{PyBytes, PyDict, PyString, PyType},
+};
+
+/// The class attribute that marks a subclass for preservation without registering it.
+const MARKER: &str = "__lize_subclass__";
+
+/// Registered classes by qualified name.
+fn registry(py: Python<'_>) -> &Bound<'_, PyDict> {
+    static REGISTRY: GILOnceCell<Py<PyDict>> = GILOnceCell::new();
+    REGISTRY
+        .get_or_init(py, || PyDict::new(py).unbind())
+        .bind(py)
+}
+
+/// `module.QualName` of a class, which is what's stored with its instances.
+fn qualified_name(cls: &Bound<'_, PyType>) -> PyResult<String> {
+    Ok(format!(
+        "{}.{}",
+        cls.getattr("__module__")?,
+        cls.getattr("__qualname__")?
+    ))
+}
+
+fn is_marked(cls: &Bound<'_, PyAny>) -> bool {
+    cls.getattr(MARKER)
+        .and_then(|marker| marker.is_truthy())
+        .unwrap_or(false)
+}
+
+/// Registers a subclass of `str` or `bytes`, so its instances keep their class
+/// through `serialize` and `deserialize`. Returns the class, so it can be used
+/// as a decorator.
+#[pyfunction]
+pub fn register<'py>(cls: &Bound<'py, PyType>) -> PyResult<Bound<'py, PyType>> {
+    let py = cls.py();
+    if !cls.is_subclass_of::<PyString>()? && !cls.is_subclass_of::<PyBytes>()? {
+        return Err(exceptions::PyTypeError::new_err(format!(
+            "only subclasses of str and bytes can be registered, got {}",
+            cls
+        )));
+    }
+
+    registry(py).set_item(qualified_name(cls)?, cls)?;
+    Ok(cls.clone())
+}
+
+/// An instance of a strict subclass of `str` or `bytes`.
+#[derive(Debug)]
+pub struct Subclass(Py<PyAny>);
+
+impl<'py> FromPyObject<'py> for Subclass {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let is_subclass = (ob.is_instance_of::<PyString>()
+            && !ob.is_exact_instance_of::<PyString>())
+            || (ob.is_instance_of::<PyBytes>() && !ob.is_exact_instance_of::<PyBytes>());
+
+        if is_subclass {
+            Ok(Self(ob.clone().unbind()))
+        } else {
+            Err(exceptions::PyTypeError::new_err(
+                "expected a subclass of str or bytes",
+            ))
+        }
+    }
+}
+
+impl<'py> IntoPyObject<'py> for Subclass {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        Ok(self.0.into_bound(py))
+    }
+}
+
+impl Subclass {
+    /// Converts the instance, keeping its class if it's registered or marked.
+    /// Otherwise it's written as its base type, with a warning if `warn` is set.
+    pub fn to_lize(&self, py: Python<'_>, warn: bool) -> Result<Value<'static>> {
+        let ob = self.0.bind(py);
+        let cls = ob.get_type();
+        let name = qualified_name(&cls)?;
+
+        let base = if let Ok(s) = ob.downcast::<PyString>() {
+            Value::StrLike(s.to_cow()?.into_owned())
+        } else {
+            Value::SliceLike(
+                ob.downcast::<PyBytes>()
+                    .map_err(PyErr::from)?
+                    .as_bytes()
+                    .to_vec(),
+            )
+        };
+
+        if !is_marked(&cls) && !registry(py).contains(&name)? {
+            if warn {
+                warn_degraded(py, &name, "written as its base type")?;
+            }
+            return Ok(base);
+        }
+
+        Ok(Value::Ext(
+            ext::SUBCLASS,
+            Box::new(Value::Vector(vec![Value::StrLike(name), base])),
+        ))
+    }
+}
+
+/// Rebuilds an instance from its qualified class name and base value.
+///
+/// Classes are looked up in the registry, then in loaded modules if they are marked.
+/// If neither finds one, `base` is returned as is, with a warning if `warn` is set.
+pub fn from_lize(py: Python<'_>, name: &str, base: Py<PyAny>, warn: bool) -> PyResult<Py<PyAny>> {
+    let cls = match registry(py).get_item(name)? {
+        Some(cls) => Some(cls),
+        None => find_marked(py, name),
+    };
+
+    match cls {
+        Some(cls) => Ok(cls.call1((base,))?.unbind()),
+        None => {
+            if warn {
+                warn_degraded(py, name, "read as its base type")?;
+            }
+            Ok(base)
+        }
+    }
+}
+
+/// Finds `module.QualName` among the loaded modules, if that's a class that
+/// sets the marker. Nothing is imported, so payloads can't trigger imports.
+fn find_marked<'py>(py: Python<'py>, name: &str) -> Option<Bound<'py, PyAny>> {
+    let modules = py.import("sys").ok()?.getattr("modules").ok()?;
+
+    // The module is the longest loaded prefix; the rest are attributes.
+    let (mut cls, attrs) = name.match_indices('.').rev().find_map(|(i, _)| {
+        let module = modules.get_item(&name[..i]).ok()?;
+        Some((module, &name[i + 1..]))
+    })?;
+    for attr in attrs.split('.') {
+        cls = cls.getattr(attr).ok()?;
+    }
+
+    (cls.is_instance_of::<PyType>() && is_marked(&cls)).then_some(cls)
+}
+
+fn warn_degraded(py: Python<'_>, name: &str, what: &str) -> PyResult<()> {
+    PyErr::warn(
+        py,
+        &py.get_type::<exceptions::PyUserWarning>(),
+        &std::ffi::CString::new(format!(
+            "{} isn't registered with lize.register, so it was {}",
+            name, what
+        ))?,
+        1,
+    )
+}
@@ -0,0 +1,84 @@
+//! `to_arrow` and `from_arrow`, which exchange lists of dicts with pyarrow
+//! through the Arrow C data interface.
+
+use arrow::{
+    pyarrow::{FromPyArrow, ToPyArrow},
+    record_batch::RecordBatch,
+};
+use lize_sys::{arrow::from_record_batch, arrow::to_record_batch, SmallVec, Value, STACK_N};
+use pyo3::{
+    exceptions,
+    prelude::*,
+    types::{PyBytes, PyDict},
+};
+
+use crate::{
+    decode_value,
+    error::into_py_err,
+    open_payload,
+    options::{DecodeOptions, EncodeOptions},
+    seal_payload,
+};
+
+/// Decodes a list of dicts into a `pyarrow.Table`, with a column per key.
+///
+/// Columns hold integers, floats, booleans, strings or bytes, and may contain
+/// `None`s or be missing from some rows. Nested values raise a `ValueError`.
+#[pyfunction]
+#[pyo3(signature = (data, **kwargs))]
+pub fn to_arrow<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    kwargs: Option<&Bound<'_, PyDict>>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let options = DecodeOptions::from_kwargs(kwargs)?;
+    let payload = open_payload(data, &options)?;
+    let value = decode_value(py, &payload, &options)?;
+    let batch = to_record_batch(&value).map_err(into_py_err)?;
+
+    py.import("pyarrow")?
+        .getattr("Table")?
+        .call_method1("from_batches", (vec![batch.to_pyarrow(py)?],))
+}
+
+/// Serializes a `pyarrow.Table` or `pyarrow.RecordBatch` as a list of dicts,
+/// one per row. Nulls are written as `None`.
+#[pyfunction]
+#[pyo3(signature = (table, **kwargs))]
+pub fn from_arrow<'py>(
+    py: Python<'py>,
+    table: &Bound<'py, PyAny>,
+    kwargs: Option<&Bound<'_, PyDict>>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let options = EncodeOptions::from_kwargs(kwargs)?;
+    if options.schema.is_some() {
+        return Err(exceptions::PyTypeError::new_err(
+            "from_arrow doesn't take a schema",
+        ));
+    }
+
+    // Tables are split into their batches, which are read one at a time.
+    let batches = if table.hasattr("to_batches")? {
+        table.call_method0("to_batches")?.try_iter()?.collect()
+    } else {
+        vec![Ok(table.clone())]
+    };
+
+    let mut rows = vec![];
+    for batch in batches {
+        let batch = RecordBatch::from_pyarrow_bound(&batch?)?;
+        if let Value::Vector(batch_rows) = &mut from_record_batch(&batch).map_err(into_py_err)? {
+            rows.append(batch_rows);
+        }
+    }
+
+    let mut buf = SmallVec::<[u8; STACK_N]>::new();
+    Value::Vector(rows)
+        .serialize_with(&options.core, &mut buf)
+        .map_err(into_py_err)?;
+
+    if options.is_framed() {
+        return Ok(PyBytes::new(py, &seal_payload(py, &buf, &options)?));
+    }
+    Ok(PyBytes::new(py, &buf))
+}
@@ -0,0 +1,30 @@
+//! `lize.tune`, the number of bytes `serialize` reserves up front.
+//!
+//! Output is written into a [`STACK_N`]-byte buffer on the stack, which moves
+//! to the heap and then doubles each time it fills. A workload whose payloads
+//! are all around 1 MiB grows it 13 times per call; reserving that much from
+//! the start allocates once.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use lize_sys::{SmallVec, STACK_N};
+use pyo3::prelude::*;
+
+static RESERVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Reserves room for the typical payload in `buf`, if it's more than fits on
+/// the stack.
+pub fn reserve(buf: &mut SmallVec<[u8; STACK_N]>) {
+    let typical_size = RESERVE.load(Ordering::Relaxed);
+    if typical_size > STACK_N {
+        buf.reserve(typical_size);
+    }
+}
+
+/// Makes later calls to `serialize` reserve `typical_size` bytes for their
+/// output before writing it, or stop reserving with 0. Returns the previous
+/// size.
+#[pyfunction]
+pub fn tune(typical_size: usize) -> usize {
+    RESERVE.swap(typical_size, Ordering::Relaxed)
+}
@@ -0,0 +1,435 @@
+//! `load_as` and `deserialize_typeddict`, which decode a payload and check it
+//! against type hints like `list[int]` or a `typing.TypedDict`.
+//!
+//! The hints are interpreted once, into a [`Schema`], before the payload is
+//! checked. Classes, `Any`, `None`, `Optional` and other unions, `Literal`,
+//! `NewType`, TypedDicts and the items of `list`, `set`, `frozenset`, `tuple`
+//! and `dict` are checked. Other generics only have their origin checked, and
+//! annotations that aren't classes at all, like `TypeVar`s, accept anything.
+
+use pyo3::{
+    exceptions,
+    prelude::*,
+    types::{PyDict, PyFloat, PyFrozenSet, PyInt, PyList, PySet, PyTuple, PyType},
+};
+
+use crate::{compat, decode_payload, error::ValidationError, open_payload, options::DecodeOptions};
+
+/// Deserializes `data` and checks it against the type hint `tp`.
+///
+/// Raises a `ValidationError` naming the path to the first value that doesn't
+/// match, or, for TypedDicts, a missing required key or one that isn't declared.
+#[pyfunction]
+#[pyo3(signature = (data, tp, **kwargs))]
+pub fn load_as(
+    py: Python<'_>,
+    data: &[u8],
+    tp: &Bound<'_, PyAny>,
+    kwargs: Option<&Bound<'_, PyDict>>,
+) -> PyResult<Py<PyAny>> {
+    let schema = Schema::new(tp)?;
+    decode_checked(py, data, &schema, kwargs)
+}
+
+/// Deserializes `data` and checks that it's a dict matching `cls`, a TypedDict.
+///
+/// Raises a `ValidationError` naming the entry if a required key is missing, a
+/// key isn't annotated, or a value doesn't match its annotation.
+#[pyfunction]
+#[pyo3(signature = (data, cls, **kwargs))]
+pub fn deserialize_typeddict(
+    py: Python<'_>,
+    data: &[u8],
+    cls: &Bound<'_, PyAny>,
+    kwargs: Option<&Bound<'_, PyDict>>,
+) -> PyResult<Py<PyAny>> {
+    if !is_typeddict(cls)? {
+        return Err(exceptions::PyTypeError::new_err(format!(
+            "{} isn't a TypedDict",
+            cls.repr()?
+        )));
+    }
+    let schema = Schema::new(cls)?;
+    decode_checked(py, data, &schema, kwargs)
+}
+
+fn decode_checked(
+    py: Python<'_>,
+    data: &[u8],
+    schema: &Schema<'_>,
+    kwargs: Option<&Bound<'_, PyDict>>,
+) -> PyResult<Py<PyAny>> {
+    let options = DecodeOptions::from_kwargs(kwargs)?;
+    let payload = open_payload(data, &options)?;
+    let value = decode_payload(py, &payload, &options)?.into_bound(py);
+    schema.check(&value, &schema.root, &mut String::from("payload"))?;
+    Ok(value.unbind())
+}
+
+/// Whether `tp` is a TypedDict class. `typing.is_typeddict` needs Python
+/// 3.10, so this looks for the `__total__` they all have instead.
+fn is_typeddict(tp: &Bound<'_, PyAny>) -> PyResult<bool> {
+    let Ok(class) = tp.downcast::<PyType>() else {
+        return Ok(false);
+    };
+    Ok(class.is_subclass_of::<PyDict>()? && class.hasattr("__total__")?)
+}
+
+/// A type hint, interpreted so values are checked without asking `typing`.
+struct Hint<'py> {
+    /// The annotation itself, named in errors.
+    tp: Bound<'py, PyAny>,
+    kind: Kind<'py>,
+}
+
+enum Kind<'py> {
+    Any,
+    None,
+    Union(Vec<Hint<'py>>),
+    Literal(Vec<Bound<'py, PyAny>>),
+    /// An index into [`Schema::typeddicts`], so they can refer to themselves.
+    TypedDict(usize),
+    Class(Bound<'py, PyType>, Items<'py>),
+}
+
+/// What the items of a class are checked against.
+enum Items<'py> {
+    Unchecked,
+    /// Each item of a `list`, `set`, `frozenset` or `tuple[X, ...]`.
+    Each(Box<Hint<'py>>),
+    /// The items of a fixed-length tuple, like `tuple[int, str]`.
+    Fixed(Vec<Hint<'py>>),
+    /// The keys and values of a `dict`.
+    Entries(Box<Hint<'py>>, Box<Hint<'py>>),
+}
+
+struct TypedDict<'py> {
+    cls: Bound<'py, PyAny>,
+    required: Vec<Bound<'py, PyAny>>,
+    /// Maps each declared key to its index in `fields`.
+    keys: Bound<'py, PyDict>,
+    fields: Vec<Hint<'py>>,
+}
+
+/// The hints of a whole payload.
+struct Schema<'py> {
+    root: Hint<'py>,
+    typeddicts: Vec<TypedDict<'py>>,
+}
+
+impl<'py> Schema<'py> {
+    fn new(tp: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let mut compiler = Compiler::new(tp.py())?;
+        let root = compiler.hint(tp)?;
+        Ok(Self {
+            root,
+            typeddicts: compiler.typeddicts.into_iter().flatten().collect(),
+        })
+    }
+
+    /// Checks `value` against `hint`, naming `path` if it doesn't match.
+    fn check(
+        &self,
+        value: &Bound<'py, PyAny>,
+        hint: &Hint<'py>,
+        path: &mut String,
+    ) -> PyResult<()> {
+        match &hint.kind {
+            Kind::Any => Ok(()),
+            Kind::None if value.is_none() => Ok(()),
+            Kind::None => Err(mismatch(path, &hint.tp, value)),
+            Kind::TypedDict(index) => self.check_typeddict(value, &self.typeddicts[*index], path),
+            Kind::Union(arms) => {
+                for arm in arms {
+                    if self.check(value, arm, &mut path.clone()).is_ok() {
+                        return Ok(());
+                    }
+                }
+                Err(mismatch(path, &hint.tp, value))
+            }
+            Kind::Literal(options) => {
+                for option in options {
+                    if value.get_type().is(&option.get_type()) && value.eq(option)? {
+                        return Ok(());
+                    }
+                }
+                Err(mismatch(path, &hint.tp, value))
+            }
+            Kind::Class(class, items) => {
+                if !matches_class(value, class)? {
+                    return Err(mismatch(path, &hint.tp, value));
+                }
+                self.check_items(value, hint, items, path)
+            }
+        }
+    }
+
+    fn check_items(
+        &self,
+        value: &Bound<'py, PyAny>,
+        hint: &Hint<'py>,
+        items: &Items<'py>,
+        path: &mut String,
+    ) -> PyResult<()> {
+        let len = path.len();
+        match items {
+            Items::Unchecked => {}
+            Items::Entries(key_hint, value_hint) => {
+                for (key, item) in value.downcast::<PyDict>()? {
+                    path.push_str(&format!("[{}]", key.repr()?));
+                    self.check(&key, key_hint, path)?;
+                    self.check(&item, value_hint, path)?;
+                    path.truncate(len);
+                }
+            }
+            Items::Fixed(item_hints) => {
+                let items = value.try_iter()?.collect::<PyResult<Vec<_>>>()?;
+                if items.len() != item_hints.len() {
+                    return Err(mismatch(path, &hint.tp, value));
+                }
+                for (i, (item, item_hint)) in items.iter().zip(item_hints).enumerate() {
+                    path.push_str(&format!("[{}]", i));
+                    self.check(item, item_hint, path)?;
+                    path.truncate(len);
+                }
+            }
+            Items::Each(item_hint) => {
+                for (i, item) in value.try_iter()?.enumerate() {
+                    path.push_str(&format!("[{}]", i));
+                    self.check(&item?, item_hint, path)?;
+                    path.truncate(len);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_typeddict(
+        &self,
+        value: &Bound<'py, PyAny>,
+        typeddict: &TypedDict<'py>,
+        path: &mut String,
+    ) -> PyResult<()> {
+        let Ok(dict) = value.downcast::<PyDict>() else {
+            return Err(mismatch(path, &typeddict.cls, value));
+        };
+
+        for key in &typeddict.required {
+            if !dict.contains(key)? {
+                return Err(invalid(
+                    path,
+                    &typeddict.cls,
+                    format!("{} is missing the required key {}", path, key.repr()?),
+                ));
+            }
+        }
+
+        for (key, item) in dict {
+            let Some(index) = typeddict.keys.get_item(&key)? else {
+                return Err(invalid(
+                    path,
+                    &typeddict.cls,
+                    format!(
+                        "{} has the key {}, which {} doesn't declare",
+                        path,
+                        key.repr()?,
+                        type_name(&typeddict.cls)
+                    ),
+                ));
+            };
+            let len = path.len();
+            path.push_str(&format!("[{}]", key.repr()?));
+            self.check(&item, &typeddict.fields[index.extract::<usize>()?], path)?;
+            path.truncate(len);
+        }
+        Ok(())
+    }
+}
+
+/// Turns annotations into [`Hint`]s, using the parts of `typing` they're
+/// compared against.
+struct Compiler<'py> {
+    typing: Bound<'py, PyModule>,
+    any: Bound<'py, PyAny>,
+    union: Bound<'py, PyAny>,
+    literal: Bound<'py, PyAny>,
+    /// `types.UnionType`, the origin of `X | Y`, see [`compat::UNION_TYPE`].
+    union_type: Option<Bound<'py, PyAny>>,
+    /// The TypedDicts seen so far, `None` while their fields are compiled.
+    typeddicts: Vec<Option<TypedDict<'py>>>,
+    classes: Vec<Bound<'py, PyAny>>,
+}
+
+impl<'py> Compiler<'py> {
+    fn new(py: Python<'py>) -> PyResult<Self> {
+        let typing = py.import("typing")?;
+        Ok(Self {
+            any: typing.getattr("Any")?,
+            union: typing.getattr("Union")?,
+            literal: typing.getattr("Literal")?,
+            union_type: match compat::UNION_TYPE.available(py) {
+                true => Some(py.import("types")?.getattr("UnionType")?),
+                false => None,
+            },
+            typing,
+            typeddicts: Vec::new(),
+            classes: Vec::new(),
+        })
+    }
+
+    fn hint(&mut self, tp: &Bound<'py, PyAny>) -> PyResult<Hint<'py>> {
+        let kind = self.kind(tp)?;
+        Ok(Hint {
+            tp: tp.clone(),
+            kind,
+        })
+    }
+
+    fn kind(&mut self, tp: &Bound<'py, PyAny>) -> PyResult<Kind<'py>> {
+        if tp.is(&self.any) {
+            return Ok(Kind::Any);
+        }
+        let py = tp.py();
+        if tp.is_none() || tp.is(&py.None().bind(py).get_type()) {
+            return Ok(Kind::None);
+        }
+        if is_typeddict(tp)? {
+            return self.typeddict(tp).map(Kind::TypedDict);
+        }
+        if let Ok(supertype) = tp.getattr("__supertype__") {
+            return self.kind(&supertype);
+        }
+
+        let origin = self.typing.call_method1("get_origin", (tp,))?;
+        let args = self
+            .typing
+            .call_method1("get_args", (tp,))?
+            .downcast_into::<PyTuple>()?;
+
+        if origin.is(&self.union) || self.union_type.as_ref().is_some_and(|u| origin.is(u)) {
+            let arms = args
+                .iter()
+                .map(|arm| self.hint(&arm))
+                .collect::<PyResult<_>>()?;
+            return Ok(Kind::Union(arms));
+        }
+        if origin.is(&self.literal) {
+            return Ok(Kind::Literal(args.iter().collect()));
+        }
+
+        let class = match origin.is_none() {
+            true => tp.clone(),
+            false => origin,
+        };
+        let Ok(class) = class.downcast_into::<PyType>() else {
+            return Ok(Kind::Any);
+        };
+        let items = if args.is_empty() {
+            Items::Unchecked
+        } else if class.is(&py.get_type::<PyDict>()) {
+            Items::Entries(
+                Box::new(self.hint(&args.get_item(0)?)?),
+                Box::new(self.hint(&args.get_item(1)?)?),
+            )
+        } else if class.is(&py.get_type::<PyTuple>())
+            && !args.get_item(args.len() - 1)?.is(&py.Ellipsis())
+        {
+            Items::Fixed(
+                args.iter()
+                    .map(|item| self.hint(&item))
+                    .collect::<PyResult<_>>()?,
+            )
+        } else if [
+            py.get_type::<PyList>(),
+            py.get_type::<PyTuple>(),
+            py.get_type::<PySet>(),
+            py.get_type::<PyFrozenSet>(),
+        ]
+        .iter()
+        .any(|t| class.is(t))
+        {
+            Items::Each(Box::new(self.hint(&args.get_item(0)?)?))
+        } else {
+            Items::Unchecked
+        };
+        Ok(Kind::Class(class, items))
+    }
+
+    /// Compiles the TypedDict `cls` once, returning its index.
+    fn typeddict(&mut self, cls: &Bound<'py, PyAny>) -> PyResult<usize> {
+        if let Some(index) = self.classes.iter().position(|seen| seen.is(cls)) {
+            return Ok(index);
+        }
+        let index = self.typeddicts.len();
+        self.typeddicts.push(None);
+        self.classes.push(cls.clone());
+
+        let hints = self
+            .typing
+            .call_method1("get_type_hints", (cls,))?
+            .downcast_into::<PyDict>()?;
+        let required = match cls.getattr("__required_keys__") {
+            Ok(keys) => keys,
+            // Before Python 3.9, every key of a total TypedDict is required.
+            Err(_) if cls.getattr("__total__")?.is_truthy()? => hints.keys().into_any(),
+            Err(_) => PyTuple::empty(cls.py()).into_any(),
+        };
+
+        let keys = PyDict::new(cls.py());
+        let mut fields = Vec::with_capacity(hints.len());
+        for (key, tp) in &hints {
+            keys.set_item(key, fields.len())?;
+            fields.push(self.hint(&tp)?);
+        }
+        self.typeddicts[index] = Some(TypedDict {
+            cls: cls.clone(),
+            required: required.try_iter()?.collect::<PyResult<_>>()?,
+            keys,
+            fields,
+        });
+        Ok(index)
+    }
+}
+
+/// `isinstance(value, class)`, except that tuples are decoded as lists, and
+/// ints are accepted for `float` like type checkers do.
+fn matches_class(value: &Bound<'_, PyAny>, class: &Bound<'_, PyType>) -> PyResult<bool> {
+    let py = value.py();
+    if class.is(&py.get_type::<PyTuple>()) && value.is_instance_of::<PyList>() {
+        return Ok(true);
+    }
+    if class.is(&py.get_type::<PyFloat>()) && value.is_instance_of::<PyInt>() {
+        return Ok(true);
+    }
+    value.is_instance(class)
+}
+
+fn mismatch(path: &str, tp: &Bound<'_, PyAny>, value: &Bound<'_, PyAny>) -> PyErr {
+    let message = format!(
+        "{} should be {}, got {}",
+        path,
+        type_name(tp),
+        type_name(&value.get_type())
+    );
+    invalid(path, tp, message)
+}
+
+/// A `ValidationError` with `path` and the name of the `expected` type as
+/// attributes, for callers that report them on their own.
+fn invalid(path: &str, expected: &Bound<'_, PyAny>, message: String) -> PyErr {
+    let err = ValidationError::new_err(message);
+    let value = err.value(expected.py());
+    let attributes = value
+        .setattr("path", path)
+        .and_then(|_| value.setattr("expected", type_name(expected)));
+    attributes.err().unwrap_or(err)
+}
+
+/// The name of a class, or how `typing` writes any other annotation.
+fn type_name(tp: &Bound<'_, PyAny>) -> String {
+    match tp.downcast::<PyType>() {
+        Ok(class) => class.name().map(|name| name.to_string()),
+        Err(_) => tp.str().map(|s| s.to_string()),
+    }
+    .unwrap_or_else(|_| "?".to_string())
+}
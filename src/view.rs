@@ -0,0 +1,172 @@
+//! `bytes_as="memoryview"`, which decodes byte slices into read-only
+//! memoryviews over the buffer passed to `deserialize` instead of copying them.
+//!
+//! Only `bytes` are decoded in place. Python code can run while a payload is
+//! decoded, from hooks, `progress` or other threads, and could write to a
+//! `bytearray` or `mmap` under values that were already checked, so those are
+//! copied first, see [`immutable`], and the memoryviews are over the copy.
+//!
+//! abi3 builds can't read buffers from Rust before Python 3.11, so they copy
+//! the bytes of anything but `bytes` through a `memoryview` instead.
+
+#[cfg(not(feature = "abi3"))]
+use pyo3::buffer::PyBuffer;
+#[cfg(feature = "abi3")]
+use pyo3::pybacked::PyBackedStr;
+use pyo3::{
+    exceptions,
+    prelude::*,
+    types::{PyByteArray, PyBytes, PyMemoryView, PySlice},
+};
+
+/// The bytes of a `bytes` object or of anything else with a contiguous buffer,
+/// like a `bytearray` or an `mmap`, which can't be resized while this lives.
+pub enum Buffer<'py> {
+    Bytes(Bound<'py, PyBytes>),
+    #[cfg(not(feature = "abi3"))]
+    Other(PyBuffer<u8>),
+}
+
+impl<'py> Buffer<'py> {
+    #[cfg(not(feature = "abi3"))]
+    pub fn get(source: &Bound<'py, PyAny>) -> PyResult<Self> {
+        if let Ok(bytes) = source.downcast::<PyBytes>() {
+            return Ok(Self::Bytes(bytes.clone()));
+        }
+
+        let buffer = PyBuffer::<u8>::get(source)?;
+        if !buffer.is_c_contiguous() {
+            return Err(exceptions::PyTypeError::new_err(
+                "expected a contiguous buffer",
+            ));
+        }
+        Ok(Self::Other(buffer))
+    }
+
+    #[cfg(feature = "abi3")]
+    pub fn get(source: &Bound<'py, PyAny>) -> PyResult<Self> {
+        if let Ok(bytes) = source.downcast::<PyBytes>() {
+            return Ok(Self::Bytes(bytes.clone()));
+        }
+
+        let view = byte_view(source)?;
+        if !view.getattr("c_contiguous")?.is_truthy()? {
+            return Err(exceptions::PyTypeError::new_err(
+                "expected a contiguous buffer",
+            ));
+        }
+        Ok(Self::Bytes(view.call_method0("tobytes")?.downcast_into()?))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Bytes(bytes) => bytes.as_bytes(),
+            // SAFETY: the buffer is contiguous and stays exported, so it can't
+            // be freed or resized while it's borrowed.
+            #[cfg(not(feature = "abi3"))]
+            Self::Other(buffer) => unsafe {
+                std::slice::from_raw_parts(buffer.buf_ptr() as *const u8, buffer.len_bytes())
+            },
+        }
+    }
+}
+
+/// The bytes of `source` as a `bytes` object, which nothing can write to
+/// while it's decoded: `source` itself if it's `bytes`, or a copy of its
+/// buffer otherwise.
+pub fn immutable<'py>(source: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyBytes>> {
+    match Buffer::get(source)? {
+        Buffer::Bytes(bytes) => Ok(bytes),
+        // Copied before any Python code runs, so the buffer can't change
+        // while it's read.
+        #[cfg(not(feature = "abi3"))]
+        buffer @ Buffer::Other(_) => Ok(PyBytes::new(source.py(), buffer.as_bytes())),
+    }
+}
+
+/// A memoryview of `source`, failing like `PyBuffer::<u8>::get` unless its
+/// items are single unsigned bytes.
+#[cfg(feature = "abi3")]
+fn byte_view<'py>(source: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyMemoryView>> {
+    let view = PyMemoryView::from(source)?;
+    let format = view.getattr("format")?.extract::<PyBackedStr>()?;
+    if !matches!(
+        format.trim_start_matches(['@', '=', '<', '>', '!']),
+        "B" | "c"
+    ) {
+        return Err(exceptions::PyBufferError::new_err(
+            "buffer contents are not compatible with u8",
+        ));
+    }
+    Ok(view)
+}
+
+/// The bytes of a `bytearray`, or of a `memoryview` of single bytes, copied
+/// straight out of its buffer rather than through a `bytes` object first.
+/// They're written like `bytes`, and decode as `bytes`.
+#[derive(Debug)]
+pub struct ByteBuffer(pub Vec<u8>);
+
+impl<'py> FromPyObject<'py> for ByteBuffer {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        if !ob.is_instance_of::<PyByteArray>() && !ob.is_instance_of::<PyMemoryView>() {
+            return Err(exceptions::PyTypeError::new_err(
+                "expected a bytearray or memoryview",
+            ));
+        }
+        // Memoryviews of wider items, like an `array("i")`, aren't `u8` buffers,
+        // and are written as the list of their items instead.
+        #[cfg(not(feature = "abi3"))]
+        let bytes = PyBuffer::<u8>::get(ob)?.to_vec(ob.py())?;
+        #[cfg(feature = "abi3")]
+        let bytes = byte_view(ob)?.call_method0("tobytes")?.extract()?;
+        Ok(Self(bytes))
+    }
+}
+
+impl<'py> IntoPyObject<'py> for ByteBuffer {
+    type Target = PyBytes;
+    type Output = Bound<'py, PyBytes>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        Ok(PyBytes::new(py, &self.0))
+    }
+}
+
+/// A read-only memoryview over the whole buffer a payload is decoded from.
+#[derive(Debug)]
+pub struct SourceView {
+    memoryview: Py<PyAny>,
+    /// The address and length of the buffer, to tell its slices apart.
+    start: usize,
+    len: usize,
+}
+
+impl SourceView {
+    pub fn new(source: &Bound<'_, PyAny>, bytes: &[u8]) -> PyResult<Self> {
+        let memoryview = PyMemoryView::from(source)?.call_method0("toreadonly")?;
+        Ok(Self {
+            memoryview: memoryview.unbind(),
+            start: bytes.as_ptr() as usize,
+            len: bytes.len(),
+        })
+    }
+}
+
+/// A read-only memoryview of `bytes`: a slice of `view` if `bytes` lies in its
+/// buffer, or of a copy otherwise, e.g. for decrypted frames.
+pub fn memoryview<'py>(
+    py: Python<'py>,
+    view: Option<&SourceView>,
+    bytes: &[u8],
+) -> PyResult<Bound<'py, PyAny>> {
+    let offset = (bytes.as_ptr() as usize).wrapping_sub(view.map_or(0, |v| v.start));
+    match view {
+        Some(view) if offset <= view.len && bytes.len() <= view.len - offset => {
+            let range = PySlice::new(py, offset as isize, (offset + bytes.len()) as isize, 1);
+            view.memoryview.bind(py).get_item(range)
+        }
+        _ => Ok(PyMemoryView::from(&PyBytes::new(py, bytes))?.into_any()),
+    }
+}